@@ -3,7 +3,7 @@
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use ignore::WalkBuilder;
-use jwalk::{Error, Parallelism, WalkDir, WalkDirGeneric};
+use jwalk::{Error, Parallelism, ReadDirOrder, WalkDir, WalkDirGeneric};
 use num_cpus;
 use rayon::prelude::*;
 use std::cmp;
@@ -11,6 +11,7 @@ use std::fs::Metadata;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::mpsc;
 use walkdir;
 
@@ -51,6 +52,10 @@ fn walk_benches(c: &mut Criterion) {
         b.iter(|| for _ in WalkDir::new(big_dir()) {})
     });
 
+    c.bench_function("jwalk (unsorted, fifo, n threads)", |b| {
+        b.iter(|| for _ in WalkDir::new(big_dir()).read_dir_order(ReadDirOrder::Fifo) {})
+    });
+
     c.bench_function("jwalk (sorted, n threads)", |b| {
         b.iter(|| for _ in WalkDir::new(big_dir()).sort(true) {})
     });
@@ -70,6 +75,26 @@ fn walk_benches(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("jwalk (for_each_ref, n threads)", |b| {
+        b.iter(|| {
+            let count = AtomicUsize::new(0);
+            WalkDir::new(big_dir()).for_each_ref(|_| {
+                count.fetch_add(1, AtomicOrdering::Relaxed);
+            });
+            black_box(count.into_inner())
+        })
+    });
+
+    c.bench_function("jwalk (for_each_raw, n threads)", |b| {
+        b.iter(|| {
+            let count = AtomicUsize::new(0);
+            WalkDir::new(big_dir()).for_each_raw(|_, _, _| {
+                count.fetch_add(1, AtomicOrdering::Relaxed);
+            });
+            black_box(count.into_inner())
+        })
+    });
+
     c.bench_function("jwalk (sorted, n threads, first 100)", |b| {
         b.iter(
             || {
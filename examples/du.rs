@@ -2,23 +2,49 @@ mod shared;
 
 use bytesize::ByteSize;
 use clap::Parser;
+use jwalk::size::{SizeCounter, SizeKind};
 use jwalk::WalkDirGeneric;
+use std::sync::Arc;
+
+#[derive(clap::Parser)]
+pub struct OurArgs {
+    /// Count apparent sizes instead of allocated blocks, like `du --apparent-size`.
+    #[arg(long)]
+    apparent_size: bool,
+    /// Count hardlinked files once per link, like `du --count-links`.
+    #[arg(long, short = 'l')]
+    count_links: bool,
+
+    #[clap(flatten)]
+    inner: shared::Args,
+}
 
 fn main() {
-    let args = shared::Args::parse();
+    let args = OurArgs::parse();
     let mut total: u64 = 0;
 
-    let parallelism = args.parallelism();
-    let path = args.root.unwrap_or_else(|| ".".into());
+    let kind = if args.apparent_size {
+        SizeKind::Apparent
+    } else {
+        SizeKind::Allocated
+    };
+    let size_counter = Arc::new(SizeCounter::new(kind).dedup_hardlinks(!args.count_links));
+
+    let parallelism = args.inner.parallelism();
+    let path = args.inner.root.unwrap_or_else(|| ".".into());
     for dir_entry_result in WalkDirGeneric::<((), Option<u64>)>::new(&path)
         .skip_hidden(false)
         .parallelism(parallelism)
-        .process_read_dir(|_, _, _, dir_entry_results| {
+        .process_read_dir(move |_, _, _, dir_entry_results| {
             dir_entry_results.iter_mut().for_each(|dir_entry_result| {
                 if let Ok(dir_entry) = dir_entry_result {
                     if !dir_entry.file_type.is_dir() {
-                        dir_entry.client_state =
-                            Some(dir_entry.metadata().map(|m| m.len()).unwrap_or_default());
+                        dir_entry.client_state = Some(
+                            dir_entry
+                                .metadata()
+                                .map(|m| size_counter.size(&m))
+                                .unwrap_or_default(),
+                        );
                     }
                 }
             })
@@ -0,0 +1,216 @@
+//! Walk into archives as if they were directories.
+//!
+//! [`ArchiveFs`](struct.ArchiveFs.html) is a
+//! [`FileSystem`](../file_system/trait.FileSystem.html) reading the local file
+//! system, except that archive files are yielded as directories containing
+//! the archive's entries. Entries inside an archive have paths below the
+//! archive's path and depths continuing from it, so indexers and scanners see
+//! one tree.
+//!
+//! `.zip` files are read with the `zip` feature and `.tar` files with the
+//! `tar` feature. Archives inside archives are yielded as files.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use jwalk::archive::ArchiveFs;
+//! use jwalk::WalkDir;
+//!
+//! for dir_entry in WalkDir::new("downloads").file_system(Some(Arc::new(ArchiveFs::new()))) {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::file_system::{FileSystem, FileType, ReadDirEntries, StdFileSystem};
+use crate::memfs::MemoryFs;
+
+/// File system walking into `.zip` and `.tar` files, see the
+/// [`archive`](index.html) module.
+///
+/// The entries of an archive are read once, when it's first read as a
+/// directory, and kept for the lifetime of the `ArchiveFs`. Their
+/// [`DirEntry::metadata`](../struct.DirEntry.html#method.metadata) fails with
+/// `io::ErrorKind::Unsupported`.
+#[derive(Debug, Default)]
+pub struct ArchiveFs {
+    archives: Mutex<HashMap<PathBuf, MemoryFs>>,
+}
+
+impl ArchiveFs {
+    /// Create a file system reading the local file system and archives.
+    pub fn new() -> ArchiveFs {
+        ArchiveFs::default()
+    }
+
+    // The outermost archive file containing `path`, or `path` itself.
+    fn archive_of<'p>(&self, path: &'p Path) -> Option<&'p Path> {
+        let archives = self.archives.lock().unwrap();
+        let ancestors: Vec<&Path> = path.ancestors().collect();
+        ancestors.into_iter().rev().find(|ancestor| {
+            is_archive_name(ancestor)
+                && (archives.contains_key(*ancestor)
+                    || fs::metadata(ancestor).is_ok_and(|metadata| metadata.is_file()))
+        })
+    }
+
+    // The entries of `archive`, read on first use.
+    fn entries(&self, archive: &Path) -> io::Result<MemoryFs> {
+        if let Some(memory_fs) = self.archives.lock().unwrap().get(archive) {
+            return Ok(memory_fs.clone());
+        }
+        let mut memory_fs = MemoryFs::new();
+        memory_fs.add_dir(archive);
+        #[cfg(feature = "zip")]
+        if has_extension(archive, "zip") {
+            read_zip(archive, &mut memory_fs)?;
+        }
+        #[cfg(feature = "tar")]
+        if has_extension(archive, "tar") {
+            read_tar(archive, &mut memory_fs)?;
+        }
+        self.archives
+            .lock()
+            .unwrap()
+            .insert(archive.to_path_buf(), memory_fs.clone());
+        Ok(memory_fs)
+    }
+}
+
+impl FileSystem for ArchiveFs {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        if let Some(archive) = self.archive_of(path) {
+            let entries: Vec<_> = self.entries(archive)?.read_dir(path)?.collect();
+            return Ok(Box::new(entries.into_iter()));
+        }
+        let parent_path = path.to_path_buf();
+        Ok(Box::new(StdFileSystem.read_dir(path)?.map(
+            move |fs_entry_result| {
+                let mut fs_entry = fs_entry_result?;
+                let is_archive = fs_entry.file_type.as_ref().is_ok_and(FileType::is_file)
+                    && is_archive_name(&parent_path.join(&fs_entry.file_name));
+                if is_archive {
+                    fs_entry.file_type = Ok(FileType::dir());
+                }
+                Ok(fs_entry)
+            },
+        )))
+    }
+
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType> {
+        match self.archive_of(path) {
+            Some(archive) if archive == path => Ok(FileType::dir()),
+            Some(archive) => self.entries(archive)?.file_type(path, follow_links),
+            None => StdFileSystem.file_type(path, follow_links),
+        }
+    }
+
+    fn metadata(&self, path: &Path, follow_links: bool) -> io::Result<fs::Metadata> {
+        match self.archive_of(path) {
+            Some(archive) if archive != path => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "archive entries don't have metadata",
+            )),
+            _ => StdFileSystem.metadata(path, follow_links),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.archive_of(path) {
+            Some(archive) if archive != path => self.entries(archive)?.read_link(path),
+            _ => StdFileSystem.read_link(path),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.archive_of(path) {
+            Some(archive) if archive != path => self.entries(archive)?.canonicalize(path),
+            _ => StdFileSystem.canonicalize(path),
+        }
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        match self.archive_of(path) {
+            Some(archive) if archive != path => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "archive entries can't be read",
+            )),
+            _ => StdFileSystem.read_to_string(path),
+        }
+    }
+}
+
+// Returns `true` if `path` has the extension of an archive format that can
+// be read.
+fn is_archive_name(path: &Path) -> bool {
+    (cfg!(feature = "zip") && has_extension(path, "zip"))
+        || (cfg!(feature = "tar") && has_extension(path, "tar"))
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|path_extension| path_extension.eq_ignore_ascii_case(extension))
+}
+
+// Path of an archive entry below `archive`, ignoring components that would
+// leave it. `None` for entries naming the archive itself.
+fn entry_path(archive: &Path, entry_path: &Path) -> Option<PathBuf> {
+    let mut path = archive.to_path_buf();
+    for component in entry_path.components() {
+        if let Component::Normal(name) = component {
+            path.push(name);
+        }
+    }
+    if path.as_path() == archive {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+#[cfg(feature = "zip")]
+fn read_zip(archive: &Path, memory_fs: &mut MemoryFs) -> io::Result<()> {
+    let zip = zip::ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+    for name in zip.file_names() {
+        let path = match entry_path(archive, Path::new(name)) {
+            Some(path) => path,
+            None => continue,
+        };
+        if name.ends_with('/') {
+            memory_fs.add_dir(path);
+        } else {
+            memory_fs.add_file(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+fn read_tar(archive: &Path, memory_fs: &mut MemoryFs) -> io::Result<()> {
+    let mut tar = tar::Archive::new(File::open(archive)?);
+    for entry in tar.entries()? {
+        let entry = entry?;
+        let path = match entry_path(archive, &entry.path()?) {
+            Some(path) => path,
+            None => continue,
+        };
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            memory_fs.add_dir(path);
+        } else if entry_type.is_symlink() {
+            match entry.link_name()? {
+                Some(target) => memory_fs.add_symlink(path, target),
+                None => memory_fs.add_file(path),
+            }
+        } else {
+            memory_fs.add_file(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! Checkpoints for resuming long walks later, available with the `checkpoint`
+//! feature.
+//!
+//! [`DirEntryIter::checkpoint`](../struct.DirEntryIter.html#method.checkpoint)
+//! records the entries that weren't yielded yet in a
+//! [`WalkCheckpoint`](struct.WalkCheckpoint.html), which can be written to a
+//! file and read back after a restart. Walking
+//! [`WalkDirGeneric::resume`](../struct.WalkDirGeneric.html#method.resume)
+//! then yields the remaining entries in the same order.
+//!
+//! ```no_run
+//! use jwalk::checkpoint::WalkCheckpoint;
+//! use jwalk::WalkDir;
+//! use std::fs::File;
+//!
+//! let mut iter = WalkDir::new(".").sort(true).into_iter();
+//! for dir_entry in iter.by_ref().take(1000) {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//! iter.checkpoint().write_to(File::create("walk.checkpoint")?)?;
+//!
+//! let checkpoint = WalkCheckpoint::read_from(File::open("walk.checkpoint")?)?;
+//! for dir_entry in WalkDir::resume(checkpoint).sort(true) {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Options aren't recorded, so they have to be set again when resuming.
+//! Errors that weren't yielded yet are lost, and ignore files of
+//! [`git_ignore`](../struct.WalkDirGeneric.html#method.git_ignore) found in
+//! ancestors of the remaining entries don't apply to them anymore.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+
+use crate::{ClientState, DirEntryIter, WalkDirGeneric};
+
+/// Remaining entries of a walk, see the [`checkpoint`](index.html) module.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WalkCheckpoint {
+    roots: Vec<PathBuf>,
+    pending: Vec<(PathBuf, usize)>,
+}
+
+impl WalkCheckpoint {
+    /// Root paths of the walk.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Paths and depths of the entries that weren't yielded yet, in the order
+    /// they are yielded. The contents of directories among them weren't
+    /// yielded either.
+    pub fn pending(&self) -> &[(PathBuf, usize)] {
+        &self.pending
+    }
+
+    /// Returns `true` if all entries of the walk were yielded.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Write the checkpoint in a compact format that can be read back with
+    /// [`read_from`](struct.WalkCheckpoint.html#method.read_from).
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(writer);
+        for root in &self.roots {
+            writer.write_all(b"r")?;
+            write_path(&mut writer, root)?;
+        }
+        for (path, depth) in &self.pending {
+            write!(writer, "p{} ", depth)?;
+            write_path(&mut writer, path)?;
+        }
+        writer.flush()
+    }
+
+    /// Read a checkpoint written with
+    /// [`write_to`](struct.WalkCheckpoint.html#method.write_to).
+    pub fn read_from<R: Read>(reader: R) -> io::Result<WalkCheckpoint> {
+        let mut checkpoint = WalkCheckpoint::default();
+        let mut reader = BufReader::new(reader);
+        let mut record = Vec::new();
+        loop {
+            record.clear();
+            if reader.read_until(0, &mut record)? == 0 {
+                return Ok(checkpoint);
+            }
+            match record.pop() {
+                Some(0) => {}
+                _ => return Err(invalid_data("truncated checkpoint record")),
+            }
+            match record.split_first() {
+                Some((b'r', path)) => checkpoint.roots.push(read_path(path)?),
+                Some((b'p', depth_and_path)) => {
+                    let separator = depth_and_path
+                        .iter()
+                        .position(|byte| *byte == b' ')
+                        .ok_or_else(|| invalid_data("missing depth in checkpoint"))?;
+                    let depth = std::str::from_utf8(&depth_and_path[..separator])
+                        .ok()
+                        .and_then(|depth| depth.parse().ok())
+                        .ok_or_else(|| invalid_data("invalid depth in checkpoint"))?;
+                    let path = read_path(&depth_and_path[separator + 1..])?;
+                    checkpoint.pending.push((path, depth));
+                }
+                _ => return Err(invalid_data("unknown checkpoint record")),
+            }
+        }
+    }
+}
+
+impl<C: ClientState> DirEntryIter<C> {
+    /// Record the entries that weren't yielded yet, so the walk can be
+    /// [resumed](struct.WalkDirGeneric.html#method.resume) later.
+    ///
+    /// Directories yielded so far were read completely, so their remaining
+    /// entries are recorded instead of them. Directories held back by
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first)
+    /// aren't recorded.
+    pub fn checkpoint(&self) -> WalkCheckpoint {
+        WalkCheckpoint {
+            roots: self.roots.clone(),
+            pending: self.pending_entries(),
+        }
+    }
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Create a builder for a recursive directory iterator yielding the
+    /// entries that remained when `checkpoint` was recorded, and their
+    /// contents.
+    ///
+    /// Paths used by filters are relative to the recorded roots, like in the
+    /// original walk.
+    pub fn resume(checkpoint: WalkCheckpoint) -> Self {
+        let WalkCheckpoint { roots, pending } = checkpoint;
+        let mut walk_dir = WalkDirGeneric::new_multi(roots);
+        walk_dir.resume_from = Some(pending);
+        walk_dir
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(unix)]
+fn write_path<W: Write>(writer: &mut W, path: &std::path::Path) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    writer.write_all(path.as_os_str().as_bytes())?;
+    writer.write_all(&[0])
+}
+
+#[cfg(not(unix))]
+fn write_path<W: Write>(writer: &mut W, path: &std::path::Path) -> io::Result<()> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| invalid_data("path is not valid unicode"))?;
+    writer.write_all(path.as_bytes())?;
+    writer.write_all(&[0])
+}
+
+#[cfg(unix)]
+fn read_path(bytes: &[u8]) -> io::Result<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn read_path(bytes: &[u8]) -> io::Result<PathBuf> {
+    let path = std::str::from_utf8(bytes).map_err(|_| invalid_data("path is not valid unicode"))?;
+    Ok(PathBuf::from(path))
+}
@@ -0,0 +1,159 @@
+//! Route the entries of one walk into several named channels.
+//!
+//! Scanners looking for several kinds of files can register a predicate per
+//! class with [`Classify`](struct.Classify.html) and walk the tree once,
+//! instead of once per class. Each entry goes to the first class whose
+//! predicate matches it.
+//!
+//! ```no_run
+//! use jwalk::classify::Classify;
+//! use jwalk::WalkDir;
+//!
+//! let classified = Classify::new(WalkDir::new("."))
+//!     .class("code", |entry| entry.file_name.to_string_lossy().ends_with(".rs"))
+//!     .class("large files", |entry| {
+//!         entry.metadata().map_or(false, |metadata| metadata.len() > 1 << 20)
+//!     })
+//!     .otherwise("everything else")
+//!     .spawn();
+//! for entry in classified.receiver("code").unwrap() {
+//!     println!("{}", entry.path().display());
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use crate::{ClientState, DirEntry, Error, WalkDirGeneric};
+
+type ClassPredicate<C> = dyn Fn(&DirEntry<C>) -> bool + Send + Sync + 'static;
+
+/// Builder for a walk routing entries into named classes.
+pub struct Classify<C: ClientState> {
+    walk_dir: WalkDirGeneric<C>,
+    classes: Vec<(String, Arc<ClassPredicate<C>>)>,
+    otherwise: Option<String>,
+}
+
+/// Receiving ends of a classified walk, created by
+/// [`Classify::spawn`](struct.Classify.html#method.spawn).
+///
+/// Channels are unbounded, so receivers may be drained in any order.
+/// Dropping a receiver discards the entries of its class.
+pub struct Classified<C: ClientState> {
+    receivers: HashMap<String, Receiver<DirEntry<C>>>,
+    errors: Receiver<Error>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl<C: ClientState> Classify<C> {
+    /// Classify the entries yielded by `walk_dir`.
+    pub fn new(walk_dir: WalkDirGeneric<C>) -> Self {
+        Classify {
+            walk_dir,
+            classes: Vec::new(),
+            otherwise: None,
+        }
+    }
+
+    /// Add a class receiving the entries matching `predicate` that didn't
+    /// match a class added before it.
+    pub fn class<F>(mut self, name: impl Into<String>, predicate: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) -> bool + Send + Sync + 'static,
+    {
+        self.classes.push((name.into(), Arc::new(predicate)));
+        self
+    }
+
+    /// Add a class receiving the entries that didn't match any other class.
+    /// Without it such entries are dropped.
+    pub fn otherwise(mut self, name: impl Into<String>) -> Self {
+        self.otherwise = Some(name.into());
+        self
+    }
+
+    /// Start the walk on a new thread, which routes entries until the walk is
+    /// finished.
+    pub fn spawn(self) -> Classified<C> {
+        let Classify {
+            walk_dir,
+            classes,
+            otherwise,
+        } = self;
+        let mut receivers = HashMap::new();
+        let mut channel_for = |name: String| {
+            receivers
+                .entry(name)
+                .or_insert_with(mpsc::channel)
+                .0
+                .clone()
+        };
+        let classes = classes
+            .into_iter()
+            .map(|(name, predicate)| (channel_for(name), predicate))
+            .collect::<Vec<_>>();
+        let otherwise = otherwise.map(channel_for);
+        let receivers = receivers
+            .into_iter()
+            .map(|(name, (_, receiver))| (name, receiver))
+            .collect();
+        let (errors_tx, errors) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            for dir_entry_result in walk_dir {
+                let dir_entry = match dir_entry_result {
+                    Ok(dir_entry) => dir_entry,
+                    Err(err) => {
+                        let _ = errors_tx.send(err);
+                        continue;
+                    }
+                };
+                let sender = classes
+                    .iter()
+                    .find(|(_, predicate)| predicate(&dir_entry))
+                    .map(|(sender, _)| sender)
+                    .or(otherwise.as_ref());
+                if let Some(sender) = sender {
+                    // The receiver of this class may have been dropped
+                    let _ = sender.send(dir_entry);
+                }
+            }
+        });
+
+        Classified {
+            receivers,
+            errors,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl<C: ClientState> Classified<C> {
+    /// The channel receiving the entries of class `name`, if such a class was
+    /// added. The channel disconnects when the walk is finished.
+    pub fn receiver(&self, name: &str) -> Option<&Receiver<DirEntry<C>>> {
+        self.receivers.get(name)
+    }
+
+    /// Take the channel receiving the entries of class `name`, for example to
+    /// move it to another thread.
+    pub fn take_receiver(&mut self, name: &str) -> Option<Receiver<DirEntry<C>>> {
+        self.receivers.remove(name)
+    }
+
+    /// The channel receiving errors of the walk.
+    pub fn errors(&self) -> &Receiver<Error> {
+        &self.errors
+    }
+
+    /// Wait for the walk to finish. Entries not received yet stay in their
+    /// channels.
+    pub fn wait(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
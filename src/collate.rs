@@ -0,0 +1,123 @@
+//! Orders of file names used when sorting the entries of a directory.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+
+/// How file names are compared by
+/// [`sort`](../struct.WalkDirGeneric.html#method.sort).
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct NameOrder {
+    // compare runs of digits by their numeric value
+    pub(crate) natural: bool,
+    // compare names lowercased
+    pub(crate) case_insensitive: bool,
+}
+
+impl NameOrder {
+    /// Whether names are sorted in another order than by their bytes.
+    pub(crate) fn is_custom(&self) -> bool {
+        self.natural || self.case_insensitive
+    }
+
+    pub(crate) fn compare(&self, a: &OsStr, b: &OsStr) -> Ordering {
+        if !self.is_custom() {
+            return a.cmp(b);
+        }
+        let (a_key, b_key) = (self.key(a), self.key(b));
+        let ordering = if self.natural {
+            natural_cmp(&a_key, &b_key)
+        } else {
+            a_key.cmp(&b_key)
+        };
+        // Names equal in this order, like `a` and `A`, are ordered by their
+        // bytes so the order doesn't depend on the order they were read in
+        ordering.then_with(|| a.cmp(b))
+    }
+
+    fn key<'a>(&self, name: &'a OsStr) -> Cow<'a, [u8]> {
+        let bytes = bytes(name);
+        if self.case_insensitive {
+            lowercase(bytes)
+        } else {
+            bytes
+        }
+    }
+}
+
+#[cfg(unix)]
+fn bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    name.as_bytes().into()
+}
+
+// Names on Windows are potentially ill-formed UTF-16, unpaired surrogates
+// are compared as U+FFFD, ties are broken by comparing the `OsStr`s.
+#[cfg(not(unix))]
+fn bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    match name.to_string_lossy() {
+        Cow::Borrowed(name) => name.as_bytes().into(),
+        Cow::Owned(name) => name.into_bytes().into(),
+    }
+}
+
+// Lowercases UTF-8 names with the `unicode-case` feature, and only ASCII
+// letters otherwise or in names that aren't UTF-8.
+fn lowercase(bytes: Cow<'_, [u8]>) -> Cow<'_, [u8]> {
+    #[cfg(feature = "unicode-case")]
+    if !bytes.is_ascii() {
+        if let Ok(name) = std::str::from_utf8(&bytes) {
+            return name.to_lowercase().into_bytes().into();
+        }
+    }
+    if bytes.iter().any(u8::is_ascii_uppercase) {
+        bytes.to_ascii_lowercase().into()
+    } else {
+        bytes
+    }
+}
+
+// Compares `a` and `b` byte by byte, except for runs of ASCII digits, which
+// are compared by their numeric value. Runs with the same value compare
+// equal regardless of leading zeros.
+fn natural_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let a_digits = digit_run(&a[i..]);
+            let b_digits = digit_run(&b[j..]);
+            i += a_digits.len();
+            j += b_digits.len();
+            let a_value = trim_leading_zeros(a_digits);
+            let b_value = trim_leading_zeros(b_digits);
+            // Without leading zeros, a longer run is a larger number
+            let ordering = a_value
+                .len()
+                .cmp(&b_value.len())
+                .then_with(|| a_value.cmp(b_value));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j))
+}
+
+fn digit_run(bytes: &[u8]) -> &[u8] {
+    let len = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_digit())
+        .unwrap_or(bytes.len());
+    &bytes[..len]
+}
+
+fn trim_leading_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&digit| digit == b'0').count();
+    &digits[zeros..]
+}
@@ -0,0 +1,5 @@
+//! APIs mirroring other directory walking crates, to ease switching to jwalk.
+//!
+//! - [`walkdir`](walkdir/index.html) mirrors the `walkdir` crate.
+
+pub mod walkdir;
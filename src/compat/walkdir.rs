@@ -0,0 +1,289 @@
+//! A drop-in replacement for the most used parts of the `walkdir` crate.
+//!
+//! [`WalkDir`](struct.WalkDir.html) has the builder methods of
+//! `walkdir::WalkDir`, and yields [`DirEntry`](struct.DirEntry.html)s with the
+//! methods of `walkdir::DirEntry`, so most code walking with `walkdir` only
+//! needs to change its imports:
+//!
+//! ```no_run
+//! use jwalk::compat::walkdir::WalkDir;
+//!
+//! for entry in WalkDir::new("foo").sort_by_file_name().into_iter().filter_entry(|e| {
+//!     !e.file_name().to_str().map_or(false, |name| name.starts_with('.'))
+//! }) {
+//!     let entry = entry?;
+//!     println!("{}", entry.path().display());
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+//!
+//! Unlike the rest of jwalk, and like `walkdir`, hidden entries are yielded.
+//! Directories are still read in parallel, use
+//! [`walk_dir`](struct.WalkDir.html#method.walk_dir) to configure the
+//! parallelism and other options of the wrapped
+//! [`jwalk::WalkDir`](../../type.WalkDir.html).
+//!
+//! Differences to `walkdir`:
+//!
+//! - [`DirEntry::file_type`](struct.DirEntry.html#method.file_type) returns a
+//!   [`jwalk::FileType`](../../struct.FileType.html), which has the same
+//!   `is_dir`, `is_file` and `is_symlink` methods as `std::fs::FileType`.
+//! - Comparators passed to [`sort_by`](struct.WalkDir.html#method.sort_by)
+//!   take [`jwalk::DirEntry`](../../struct.DirEntry.html)s, which have the
+//!   same `file_name`, `file_type`, `depth` and `metadata` methods.
+//! - Root symlinks are always followed, like with `walkdir`'s default.
+
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::FileType;
+
+pub use crate::{Error, Result};
+
+type JwalkDirEntry = crate::DirEntry<((), ())>;
+
+/// Builder for a recursive directory iterator, like `walkdir::WalkDir`.
+pub struct WalkDir {
+    walk_dir: crate::WalkDir,
+    contents_first: bool,
+}
+
+/// Iterator over the entries of a [`WalkDir`](struct.WalkDir.html), like
+/// `walkdir::IntoIter`.
+pub struct IntoIter {
+    dir_entry_iter: crate::DirEntryIter<((), ())>,
+    contents_first: bool,
+    // error reading the contents of the previously yielded directory
+    read_children_error: Option<Error>,
+}
+
+/// Iterator skipping entries and the contents of directories rejected by a
+/// predicate, see [`IntoIter::filter_entry`](struct.IntoIter.html#method.filter_entry).
+pub struct FilterEntry<P> {
+    into_iter: IntoIter,
+    predicate: P,
+}
+
+/// A directory entry, like `walkdir::DirEntry`.
+#[derive(Debug)]
+pub struct DirEntry {
+    path: PathBuf,
+    dir_entry: JwalkDirEntry,
+}
+
+impl WalkDir {
+    /// Create a builder for a recursive directory iterator starting at
+    /// `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        WalkDir {
+            walk_dir: crate::WalkDir::new(root).skip_hidden(false),
+            contents_first: false,
+        }
+    }
+
+    /// Set the minimum depth of entries yielded by the iterator.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.walk_dir = self.walk_dir.min_depth(depth);
+        self
+    }
+
+    /// Set the maximum depth of entries yielded by the iterator.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.walk_dir = self.walk_dir.max_depth(depth);
+        self
+    }
+
+    /// Follow symbolic links. By default, this is disabled.
+    pub fn follow_links(mut self, follow_links: bool) -> Self {
+        self.walk_dir = self.walk_dir.follow_links(follow_links);
+        self
+    }
+
+    /// Has no effect, directories are closed as soon as they are read.
+    pub fn max_open(self, _max_open: usize) -> Self {
+        self
+    }
+
+    /// Sort entries per directory with `compare`.
+    pub fn sort_by<F>(mut self, compare: F) -> Self
+    where
+        F: Fn(&JwalkDirEntry, &JwalkDirEntry) -> Ordering + Send + Sync + 'static,
+    {
+        self.walk_dir = self.walk_dir.sort_by(compare);
+        self
+    }
+
+    /// Sort entries per directory by the keys returned by `key`.
+    pub fn sort_by_key<K, F>(self, key: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&JwalkDirEntry) -> K + Send + Sync + 'static,
+    {
+        self.sort_by(move |a, b| key(a).cmp(&key(b)))
+    }
+
+    /// Sort entries per directory by their file names.
+    pub fn sort_by_file_name(mut self) -> Self {
+        self.walk_dir = self.walk_dir.sort(true);
+        self
+    }
+
+    /// Yield directories after their contents instead of before.
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.walk_dir = self.walk_dir.contents_first(contents_first);
+        self.contents_first = contents_first;
+        self
+    }
+
+    /// Don't walk into directories on other file systems than the root.
+    pub fn same_file_system(mut self, same_file_system: bool) -> Self {
+        self.walk_dir = self.walk_dir.same_file_system(same_file_system);
+        self
+    }
+
+    /// Configure the wrapped [`jwalk::WalkDir`](../../type.WalkDir.html),
+    /// such as its [`parallelism`](../../struct.WalkDirGeneric.html#method.parallelism).
+    pub fn walk_dir<F>(mut self, configure: F) -> Self
+    where
+        F: FnOnce(crate::WalkDir) -> crate::WalkDir,
+    {
+        self.walk_dir = configure(self.walk_dir);
+        self
+    }
+}
+
+impl IntoIterator for WalkDir {
+    type Item = Result<DirEntry>;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            dir_entry_iter: self.walk_dir.into_iter(),
+            contents_first: self.contents_first,
+            read_children_error: None,
+        }
+    }
+}
+
+impl IntoIter {
+    /// Skip the contents of the previously yielded directory, or the rest of
+    /// the directory containing the previously yielded entry.
+    pub fn skip_current_dir(&mut self) {
+        self.read_children_error = None;
+        self.dir_entry_iter.skip_current_dir();
+    }
+
+    /// Yield only entries for which `predicate` returns `true`, skipping the
+    /// contents of rejected directories. With
+    /// [`contents_first`](struct.WalkDir.html#method.contents_first) the
+    /// contents were already yielded when a directory is rejected.
+    pub fn filter_entry<P>(self, predicate: P) -> FilterEntry<P>
+    where
+        P: FnMut(&DirEntry) -> bool,
+    {
+        FilterEntry {
+            into_iter: self,
+            predicate,
+        }
+    }
+}
+
+impl Iterator for IntoIter {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Result<DirEntry>> {
+        // Like `walkdir`, yield the error reading a directory after it
+        if let Some(err) = self.read_children_error.take() {
+            return Some(Err(err));
+        }
+        let mut dir_entry = match self.dir_entry_iter.next()? {
+            Ok(dir_entry) => dir_entry,
+            Err(err) => return Some(Err(err)),
+        };
+        self.read_children_error = dir_entry.read_children_error.take();
+        Some(Ok(DirEntry {
+            path: dir_entry.path(),
+            dir_entry,
+        }))
+    }
+}
+
+impl<P> FilterEntry<P>
+where
+    P: FnMut(&DirEntry) -> bool,
+{
+    /// Skip the contents of the previously yielded directory, or the rest of
+    /// the directory containing the previously yielded entry.
+    pub fn skip_current_dir(&mut self) {
+        self.into_iter.skip_current_dir();
+    }
+}
+
+impl<P> Iterator for FilterEntry<P>
+where
+    P: FnMut(&DirEntry) -> bool,
+{
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Result<DirEntry>> {
+        loop {
+            let dir_entry = match self.into_iter.next()? {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => return Some(Err(err)),
+            };
+            if (self.predicate)(&dir_entry) {
+                return Some(Ok(dir_entry));
+            }
+            if dir_entry.file_type().is_dir() && !self.into_iter.contents_first {
+                self.into_iter.skip_current_dir();
+            }
+        }
+    }
+}
+
+impl DirEntry {
+    /// The full path of this entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Consume this entry, returning its path.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Returns `true` if this entry was created from a symbolic link, even if
+    /// it was followed.
+    pub fn path_is_symlink(&self) -> bool {
+        self.dir_entry.path_is_symlink()
+    }
+
+    /// The metadata of the file this entry points to, following symbolic
+    /// links if [`follow_links`](struct.WalkDir.html#method.follow_links) is
+    /// enabled.
+    pub fn metadata(&self) -> Result<fs::Metadata> {
+        self.dir_entry.metadata()
+    }
+
+    /// The type of the file this entry points to.
+    pub fn file_type(&self) -> FileType {
+        self.dir_entry.file_type()
+    }
+
+    /// The file name of this entry, or its full path if it has none.
+    pub fn file_name(&self) -> &OsStr {
+        self.dir_entry.file_name()
+    }
+
+    /// The depth of this entry relative to the root, which has depth `0`.
+    pub fn depth(&self) -> usize {
+        self.dir_entry.depth()
+    }
+
+    /// The wrapped [`jwalk::DirEntry`](../../struct.DirEntry.html).
+    pub fn dir_entry(&self) -> &JwalkDirEntry {
+        &self.dir_entry
+    }
+}
@@ -0,0 +1,79 @@
+use crossbeam::channel::TryRecvError;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+use super::*;
+
+/// Reads pending directories with as many rayon tasks as the queue of
+/// pending directories is deep, up to `max_tasks`.
+///
+/// Another task is spawned whenever more directories are waiting than tasks
+/// are running, tasks end once there is nothing to read for them. Only tasks
+/// that are reading a directory add directories to the queue, and they check
+/// it again afterwards, so no task has to wait for directories to arrive.
+pub(crate) struct AdaptiveWalk<C: ClientState> {
+    read_dir_spec_iter: Mutex<OrderedQueueIter<ReadDirSpec<C>>>,
+    max_tasks: usize,
+    tasks: AtomicUsize,
+    reading: AtomicUsize,
+}
+
+impl<C: ClientState> AdaptiveWalk<C> {
+    /// Walks with up to `max_threads` tasks on the current rayon pool, as
+    /// many as it has threads if `0`.
+    pub(crate) fn run(
+        read_dir_spec_iter: OrderedQueueIter<ReadDirSpec<C>>,
+        run_context: RunContext<C>,
+        max_threads: usize,
+    ) {
+        let max_tasks = match max_threads {
+            0 => rayon::current_num_threads(),
+            max_threads => max_threads,
+        };
+        let adaptive_walk = Arc::new(AdaptiveWalk {
+            read_dir_spec_iter: Mutex::new(read_dir_spec_iter),
+            max_tasks,
+            tasks: AtomicUsize::new(1),
+            reading: AtomicUsize::new(0),
+        });
+        adaptive_walk.run_task(run_context);
+    }
+
+    fn run_task(self: Arc<Self>, mut run_context: RunContext<C>) {
+        loop {
+            let next = self.read_dir_spec_iter.lock().unwrap().try_next();
+            match next {
+                Ok(ordered_read_dir_spec) => {
+                    self.reading.fetch_add(1, AtomicOrdering::SeqCst);
+                    multi_threaded_walk_dir(ordered_read_dir_spec, &mut run_context);
+                    self.reading.fetch_sub(1, AtomicOrdering::SeqCst);
+                    self.spawn_while_deep(&run_context);
+                }
+                Err(TryRecvError::Empty) => {
+                    // Near the frontier, leave the remaining reads to the
+                    // tasks that are still reading
+                    self.tasks.fetch_sub(1, AtomicOrdering::SeqCst);
+                    return;
+                }
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+    }
+
+    fn spawn_while_deep(self: &Arc<Self>, run_context: &RunContext<C>) {
+        let waiting = run_context
+            .read_dir_spec_queue
+            .pending_count()
+            .saturating_sub(self.reading.load(AtomicOrdering::SeqCst));
+        let spawned =
+            self.tasks
+                .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |tasks| {
+                    Some(tasks + 1).filter(|_| tasks < self.max_tasks && waiting > tasks)
+                });
+        if spawned.is_ok() {
+            let adaptive_walk = self.clone();
+            let run_context = run_context.clone();
+            rayon::spawn(move || adaptive_walk.run_task(run_context));
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::{Condvar, Mutex};
+
+use super::{ClientState, ReadDir};
+use crate::Result;
+
+/// Bound on the read directories buffered for the iterator, see
+/// [`WalkDirGeneric::buffer_limit`](struct.WalkDirGeneric.html#method.buffer_limit).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferLimit {
+    /// At most this many read directories.
+    ReadDirs(usize),
+    /// At most this many entries of read directories, counting errors as
+    /// entries. A directory with more entries is still read, but only once
+    /// nothing else is buffered.
+    Entries(usize),
+}
+
+/// Blocks reading directories while the iterator hasn't consumed more than
+/// a [`BufferLimit`] of read directories.
+///
+/// Reads continue while the iterator waits, as the directory it waits for
+/// might not have been read yet. So the limit can be exceeded by the
+/// directories read until then.
+pub(crate) struct Backpressure {
+    limit: BufferLimit,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+struct State {
+    buffered: usize,
+    consumer_waiting: bool,
+    stopped: bool,
+}
+
+impl BufferLimit {
+    // The amount `read_dir_result` counts towards this limit.
+    fn amount<C: ClientState>(&self, read_dir_result: &Result<ReadDir<C>>) -> usize {
+        match (self, read_dir_result) {
+            (BufferLimit::ReadDirs(_), _) => 1,
+            (BufferLimit::Entries(_), Ok(read_dir)) => read_dir.results_list.len(),
+            (BufferLimit::Entries(_), Err(_)) => 1,
+        }
+    }
+
+    fn max(&self) -> usize {
+        match *self {
+            BufferLimit::ReadDirs(max) | BufferLimit::Entries(max) => max.max(1),
+        }
+    }
+}
+
+impl Backpressure {
+    pub(crate) fn new(limit: BufferLimit) -> Backpressure {
+        Backpressure {
+            limit,
+            state: Mutex::new(State {
+                buffered: 0,
+                consumer_waiting: false,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until another directory may be read.
+    pub(crate) fn wait_for_capacity(&self) {
+        let max = self.limit.max();
+        let mut state = self.state.lock().unwrap();
+        while state.buffered >= max && !state.consumer_waiting && !state.stopped {
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Called when `read_dir_result` is buffered for the iterator.
+    pub(crate) fn produced<C: ClientState>(&self, read_dir_result: &Result<ReadDir<C>>) {
+        self.state.lock().unwrap().buffered += self.limit.amount(read_dir_result);
+    }
+
+    /// Called when the iterator took `read_dir_result` from the buffer.
+    pub(crate) fn consumed<C: ClientState>(&self, read_dir_result: &Result<ReadDir<C>>) {
+        let mut state = self.state.lock().unwrap();
+        state.buffered = state
+            .buffered
+            .saturating_sub(self.limit.amount(read_dir_result));
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    /// Called before and after the iterator waits for the next read
+    /// directory.
+    pub(crate) fn consumer_waiting(&self, consumer_waiting: bool) {
+        self.state.lock().unwrap().consumer_waiting = consumer_waiting;
+        if consumer_waiting {
+            self.changed.notify_all();
+        }
+    }
+
+    /// Unblocks all reads once the iterator is dropped.
+    pub(crate) fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+}
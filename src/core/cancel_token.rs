@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Handle canceling a walk from another thread.
+///
+/// Pass a clone to
+/// [`WalkDirGeneric::cancel_token`](struct.WalkDirGeneric.html#method.cancel_token),
+/// or create both with
+/// [`WalkDirGeneric::cancelable`](struct.WalkDirGeneric.html#method.cancelable),
+/// and keep another to cancel the walk later. Once canceled no more
+/// directories are read and the iterator stops yielding entries. Reads that
+/// are already running finish first.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    canceled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Create a token that isn't canceled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancel all walks using this token. Can't be undone.
+    pub fn cancel(&self) {
+        self.canceled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if [`cancel`](struct.CancelToken.html#method.cancel)
+    /// was called.
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Handle limiting the number of directories read at the same time, which
+/// can be changed while a walk is running.
+///
+/// Pass a clone to
+/// [`WalkDirGeneric::concurrency_limit`](struct.WalkDirGeneric.html#method.concurrency_limit)
+/// and keep another to adjust the limit later, for example to reduce the load
+/// a walk causes while the system is busy. Reads that are already running
+/// when the limit is lowered are not interrupted.
+///
+/// An [`adaptive`](struct.ConcurrencyLimit.html#method.adaptive) limit
+/// also scales down with the number of read directories the iterator hasn't
+/// consumed yet, so slow consumers don't cause lots of buffering while fast
+/// consumers get the full limit.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    in_flight: usize,
+    backlog: usize,
+    max_backlog: Option<usize>,
+}
+
+/// Use of a [`ConcurrencyLimit`] by a single walk. Once all of its clones
+/// are dropped, the read dirs the walk left unconsumed no longer count
+/// towards the backlog of the handle.
+#[derive(Clone)]
+pub(crate) struct WalkConcurrencyLimit {
+    walk: Arc<WalkBacklog>,
+}
+
+struct WalkBacklog {
+    limit: ConcurrencyLimit,
+    // part of the handle's backlog, only changed while holding its state
+    backlog: AtomicUsize,
+}
+
+/// Releases its read dir permit when dropped.
+pub(crate) struct ConcurrencyPermit<'a> {
+    walk: &'a WalkBacklog,
+}
+
+impl ConcurrencyLimit {
+    /// Create a handle allowing `limit` directories to be read at the same
+    /// time. A `limit` of `0` is treated as `1`.
+    pub fn new(limit: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit::with_max_backlog(limit, None)
+    }
+
+    /// Create a handle allowing up to `limit` directories to be read at the
+    /// same time, scaling down linearly to a single directory as the number
+    /// of read directories waiting to be consumed grows to `max_backlog`.
+    pub fn adaptive(limit: usize, max_backlog: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit::with_max_backlog(limit, Some(max_backlog.max(1)))
+    }
+
+    fn with_max_backlog(limit: usize, max_backlog: Option<usize>) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    limit: limit.max(1),
+                    in_flight: 0,
+                    backlog: 0,
+                    max_backlog,
+                }),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Change the limit of all walks using this handle. A `limit` of `0` is
+    /// treated as `1`.
+    pub fn set(&self, limit: usize) {
+        self.inner.state.lock().unwrap().limit = limit.max(1);
+        self.inner.changed.notify_all();
+    }
+
+    /// The current limit.
+    pub fn get(&self) -> usize {
+        self.inner.state.lock().unwrap().limit
+    }
+
+    /// The number of directories currently being read.
+    pub fn in_flight(&self) -> usize {
+        self.inner.state.lock().unwrap().in_flight
+    }
+
+    /// The number of read directories the iterators of the walks using this
+    /// handle haven't consumed yet. Walks that are dropped no longer count.
+    pub fn backlog(&self) -> usize {
+        self.inner.state.lock().unwrap().backlog
+    }
+
+    /// The current limit after scaling it down by the backlog of an
+    /// [`adaptive`](struct.ConcurrencyLimit.html#method.adaptive) limit.
+    pub fn effective(&self) -> usize {
+        self.inner.state.lock().unwrap().effective_limit()
+    }
+
+    /// Starts counting the backlog of another walk.
+    pub(crate) fn start_walk(&self) -> WalkConcurrencyLimit {
+        WalkConcurrencyLimit {
+            walk: Arc::new(WalkBacklog {
+                limit: self.clone(),
+                backlog: AtomicUsize::new(0),
+            }),
+        }
+    }
+}
+
+impl WalkConcurrencyLimit {
+    /// Blocks until reading another directory is within the limit.
+    pub(crate) fn acquire(&self) -> ConcurrencyPermit<'_> {
+        let inner = &self.walk.limit.inner;
+        let mut state = inner.state.lock().unwrap();
+        while state.in_flight >= state.effective_limit() {
+            state = inner.changed.wait(state).unwrap();
+        }
+        state.in_flight += 1;
+        ConcurrencyPermit { walk: &self.walk }
+    }
+
+    /// Called when the iterator consumed a read directory.
+    pub(crate) fn consumed(&self) {
+        let inner = &self.walk.limit.inner;
+        let mut state = inner.state.lock().unwrap();
+        let consumed = self.walk.backlog.fetch_update(
+            AtomicOrdering::Relaxed,
+            AtomicOrdering::Relaxed,
+            |backlog| backlog.checked_sub(1),
+        );
+        if consumed.is_ok() {
+            state.backlog -= 1;
+        }
+        drop(state);
+        inner.changed.notify_all();
+    }
+}
+
+impl Drop for WalkBacklog {
+    fn drop(&mut self) {
+        let inner = &self.limit.inner;
+        let mut state = inner.state.lock().unwrap();
+        state.backlog = state
+            .backlog
+            .saturating_sub(self.backlog.load(AtomicOrdering::Relaxed));
+        drop(state);
+        inner.changed.notify_all();
+    }
+}
+
+impl State {
+    fn effective_limit(&self) -> usize {
+        match self.max_backlog {
+            Some(max_backlog) => {
+                let backlog = self.backlog.min(max_backlog);
+                (self.limit - self.limit * backlog / max_backlog).max(1)
+            }
+            None => self.limit,
+        }
+    }
+}
+
+impl Drop for ConcurrencyPermit<'_> {
+    fn drop(&mut self) {
+        let inner = &self.walk.limit.inner;
+        let mut state = inner.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.backlog += 1;
+        self.walk.backlog.fetch_add(1, AtomicOrdering::Relaxed);
+        drop(state);
+        inner.changed.notify_one();
+    }
+}
@@ -0,0 +1,78 @@
+use std::io;
+use std::path::Path;
+
+/// Returns an identifier of the device (or volume on Windows) holding `path`.
+#[cfg(unix)]
+pub(crate) fn device_num(path: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).map(|metadata| metadata.dev())
+}
+
+/// Returns an identifier of the device (or volume on Windows) holding `path`.
+#[cfg(windows)]
+pub(crate) fn device_num(path: &Path) -> io::Result<u64> {
+    use std::ffi::c_void;
+    use std::mem::MaybeUninit;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+
+    #[repr(C)]
+    struct FileTime {
+        low_date_time: u32,
+        high_date_time: u32,
+    }
+
+    #[repr(C)]
+    struct ByHandleFileInformation {
+        file_attributes: u32,
+        creation_time: FileTime,
+        last_access_time: FileTime,
+        last_write_time: FileTime,
+        volume_serial_number: u32,
+        file_size_high: u32,
+        file_size_low: u32,
+        number_of_links: u32,
+        file_index_high: u32,
+        file_index_low: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetFileInformationByHandle(
+            file: *mut c_void,
+            file_information: *mut ByHandleFileInformation,
+        ) -> i32;
+    }
+
+    // Required to open directories.
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+    let mut information = MaybeUninit::<ByHandleFileInformation>::uninit();
+    // SAFETY: `file` is a valid open handle and `information` is large
+    // enough to hold the structure written by the call.
+    let information = unsafe {
+        if GetFileInformationByHandle(
+            file.as_raw_handle() as *mut c_void,
+            information.as_mut_ptr(),
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        information.assume_init()
+    };
+    Ok(u64::from(information.volume_serial_number))
+}
+
+/// Returns an identifier of the device (or volume on Windows) holding `path`.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn device_num(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "device numbers are not supported on this platform",
+    ))
+}
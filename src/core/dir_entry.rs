@@ -1,9 +1,17 @@
 use std::ffi::{OsStr, OsString};
 use std::fmt;
-use std::fs::{self, FileType};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use super::{DirFd, Operation};
+use crate::file_system::{self, FileSystem, FileSystemEntry, FileType, FileTypeSource, ReparseTag};
+#[cfg(feature = "filter")]
+use crate::filter::DirFilterRules;
+#[cfg(feature = "size")]
+use crate::size::{SizeCounter, SubtreeSize};
+#[cfg(target_os = "linux")]
+use crate::statx::{self, Statx, StatxFields};
 use crate::{ClientState, Error, ReadDirSpec, Result};
 
 /// Representation of a file or directory.
@@ -35,23 +43,63 @@ pub struct DirEntry<C: ClientState> {
     /// If `read_children_path` is set and resulting `fs::read_dir` generates an error
     /// then that error is stored here.
     pub read_children_error: Option<Error>,
+    /// Priority of reading this entry's contents while walking in parallel,
+    /// directories with a higher priority are read first. Defaults to the
+    /// `read_children_priority` of the parent directory, `0` for roots. The
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback may change it to read some directories ahead of others,
+    /// such as user-visible folders before caches. Directories of
+    /// [`priority_paths`](struct.WalkDirGeneric.html#method.priority_paths)
+    /// are still read first.
+    pub read_children_priority: usize,
     // True if [`follow_links`] is `true` AND was created from a symlink path.
     follow_link: bool,
+    // Tag of the entry itself if it is a reparse point, also when followed.
+    reparse_tag: Option<ReparseTag>,
     // Origins of symlinks followed to get to this entry.
     follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    // True if this entry is only traversed to reach its descendants and
+    // should not be yielded itself.
+    pub(crate) skip_yield: bool,
+    // True if `flag_duplicate_hardlinks` is enabled and another link to this
+    // file was seen before.
+    pub(crate) duplicate_hardlink: bool,
+    // Projection of the parent's `ReadDirState` if `project_read_dir_state`
+    // is set, the contents of this entry are read with it.
+    pub(crate) parent_read_dir_state: Option<C::ReadDirState>,
+    // Filter rules inherited from the parent directory.
+    #[cfg(feature = "filter")]
+    pub(crate) filter_rules: Option<Arc<DirFilterRules>>,
+    // Metadata read while walking if `preload_metadata` is enabled.
+    metadata: Option<fs::Metadata>,
+    // Metadata read while walking if `preload_metadata_ext` is enabled.
+    #[cfg(target_os = "linux")]
+    statx: Option<Statx>,
+    // Source of this entry if it wasn't read with `std::fs`.
+    pub(crate) file_system: Option<Arc<dyn FileSystem>>,
+    // Open parent directory if `dir_fds` is enabled and this is a directory.
+    pub(crate) parent_dir_fd: Option<Arc<DirFd>>,
+    // Size of this entry, plus its contents once they were yielded, if
+    // `aggregate_sizes` is enabled.
+    #[cfg(feature = "size")]
+    pub(crate) subtree_size: Option<SubtreeSize>,
 }
 
 impl<C: ClientState> DirEntry<C> {
     pub(crate) fn from_entry(
         depth: usize,
         parent_path: Arc<Path>,
-        fs_dir_entry: &fs::DirEntry,
+        fs_entry: FileSystemEntry,
         follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+        file_system: Option<Arc<dyn FileSystem>>,
     ) -> Result<Self> {
-        let file_type = fs_dir_entry
-            .file_type()
-            .map_err(|err| Error::from_path(depth, fs_dir_entry.path(), err))?;
-        let file_name = fs_dir_entry.file_name();
+        let FileSystemEntry {
+            file_name,
+            file_type,
+        } = fs_entry;
+        let file_type = file_type.map_err(|err| {
+            Error::from_path(depth, parent_path.join(&file_name), err).during(Operation::Metadata)
+        })?;
         let read_children_path: Option<Arc<Path>> = if file_type.is_dir() {
             Some(Arc::from(parent_path.join(&file_name)))
         } else {
@@ -65,9 +113,23 @@ impl<C: ClientState> DirEntry<C> {
             parent_path,
             read_children_path,
             read_children_error: None,
+            read_children_priority: 0,
             client_state: C::DirEntryState::default(),
             follow_link: false,
+            reparse_tag: file_type.reparse_tag(),
             follow_link_ancestors,
+            skip_yield: false,
+            duplicate_hardlink: false,
+            parent_read_dir_state: None,
+            #[cfg(feature = "filter")]
+            filter_rules: None,
+            metadata: None,
+            #[cfg(target_os = "linux")]
+            statx: None,
+            file_system,
+            parent_dir_fd: None,
+            #[cfg(feature = "size")]
+            subtree_size: None,
         })
     }
 
@@ -77,33 +139,66 @@ impl<C: ClientState> DirEntry<C> {
         path: &Path,
         follow_link: bool,
         follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+        file_system: Option<Arc<dyn FileSystem>>,
     ) -> Result<Self> {
-        let metadata = if follow_link {
-            fs::metadata(path).map_err(|err| Error::from_path(depth, path.to_owned(), err))?
-        } else {
-            fs::symlink_metadata(path)
-                .map_err(|err| Error::from_path(depth, path.to_owned(), err))?
-        };
+        let file_type = file_system::or_std(file_system.as_deref())
+            .file_type(path, follow_link)
+            .map(|file_type| file_type.with_source(FileTypeSource::Stat))
+            .map_err(|err| {
+                Error::from_path(depth, path.to_owned(), err).during(Operation::Metadata)
+            })?;
+        Ok(Self::from_path_and_type(
+            depth,
+            path,
+            file_type,
+            follow_link,
+            follow_link_ancestors,
+            file_system,
+        ))
+    }
 
+    // Like `from_path`, for an entry whose type is already known.
+    pub(crate) fn from_path_and_type(
+        depth: usize,
+        path: &Path,
+        file_type: FileType,
+        follow_link: bool,
+        follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+        file_system: Option<Arc<dyn FileSystem>>,
+    ) -> Self {
         let root_name = path.file_name().unwrap_or(path.as_os_str());
 
-        let read_children_path: Option<Arc<Path>> = if metadata.file_type().is_dir() {
+        let read_children_path: Option<Arc<Path>> = if file_type.is_dir() {
             Some(Arc::from(path))
         } else {
             None
         };
 
-        Ok(DirEntry {
+        DirEntry {
             depth,
             file_name: root_name.to_owned(),
-            file_type: metadata.file_type(),
+            file_type,
             parent_path: Arc::from(path.parent().map(Path::to_path_buf).unwrap_or_default()),
             read_children_path,
             read_children_error: None,
+            read_children_priority: 0,
             client_state: C::DirEntryState::default(),
             follow_link,
+            reparse_tag: file_type.reparse_tag(),
             follow_link_ancestors,
-        })
+            skip_yield: false,
+            duplicate_hardlink: false,
+            parent_read_dir_state: None,
+            #[cfg(feature = "filter")]
+            filter_rules: None,
+            metadata: None,
+            #[cfg(target_os = "linux")]
+            statx: None,
+            file_system,
+            parent_dir_fd: None,
+            #[cfg(feature = "size")]
+            subtree_size: None,
+        }
     }
 
     /// Return the file type for the file that this entry points to.
@@ -156,6 +251,18 @@ impl<C: ClientState> DirEntry<C> {
         self.file_type.is_symlink() || self.follow_link
     }
 
+    /// The tag of this entry if it is a reparse point on Windows, such as a
+    /// junction. This is unaffected by the [`follow_links`] setting, like
+    /// [`path_is_symlink`].
+    ///
+    /// `None` for other entries and on other platforms.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`path_is_symlink`]: struct.DirEntry.html#method.path_is_symlink
+    pub fn reparse_tag(&self) -> Option<ReparseTag> {
+        self.reparse_tag
+    }
+
     /// Return the metadata for the file that this entry points to.
     ///
     /// This will follow symbolic links if and only if the [`WalkDir`] value
@@ -168,6 +275,13 @@ impl<C: ClientState> DirEntry<C> {
     /// If this entry is a symbolic link and [`follow_links`] is enabled, then
     /// [`std::fs::metadata`] is called instead.
     ///
+    /// Entries of a walk with another [`file_system`] get the metadata from
+    /// it instead.
+    ///
+    /// If [`preload_metadata`] is enabled, the metadata read while walking is
+    /// returned without any system calls. It is only read again if reading
+    /// it while walking failed.
+    ///
     /// # Errors
     ///
     /// Similar to [`std::fs::metadata`], returns errors for path values that
@@ -176,15 +290,43 @@ impl<C: ClientState> DirEntry<C> {
     ///
     /// [`WalkDir`]: struct.WalkDir.html
     /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    /// [`preload_metadata`]: struct.WalkDirGeneric.html#method.preload_metadata
+    /// [`file_system`]: struct.WalkDirGeneric.html#method.file_system
     /// [`std::fs::metadata`]: https://doc.rust-lang.org/std/fs/fn.metadata.html
     /// [`std::fs::symlink_metadata`]: https://doc.rust-lang.org/stable/std/fs/fn.symlink_metadata.html
     pub fn metadata(&self) -> Result<fs::Metadata> {
-        if self.follow_link {
-            fs::metadata(self.path())
-        } else {
-            fs::symlink_metadata(self.path())
+        if let Some(metadata) = self.metadata.as_ref() {
+            return Ok(metadata.clone());
         }
-        .map_err(|err| Error::from_entry(self, err))
+        file_system::or_std(self.file_system.as_deref())
+            .metadata(&self.path(), self.follow_link)
+            .map_err(|err| Error::from_entry(self, err).during(Operation::Metadata))
+    }
+
+    /// The metadata read with `statx` while walking if
+    /// [`preload_metadata_ext`](struct.WalkDirGeneric.html#method.preload_metadata_ext)
+    /// is enabled, `None` otherwise or if reading it failed.
+    ///
+    /// Follows symbolic links like [`metadata`](struct.DirEntry.html#method.metadata).
+    #[cfg(target_os = "linux")]
+    pub fn statx(&self) -> Option<&Statx> {
+        self.statx.as_ref()
+    }
+
+    /// Whether another link to the same file was seen before in this walk,
+    /// if [`flag_duplicate_hardlinks`] is enabled. Always `false` otherwise.
+    ///
+    /// [`flag_duplicate_hardlinks`]: struct.WalkDirGeneric.html#method.flag_duplicate_hardlinks
+    pub fn is_duplicate_hardlink(&self) -> bool {
+        self.duplicate_hardlink
+    }
+
+    /// The `ReadDirState` of the directory containing this entry, as
+    /// projected for it by
+    /// [`project_read_dir_state`](struct.WalkDirGeneric.html#method.project_read_dir_state).
+    /// `None` if no projection is set.
+    pub fn parent_read_dir_state(&self) -> Option<&C::ReadDirState> {
+        self.parent_read_dir_state.as_ref()
     }
 
     /// Reference to the path of the directory containing this entry.
@@ -192,6 +334,54 @@ impl<C: ClientState> DirEntry<C> {
         &self.parent_path
     }
 
+    /// Total size and number of files of this entry and its descendants if
+    /// [`aggregate_sizes`](struct.WalkDirGeneric.html#method.aggregate_sizes)
+    /// is enabled, `None` otherwise.
+    ///
+    /// Entries that aren't directories count their own size and as one file.
+    /// Entries whose metadata couldn't be read count as `0` bytes.
+    #[cfg(feature = "size")]
+    pub fn subtree_size(&self) -> Option<SubtreeSize> {
+        self.subtree_size
+    }
+
+    // Sets `subtree_size` to the size of this entry alone, its contents are
+    // added while they are yielded.
+    #[cfg(feature = "size")]
+    pub(crate) fn count_size(&mut self, size_counter: &SizeCounter) {
+        if self.duplicate_hardlink {
+            // Already counted for the first link
+            self.subtree_size = Some(SubtreeSize::default());
+            return;
+        }
+        self.subtree_size = Some(SubtreeSize {
+            bytes: self
+                .metadata()
+                .map_or(0, |metadata| size_counter.size(&metadata)),
+            files: if self.file_type.is_dir() { 0 } else { 1 },
+        });
+    }
+
+    // The metadata read while walking if `preload_metadata` is enabled.
+    #[cfg(feature = "serde")]
+    pub(crate) fn preloaded_metadata(&self) -> Option<&fs::Metadata> {
+        self.metadata.as_ref()
+    }
+
+    // Reads and caches the metadata returned by `metadata`. Errors are not
+    // cached so they are reported when `metadata` is called.
+    pub(crate) fn preload_metadata(&mut self) {
+        self.metadata = self.metadata().ok();
+    }
+
+    // Reads and caches `fields` of the metadata returned by `statx`.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn preload_statx(&mut self, fields: StatxFields) {
+        if self.file_system.is_none() {
+            self.statx = statx::statx(&self.path(), fields, self.follow_link).ok();
+        }
+    }
+
     pub(crate) fn read_children_spec(
         &self,
         client_read_state: C::ReadDirState,
@@ -200,24 +390,47 @@ impl<C: ClientState> DirEntry<C> {
             .as_ref()
             .map(|read_children_path| ReadDirSpec {
                 depth: self.depth,
-                client_read_state,
+                client_read_state: self
+                    .parent_read_dir_state
+                    .clone()
+                    .unwrap_or(client_read_state),
                 path: read_children_path.clone(),
+                priority: self.read_children_priority,
                 follow_link_ancestors: self.follow_link_ancestors.clone(),
+                #[cfg(feature = "filter")]
+                filter_rules: self.filter_rules.clone(),
+                // Only open it relative to the parent if `process_read_dir`
+                // didn't point it elsewhere
+                parent_dir_fd: self.parent_dir_fd.clone().filter(|_| {
+                    read_children_path.parent() == Some(&*self.parent_path)
+                        && read_children_path.file_name() == Some(&*self.file_name)
+                }),
+                chunks: None,
             })
     }
 
     pub(crate) fn follow_symlink(&self) -> Result<Self> {
         let path = self.path();
         let origins = self.follow_link_ancestors.clone();
-        let dir_entry = DirEntry::from_path(self.depth, &path, true, origins)?;
+        let mut dir_entry =
+            DirEntry::from_path(self.depth, &path, true, origins, self.file_system.clone())?;
+        dir_entry.skip_yield = self.skip_yield;
+        #[cfg(feature = "filter")]
+        {
+            dir_entry.filter_rules = self.filter_rules.clone();
+        }
+        dir_entry.read_children_priority = self.read_children_priority;
+        dir_entry.reparse_tag = self.reparse_tag;
 
         if dir_entry.file_type.is_dir() {
-            let target = fs::read_link(&path).map_err(|err| Error::from_io(self.depth, err))?;
-            for ancestor in self.follow_link_ancestors.iter().rev() {
+            let target = file_system::or_std(self.file_system.as_deref())
+                .read_link(&path)
+                .map_err(|err| Error::from_io(self.depth, err))?;
+            for (i, ancestor) in self.follow_link_ancestors.iter().enumerate().rev() {
                 if target.as_path() == ancestor.as_ref() {
                     return Err(Error::from_loop(
                         self.depth,
-                        ancestor.as_ref(),
+                        &self.follow_link_ancestors[i..],
                         path.as_ref(),
                     ));
                 }
@@ -1,55 +1,452 @@
 use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Instant;
 
 use super::*;
-use crate::Result;
+#[cfg(feature = "size")]
+use crate::size::SubtreeSize;
+use crate::{LeafDirs, ReduceReadDirFunction, Result};
 
 /// DirEntry iterator from `WalkDir.into_iter()`.
 ///
 /// Yields entries from recursive traversal of filesystem.
 pub struct DirEntryIter<C: ClientState> {
+    // roots of the walk, recorded in checkpoints
+    #[cfg(feature = "checkpoint")]
+    pub(crate) roots: Vec<PathBuf>,
     min_depth: usize,
+    // yield directories after their contents
+    pub(crate) contents_first: bool,
+    // yield directories before their contents, and the contents of
+    // directories in walk order rather than as soon as they were read
+    strict_order: bool,
+    // used to describe the thread-pool if it was too busy to start the walk
+    pub(crate) parallelism: Parallelism,
+    // told about consumed read dirs so it can adapt to the backlog
+    concurrency_limit: Option<WalkConcurrencyLimit>,
+    // stops yielding entries once canceled
+    cancel_token: Option<CancelToken>,
+    // reduces the state of a directory's contents into its parent
+    pub(crate) reduce_read_dir: Option<Arc<ReduceReadDirFunction<C>>>,
+    // extended-length roots and the roots they were made from, yielded paths
+    // are mapped back to the latter
+    pub(crate) long_path_roots: Vec<(PathBuf, PathBuf)>,
+    // maximum number of entries to yield, and the token stopping directory
+    // reads once they were yielded
+    pub(crate) max_entries: Option<(usize, CancelToken)>,
+    yielded_entries: usize,
+    // yields an error and ends once passed, directories aren't read anymore
+    pub(crate) deadline: Option<Instant>,
+    // don't yield directories whose read returned no results
+    pub(crate) skip_empty_dirs: bool,
+    // only yield directories that are leaves
+    pub(crate) leaf_dirs_only: Option<LeafDirs>,
     // iterator yielding next ReadDir results when needed
     pub(crate) read_dir_iter: Option<Peekable<ReadDirIter<C>>>,
     // stack of ReadDir results, track location in filesystem traversal
-    read_dir_results_stack: Vec<vec::IntoIter<Result<DirEntry<C>>>>,
+    read_dir_results_stack: Vec<ReadDirResults<C>>,
+    // directories skipped by the consumer whose pending reads are skipped too
+    skipped_dirs: Arc<RwLock<Vec<Arc<Path>>>>,
+}
+
+// Results of a ReadDir, with the directory they were read from when it is
+// yielded after them.
+struct ReadDirResults<C: ClientState> {
+    results: vec::IntoIter<Result<DirEntry<C>>>,
+    read_dir_state: C::ReadDirState,
+    // directory whose next chunk replaces the results once they were taken
+    next_chunk: Option<Arc<Path>>,
+    contents_first_dir: Option<DirEntry<C>>,
+    // sizes of the results taken so far, added to contents_first_dir
+    #[cfg(feature = "size")]
+    contents_size: SubtreeSize,
+}
+
+impl<C: ClientState> ReadDirResults<C> {
+    fn new(
+        read_dir_state: C::ReadDirState,
+        results: Vec<Result<DirEntry<C>>>,
+        next_chunk: Option<Arc<Path>>,
+    ) -> Self {
+        ReadDirResults {
+            results: results.into_iter(),
+            read_dir_state,
+            next_chunk,
+            contents_first_dir: None,
+            #[cfg(feature = "size")]
+            contents_size: SubtreeSize::default(),
+        }
+    }
+
+    // Whether the directory has no results, including in further chunks.
+    fn is_empty(&self) -> bool {
+        self.results.len() == 0 && self.next_chunk.is_none()
+    }
+
+    fn is_leaf(&self, leaf_dirs: LeafDirs) -> bool {
+        match leaf_dirs {
+            LeafDirs::WithoutSubdirs => {
+                self.next_chunk.is_none()
+                    && !self
+                        .results
+                        .as_slice()
+                        .iter()
+                        .flatten()
+                        .any(|dir_entry| dir_entry.file_type.is_dir())
+            }
+            LeafDirs::Empty => self.is_empty(),
+        }
+    }
 }
 
 impl<C: ClientState> DirEntryIter<C> {
     pub(crate) fn new(
         root_entry_results: Vec<Result<DirEntry<C>>>,
-        parallelism: Parallelism,
+        schedule: ReadDirSchedule<'_, C>,
         min_depth: usize,
+        contents_first: bool,
         root_read_dir_state: C::ReadDirState,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
     ) -> DirEntryIter<C> {
-        // 1. Gather read_dir_specs from root level
-        let read_dir_specs: Vec<_> = root_entry_results
-            .iter()
-            .flat_map(|dir_entry_result| {
-                dir_entry_result
-                    .as_ref()
-                    .ok()?
-                    .read_children_spec(root_read_dir_state.clone())
+        let ReadDirSchedule {
+            mut parallelism,
+            busy_policy,
+            spawn_walk,
+            pacing,
+            concurrency_limit,
+            cancel_token,
+            queueing,
+        } = schedule;
+
+        // 0. Skip reading directories inside of directories skipped by the
+        //    consumer, they might already be scheduled
+        let skipped_dirs: Arc<RwLock<Vec<Arc<Path>>>> = Default::default();
+        let core_read_dir_callback: Arc<ReadDirCallback<C>> = {
+            let skipped_dirs = skipped_dirs.clone();
+            Arc::new(move |read_dir_spec: ReadDirSpec<C>| {
+                let skipped = skipped_dirs
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .any(|skipped_dir| read_dir_spec.path.starts_with(skipped_dir));
+                if skipped {
+                    return Ok(ReadDir::new(read_dir_spec.client_read_state, Vec::new()));
+                }
+                core_read_dir_callback(read_dir_spec)
             })
-            .collect();
+        };
 
-        // 2. Init new read_dir_iter from those specs
-        let read_dir_iter =
-            ReadDirIter::try_new(read_dir_specs, parallelism, core_read_dir_callback)
-                .map(|iter| iter.peekable());
+        // 1. Gather read_dir_specs from root level
+        let read_dir_specs = || -> Vec<_> {
+            root_entry_results
+                .iter()
+                .flat_map(|dir_entry_result| {
+                    dir_entry_result
+                        .as_ref()
+                        .ok()?
+                        .read_children_spec(root_read_dir_state.clone())
+                })
+                .collect()
+        };
+
+        // 2. Init new read_dir_iter from those specs, falling back if the
+        //    thread-pool is too busy
+        let busy_timeout = match busy_policy {
+            BusyPolicy::WaitForever => None,
+            _ => parallelism.timeout(),
+        };
+        let mut read_dir_iter = ReadDirIter::try_new(
+            read_dir_specs(),
+            parallelism.clone(),
+            busy_timeout,
+            pacing,
+            core_read_dir_callback.clone(),
+            queueing.clone(),
+            spawn_walk,
+        );
+        if let (None, Some(fallback_parallelism)) =
+            (&read_dir_iter, busy_policy.fallback_parallelism())
+        {
+            parallelism = fallback_parallelism;
+            read_dir_iter = ReadDirIter::try_new(
+                read_dir_specs(),
+                parallelism.clone(),
+                parallelism.timeout(),
+                pacing,
+                core_read_dir_callback,
+                queueing,
+                None,
+            );
+        }
+        // Without a read_dir_iter the busy error is yielded in place of the
+        // first directory's contents
+        let strict_order = pacing.strict_order || read_dir_iter.is_none();
+        let read_dir_iter = read_dir_iter.map(|iter| iter.peekable());
 
         // 3. Return DirEntryIter that will return initial root entries and then
         //    fill and process read_dir_iter until complete
         DirEntryIter {
+            #[cfg(feature = "checkpoint")]
+            roots: Vec::new(),
             min_depth,
+            contents_first,
+            strict_order,
+            parallelism,
+            concurrency_limit,
+            cancel_token,
+            reduce_read_dir: None,
+            long_path_roots: Vec::new(),
+            max_entries: None,
+            yielded_entries: 0,
+            deadline: None,
+            skip_empty_dirs: false,
+            leaf_dirs_only: None,
             read_dir_iter,
-            read_dir_results_stack: vec![root_entry_results.into_iter()],
+            read_dir_results_stack: vec![ReadDirResults::new(
+                root_read_dir_state,
+                root_entry_results,
+                None,
+            )],
+            skipped_dirs,
+        }
+    }
+
+    /// Skip the remaining entries of the directory that contains the
+    /// previously yielded entry, or of the previously yielded entry itself if
+    /// it is a directory, matching `walkdir`.
+    ///
+    /// Pending reads of skipped directories are skipped too, directories that
+    /// are already being read are read to the end.
+    ///
+    /// With [`strict_order(false)`](struct.WalkDirGeneric.html#method.strict_order)
+    /// only the remaining entries of the directory whose entries are being
+    /// yielded are skipped.
+    pub fn skip_current_dir(&mut self) {
+        if !self.strict_order {
+            if let Some(top_read_dir_results) = self.read_dir_results_stack.last_mut() {
+                top_read_dir_results.results = Vec::new().into_iter();
+            }
+            return;
+        }
+        if let Some(top_read_dir_results) = self.read_dir_results_stack.last_mut() {
+            let results =
+                std::mem::replace(&mut top_read_dir_results.results, Vec::new().into_iter());
+            let next_chunk = top_read_dir_results.next_chunk.take();
+            self.skip_read_dir_results(results, next_chunk);
+        }
+    }
+
+    // Consumes the ReadDir results of all directories in `results` and their
+    // descendants, and the remaining chunks of `next_chunk`, so the next
+    // ReadDir result belongs to the next directory that isn't skipped.
+    fn skip_read_dir_results(
+        &mut self,
+        results: vec::IntoIter<Result<DirEntry<C>>>,
+        next_chunk: Option<Arc<Path>>,
+    ) {
+        let iter = match self.read_dir_iter.as_mut() {
+            Some(iter) => iter,
+            None => return,
+        };
+        let results: Vec<_> = results.collect();
+        let skipped_dirs: Vec<Arc<Path>> = results
+            .iter()
+            .flatten()
+            .flat_map(|dir_entry| dir_entry.read_children_path.clone())
+            .chain(next_chunk.clone())
+            .collect();
+        if skipped_dirs.is_empty() {
+            return;
+        }
+        self.skipped_dirs
+            .write()
+            .unwrap()
+            .extend(skipped_dirs.iter().cloned());
+
+        let mut results_stack = vec![(results.into_iter(), next_chunk.is_some())];
+        while let Some((results, next_chunk)) = results_stack.last_mut() {
+            let next_chunk = *next_chunk;
+            let read_next = match results.next() {
+                Some(Ok(dir_entry)) => dir_entry.read_children_path.is_some(),
+                Some(Err(_)) => false,
+                // The next chunk continues in place of the taken results
+                None => {
+                    results_stack.pop();
+                    next_chunk
+                }
+            };
+            if read_next {
+                if let Some(Ok(ReadDir {
+                    results_list,
+                    chunks,
+                    ..
+                })) = iter.next()
+                {
+                    results_stack.push((results_list.into_iter(), chunks.is_some()));
+                }
+                if let Some(concurrency_limit) = &self.concurrency_limit {
+                    concurrency_limit.consumed();
+                }
+            }
+        }
+
+        // No reads inside of skipped_dirs are pending anymore
+        self.skipped_dirs
+            .write()
+            .unwrap()
+            .retain(|skipped_dir| !skipped_dirs.contains(skipped_dir));
+    }
+
+    // Paths and depths of the entries that weren't yielded yet and whose
+    // contents weren't read yet, in the order they would be yielded.
+    #[cfg(feature = "checkpoint")]
+    pub(crate) fn pending_entries(&self) -> Vec<(PathBuf, usize)> {
+        self.read_dir_results_stack
+            .iter()
+            .rev()
+            .flat_map(|read_dir_results| read_dir_results.results.as_slice())
+            .flatten()
+            .map(|dir_entry| (dir_entry.path(), dir_entry.depth))
+            .collect()
+    }
+
+    // Smallest path of the entries that weren't yielded yet, as they will be
+    // yielded, if the entries of each directory are sorted by file name. The
+    // contents of directories have larger paths than the directories.
+    pub(crate) fn min_pending_path(&self) -> Option<PathBuf> {
+        self.read_dir_results_stack
+            .iter()
+            .enumerate()
+            .flat_map(|(i, read_dir_results)| {
+                let mut remaining = read_dir_results.results.as_slice().iter().flatten();
+                // Roots aren't sorted
+                let next = if i == 0 {
+                    remaining
+                        .map(DirEntry::path)
+                        .min_by(|a, b| a.as_os_str().cmp(b.as_os_str()))
+                } else {
+                    remaining.next().map(DirEntry::path)
+                };
+                next.into_iter().chain(
+                    read_dir_results
+                        .contents_first_dir
+                        .as_ref()
+                        .map(DirEntry::path),
+                )
+            })
+            .map(|path| self.normalized_path(path))
+            .min_by(|a, b| a.as_os_str().cmp(b.as_os_str()))
+    }
+
+    // Maps `path` from the extended-length root containing it back to the
+    // root it was made from, like `normalize_long_path`.
+    fn normalized_path(&self, path: PathBuf) -> PathBuf {
+        for (long_path_root, root) in &self.long_path_roots {
+            if path == *long_path_root {
+                return root.clone();
+            }
+            if let Ok(relative_path) = path.strip_prefix(long_path_root) {
+                return root.join(relative_path);
+            }
+        }
+        path
+    }
+
+    // Adds the sizes of the contents of `dir_entry` to its own, and its total
+    // to the directory containing it.
+    #[cfg(feature = "size")]
+    fn add_contents_size(
+        &mut self,
+        mut dir_entry: DirEntry<C>,
+        contents_size: SubtreeSize,
+    ) -> DirEntry<C> {
+        if let Some(subtree_size) = dir_entry.subtree_size.as_mut() {
+            *subtree_size += contents_size;
+            if let Some(top) = self.read_dir_results_stack.last_mut() {
+                top.contents_size += *subtree_size;
+            }
+        }
+        dir_entry
+    }
+
+    // Counts `dir_entry` as yielded, stopping reads once `max_entries` were
+    // yielded.
+    fn yield_entry(&mut self, dir_entry: DirEntry<C>) -> Option<Result<DirEntry<C>>> {
+        if let Some((max_entries, reached)) = &self.max_entries {
+            self.yielded_entries += 1;
+            if self.yielded_entries >= *max_entries {
+                reached.cancel();
+            }
+        }
+        Some(Ok(self.normalize_long_path(dir_entry)))
+    }
+
+    // Maps the path of `dir_entry` from the extended-length root containing
+    // it back to the root it was made from.
+    fn normalize_long_path(&self, mut dir_entry: DirEntry<C>) -> DirEntry<C> {
+        if self.long_path_roots.is_empty() {
+            return dir_entry;
+        }
+        let path = dir_entry.path();
+        for (long_path_root, root) in &self.long_path_roots {
+            if path == *long_path_root {
+                dir_entry.parent_path = Arc::from(root.parent().unwrap_or(Path::new("")));
+                dir_entry.file_name = root.file_name().unwrap_or(root.as_os_str()).to_os_string();
+                break;
+            }
+            if let Ok(relative_path) = path.strip_prefix(long_path_root) {
+                let path = root.join(relative_path);
+                dir_entry.parent_path = Arc::from(path.parent().unwrap_or(Path::new("")));
+                break;
+            }
+        }
+        dir_entry
+    }
+
+    // Yields the entries of directories in the order they were read, for
+    // `strict_order(false)`. Directories were yielded before they were read,
+    // so read errors are yielded on their own.
+    fn next_unordered(&mut self) -> Option<Result<DirEntry<C>>> {
+        loop {
+            if let Some(top_read_dir_results) = self.read_dir_results_stack.last_mut() {
+                match top_read_dir_results.results.next() {
+                    Some(Ok(dir_entry)) => {
+                        if dir_entry.depth >= self.min_depth && !dir_entry.skip_yield {
+                            return self.yield_entry(dir_entry);
+                        }
+                    }
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => {
+                        self.read_dir_results_stack.pop();
+                    }
+                }
+                continue;
+            }
+
+            let read_dir_result = self.read_dir_iter.as_mut()?.next()?;
+            if let Some(concurrency_limit) = &self.concurrency_limit {
+                concurrency_limit.consumed();
+            }
+            let ReadDir {
+                read_dir_state,
+                results_list,
+                ..
+            } = match read_dir_result {
+                Ok(read_dir) => read_dir,
+                Err(err) => return Some(Err(err)),
+            };
+            self.read_dir_results_stack.push(ReadDirResults::new(
+                read_dir_state,
+                results_list,
+                None,
+            ));
         }
     }
 
     fn push_next_read_dir_results(
         iter: &mut Peekable<ReadDirIter<C>>,
-        results: &mut Vec<vec::IntoIter<Result<DirEntry<C>>>>,
+        results: &mut Vec<ReadDirResults<C>>,
     ) -> Result<()> {
         // Push next read dir results or return error if read failed
         let read_dir_result = iter.next().unwrap();
@@ -58,8 +455,16 @@ impl<C: ClientState> DirEntryIter<C> {
             Err(err) => return Err(err),
         };
 
-        let ReadDir { results_list, .. } = read_dir;
-        results.push(results_list.into_iter());
+        let ReadDir {
+            read_dir_state,
+            results_list,
+            chunks,
+        } = read_dir;
+        results.push(ReadDirResults::new(
+            read_dir_state,
+            results_list,
+            chunks.map(|chunks| chunks.path.clone()),
+        ));
 
         Ok(())
     }
@@ -68,12 +473,34 @@ impl<C: ClientState> DirEntryIter<C> {
 impl<C: ClientState> Iterator for DirEntryIter<C> {
     type Item = Result<DirEntry<C>>;
     fn next(&mut self) -> Option<Self::Item> {
+        if self
+            .cancel_token
+            .as_ref()
+            .is_some_and(CancelToken::is_canceled)
+            || self
+                .max_entries
+                .as_ref()
+                .is_some_and(|(_, reached)| reached.is_canceled())
+        {
+            return None;
+        }
+        if let Some(deadline) = self.deadline.filter(|deadline| Instant::now() >= *deadline) {
+            // End after the error, dropping the directories read so far
+            self.deadline = None;
+            self.read_dir_results_stack.clear();
+            self.read_dir_iter = None;
+            return Some(Err(Error::deadline(deadline)));
+        }
+        if !self.strict_order {
+            return self.next_unordered();
+        }
         loop {
             // 1. Get current read dir results iter from top of stack
-            let top_read_dir_results = self.read_dir_results_stack.last_mut()?;
+            let top_index = self.read_dir_results_stack.len().checked_sub(1)?;
+            let top_read_dir_results = &mut self.read_dir_results_stack[top_index];
 
             // 2. If more results in current read dir then process
-            if let Some(dir_entry_result) = top_read_dir_results.next() {
+            if let Some(dir_entry_result) = top_read_dir_results.results.next() {
                 // 2.1 Handle error case
                 let mut dir_entry = match dir_entry_result {
                     Ok(dir_entry) => dir_entry,
@@ -82,24 +509,102 @@ impl<C: ClientState> Iterator for DirEntryIter<C> {
                 // 2.2 If dir_entry has a read_children_path means we need to read a new
                 // directory and push those results onto read_dir_results_stack
                 if dir_entry.read_children_path.is_some() {
-                    let iter = match self.read_dir_iter.as_mut().ok_or_else(Error::busy) {
+                    let parallelism = &self.parallelism;
+                    let iter = match self
+                        .read_dir_iter
+                        .as_mut()
+                        .ok_or_else(|| parallelism.busy_error())
+                    {
                         Ok(iter) => iter,
                         Err(err) => return Some(Err(err)),
                     };
-                    if let Err(err) =
-                        Self::push_next_read_dir_results(iter, &mut self.read_dir_results_stack)
-                    {
-                        dir_entry.read_children_error = Some(err);
+                    let pushed = match Self::push_next_read_dir_results(
+                        iter,
+                        &mut self.read_dir_results_stack,
+                    ) {
+                        Ok(()) => true,
+                        Err(err) => {
+                            dir_entry.read_children_error = Some(err);
+                            false
+                        }
+                    };
+                    if let Some(concurrency_limit) = &self.concurrency_limit {
+                        concurrency_limit.consumed();
                     }
+                    if pushed {
+                        let top = self.read_dir_results_stack.last().unwrap();
+                        if self.skip_empty_dirs && top.is_empty() {
+                            dir_entry.skip_yield = true;
+                        }
+                        if let Some(leaf_dirs) = self.leaf_dirs_only {
+                            if !top.is_leaf(leaf_dirs) {
+                                dir_entry.skip_yield = true;
+                            }
+                        }
+                    }
+                    if pushed && self.contents_first {
+                        // 2.3 Yield dir_entry once its contents were popped
+                        let top = self.read_dir_results_stack.last_mut().unwrap();
+                        top.contents_first_dir = Some(dir_entry);
+                        continue;
+                    }
+                } else if self.leaf_dirs_only.is_some() {
+                    dir_entry.skip_yield = true;
+                }
+
+                #[cfg(feature = "size")]
+                if let Some(subtree_size) = dir_entry.subtree_size {
+                    self.read_dir_results_stack[top_index].contents_size += subtree_size;
                 }
 
-                if dir_entry.depth >= self.min_depth {
-                    // 2.3 Finished, return dir_entry
-                    return Some(Ok(dir_entry));
+                if dir_entry.depth >= self.min_depth && !dir_entry.skip_yield {
+                    // 2.4 Finished, return dir_entry
+                    return self.yield_entry(dir_entry);
+                }
+            } else if top_read_dir_results.next_chunk.take().is_some() {
+                // 3. Continue with the next chunk of the directory, keeping
+                // its state
+                let next_chunk = self.read_dir_iter.as_mut().and_then(Iterator::next);
+                if let Some(concurrency_limit) = &self.concurrency_limit {
+                    concurrency_limit.consumed();
+                }
+                match next_chunk {
+                    Some(Ok(ReadDir {
+                        results_list,
+                        chunks,
+                        ..
+                    })) => {
+                        let top_read_dir_results = &mut self.read_dir_results_stack[top_index];
+                        top_read_dir_results.results = results_list.into_iter();
+                        top_read_dir_results.next_chunk = chunks.map(|chunks| chunks.path.clone());
+                    }
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => {}
                 }
             } else {
-                // If no more results in current then pop stack
-                self.read_dir_results_stack.pop();
+                // If no more results in current then pop stack, yielding the
+                // directory they were read from if it was deferred
+                let read_dir_results = self.read_dir_results_stack.pop()?;
+                if let Some(mut dir_entry) = read_dir_results.contents_first_dir {
+                    #[cfg(feature = "size")]
+                    {
+                        dir_entry =
+                            self.add_contents_size(dir_entry, read_dir_results.contents_size);
+                    }
+                    if let (Some(reduce_read_dir), Some(parent)) = (
+                        self.reduce_read_dir.as_ref(),
+                        self.read_dir_results_stack.last_mut(),
+                    ) {
+                        reduce_read_dir(
+                            &mut parent.read_dir_state,
+                            &mut dir_entry,
+                            read_dir_results.read_dir_state,
+                        );
+                    }
+                    if dir_entry.depth >= self.min_depth && !dir_entry.skip_yield {
+                        return self.yield_entry(dir_entry);
+                    }
+                }
             }
         }
     }
@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::file_system::SendReadDirEntries;
+use crate::ReadDirBackend;
+
+/// Returns the current path of the directory opened as `dir`, failing if it
+/// was removed or that path no longer refers to the same directory. The path
+/// only names the entries of a walk through `dir`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn dir_handle_path(dir: &File) -> io::Result<std::path::PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+
+    let handle_metadata = dir.metadata()?;
+    if !handle_metadata.is_dir() {
+        return Err(io::Error::other("handle does not refer to a directory"));
+    }
+    let path = std::fs::read_link(format!("/proc/self/fd/{}", dir.as_raw_fd()))?;
+    let path_metadata = std::fs::metadata(&path)?;
+    if (path_metadata.dev(), path_metadata.ino()) != (handle_metadata.dev(), handle_metadata.ino())
+    {
+        return Err(io::Error::other(format!(
+            "directory handle no longer refers to {}",
+            path.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// Directory kept open while its subdirectories are waiting to be read, so
+/// they can be opened relative to it instead of resolving their full paths.
+#[derive(Debug)]
+pub(crate) struct DirFd {
+    dir: File,
+}
+
+impl DirFd {
+    /// The directory opened as `dir`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_dir(dir: File) -> DirFd {
+        DirFd { dir }
+    }
+
+    /// Opens the directory at `path`, relative to `parent` if `path` is one of
+    /// its entries. Entries of `parent` are opened without following
+    /// symlinks.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn open(parent: Option<&DirFd>, path: &Path) -> io::Result<DirFd> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let dir = match (parent, path.file_name()) {
+            (Some(parent), Some(file_name)) => {
+                let name = CString::new(file_name.as_bytes())?;
+                // SAFETY: `parent.dir` is a valid open file descriptor and
+                // `name` is NUL-terminated.
+                let fd = unsafe {
+                    libc::openat(
+                        parent.dir.as_raw_fd(),
+                        name.as_ptr(),
+                        libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                    )
+                };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // SAFETY: `fd` was just opened and isn't owned by anything else
+                unsafe { File::from_raw_fd(fd) }
+            }
+            _ => std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECTORY)
+                .open(path)?,
+        };
+        Ok(DirFd { dir })
+    }
+
+    /// Opens the directory at `path`, relative to `parent` if `path` is one of
+    /// its entries.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn open(_parent: Option<&DirFd>, _path: &Path) -> io::Result<DirFd> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "opening directories relative to their parent is not supported on this platform",
+        ))
+    }
+
+    /// Reads the entries of the directory through its handle, with
+    /// `getdents64` and a stat relative to the handle for entries whose type
+    /// isn't returned by the file system, or with io_uring stats for
+    /// [`ReadDirBackend::IoUring`](../enum.ReadDirBackend.html#variant.IoUring).
+    /// `path` is only used for errors.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn read_dir(
+        &self,
+        path: &Path,
+        backend: ReadDirBackend,
+    ) -> io::Result<SendReadDirEntries> {
+        use std::os::unix::io::AsRawFd;
+
+        // Read from the start, the handle may have been read before
+        // SAFETY: `self.dir` is a valid open file descriptor.
+        if unsafe { libc::lseek(self.dir.as_raw_fd(), 0, libc::SEEK_SET) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        match backend {
+            #[cfg(feature = "io-uring")]
+            ReadDirBackend::IoUring => super::uring::read_open_dir(&self.dir, path),
+            _ => super::getdents::read_open_dir(&self.dir, path, super::getdents::stat_file_types),
+        }
+    }
+
+    /// Reads the entries of the directory through its handle.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn read_dir(
+        &self,
+        _path: &Path,
+        _backend: ReadDirBackend,
+    ) -> io::Result<SendReadDirEntries> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "reading directory handles is not supported on this platform",
+        ))
+    }
+}
@@ -2,6 +2,8 @@ use std::error;
 use std::fmt;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::{ClientState, DirEntry};
 
@@ -30,17 +32,68 @@ pub struct Error {
     inner: ErrorInner,
 }
 
+/// Category of an [`Error`](struct.Error.html), see
+/// [`Error::kind`](struct.Error.html#method.kind).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A path didn't exist, for example because it was removed while
+    /// walking.
+    NotFound,
+    /// Missing permissions to access a path.
+    PermissionDenied,
+    /// A loop was found while following symbolic links.
+    Loop,
+    /// Reading the contents of a directory failed for another reason.
+    ReadDirFailed,
+    /// Reading the metadata or file type of an entry failed for another
+    /// reason.
+    MetadataFailed,
+    /// The thread-pool was too busy to start the walk.
+    ThreadpoolBusy,
+    /// The deadline of the walk passed before all entries were yielded.
+    DeadlineExceeded,
+    /// Any other error, such as reading a link or an ignore file.
+    Other,
+}
+
+// Operation that failed with an io error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Operation {
+    ReadDir,
+    Metadata,
+    Other,
+}
+
+/// A loop found while following symbolic links, see
+/// [`Error::loop_detected`](struct.Error.html#method.loop_detected).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoopDetected {
+    /// Directory that the symbolic link points to.
+    pub ancestor: PathBuf,
+    /// Path of the symbolic link pointing to `ancestor`.
+    pub child: PathBuf,
+    /// Directories walked from `ancestor` to the directory containing
+    /// `child`, starting with `ancestor`. Symbolic links among them were
+    /// followed to get to `child`.
+    pub chain: Vec<PathBuf>,
+}
+
 #[derive(Debug)]
 enum ErrorInner {
     Io {
         path: Option<PathBuf>,
         err: io::Error,
+        operation: Operation,
     },
-    Loop {
-        ancestor: PathBuf,
-        child: PathBuf,
+    Loop(LoopDetected),
+    ThreadpoolBusy {
+        busy_timeout: Option<Duration>,
+        num_threads: Option<usize>,
+    },
+    DeadlineExceeded {
+        deadline: Instant,
     },
-    ThreadpoolBusy,
 }
 
 impl Error {
@@ -52,13 +105,13 @@ impl Error {
     /// [`std::fs::read_dir`]: https://doc.rust-lang.org/stable/std/fs/fn.read_dir.html
     pub fn path(&self) -> Option<&Path> {
         match self.inner {
-            ErrorInner::ThreadpoolBusy => None,
+            ErrorInner::ThreadpoolBusy { .. } | ErrorInner::DeadlineExceeded { .. } => None,
             ErrorInner::Io { path: None, .. } => None,
             ErrorInner::Io {
                 path: Some(ref path),
                 ..
             } => Some(path),
-            ErrorInner::Loop { ref child, .. } => Some(child),
+            ErrorInner::Loop(LoopDetected { ref child, .. }) => Some(child),
         }
     }
 
@@ -75,12 +128,41 @@ impl Error {
     /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
     /// [`path`]: struct.Error.html#path
     pub fn loop_ancestor(&self) -> Option<&Path> {
+        self.loop_detected()
+            .map(|loop_detected| loop_detected.ancestor.as_path())
+    }
+
+    /// Returns both paths of a cycle and the directories leading from one to
+    /// the other if one was detected.
+    pub fn loop_detected(&self) -> Option<&LoopDetected> {
         match self.inner {
-            ErrorInner::Loop { ref ancestor, .. } => Some(ancestor),
+            ErrorInner::Loop(ref loop_detected) => Some(loop_detected),
             _ => None,
         }
     }
 
+    /// Returns the category of this error.
+    ///
+    /// Io errors are categorized by their
+    /// [`io::ErrorKind`](https://doc.rust-lang.org/stable/std/io/enum.ErrorKind.html)
+    /// first, and by the operation that failed otherwise.
+    pub fn kind(&self) -> ErrorKind {
+        match self.inner {
+            ErrorInner::Io {
+                ref err, operation, ..
+            } => match (err.kind(), operation) {
+                (io::ErrorKind::NotFound, _) => ErrorKind::NotFound,
+                (io::ErrorKind::PermissionDenied, _) => ErrorKind::PermissionDenied,
+                (_, Operation::ReadDir) => ErrorKind::ReadDirFailed,
+                (_, Operation::Metadata) => ErrorKind::MetadataFailed,
+                (_, Operation::Other) => ErrorKind::Other,
+            },
+            ErrorInner::Loop(_) => ErrorKind::Loop,
+            ErrorInner::ThreadpoolBusy { .. } => ErrorKind::ThreadpoolBusy,
+            ErrorInner::DeadlineExceeded { .. } => ErrorKind::DeadlineExceeded,
+        }
+    }
+
     /// Returns the depth at which this error occurred relative to the root.
     ///
     /// The smallest depth is `0` and always corresponds to the path given to
@@ -162,7 +244,36 @@ impl Error {
     ///
     /// Note that business detection is timeout based, and we don't know if it would have been a deadlock or not.
     pub fn is_busy(&self) -> bool {
-        matches!(self.inner, ErrorInner::ThreadpoolBusy)
+        matches!(self.inner, ErrorInner::ThreadpoolBusy { .. })
+    }
+
+    /// Returns the `busy_timeout` that expired if this error is due to a busy
+    /// thread-pool.
+    pub fn busy_timeout(&self) -> Option<Duration> {
+        match self.inner {
+            ErrorInner::ThreadpoolBusy { busy_timeout, .. } => busy_timeout,
+            _ => None,
+        }
+    }
+
+    /// Returns the number of threads of the thread-pool if this error is due
+    /// to a busy thread-pool.
+    pub fn busy_pool_threads(&self) -> Option<usize> {
+        match self.inner {
+            ErrorInner::ThreadpoolBusy { num_threads, .. } => num_threads,
+            _ => None,
+        }
+    }
+
+    /// Returns the deadline that passed if this error is due to the walk
+    /// exceeding its
+    /// [`deadline`](struct.WalkDirGeneric.html#method.deadline) or
+    /// [`timeout`](struct.WalkDirGeneric.html#method.timeout).
+    pub fn deadline_exceeded(&self) -> Option<Instant> {
+        match self.inner {
+            ErrorInner::DeadlineExceeded { deadline } => Some(deadline),
+            _ => None,
+        }
     }
 
     /// Similar to [`io_error`] except consumes self to convert to the original
@@ -177,18 +288,30 @@ impl Error {
         }
     }
 
-    pub(crate) fn busy() -> Self {
+    pub(crate) fn busy(busy_timeout: Option<Duration>, num_threads: Option<usize>) -> Self {
         Error {
             depth: 0,
-            inner: ErrorInner::ThreadpoolBusy,
+            inner: ErrorInner::ThreadpoolBusy {
+                busy_timeout,
+                num_threads,
+            },
         }
     }
+
+    pub(crate) fn deadline(deadline: Instant) -> Self {
+        Error {
+            depth: 0,
+            inner: ErrorInner::DeadlineExceeded { deadline },
+        }
+    }
+
     pub(crate) fn from_path(depth: usize, pb: PathBuf, err: io::Error) -> Self {
         Error {
             depth,
             inner: ErrorInner::Io {
                 path: Some(pb),
                 err,
+                operation: Operation::Other,
             },
         }
     }
@@ -199,6 +322,7 @@ impl Error {
             inner: ErrorInner::Io {
                 path: Some(dent.path()),
                 err,
+                operation: Operation::Other,
             },
         }
     }
@@ -206,17 +330,34 @@ impl Error {
     pub(crate) fn from_io(depth: usize, err: io::Error) -> Self {
         Error {
             depth,
-            inner: ErrorInner::Io { path: None, err },
+            inner: ErrorInner::Io {
+                path: None,
+                err,
+                operation: Operation::Other,
+            },
+        }
+    }
+
+    // Records the failed operation of io errors, see `kind`.
+    pub(crate) fn during(mut self, failed_operation: Operation) -> Self {
+        if let ErrorInner::Io {
+            ref mut operation, ..
+        } = self.inner
+        {
+            *operation = failed_operation;
         }
+        self
     }
 
-    pub(crate) fn from_loop(depth: usize, ancestor: &Path, child: &Path) -> Self {
+    // `chain` starts with the ancestor that `child` points to.
+    pub(crate) fn from_loop(depth: usize, chain: &[Arc<Path>], child: &Path) -> Self {
         Error {
             depth,
-            inner: ErrorInner::Loop {
-                ancestor: ancestor.to_path_buf(),
+            inner: ErrorInner::Loop(LoopDetected {
+                ancestor: chain[0].to_path_buf(),
                 child: child.to_path_buf(),
-            },
+                chain: chain.iter().map(|path| path.to_path_buf()).collect(),
+            }),
         }
     }
 }
@@ -225,7 +366,9 @@ impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self.inner {
             ErrorInner::Io { ref err, .. } => Some(err),
-            ErrorInner::Loop { .. } | ErrorInner::ThreadpoolBusy => None,
+            ErrorInner::Loop(_)
+            | ErrorInner::ThreadpoolBusy { .. }
+            | ErrorInner::DeadlineExceeded { .. } => None,
         }
     }
 
@@ -233,8 +376,9 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match self.inner {
             ErrorInner::Io { ref err, .. } => err.description(),
-            ErrorInner::Loop { .. } => "file system loop found",
-            ErrorInner::ThreadpoolBusy => "thread-pool busy",
+            ErrorInner::Loop(_) => "file system loop found",
+            ErrorInner::ThreadpoolBusy { .. } => "thread-pool busy",
+            ErrorInner::DeadlineExceeded { .. } => "walk deadline exceeded",
         }
     }
 
@@ -246,19 +390,38 @@ impl error::Error for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.inner {
-            ErrorInner::ThreadpoolBusy => f.write_str("rayon thread-pool too busy or dependency loop detected - aborting before possibility of deadlock"),
+            ErrorInner::ThreadpoolBusy {
+                busy_timeout,
+                num_threads,
+            } => {
+                f.write_str("rayon thread-pool too busy or dependency loop detected - aborting before possibility of deadlock")?;
+                if let Some(busy_timeout) = busy_timeout {
+                    write!(f, " (no thread available within {:?}", busy_timeout)?;
+                    if let Some(num_threads) = num_threads {
+                        write!(f, " on a pool of {} threads", num_threads)?;
+                    }
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+            ErrorInner::DeadlineExceeded { .. } => {
+                f.write_str("walk deadline exceeded before all entries were yielded")
+            }
             ErrorInner::Io {
                 path: None,
                 ref err,
+                ..
             } => err.fmt(f),
             ErrorInner::Io {
                 path: Some(ref path),
                 ref err,
+                ..
             } => write!(f, "IO error for operation on {}: {}", path.display(), err),
-            ErrorInner::Loop {
+            ErrorInner::Loop(LoopDetected {
                 ref ancestor,
                 ref child,
-            } => write!(
+                ..
+            }) => write!(
                 f,
                 "File system loop found: \
                  {} points to an ancestor {}",
@@ -288,13 +451,17 @@ impl From<Error> for io::Error {
                 ..
             } => err.kind(),
             Error {
-                inner: ErrorInner::Loop { .. },
+                inner: ErrorInner::Loop(_),
                 ..
             } => io::ErrorKind::Other,
             Error {
-                inner: ErrorInner::ThreadpoolBusy,
+                inner: ErrorInner::ThreadpoolBusy { .. },
                 ..
             } => io::ErrorKind::Other,
+            Error {
+                inner: ErrorInner::DeadlineExceeded { .. },
+                ..
+            } => io::ErrorKind::TimedOut,
         };
         io::Error::new(kind, walk_err)
     }
@@ -0,0 +1,165 @@
+use std::ffi::{CString, OsStr};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+
+use crate::file_system::{FileSystemEntry, FileType, FileTypeSource, SendReadDirEntries};
+
+// Size of the buffer entries are read into, large enough for thousands of
+// entries per syscall
+const BUFFER_SIZE: usize = 256 * 1024;
+
+// Offsets in `struct linux_dirent64`
+const RECLEN_OFFSET: usize = 16;
+const TYPE_OFFSET: usize = 18;
+const NAME_OFFSET: usize = 19;
+
+// Values of `d_type`
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+
+/// Reads the entries of the directory at `path` with the `getdents64`
+/// syscall. Entries of other types than directories, regular files and
+/// symlinks, or whose type isn't returned by the file system, are stat'ed.
+pub(crate) fn read_dir(path: &Path) -> io::Result<SendReadDirEntries> {
+    read_dir_with(path, stat_file_types)
+}
+
+/// Reads the entries of the directory at `path` with the `getdents64`
+/// syscall, calling `resolve_file_types` with the indices of entries whose
+/// type must be stat'ed.
+pub(crate) fn read_dir_with(
+    path: &Path,
+    resolve_file_types: ResolveFileTypes,
+) -> io::Result<SendReadDirEntries> {
+    read_open_dir(&File::open(path)?, path, resolve_file_types)
+}
+
+/// Reads the entries of the directory opened as `dir` at `path` from the
+/// current offset of `dir`, like
+/// [`read_dir_with`](fn.read_dir_with.html).
+pub(crate) fn read_open_dir(
+    dir: &File,
+    path: &Path,
+    resolve_file_types: ResolveFileTypes,
+) -> io::Result<SendReadDirEntries> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut entries = Vec::new();
+    let mut unknown = Vec::new();
+    loop {
+        // SAFETY: `dir` is a valid open file descriptor and the kernel
+        // writes at most `buffer.len()` bytes to `buffer`.
+        let read = unsafe {
+            libc::syscall(
+                libc::SYS_getdents64,
+                dir.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if read < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if read == 0 {
+            resolve_file_types(dir, path, &mut entries, &unknown);
+            return Ok(Box::new(entries.into_iter().map(Ok)));
+        }
+
+        let mut records = &buffer[..read as usize];
+        while records.len() > NAME_OFFSET {
+            let reclen = u16::from_ne_bytes([records[RECLEN_OFFSET], records[RECLEN_OFFSET + 1]]);
+            let (record, rest) = records.split_at(usize::from(reclen).min(records.len()));
+            records = rest;
+
+            let name = &record[NAME_OFFSET..];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            if name == b"." || name == b".." {
+                continue;
+            }
+            let file_type = match record[TYPE_OFFSET] {
+                DT_DIR => Ok(FileType::dir()),
+                DT_REG => Ok(FileType::file()),
+                DT_LNK => Ok(FileType::symlink()),
+                _ => {
+                    // Replaced by `resolve_file_types`
+                    unknown.push(entries.len());
+                    Err(io::ErrorKind::NotFound.into())
+                }
+            };
+            entries.push(FileSystemEntry {
+                file_name: OsStr::from_bytes(name).to_os_string(),
+                file_type,
+            });
+        }
+    }
+}
+
+/// Sets the types of the `entries` at `unknown`, read from `dir` at `path`.
+pub(crate) type ResolveFileTypes = fn(&File, &Path, &mut [FileSystemEntry], &[usize]);
+
+/// Sets the types of the `entries` at `unknown` with a stat per entry,
+/// relative to `dir`.
+pub(crate) fn stat_file_types(
+    dir: &File,
+    _path: &Path,
+    entries: &mut [FileSystemEntry],
+    unknown: &[usize],
+) {
+    for &index in unknown {
+        let entry = &mut entries[index];
+        entry.file_type = stat_file_type_at(dir, &entry.file_name);
+    }
+}
+
+/// The type of the entry `file_name` of the directory opened as `dir`,
+/// without following symlinks.
+pub(crate) fn stat_file_type_at(dir: &File, file_name: &OsStr) -> io::Result<FileType> {
+    let name = CString::new(file_name.as_bytes())?;
+    let mut stat = mem::MaybeUninit::<libc::stat>::uninit();
+    // SAFETY: `dir` is a valid open file descriptor, `name` is NUL-terminated
+    // and the kernel initializes `stat` if the call succeeds.
+    let result = unsafe {
+        libc::fstatat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            stat.as_mut_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: initialized by the successful call
+    let file_type = match unsafe { stat.assume_init() }.st_mode & libc::S_IFMT {
+        libc::S_IFDIR => FileType::dir(),
+        libc::S_IFREG => FileType::file(),
+        libc::S_IFLNK => FileType::symlink(),
+        // Other types can only be read from `std::fs::Metadata`, of the entry
+        // opened without following it or blocking on it
+        _ => {
+            // SAFETY: as above
+            let fd = unsafe {
+                libc::openat(
+                    dir.as_raw_fd(),
+                    name.as_ptr(),
+                    libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: `fd` was just opened and isn't owned by anything else
+            let entry = unsafe { File::from_raw_fd(fd) };
+            FileType::from(entry.metadata()?.file_type())
+        }
+    };
+    Ok(file_type.with_source(FileTypeSource::Stat))
+}
@@ -0,0 +1,61 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf, Prefix};
+
+/// The extended-length form of `path`, starting with `\\?\`, so paths below
+/// it aren't limited to `MAX_PATH` characters. Relative paths are made
+/// absolute and `.` and `..` components are removed, as Windows doesn't
+/// resolve them in extended-length paths.
+///
+/// Returns `path` unchanged if it already is an extended-length or device
+/// path, or if it can't be made absolute, such as drive-relative paths like
+/// `C:foo`. Paths on other platforms than Windows don't have prefixes and
+/// are always returned unchanged.
+pub(crate) fn to_verbatim(path: &Path) -> PathBuf {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match env::current_dir() {
+            Ok(current_dir) => current_dir.join(path),
+            Err(_) => return path.to_path_buf(),
+        }
+    };
+
+    let mut components = absolute.components();
+    let mut verbatim = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(drive) => {
+                let mut verbatim = OsString::from(r"\\?\");
+                verbatim.push(format!("{}:\\", char::from(drive)));
+                verbatim
+            }
+            Prefix::UNC(server, share) => {
+                let mut verbatim = OsString::from(r"\\?\UNC\");
+                verbatim.push(server);
+                verbatim.push(r"\");
+                verbatim.push(share);
+                verbatim.push(r"\");
+                verbatim
+            }
+            _ => return path.to_path_buf(),
+        },
+        _ => return path.to_path_buf(),
+    };
+    let mut names = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(name) => names.push(name),
+            Component::ParentDir => {
+                names.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    for (i, name) in names.into_iter().enumerate() {
+        if i > 0 {
+            verbatim.push(r"\");
+        }
+        verbatim.push(name);
+    }
+    PathBuf::from(verbatim)
+}
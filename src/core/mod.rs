@@ -1,29 +1,89 @@
+#[cfg(feature = "rayon")]
+mod adaptive;
+mod backpressure;
+mod cancel_token;
+mod concurrency_limit;
+mod device;
 mod dir_entry;
 mod dir_entry_iter;
+mod dir_handle;
 mod error;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod getdents;
 mod index_path;
+mod long_path;
+#[cfg(windows)]
+mod nt_query;
 mod ordered;
 mod ordered_queue;
+#[cfg(feature = "rayon")]
+mod par_entries;
 mod read_dir;
+mod read_dir_chunks;
 mod read_dir_iter;
 mod read_dir_spec;
+mod read_dir_steps;
+#[cfg(windows)]
+mod reparse;
 mod run_context;
+mod std_threads;
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+mod uring;
 
+#[cfg(feature = "rayon")]
 use rayon::prelude::*;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::Arc;
 use std::vec;
 
+#[cfg(feature = "rayon")]
+use adaptive::*;
 use index_path::*;
-use ordered::*;
 use ordered_queue::*;
 use read_dir_iter::*;
 use run_context::*;
+use std_threads::*;
 
+pub use backpressure::BufferLimit;
+pub use cancel_token::CancelToken;
+pub use concurrency_limit::ConcurrencyLimit;
+pub(crate) use concurrency_limit::WalkConcurrencyLimit;
 pub use dir_entry::DirEntry;
 pub use dir_entry_iter::DirEntryIter;
-pub use error::Error;
+pub use error::{Error, ErrorKind, LoopDetected};
+
+pub(crate) use error::Operation;
 pub use read_dir::ReadDir;
 pub use read_dir_spec::ReadDirSpec;
 
-use crate::{ClientState, Parallelism};
+pub(crate) use backpressure::Backpressure;
+pub(crate) use device::device_num;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use dir_handle::dir_handle_path;
+pub(crate) use dir_handle::DirFd;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use getdents::read_dir as getdents_read_dir;
+pub(crate) use long_path::to_verbatim;
+#[cfg(windows)]
+pub(crate) use nt_query::read_dir as nt_query_read_dir;
+pub(crate) use ordered::Ordered;
+pub(crate) use ordered_queue::ReceiveBuffer;
+#[cfg(feature = "rayon")]
+pub(crate) use par_entries::par_entries;
+pub(crate) use read_dir_chunks::{ChunkEntries, ReadDirChunks};
+pub(crate) use read_dir_iter::{
+    NewSchedulerCallback, ReadDirCallback, ReadDirPacing, ReadDirQueueing, ReadDirSchedule,
+    ReadDirSpecPriorityCallback, SpawnWalk,
+};
+#[cfg(feature = "rayon")]
+pub(crate) use read_dir_steps::is_hidden;
+pub(crate) use read_dir_steps::{
+    EntryFilter, EntryPreload, EntrySort, FollowLinks, MetadataFilter, ReadDirLimits,
+    ReadDirSource, ReadDirSteps, WalkRoot,
+};
+#[cfg(windows)]
+pub(crate) use reparse::reparse_tag;
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+pub(crate) use uring::read_dir as io_uring_read_dir;
+
+use crate::{BusyPolicy, ClientState, Parallelism, ReadDirOrder};
@@ -0,0 +1,170 @@
+use std::ffi::{c_void, OsString};
+use std::fs::OpenOptions;
+use std::io;
+use std::os::windows::ffi::OsStringExt;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use std::ptr;
+
+use crate::file_system::{read_std_dir, FileSystemEntry, FileType, ReparseTag, SendReadDirEntries};
+
+#[repr(C)]
+struct IoStatusBlock {
+    status: isize,
+    information: usize,
+}
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtQueryDirectoryFile(
+        file_handle: *mut c_void,
+        event: *mut c_void,
+        apc_routine: *mut c_void,
+        apc_context: *mut c_void,
+        io_status_block: *mut IoStatusBlock,
+        file_information: *mut c_void,
+        length: u32,
+        file_information_class: u32,
+        return_single_entry: u8,
+        file_name: *mut c_void,
+        restart_scan: u8,
+    ) -> i32;
+
+    fn RtlNtStatusToDosError(status: i32) -> u32;
+}
+
+// Required to open directories.
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+// `FILE_INFORMATION_CLASS` returning `FILE_ID_BOTH_DIR_INFORMATION` records
+const FILE_ID_BOTH_DIRECTORY_INFORMATION: u32 = 37;
+
+// Size of the buffer records are read into, the largest size supported by
+// SMB shares
+const BUFFER_SIZE: usize = 64 * 1024;
+
+const STATUS_NO_MORE_FILES: i32 = 0x8000_0006_u32 as i32;
+const STATUS_INVALID_INFO_CLASS: i32 = 0xC000_0003_u32 as i32;
+const STATUS_INVALID_PARAMETER: i32 = 0xC000_000D_u32 as i32;
+const STATUS_NOT_SUPPORTED: i32 = 0xC000_00BB_u32 as i32;
+
+// Offsets in `FILE_ID_BOTH_DIR_INFORMATION`
+const NEXT_ENTRY_OFFSET: usize = 0;
+const FILE_ATTRIBUTES_OFFSET: usize = 56;
+const FILE_NAME_LENGTH_OFFSET: usize = 60;
+const EA_SIZE_OFFSET: usize = 64;
+const FILE_NAME_OFFSET: usize = 104;
+
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+// Reparse tags of links, such as symlinks and junctions
+const REPARSE_TAG_NAME_SURROGATE: u32 = 0x2000_0000;
+
+/// Reads the entries of the directory at `path` with `NtQueryDirectoryFile`,
+/// returning many entries with their attributes per call. Falls back to
+/// `std::fs::read_dir` on file systems not supporting
+/// `FileIdBothDirectoryInformation`.
+pub(crate) fn read_dir(path: &Path) -> io::Result<SendReadDirEntries> {
+    let dir = OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+    // `u64`s to align records
+    let mut buffer = vec![0u64; BUFFER_SIZE / 8];
+    let mut entries = Vec::new();
+    let mut restart_scan = 1;
+    loop {
+        let mut io_status_block = IoStatusBlock {
+            status: 0,
+            information: 0,
+        };
+        // SAFETY: `dir` is a valid synchronous handle, so the call completes
+        // before returning, and `buffer` holds `BUFFER_SIZE` bytes.
+        let status = unsafe {
+            NtQueryDirectoryFile(
+                dir.as_raw_handle() as *mut c_void,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut io_status_block,
+                buffer.as_mut_ptr() as *mut c_void,
+                BUFFER_SIZE as u32,
+                FILE_ID_BOTH_DIRECTORY_INFORMATION,
+                0,
+                ptr::null_mut(),
+                restart_scan,
+            )
+        };
+        match status {
+            STATUS_NO_MORE_FILES => return Ok(Box::new(entries.into_iter().map(Ok))),
+            STATUS_INVALID_INFO_CLASS | STATUS_INVALID_PARAMETER | STATUS_NOT_SUPPORTED
+                if restart_scan == 1 =>
+            {
+                return read_std_dir(path);
+            }
+            status if status < 0 => {
+                // SAFETY: Only converts the status code.
+                let error = unsafe { RtlNtStatusToDosError(status) };
+                return Err(io::Error::from_raw_os_error(error as i32));
+            }
+            _ => {}
+        }
+        restart_scan = 0;
+
+        // SAFETY: `buffer` is `BUFFER_SIZE` bytes of initialized integers.
+        let records: &[u8] =
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr() as *const u8, BUFFER_SIZE) };
+        let mut offset = 0;
+        loop {
+            let record = &records[offset..];
+            let file_name_length = read_u32(record, FILE_NAME_LENGTH_OFFSET) as usize;
+            let file_name: Vec<u16> = record[FILE_NAME_OFFSET..FILE_NAME_OFFSET + file_name_length]
+                .chunks_exact(2)
+                .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+                .collect();
+            if file_name != [u16::from(b'.')] && file_name != [u16::from(b'.'); 2] {
+                entries.push(FileSystemEntry {
+                    file_name: OsString::from_wide(&file_name),
+                    file_type: Ok(file_type(
+                        read_u32(record, FILE_ATTRIBUTES_OFFSET),
+                        read_u32(record, EA_SIZE_OFFSET),
+                    )),
+                });
+            }
+
+            let next_entry_offset = read_u32(record, NEXT_ENTRY_OFFSET) as usize;
+            if next_entry_offset == 0 {
+                break;
+            }
+            offset += next_entry_offset;
+        }
+    }
+}
+
+fn read_u32(record: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([
+        record[offset],
+        record[offset + 1],
+        record[offset + 2],
+        record[offset + 3],
+    ])
+}
+
+// The type of an entry with `attributes`, whose `EaSize` holds the reparse tag
+// of reparse points. Links are symlinks like in `std::fs::FileType`.
+fn file_type(attributes: u32, reparse_tag: u32) -> FileType {
+    let is_reparse_point = attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0;
+    let file_type = if is_reparse_point && reparse_tag & REPARSE_TAG_NAME_SURROGATE != 0 {
+        FileType::symlink()
+    } else if attributes & FILE_ATTRIBUTE_DIRECTORY != 0 {
+        FileType::dir()
+    } else {
+        FileType::file()
+    };
+    file_type.with_reparse_tag(if is_reparse_point {
+        Some(ReparseTag::from_raw(reparse_tag))
+    } else {
+        None
+    })
+}
@@ -1,4 +1,4 @@
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
 
 use super::index_path::IndexPath;
 
@@ -6,6 +6,9 @@ pub struct Ordered<T> {
     pub value: T,
     pub index_path: IndexPath,
     pub(crate) child_count: usize,
+    pub(crate) priority: usize,
+    // enqueue order, only set when reading dirs in FIFO order
+    pub(crate) sequence: usize,
 }
 
 impl<T> Ordered<T> {
@@ -14,13 +17,27 @@ impl<T> Ordered<T> {
             value,
             index_path,
             child_count,
+            priority: 0,
+            sequence: 0,
         }
     }
+
+    pub fn with_priority(mut self, priority: usize) -> Ordered<T> {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: usize) -> Ordered<T> {
+        self.sequence = sequence;
+        self
+    }
 }
 
 impl<T> PartialEq for Ordered<T> {
     fn eq(&self, o: &Self) -> bool {
-        self.index_path.eq(&o.index_path)
+        self.priority == o.priority
+            && self.sequence == o.sequence
+            && self.index_path.eq(&o.index_path)
     }
 }
 
@@ -28,12 +45,15 @@ impl<T> Eq for Ordered<T> {}
 
 impl<T> PartialOrd for Ordered<T> {
     fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
-        Some(self.index_path.cmp(&o.index_path))
+        Some(self.cmp(o))
     }
 }
 
 impl<T> Ord for Ordered<T> {
     fn cmp(&self, o: &Self) -> Ordering {
-        self.index_path.cmp(&o.index_path)
+        self.priority
+            .cmp(&o.priority)
+            .then_with(|| Reverse(self.sequence).cmp(&Reverse(o.sequence)))
+            .then_with(|| self.index_path.cmp(&o.index_path))
     }
 }
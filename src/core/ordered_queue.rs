@@ -30,10 +30,19 @@ where
     stop: Arc<AtomicBool>,
     receiver: Receiver<Ordered<T>>,
     receive_buffer: BinaryHeap<Ordered<T>>,
+    // replaces `receive_buffer` for relaxed ordering if set
+    scheduler: Option<Box<dyn ReceiveBuffer<T>>>,
     pending_count: Arc<AtomicUsize>,
     ordered_matcher: OrderedMatcher,
 }
 
+/// Buffer of the received items of a relaxed queue, deciding which one is
+/// yielded next.
+pub(crate) trait ReceiveBuffer<T>: Send {
+    fn push(&mut self, ordered: Ordered<T>);
+    fn pop(&mut self) -> Option<Ordered<T>>;
+}
+
 struct OrderedMatcher {
     looking_for: IndexPath,
     child_count_stack: Vec<usize>,
@@ -42,6 +51,7 @@ struct OrderedMatcher {
 pub(crate) fn new_ordered_queue<T>(
     stop: Arc<AtomicBool>,
     ordering: Ordering,
+    root_count: usize,
 ) -> (OrderedQueue<T>, OrderedQueueIter<T>)
 where
     T: Send,
@@ -57,8 +67,9 @@ where
         OrderedQueueIter {
             ordering,
             receiver,
-            ordered_matcher: OrderedMatcher::default(),
+            ordered_matcher: OrderedMatcher::new(root_count),
             receive_buffer: BinaryHeap::new(),
+            scheduler: None,
             pending_count,
             stop,
         },
@@ -77,6 +88,12 @@ where
     pub fn complete_item(&self) {
         self.pending_count.fetch_sub(1, AtomicOrdering::SeqCst);
     }
+
+    /// Items that were pushed but not completed yet.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn pending_count(&self) -> usize {
+        self.pending_count.load(AtomicOrdering::SeqCst)
+    }
 }
 
 impl<T> Clone for OrderedQueue<T>
@@ -96,6 +113,20 @@ impl<T> OrderedQueueIter<T>
 where
     T: Send,
 {
+    /// Ends once the items pending in `queue` were completed, instead of the
+    /// items pushed to this queue, for relaxed ordering.
+    pub(crate) fn pending_while<U: Send>(mut self, queue: &OrderedQueue<U>) -> Self {
+        self.pending_count = queue.pending_count.clone();
+        self
+    }
+
+    /// Yields received items in the order of `scheduler`, for relaxed
+    /// ordering.
+    pub(crate) fn scheduled_by(mut self, scheduler: Box<dyn ReceiveBuffer<T>>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
     fn pending_count(&self) -> usize {
         self.pending_count.load(AtomicOrdering::SeqCst)
     }
@@ -104,19 +135,38 @@ where
         self.stop.load(AtomicOrdering::SeqCst)
     }
 
+    /// Next item if one is available, without waiting for pending items.
+    pub(crate) fn try_next(&mut self) -> Result<Ordered<T>, TryRecvError> {
+        match self.ordering {
+            Ordering::Relaxed => self.try_next_relaxed(),
+            Ordering::Strict => self.try_next_strict(),
+        }
+    }
+
     fn try_next_relaxed(&mut self) -> Result<Ordered<T>, TryRecvError> {
         if self.is_stop() {
             return Err(TryRecvError::Disconnected);
         }
 
         while let Ok(ordered_work) = self.receiver.try_recv() {
-            self.receive_buffer.push(ordered_work)
+            match self.scheduler.as_mut() {
+                Some(scheduler) => scheduler.push(ordered_work),
+                None => self.receive_buffer.push(ordered_work),
+            }
         }
 
-        if let Some(ordered_work) = self.receive_buffer.pop() {
+        let next = match self.scheduler.as_mut() {
+            Some(scheduler) => scheduler.pop(),
+            None => self.receive_buffer.pop(),
+        };
+        if let Some(ordered_work) = next {
             Ok(ordered_work)
         } else if self.pending_count() == 0 {
-            Err(TryRecvError::Disconnected)
+            // Items might have been sent right before the last pending item
+            // was completed
+            self.receiver
+                .try_recv()
+                .map_err(|_| TryRecvError::Disconnected)
         } else {
             Err(TryRecvError::Empty)
         }
@@ -165,11 +215,7 @@ where
     type Item = Ordered<T>;
     fn next(&mut self) -> Option<Ordered<T>> {
         loop {
-            let try_next = match self.ordering {
-                Ordering::Relaxed => self.try_next_relaxed(),
-                Ordering::Strict => self.try_next_strict(),
-            };
-            match try_next {
+            match self.try_next() {
                 Ok(next) => {
                     return Some(next);
                 }
@@ -211,11 +257,12 @@ impl OrderedMatcher {
     }
 }
 
-impl Default for OrderedMatcher {
-    fn default() -> OrderedMatcher {
+impl OrderedMatcher {
+    // Matches `root_count` top level items indexed from `[0]`.
+    fn new(root_count: usize) -> OrderedMatcher {
         OrderedMatcher {
             looking_for: IndexPath::new(vec![0]),
-            child_count_stack: vec![1],
+            child_count_stack: vec![root_count],
         }
     }
 }
@@ -0,0 +1,141 @@
+use rayon::iter::{self, ParallelIterator};
+
+use super::*;
+use crate::Result;
+
+/// Entry waiting to be yielded, with the spec for reading its children.
+struct PendingEntry<C: ClientState> {
+    // `None` if only the next chunk of a directory is read
+    dir_entry_result: Option<Result<DirEntry<C>>>,
+    read_children_spec: Option<ReadDirSpec<C>>,
+}
+
+/// Part of a walk that is split off to other threads while there is more
+/// than one pending entry, and walked depth first otherwise.
+struct WalkPart<C: ClientState> {
+    pending: Vec<PendingEntry<C>>,
+    min_depth: usize,
+    core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    concurrency_limit: Option<WalkConcurrencyLimit>,
+}
+
+/// Yields entries as a rayon `ParallelIterator`, in no particular order.
+pub(crate) fn par_entries<C: ClientState>(
+    root_entry_results: Vec<Result<DirEntry<C>>>,
+    min_depth: usize,
+    root_read_dir_state: C::ReadDirState,
+    core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    concurrency_limit: Option<WalkConcurrencyLimit>,
+) -> impl ParallelIterator<Item = Result<DirEntry<C>>> {
+    let pending = root_entry_results
+        .into_iter()
+        .rev()
+        .map(|dir_entry_result| PendingEntry::new(dir_entry_result, &root_read_dir_state))
+        .collect();
+    let walk_part = WalkPart {
+        pending,
+        min_depth,
+        core_read_dir_callback,
+        concurrency_limit,
+    };
+    iter::split(walk_part, WalkPart::split).flat_map_iter(|walk_part| walk_part)
+}
+
+impl<C: ClientState> PendingEntry<C> {
+    fn new(dir_entry_result: Result<DirEntry<C>>, read_dir_state: &C::ReadDirState) -> Self {
+        let read_children_spec = dir_entry_result
+            .as_ref()
+            .ok()
+            .and_then(|dir_entry| dir_entry.read_children_spec(read_dir_state.clone()));
+        PendingEntry {
+            dir_entry_result: Some(dir_entry_result),
+            read_children_spec,
+        }
+    }
+
+    fn next_chunk(read_dir_spec: ReadDirSpec<C>) -> Self {
+        PendingEntry {
+            dir_entry_result: None,
+            read_children_spec: Some(read_dir_spec),
+        }
+    }
+}
+
+impl<C: ClientState> WalkPart<C> {
+    fn split(mut self) -> (Self, Option<Self>) {
+        // Read the only pending directory so its children can be split
+        if self.pending.len() == 1 {
+            let mut pending = self.pending.pop().unwrap();
+            self.read_children(&mut pending);
+            self.pending.push(pending);
+        }
+        if self.pending.len() < 2 {
+            return (self, None);
+        }
+        let other = WalkPart {
+            pending: self.pending.split_off(self.pending.len() / 2),
+            min_depth: self.min_depth,
+            core_read_dir_callback: self.core_read_dir_callback.clone(),
+            concurrency_limit: self.concurrency_limit.clone(),
+        };
+        (self, Some(other))
+    }
+
+    // Reads the children of `pending`, pushing them and the next chunk of
+    // the directory in front of the other pending entries. Read errors are
+    // stored in `read_children_error`.
+    fn read_children(&mut self, pending: &mut PendingEntry<C>) {
+        let read_children_spec = match pending.read_children_spec.take() {
+            Some(read_children_spec) => read_children_spec,
+            None => return,
+        };
+        let read_dir_result = (self.core_read_dir_callback)(read_children_spec);
+        if let Some(concurrency_limit) = &self.concurrency_limit {
+            concurrency_limit.consumed();
+        }
+        match read_dir_result {
+            Ok(read_dir) => {
+                let next_chunk = read_dir
+                    .chunks
+                    .as_ref()
+                    .map(|chunks| chunks.read_dir_spec(read_dir.read_dir_state.clone()));
+                let ReadDir {
+                    read_dir_state,
+                    results_list,
+                    ..
+                } = read_dir;
+                // Children are popped from the end, in order
+                let index = self.pending.len();
+                self.pending.extend(
+                    results_list.into_iter().map(|dir_entry_result| {
+                        PendingEntry::new(dir_entry_result, &read_dir_state)
+                    }),
+                );
+                self.pending
+                    .extend(next_chunk.map(PendingEntry::next_chunk));
+                self.pending[index..].reverse();
+            }
+            Err(err) => match pending.dir_entry_result.as_mut() {
+                Some(Ok(dir_entry)) => dir_entry.read_children_error = Some(err),
+                Some(Err(_)) => {}
+                None => pending.dir_entry_result = Some(Err(err)),
+            },
+        }
+    }
+}
+
+impl<C: ClientState> Iterator for WalkPart<C> {
+    type Item = Result<DirEntry<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut pending = self.pending.pop()?;
+            self.read_children(&mut pending);
+            match pending.dir_entry_result {
+                None => {}
+                Some(Ok(dir_entry)) if dir_entry.depth < self.min_depth || dir_entry.skip_yield => {
+                }
+                Some(dir_entry_result) => return Some(dir_entry_result),
+            }
+        }
+    }
+}
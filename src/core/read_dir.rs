@@ -1,4 +1,6 @@
-use super::{ClientState, DirEntry, IndexPath, Ordered, ReadDirSpec};
+use std::sync::Arc;
+
+use super::{ClientState, DirEntry, IndexPath, Ordered, ReadDirChunks, ReadDirSpec};
 use crate::Result;
 
 /// Results of successfully reading a directory.
@@ -6,6 +8,9 @@ use crate::Result;
 pub struct ReadDir<C: ClientState> {
     pub(crate) read_dir_state: C::ReadDirState,
     pub(crate) results_list: Vec<Result<DirEntry<C>>>,
+    // Remaining entries if the directory is read in chunks, read after the
+    // children of this chunk.
+    pub(crate) chunks: Option<Arc<ReadDirChunks>>,
 }
 
 impl<C: ClientState> ReadDir<C> {
@@ -16,15 +21,28 @@ impl<C: ClientState> ReadDir<C> {
         ReadDir {
             read_dir_state,
             results_list,
+            chunks: None,
         }
     }
 
+    pub(crate) fn with_chunks(mut self, chunks: Option<Arc<ReadDirChunks>>) -> ReadDir<C> {
+        self.chunks = chunks;
+        self
+    }
+
     pub fn read_children_specs(&self) -> impl Iterator<Item = ReadDirSpec<C>> + '_ {
-        self.results_list.iter().filter_map(move |each| {
-            each.as_ref()
-                .ok()?
-                .read_children_spec(self.read_dir_state.clone())
-        })
+        self.results_list
+            .iter()
+            .filter_map(move |each| {
+                each.as_ref()
+                    .ok()?
+                    .read_children_spec(self.read_dir_state.clone())
+            })
+            .chain(
+                self.chunks
+                    .iter()
+                    .map(move |chunks| chunks.read_dir_spec(self.read_dir_state.clone())),
+            )
     }
 
     pub fn ordered_read_children_specs(
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{DirFd, ReadDirSpec};
+use crate::file_system::{FileSystemEntry, SendReadDirEntries};
+#[cfg(feature = "filter")]
+use crate::filter::DirFilterRules;
+use crate::ClientState;
+
+/// Entries of a directory read in chunks that weren't processed yet, with
+/// what was prepared to process them when the directory was first read.
+#[derive(Debug)]
+pub(crate) struct ReadDirChunks {
+    pub(crate) path: Arc<Path>,
+    pub(crate) depth: usize,
+    pub(crate) priority: usize,
+    // only taken by the read of the next chunk
+    pub(crate) fs_entries: Mutex<ChunkEntries>,
+    pub(crate) follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    #[cfg(feature = "filter")]
+    pub(crate) filter_rules: Option<Arc<DirFilterRules>>,
+    #[cfg(feature = "filter")]
+    pub(crate) children_filter_rules: Option<Arc<DirFilterRules>>,
+    pub(crate) canonical_path: Option<PathBuf>,
+    pub(crate) dir_fd: Option<Arc<DirFd>>,
+}
+
+impl ReadDirChunks {
+    /// Whether filter rules apply to the entries.
+    #[cfg(feature = "filter")]
+    pub(crate) fn has_filter_rules(&self) -> bool {
+        self.filter_rules.is_some()
+    }
+
+    #[cfg(not(feature = "filter"))]
+    pub(crate) fn has_filter_rules(&self) -> bool {
+        false
+    }
+
+    /// Whether the filter rules exclude the entry at `relative_path`.
+    #[cfg(feature = "filter")]
+    pub(crate) fn excludes(&self, relative_path: &Path, is_dir: bool) -> bool {
+        self.filter_rules
+            .as_ref()
+            .is_some_and(|filter_rules| filter_rules.is_excluded(relative_path, is_dir))
+    }
+
+    #[cfg(not(feature = "filter"))]
+    pub(crate) fn excludes(&self, _relative_path: &Path, _is_dir: bool) -> bool {
+        false
+    }
+
+    /// Takes the next `chunk_size` entries, and whether more are left.
+    pub(crate) fn next_chunk(&self, chunk_size: usize) -> (Vec<io::Result<FileSystemEntry>>, bool) {
+        self.fs_entries.lock().unwrap().next_chunk(chunk_size)
+    }
+
+    /// Spec reading the next chunk with the `ReadDirState` left by the
+    /// previous one.
+    pub(crate) fn read_dir_spec<C: ClientState>(
+        self: &Arc<Self>,
+        client_read_state: C::ReadDirState,
+    ) -> ReadDirSpec<C> {
+        ReadDirSpec {
+            depth: self.depth,
+            path: self.path.clone(),
+            client_read_state,
+            priority: self.priority,
+            follow_link_ancestors: self.follow_link_ancestors.clone(),
+            #[cfg(feature = "filter")]
+            filter_rules: None,
+            parent_dir_fd: None,
+            chunks: Some(self.clone()),
+        }
+    }
+}
+
+/// Entries of a directory read in chunks, read from the directory as chunks
+/// are taken.
+pub(crate) struct ChunkEntries(Peekable<SendReadDirEntries>);
+
+impl ChunkEntries {
+    pub(crate) fn new(fs_entries: SendReadDirEntries) -> ChunkEntries {
+        ChunkEntries(fs_entries.peekable())
+    }
+
+    /// Takes the next `chunk_size` entries, and whether more are left.
+    pub(crate) fn next_chunk(
+        &mut self,
+        chunk_size: usize,
+    ) -> (Vec<io::Result<FileSystemEntry>>, bool) {
+        let chunk = self.0.by_ref().take(chunk_size).collect();
+        (chunk, self.0.peek().is_some())
+    }
+}
+
+impl Default for ChunkEntries {
+    fn default() -> ChunkEntries {
+        ChunkEntries::new(Box::new(std::iter::empty()))
+    }
+}
+
+impl fmt::Debug for ChunkEntries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkEntries").finish_non_exhaustive()
+    }
+}
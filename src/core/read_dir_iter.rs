@@ -1,12 +1,62 @@
 use std::sync::Arc;
 
 use super::*;
+use crate::scheduler::Scheduler;
 use crate::Result;
 
 /// Client's read dir function.
 pub(crate) type ReadDirCallback<C> =
     dyn Fn(ReadDirSpec<C>) -> Result<ReadDir<C>> + Send + Sync + 'static;
 
+/// Scheduling priority of a pending read dir, higher priorities are read first.
+pub(crate) type ReadDirSpecPriorityCallback<C> =
+    dyn Fn(&ReadDirSpec<C>) -> usize + Send + Sync + 'static;
+
+/// Creates the scheduler of the pending read dirs of a walk.
+pub(crate) type NewSchedulerCallback<C> = dyn Fn() -> Box<dyn Scheduler<C>> + Send + Sync + 'static;
+
+/// Spawns the parallel walk onto a caller provided rayon scope or pool.
+pub(crate) type SpawnWalk<'a> = dyn Fn(Box<dyn FnOnce() + Send>) + 'a;
+
+/// How each read dir task is run on the thread-pool.
+#[derive(Clone, Copy)]
+pub(crate) struct ReadDirPacing {
+    #[cfg(feature = "rayon")]
+    pub(crate) yield_between_dirs: bool,
+    pub(crate) order: ReadDirOrder,
+    pub(crate) buffer_limit: Option<BufferLimit>,
+    // yield read dirs in walk order rather than as soon as they were read
+    pub(crate) strict_order: bool,
+}
+
+/// How read dirs are scheduled.
+pub(crate) struct ReadDirSchedule<'a, C: ClientState> {
+    pub(crate) parallelism: Parallelism,
+    pub(crate) busy_policy: BusyPolicy,
+    // spawns instead of onto the parallelism's thread-pool
+    pub(crate) spawn_walk: Option<&'a SpawnWalk<'a>>,
+    pub(crate) pacing: ReadDirPacing,
+    pub(crate) concurrency_limit: Option<WalkConcurrencyLimit>,
+    pub(crate) cancel_token: Option<CancelToken>,
+    pub(crate) queueing: ReadDirQueueing<C>,
+}
+
+/// How pending read dirs are prioritized and ordered.
+pub(crate) struct ReadDirQueueing<C: ClientState> {
+    pub(crate) read_dir_spec_priority: Option<Arc<ReadDirSpecPriorityCallback<C>>>,
+    // `DepthFirst` if not set
+    pub(crate) new_scheduler: Option<Arc<NewSchedulerCallback<C>>>,
+}
+
+impl<C: ClientState> Clone for ReadDirQueueing<C> {
+    fn clone(&self) -> Self {
+        ReadDirQueueing {
+            read_dir_spec_priority: self.read_dir_spec_priority.clone(),
+            new_scheduler: self.new_scheduler.clone(),
+        }
+    }
+}
+
 /// Result<ReadDir> Iterator.
 ///
 /// Yields ReadDirs (results of fs::read_dir) in order required for recursive
@@ -19,6 +69,7 @@ pub enum ReadDirIter<C: ClientState> {
     },
     ParWalk {
         read_dir_result_iter: OrderedQueueIter<Result<ReadDir<C>>>,
+        backpressure: Option<Arc<Backpressure>>,
     },
 }
 
@@ -26,7 +77,11 @@ impl<C: ClientState> ReadDirIter<C> {
     pub(crate) fn try_new(
         read_dir_specs: Vec<ReadDirSpec<C>>,
         parallelism: Parallelism,
+        busy_timeout: Option<std::time::Duration>,
+        pacing: ReadDirPacing,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
+        queueing: ReadDirQueueing<C>,
+        spawn_walk: Option<&SpawnWalk<'_>>,
     ) -> Option<Self> {
         if let Parallelism::Serial = parallelism {
             ReadDirIter::Walk {
@@ -35,50 +90,95 @@ impl<C: ClientState> ReadDirIter<C> {
             }
         } else {
             let stop = Arc::new(AtomicBool::new(false));
-            let read_dir_result_queue = new_ordered_queue(stop.clone(), Ordering::Strict);
+            let root_count = read_dir_specs.len();
+            let read_dir_spec_queue =
+                new_ordered_queue(stop.clone(), Ordering::Relaxed, root_count);
+            let (read_dir_spec_queue, mut read_dir_spec_iter) = read_dir_spec_queue;
+            if let Some(new_scheduler) = queueing.new_scheduler {
+                read_dir_spec_iter = read_dir_spec_iter.scheduled_by(Box::new(new_scheduler()));
+            }
+            let read_dir_result_queue = if pacing.strict_order {
+                new_ordered_queue(stop.clone(), Ordering::Strict, root_count)
+            } else {
+                // Results are yielded as they arrive until all specs were read
+                let (queue, iter) = new_ordered_queue(stop.clone(), Ordering::Relaxed, root_count);
+                (queue, iter.pending_while(&read_dir_spec_queue))
+            };
             let (read_dir_result_queue, read_dir_result_iter) = read_dir_result_queue;
-            let read_dir_spec_queue = new_ordered_queue(stop.clone(), Ordering::Relaxed);
-            let (read_dir_spec_queue, read_dir_spec_iter) = read_dir_spec_queue;
 
             for (i, read_dir_spec) in read_dir_specs.into_iter().enumerate() {
+                let priority = read_dir_spec.priority;
                 read_dir_spec_queue
-                    .push(Ordered::new(read_dir_spec, IndexPath::new(vec![0]), i))
+                    .push(
+                        Ordered::new(read_dir_spec, IndexPath::new(vec![i]), 0)
+                            .with_priority(priority),
+                    )
                     .unwrap();
             }
 
+            let backpressure = pacing
+                .buffer_limit
+                .map(|limit| Arc::new(Backpressure::new(limit)));
             let run_context = RunContext {
                 stop,
                 read_dir_spec_queue,
                 read_dir_result_queue,
                 core_read_dir_callback,
+                read_dir_spec_priority: queueing.read_dir_spec_priority,
+                pacing,
+                enqueued: Arc::new(AtomicUsize::new(0)),
+                backpressure: backpressure.clone(),
             };
 
-            let (startup_tx, startup_rx) = parallelism
-                .timeout()
+            let (startup_tx, startup_rx) = busy_timeout
                 .map(|duration| {
                     let (tx, rx) = crossbeam::channel::unbounded();
                     (Some(tx), Some((rx, duration)))
                 })
                 .unwrap_or((None, None));
-            parallelism.spawn(move || {
+            #[cfg(feature = "rayon")]
+            let adaptive_max_threads = match parallelism {
+                Parallelism::Adaptive { max_threads, .. } => Some(max_threads),
+                _ => None,
+            };
+            let std_threads = parallelism.std_threads();
+            let walk = move || {
                 if let Some(tx) = startup_tx {
                     if tx.send(()).is_err() {
                         // rayon didn't install this function in time so the listener exited. Do the same.
                         return;
                     }
                 }
-                read_dir_spec_iter.par_bridge().for_each_with(
-                    run_context,
-                    |run_context, ordered_read_dir_spec| {
-                        multi_threaded_walk_dir(ordered_read_dir_spec, run_context);
-                    },
-                );
-            });
+                #[cfg(feature = "rayon")]
+                if let Some(max_threads) = adaptive_max_threads {
+                    AdaptiveWalk::run(read_dir_spec_iter, run_context, max_threads);
+                    return;
+                }
+                match std_threads {
+                    Some(num_threads) => {
+                        walk_on_std_threads(read_dir_spec_iter, run_context, num_threads)
+                    }
+                    #[cfg(feature = "rayon")]
+                    None => read_dir_spec_iter.par_bridge().for_each_with(
+                        run_context,
+                        |run_context, ordered_read_dir_spec| {
+                            multi_threaded_walk_dir(ordered_read_dir_spec, run_context);
+                        },
+                    ),
+                    #[cfg(not(feature = "rayon"))]
+                    None => unreachable!("only std threads walk in parallel without rayon"),
+                }
+            };
+            match spawn_walk {
+                Some(spawn_walk) => spawn_walk(Box::new(walk)),
+                None => parallelism.spawn(walk),
+            }
             if startup_rx.map_or(false, |(rx, duration)| rx.recv_timeout(duration).is_err()) {
                 return None;
             }
             ReadDirIter::ParWalk {
                 read_dir_result_iter,
+                backpressure,
             }
         }
         .into()
@@ -112,14 +212,40 @@ impl<C: ClientState> Iterator for ReadDirIter<C> {
 
             ReadDirIter::ParWalk {
                 read_dir_result_iter,
-            } => read_dir_result_iter
-                .next()
-                .map(|read_dir_result| read_dir_result.value),
+                backpressure,
+            } => {
+                let backpressure = match backpressure {
+                    Some(backpressure) => backpressure,
+                    None => {
+                        return read_dir_result_iter
+                            .next()
+                            .map(|read_dir_result| read_dir_result.value)
+                    }
+                };
+                backpressure.consumer_waiting(true);
+                let read_dir_result = read_dir_result_iter.next();
+                backpressure.consumer_waiting(false);
+                let read_dir_result = read_dir_result?.value;
+                backpressure.consumed(&read_dir_result);
+                Some(read_dir_result)
+            }
         }
     }
 }
 
-fn multi_threaded_walk_dir<C: ClientState>(
+impl<C: ClientState> Drop for ReadDirIter<C> {
+    fn drop(&mut self) {
+        if let ReadDirIter::ParWalk {
+            backpressure: Some(backpressure),
+            ..
+        } = self
+        {
+            backpressure.stop();
+        }
+    }
+}
+
+pub(crate) fn multi_threaded_walk_dir<C: ClientState>(
     ordered_read_dir_spec: Ordered<ReadDirSpec<C>>,
     run_context: &mut RunContext<C>,
 ) {
@@ -129,7 +255,19 @@ fn multi_threaded_walk_dir<C: ClientState>(
         ..
     } = ordered_read_dir_spec;
 
+    #[cfg(feature = "rayon")]
+    if run_context.pacing.yield_between_dirs {
+        // Run at most one other job so the walk only ever lags behind.
+        rayon::yield_now();
+    }
+
+    if let Some(backpressure) = &run_context.backpressure {
+        backpressure.wait_for_capacity();
+    }
     let read_dir_result = (run_context.core_read_dir_callback)(read_dir_spec);
+    if let Some(backpressure) = &run_context.backpressure {
+        backpressure.produced(&read_dir_result);
+    }
     let ordered_read_children_specs = read_dir_result
         .as_ref()
         .ok()
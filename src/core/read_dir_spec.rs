@@ -1,6 +1,9 @@
 use std::path::Path;
 use std::sync::Arc;
 
+use super::{DirFd, ReadDirChunks};
+#[cfg(feature = "filter")]
+use crate::filter::DirFilterRules;
 use crate::ClientState;
 
 /// Specification for reading a directory.
@@ -22,6 +25,16 @@ pub struct ReadDirSpec<C: ClientState> {
     /// when reading this directory's parent. One intended use case is to store
     /// `.gitignore` state to filter entries during the walk.
     pub client_read_state: C::ReadDirState,
+    // Scheduling priority, see `DirEntry::read_children_priority`.
+    pub(crate) priority: usize,
     // Origins of symlinks followed to get to this entry.
     pub(crate) follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    // Filter rules inherited from the parent directory.
+    #[cfg(feature = "filter")]
+    pub(crate) filter_rules: Option<Arc<DirFilterRules>>,
+    // Open parent directory to open this one relative to, if `dir_fds` is
+    // enabled.
+    pub(crate) parent_dir_fd: Option<Arc<DirFd>>,
+    // Remaining entries if this reads the next chunk of a directory.
+    pub(crate) chunks: Option<Arc<ReadDirChunks>>,
 }
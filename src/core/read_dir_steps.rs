@@ -0,0 +1,889 @@
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::vec;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::getdents_read_dir;
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+use super::io_uring_read_dir;
+#[cfg(windows)]
+use super::nt_query_read_dir;
+use super::{
+    device_num, CancelToken, ChunkEntries, DirEntry, DirFd, Error, Operation, ReadDir,
+    ReadDirChunks, ReadDirSpec, WalkConcurrencyLimit,
+};
+use crate::collate::NameOrder;
+use crate::file_system::{self, FileSystem, ReadDirEntries, SendReadDirEntries};
+#[cfg(feature = "filter")]
+use crate::filter::DirFilterRules;
+use crate::hardlinks::HardlinkSet;
+use crate::pattern::Pattern;
+#[cfg(feature = "size")]
+use crate::size::SizeCounter;
+#[cfg(target_os = "linux")]
+use crate::statx::StatxFields;
+use crate::{
+    ClientState, FilterEntryFunction, HardlinkDedup, InitClientStateFunction, MapEntryFunction,
+    Order, ProcessReadDirFunction, ProjectReadDirStateFunction, ReadDirBackend, ReparseTag, Result,
+    ScheduleRootChildrenFunction, SkippedBuildDirFunction, SortByFunction, SortKey,
+};
+
+/// Steps reading a directory of the walk and preparing its entries, in the
+/// order they are applied.
+pub(crate) struct ReadDirSteps<C: ClientState> {
+    pub(crate) limits: ReadDirLimits,
+    pub(crate) concurrency_limit: Option<WalkConcurrencyLimit>,
+    pub(crate) source: ReadDirSource,
+    pub(crate) follow_links: FollowLinks,
+    pub(crate) walk_roots: Arc<Vec<WalkRoot>>,
+    pub(crate) entry_filter: EntryFilter<C>,
+    pub(crate) entry_preload: EntryPreload<C>,
+    pub(crate) metadata_filter: MetadataFilter,
+    pub(crate) filter_entry: Option<Arc<FilterEntryFunction<C>>>,
+    #[cfg(feature = "size")]
+    pub(crate) aggregate_sizes: Option<Arc<SizeCounter>>,
+    pub(crate) entry_sort: EntrySort<C>,
+    pub(crate) hardlink_dedup: Option<HardlinkDedup>,
+    pub(crate) hardlinks: Arc<HardlinkSet>,
+    pub(crate) process_read_dir: Option<Arc<ProcessReadDirFunction<C>>>,
+    pub(crate) map_entries: Option<Arc<MapEntryFunction<C>>>,
+    pub(crate) project_read_dir_state: Option<Arc<ProjectReadDirStateFunction<C>>>,
+    pub(crate) schedule_root_children_by: Option<Arc<ScheduleRootChildrenFunction<C>>>,
+    pub(crate) root_children_priorities: Arc<RwLock<HashMap<PathBuf, usize>>>,
+}
+
+impl<C: ClientState> ReadDirSteps<C> {
+    /// Reads the directory of `read_dir_spec`, or its next chunk, and
+    /// prepares its entries.
+    pub(crate) fn read_dir(&self, mut read_dir_spec: ReadDirSpec<C>) -> Result<ReadDir<C>> {
+        let depth = read_dir_spec.depth;
+        let mut client_read_state = std::mem::take(&mut read_dir_spec.client_read_state);
+        if self.limits.are_reached(depth + 1) {
+            return Ok(ReadDir::new(client_read_state, Vec::new()));
+        }
+        let _permit = self
+            .concurrency_limit
+            .as_ref()
+            .map(WalkConcurrencyLimit::acquire);
+
+        let path = read_dir_spec.path.clone();
+        let walk_root = walk_root_of(&self.walk_roots, &path);
+        let (fs_entries, remaining_chunks, chunks) = match read_dir_spec.chunks.take() {
+            Some(chunks) => self.source.next_chunk(chunks),
+            None => self.source.open(
+                read_dir_spec,
+                self.follow_links,
+                !self.entry_filter.exclude_paths.is_empty(),
+            )?,
+        };
+
+        let mut dir_entry_results: Vec<_> = fs_entries
+            .filter_map(|dir_entry_result| {
+                let fs_entry = match dir_entry_result {
+                    Ok(fs_entry) => fs_entry,
+                    Err(err) => {
+                        return Some(Err(
+                            Error::from_io(depth + 1, err).during(Operation::ReadDir)
+                        ))
+                    }
+                };
+                let mut dir_entry = match DirEntry::from_entry(
+                    depth + 1,
+                    path.clone(),
+                    fs_entry,
+                    chunks.follow_link_ancestors.clone(),
+                    self.source.file_system.clone(),
+                ) {
+                    Ok(dir_entry) => dir_entry,
+                    Err(err) => return Some(Err(err)),
+                };
+                if dir_entry.read_children_path.is_some() {
+                    dir_entry.parent_dir_fd = chunks.dir_fd.clone();
+                }
+
+                let skip_yield = self.entry_filter.check(&dir_entry, walk_root, &chunks)?;
+                #[cfg(feature = "filter")]
+                {
+                    dir_entry.filter_rules = chunks.children_filter_rules.clone();
+                }
+                dir_entry.read_children_priority = chunks.priority;
+
+                Some(
+                    process_dir_entry_result(
+                        Ok(dir_entry),
+                        self.follow_links,
+                        walk_root.and_then(|walk_root| walk_root.target_depth_root.as_deref()),
+                    )
+                    .map(|mut dir_entry| {
+                        dir_entry.skip_yield = skip_yield;
+                        if let (Some(root_device), Some(read_children_path)) = (
+                            walk_root.and_then(|walk_root| walk_root.device),
+                            dir_entry.read_children_path.as_ref(),
+                        ) {
+                            if device_num(read_children_path)
+                                .is_ok_and(|device| device != root_device)
+                            {
+                                dir_entry.read_children_path = None;
+                            }
+                        }
+                        self.entry_preload.apply(&mut dir_entry);
+                        dir_entry
+                    }),
+                )
+            })
+            .collect();
+
+        if self.metadata_filter.is_active() {
+            dir_entry_results = dir_entry_results
+                .into_iter()
+                .filter_map(|dir_entry_result| match dir_entry_result {
+                    Ok(dir_entry) => self.metadata_filter.apply(dir_entry),
+                    Err(err) => Some(Err(err)),
+                })
+                .collect();
+        }
+        if let Some(filter_entry) = self.filter_entry.as_ref() {
+            retain_filtered_entries(&mut dir_entry_results, filter_entry.as_ref());
+        }
+        self.entry_sort.apply(&mut dir_entry_results);
+        if let Some(hardlink_dedup) = self.hardlink_dedup {
+            dedup_hardlinks(&mut dir_entry_results, hardlink_dedup, &self.hardlinks);
+        }
+        #[cfg(feature = "size")]
+        if let Some(size_counter) = self.aggregate_sizes.as_ref() {
+            count_sizes(&mut dir_entry_results, size_counter);
+        }
+        self.finish_entries(
+            Some(depth),
+            &path,
+            &mut client_read_state,
+            &mut dir_entry_results,
+        );
+        if depth == 0 {
+            self.prioritize_root_children(&dir_entry_results);
+        }
+
+        Ok(ReadDir::new(client_read_state, dir_entry_results).with_chunks(remaining_chunks))
+    }
+
+    /// Prepares a root entry of the walk, starting at `start_path`.
+    pub(crate) fn root_entry(
+        &self,
+        root_entry: Result<DirEntry<C>>,
+        start_path: &Path,
+        #[cfg(feature = "filter")] filter_rules: Option<&Arc<DirFilterRules>>,
+        root_read_dir_state: &mut C::ReadDirState,
+    ) -> Vec<Result<DirEntry<C>>> {
+        let root_parent_path = root_entry
+            .as_ref()
+            .map(|root| root.parent_path().to_owned())
+            .unwrap_or_default();
+        let include_only = &self.entry_filter.include_only;
+        let mut root_entries = vec![
+            process_dir_entry_result(root_entry, self.follow_links, None).map(|mut root_entry| {
+                root_entry.skip_yield = !include_only.is_empty()
+                    && include_only_position(start_path, include_only) != Some(true);
+                #[cfg(feature = "filter")]
+                {
+                    root_entry.filter_rules = filter_rules.cloned();
+                }
+                self.entry_preload.apply(&mut root_entry);
+                root_entry
+            }),
+        ];
+        if let Some(filter_entry) = self.filter_entry.as_ref() {
+            retain_filtered_entries(&mut root_entries, filter_entry.as_ref());
+        }
+        if let Some(hardlink_dedup) = self.hardlink_dedup {
+            dedup_hardlinks(&mut root_entries, hardlink_dedup, &self.hardlinks);
+        }
+        #[cfg(feature = "size")]
+        if let Some(size_counter) = self.aggregate_sizes.as_ref() {
+            count_sizes(&mut root_entries, size_counter);
+        }
+        self.finish_entries(
+            None,
+            &root_parent_path,
+            root_read_dir_state,
+            &mut root_entries,
+        );
+        root_entries
+    }
+
+    // Passes the entries of a directory, or the roots, to the client
+    // callbacks.
+    fn finish_entries(
+        &self,
+        read_dir_depth: Option<usize>,
+        path: &Path,
+        read_dir_state: &mut C::ReadDirState,
+        dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    ) {
+        if let Some(process_read_dir) = self.process_read_dir.as_ref() {
+            process_read_dir(read_dir_depth, path, read_dir_state, dir_entry_results);
+        }
+        if let Some(map_entry) = self.map_entries.as_ref() {
+            map_entry_results(dir_entry_results, map_entry.as_ref());
+        }
+        if let Some(project_read_dir_state) = self.project_read_dir_state.as_ref() {
+            project_read_dir_states(
+                dir_entry_results,
+                read_dir_state,
+                project_read_dir_state.as_ref(),
+            );
+        }
+    }
+
+    // Ranks the directories inside of a root by `schedule_root_children_by`,
+    // see `root_children_priority`.
+    fn prioritize_root_children(&self, dir_entry_results: &[Result<DirEntry<C>>]) {
+        let schedule_root_children_by = match self.schedule_root_children_by.as_ref() {
+            Some(schedule_root_children_by) => schedule_root_children_by,
+            None => return,
+        };
+        let mut root_children: Vec<_> = dir_entry_results
+            .iter()
+            .filter_map(|dir_entry_result| dir_entry_result.as_ref().ok())
+            .filter(|dir_entry| dir_entry.read_children_path.is_some())
+            .collect();
+        root_children.sort_by(|a, b| schedule_root_children_by(a, b));
+        let mut root_children_priorities = self.root_children_priorities.write().unwrap();
+        for (i, dir_entry) in root_children.iter().enumerate() {
+            let read_children_path = dir_entry.read_children_path.as_ref().unwrap();
+            root_children_priorities
+                .insert(read_children_path.to_path_buf(), root_children.len() - i);
+        }
+    }
+
+    /// Priority of the children of a root given by `schedule_root_children_by`.
+    pub(crate) fn root_children_priority(
+        root_children_priorities: &RwLock<HashMap<PathBuf, usize>>,
+        read_dir_spec: &ReadDirSpec<C>,
+    ) -> usize {
+        read_dir_spec
+            .path
+            .ancestors()
+            .nth(read_dir_spec.depth.saturating_sub(1))
+            .and_then(|root_child| {
+                root_children_priorities
+                    .read()
+                    .unwrap()
+                    .get(root_child)
+                    .copied()
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Limits ending the walk before a directory is read.
+pub(crate) struct ReadDirLimits {
+    pub(crate) max_depth: usize,
+    pub(crate) cancel_token: Option<CancelToken>,
+    // canceled once `max_entries` were yielded
+    pub(crate) max_entries_reached: Option<CancelToken>,
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl ReadDirLimits {
+    /// Whether entries at `depth` shouldn't be read anymore.
+    pub(crate) fn are_reached(&self, depth: usize) -> bool {
+        depth > self.max_depth
+            || self
+                .cancel_token
+                .as_ref()
+                .is_some_and(CancelToken::is_canceled)
+            || self
+                .max_entries_reached
+                .as_ref()
+                .is_some_and(CancelToken::is_canceled)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Where and how directories are read.
+pub(crate) struct ReadDirSource {
+    pub(crate) file_system: Option<Arc<dyn FileSystem>>,
+    pub(crate) dir_fds: bool,
+    // Root read through the handle it was created from, see `from_dir_handle`
+    pub(crate) root_dir_fd: Option<(Arc<Path>, Arc<DirFd>)>,
+    pub(crate) backend: ReadDirBackend,
+    pub(crate) chunk_size: Option<usize>,
+}
+
+type ReadDirEntriesAndChunks<'a> = (
+    ReadDirEntries<'a>,
+    Option<Arc<ReadDirChunks>>,
+    Arc<ReadDirChunks>,
+);
+
+impl ReadDirSource {
+    // Opens a directory and reads its entries, or the first chunk of them,
+    // with what is needed to process them.
+    fn open<C: ClientState>(
+        &self,
+        read_dir_spec: ReadDirSpec<C>,
+        follow_links: FollowLinks,
+        canonicalize: bool,
+    ) -> Result<ReadDirEntriesAndChunks<'_>> {
+        let ReadDirSpec {
+            path,
+            depth,
+            priority,
+            follow_link_ancestors,
+            #[cfg(feature = "filter")]
+            filter_rules,
+            parent_dir_fd,
+            ..
+        } = read_dir_spec;
+
+        let follow_link_ancestors = if follow_links.any() {
+            let mut ancestors = Vec::with_capacity(follow_link_ancestors.len() + 1);
+            ancestors.extend(follow_link_ancestors.iter().cloned());
+            ancestors.push(path.clone());
+            Arc::new(ancestors)
+        } else {
+            follow_link_ancestors
+        };
+
+        #[cfg(feature = "filter")]
+        let (filter_rules, children_filter_rules) = match filter_rules {
+            Some(filter_rules) => {
+                let (filter_rules, children_filter_rules) = filter_rules
+                    .enter_dir(
+                        &path,
+                        depth,
+                        file_system::or_std(self.file_system.as_deref()),
+                    )
+                    .map_err(|err| Error::from_path(depth, path.to_path_buf(), err))?;
+                (Some(filter_rules), Some(children_filter_rules))
+            }
+            None => (None, None),
+        };
+
+        let canonical_path = if canonicalize {
+            file_system::or_std(self.file_system.as_deref())
+                .canonicalize(&path)
+                .ok()
+        } else {
+            None
+        };
+
+        // Directories that can't be opened through a handle are read by path
+        let dir_fd = match &self.root_dir_fd {
+            Some((root, root_dir_fd)) if depth == 0 && *root == path => Some(root_dir_fd.clone()),
+            _ if self.dir_fds => DirFd::open(parent_dir_fd.as_deref(), &path)
+                .ok()
+                .map(Arc::new),
+            _ => None,
+        };
+        let fs_entries = self.read_entries(&path, dir_fd.as_deref()).map_err(|err| {
+            Error::from_path(0, path.to_path_buf(), err).during(Operation::ReadDir)
+        })?;
+
+        // The entries of a directory read in chunks are read as its chunks
+        // are taken
+        let (fs_entries, remaining_fs_entries) = match (self.chunk_size, fs_entries) {
+            (Some(chunk_size), fs_entries) => {
+                let mut fs_entries = ChunkEntries::new(match fs_entries {
+                    FsEntries::Send(fs_entries) => fs_entries,
+                    // Entries borrowing a custom file system can't be kept
+                    FsEntries::Borrowed(fs_entries) => {
+                        Box::new(fs_entries.collect::<Vec<_>>().into_iter())
+                    }
+                });
+                let (chunk, more) = fs_entries.next_chunk(chunk_size);
+                (
+                    Box::new(chunk.into_iter()) as ReadDirEntries<'_>,
+                    Some(fs_entries).filter(|_| more),
+                )
+            }
+            (None, FsEntries::Send(fs_entries)) => (fs_entries as ReadDirEntries<'_>, None),
+            (None, FsEntries::Borrowed(fs_entries)) => (fs_entries, None),
+        };
+        let more = remaining_fs_entries.is_some();
+        let chunks = Arc::new(ReadDirChunks {
+            path,
+            depth,
+            priority,
+            fs_entries: Mutex::new(remaining_fs_entries.unwrap_or_default()),
+            follow_link_ancestors,
+            #[cfg(feature = "filter")]
+            filter_rules,
+            #[cfg(feature = "filter")]
+            children_filter_rules,
+            canonical_path,
+            dir_fd,
+        });
+        let remaining_chunks = Some(chunks.clone()).filter(|_| more);
+        Ok((fs_entries, remaining_chunks, chunks))
+    }
+
+    // Later chunks of a directory are processed with what was prepared when
+    // it was first read.
+    fn next_chunk(&self, chunks: Arc<ReadDirChunks>) -> ReadDirEntriesAndChunks<'_> {
+        let (fs_entries, more) = chunks.next_chunk(self.chunk_size.unwrap_or(usize::MAX));
+        let remaining_chunks = Some(chunks.clone()).filter(|_| more);
+        (Box::new(fs_entries.into_iter()), remaining_chunks, chunks)
+    }
+
+    // Reads the entries of a directory, through its handle if it was opened
+    // as `dir_fd`.
+    fn read_entries(&self, path: &Path, dir_fd: Option<&DirFd>) -> std::io::Result<FsEntries<'_>> {
+        if let Some(dir_fd) = dir_fd {
+            return dir_fd.read_dir(path, self.backend).map(FsEntries::Send);
+        }
+        let fs_entries = match (self.backend, self.file_system.as_deref()) {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            (ReadDirBackend::Getdents, None) => getdents_read_dir(path),
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            (ReadDirBackend::IoUring, None) => io_uring_read_dir(path),
+            #[cfg(windows)]
+            (ReadDirBackend::NtQueryDirectoryFile, None) => nt_query_read_dir(path),
+            (_, None) => file_system::read_std_dir(path),
+            (_, Some(file_system)) => return file_system.read_dir(path).map(FsEntries::Borrowed),
+        };
+        fs_entries.map(FsEntries::Send)
+    }
+}
+
+// Entries of a directory, read by `jwalk` itself or by a custom file system.
+enum FsEntries<'a> {
+    Send(SendReadDirEntries),
+    Borrowed(ReadDirEntries<'a>),
+}
+
+/// Skips entries by name, path and pattern while their directory is read.
+pub(crate) struct EntryFilter<C: ClientState> {
+    pub(crate) skip_hidden: bool,
+    pub(crate) skip_vcs_dirs: bool,
+    pub(crate) vcs_dir_names: Arc<Vec<OsString>>,
+    pub(crate) skip_build_dirs: bool,
+    pub(crate) build_dir_names: Arc<Vec<OsString>>,
+    pub(crate) on_skipped_build_dir: Option<Arc<SkippedBuildDirFunction<C>>>,
+    pub(crate) exclude_paths: Arc<HashSet<PathBuf>>,
+    pub(crate) exclude_devices: Arc<HashSet<u64>>,
+    pub(crate) exclude_patterns: Arc<Vec<Pattern>>,
+    pub(crate) include_patterns: Arc<Vec<Pattern>>,
+    pub(crate) include_only: Vec<PathBuf>,
+}
+
+impl<C: ClientState> EntryFilter<C> {
+    /// `None` if `dir_entry` is skipped, otherwise whether it is only
+    /// descended into without being yielded, see `include_only`.
+    fn check(
+        &self,
+        dir_entry: &DirEntry<C>,
+        walk_root: Option<&WalkRoot>,
+        chunks: &ReadDirChunks,
+    ) -> Option<bool> {
+        if self.skip_hidden && is_hidden(&dir_entry.file_name) {
+            return None;
+        }
+
+        if self.skip_vcs_dirs
+            && dir_entry.file_type.is_dir()
+            && self.vcs_dir_names.contains(&dir_entry.file_name)
+        {
+            return None;
+        }
+
+        if self.skip_build_dirs
+            && dir_entry.file_type.is_dir()
+            && self.build_dir_names.contains(&dir_entry.file_name)
+        {
+            if let Some(on_skipped_build_dir) = self.on_skipped_build_dir.as_ref() {
+                on_skipped_build_dir(dir_entry);
+            }
+            return None;
+        }
+
+        if let Some(canonical_path) = chunks.canonical_path.as_ref() {
+            if self
+                .exclude_paths
+                .contains(&canonical_path.join(&dir_entry.file_name))
+            {
+                return None;
+            }
+        }
+
+        if !self.exclude_devices.is_empty() && dir_entry.read_children_path.is_some() {
+            if let Ok(device) = device_num(&dir_entry.path()) {
+                if self.exclude_devices.contains(&device) {
+                    return None;
+                }
+            }
+        }
+
+        if !self.exclude_patterns.is_empty()
+            || !self.include_patterns.is_empty()
+            || chunks.has_filter_rules()
+        {
+            let path = dir_entry.path();
+            let relative_path = walk_root
+                .and_then(|walk_root| path.strip_prefix(&walk_root.path).ok())
+                .unwrap_or(&path);
+            let is_dir = dir_entry.file_type.is_dir();
+            if self
+                .exclude_patterns
+                .iter()
+                .any(|pattern| pattern.matches(relative_path, is_dir))
+            {
+                return None;
+            }
+            if chunks.excludes(relative_path, is_dir) {
+                return None;
+            }
+            if !is_dir
+                && !self.include_patterns.is_empty()
+                && !self
+                    .include_patterns
+                    .iter()
+                    .any(|pattern| pattern.matches(relative_path, false))
+            {
+                return None;
+            }
+        }
+
+        if self.include_only.is_empty() {
+            Some(false)
+        } else {
+            include_only_position(&dir_entry.path(), &self.include_only).map(|inside| !inside)
+        }
+    }
+}
+
+/// Metadata and client state prepared for each entry while reading its
+/// directory.
+pub(crate) struct EntryPreload<C: ClientState> {
+    pub(crate) metadata: bool,
+    #[cfg(target_os = "linux")]
+    pub(crate) statx: Option<StatxFields>,
+    pub(crate) init_client_state: Option<Arc<InitClientStateFunction<C>>>,
+}
+
+impl<C: ClientState> EntryPreload<C> {
+    fn apply(&self, dir_entry: &mut DirEntry<C>) {
+        if self.metadata {
+            dir_entry.preload_metadata();
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(fields) = self.statx {
+            dir_entry.preload_statx(fields);
+        }
+        if let Some(init_client_state) = self.init_client_state.as_ref() {
+            dir_entry.client_state = init_client_state(dir_entry);
+        }
+    }
+}
+
+/// Order of the entries of each directory.
+pub(crate) struct EntrySort<C: ClientState> {
+    pub(crate) sort: bool,
+    pub(crate) name_order: NameOrder,
+    pub(crate) sort_by: Option<Arc<SortByFunction<C>>>,
+    pub(crate) sort_key: Option<(SortKey, Order)>,
+}
+
+impl<C: ClientState> EntrySort<C> {
+    // Sorts entries, placing errors last.
+    fn apply(&self, dir_entry_results: &mut [Result<DirEntry<C>>]) {
+        let compare: &dyn Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering =
+            if let Some(sort_by) = self.sort_by.as_ref() {
+                sort_by.as_ref()
+            } else if let Some((key, order)) = self.sort_key {
+                &move |a, b| compare_by_key(key, order, a, b)
+            } else if self.sort {
+                &|a, b| self.name_order.compare(&a.file_name, &b.file_name)
+            } else {
+                return;
+            };
+        dir_entry_results.sort_by(|a, b| match (a, b) {
+            (Ok(a), Ok(b)) => compare(a, b),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => Ordering::Equal,
+        });
+    }
+}
+
+// Skips or flags entries linking to files that were seen before.
+fn dedup_hardlinks<C: ClientState>(
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    hardlink_dedup: HardlinkDedup,
+    hardlinks: &HardlinkSet,
+) {
+    dir_entry_results.retain_mut(|dir_entry_result| match dir_entry_result {
+        Ok(dir_entry) if !dir_entry.file_type.is_dir() => {
+            dir_entry.duplicate_hardlink = dir_entry
+                .metadata()
+                .is_ok_and(|metadata| hardlinks.is_duplicate_at(&dir_entry.path(), &metadata));
+            !(dir_entry.duplicate_hardlink && hardlink_dedup == HardlinkDedup::Skip)
+        }
+        _ => true,
+    });
+}
+
+/// Filters entries by their metadata while reading directories.
+pub(crate) struct MetadataFilter {
+    pub(crate) file_sizes: RangeInclusive<u64>,
+    pub(crate) modified_after: Option<SystemTime>,
+    pub(crate) prune_unmodified_dirs: bool,
+}
+
+impl MetadataFilter {
+    fn is_active(&self) -> bool {
+        self.file_sizes != (0..=u64::MAX) || self.modified_after.is_some()
+    }
+
+    fn apply<C: ClientState>(&self, mut dir_entry: DirEntry<C>) -> Option<Result<DirEntry<C>>> {
+        if dir_entry.file_type.is_file() {
+            let metadata = match dir_entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => return Some(Err(err)),
+            };
+            if !self.file_sizes.contains(&metadata.len()) {
+                return None;
+            }
+            if let Some(modified_after) = self.modified_after {
+                match metadata.modified() {
+                    Ok(modified) if modified > modified_after => {}
+                    Ok(_) => return None,
+                    Err(err) => {
+                        return Some(Err(
+                            Error::from_entry(&dir_entry, err).during(Operation::Metadata)
+                        ))
+                    }
+                }
+            }
+        } else if let (Some(modified_after), true, true) = (
+            self.modified_after,
+            self.prune_unmodified_dirs,
+            dir_entry.read_children_path.is_some(),
+        ) {
+            let changed = dir_entry
+                .metadata()
+                .ok()
+                .and_then(|metadata| last_changed(&metadata));
+            if changed.is_some_and(|changed| changed <= modified_after) {
+                dir_entry.read_children_path = None;
+            }
+        }
+        Some(Ok(dir_entry))
+    }
+}
+
+// Latest of the modification and, on Unix, status change time.
+fn last_changed(metadata: &fs::Metadata) -> Option<SystemTime> {
+    let modified = metadata.modified().ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let changed = u64::try_from(metadata.ctime())
+            .ok()
+            .map(|secs| UNIX_EPOCH + Duration::new(secs, metadata.ctime_nsec() as u32));
+        if let Some(changed) = changed {
+            return Some(modified.max(changed));
+        }
+    }
+    Some(modified)
+}
+
+fn retain_filtered_entries<C: ClientState>(
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    filter_entry: &FilterEntryFunction<C>,
+) {
+    dir_entry_results
+        .retain(|dir_entry_result| dir_entry_result.as_ref().map_or(true, filter_entry));
+}
+
+// Calls `map_entry` with each entry, in parallel if called on a thread of a
+// rayon pool.
+fn map_entry_results<C: ClientState>(
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+    map_entry: &MapEntryFunction<C>,
+) {
+    #[cfg(feature = "rayon")]
+    if rayon::current_thread_index().is_some() {
+        dir_entry_results
+            .par_iter_mut()
+            .for_each(|dir_entry_result| {
+                if let Ok(dir_entry) = dir_entry_result {
+                    map_entry(dir_entry);
+                }
+            });
+        return;
+    }
+    dir_entry_results.iter_mut().flatten().for_each(map_entry);
+}
+
+fn project_read_dir_states<C: ClientState>(
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+    read_dir_state: &C::ReadDirState,
+    project_read_dir_state: &ProjectReadDirStateFunction<C>,
+) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        dir_entry.parent_read_dir_state = Some(project_read_dir_state(read_dir_state, dir_entry));
+    }
+}
+
+#[cfg(feature = "size")]
+fn count_sizes<C: ClientState>(
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+    size_counter: &SizeCounter,
+) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        dir_entry.count_size(size_counter);
+    }
+}
+
+fn process_dir_entry_result<C: ClientState>(
+    dir_entry_result: Result<DirEntry<C>>,
+    follow_links: FollowLinks,
+    target_depth_root: Option<&Path>,
+) -> Result<DirEntry<C>> {
+    match dir_entry_result {
+        Ok(mut dir_entry) => {
+            if follow_links.follows(&dir_entry) {
+                dir_entry = dir_entry.follow_symlink()?;
+
+                if let Some(target_depth_root) = target_depth_root {
+                    // Count depth along the resolved target when it is
+                    // inside of root, see `LinkDepth::TargetPath`.
+                    let target = fs::canonicalize(dir_entry.path())
+                        .map_err(|err| Error::from_entry(&dir_entry, err))?;
+                    if let Ok(relative_target) = target.strip_prefix(target_depth_root) {
+                        dir_entry.depth = relative_target.components().count();
+                    }
+                }
+            }
+
+            if dir_entry.depth == 0 && dir_entry.file_type.is_symlink() {
+                // As a special case, if we are processing a root entry, then we
+                // always follow it even if it's a symlink and follow_links is
+                // false. We are careful to not let this change the semantics of
+                // the DirEntry however. Namely, the DirEntry should still
+                // respect the follow_links setting. When it's disabled, it
+                // should report itself as a symlink. When it's enabled, it
+                // should always report itself as the target.
+                let file_type = file_system::or_std(dir_entry.file_system.as_deref())
+                    .file_type(&dir_entry.path(), true)
+                    .map_err(|err| {
+                        Error::from_path(0, dir_entry.path(), err).during(Operation::Metadata)
+                    })?;
+                if file_type.is_dir() {
+                    dir_entry.read_children_path = Some(Arc::from(dir_entry.path()));
+                }
+            }
+
+            Ok(dir_entry)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Which links are followed, see `follow_links` and `follow_junctions`.
+#[derive(Clone, Copy)]
+pub(crate) struct FollowLinks {
+    pub(crate) symlinks: bool,
+    pub(crate) junctions: bool,
+}
+
+impl FollowLinks {
+    pub(crate) fn any(self) -> bool {
+        self.symlinks || self.junctions
+    }
+
+    fn follows<C: ClientState>(self, dir_entry: &DirEntry<C>) -> bool {
+        if !dir_entry.file_type.is_symlink() {
+            return false;
+        }
+        if dir_entry.reparse_tag() == Some(ReparseTag::MountPoint) {
+            self.junctions
+        } else {
+            self.symlinks
+        }
+    }
+}
+
+// Value of a `SortKey` in the metadata of an entry.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKeyValue {
+    Size(u64),
+    Time(SystemTime),
+}
+
+// Compares entries by `key` in `order`, placing entries whose key can't be
+// read last, and then by file name.
+fn compare_by_key<C: ClientState>(
+    key: SortKey,
+    order: Order,
+    a: &DirEntry<C>,
+    b: &DirEntry<C>,
+) -> Ordering {
+    let value = |dir_entry: &DirEntry<C>| {
+        let metadata = dir_entry.metadata().ok()?;
+        match key {
+            SortKey::Size => Some(SortKeyValue::Size(metadata.len())),
+            SortKey::Modified => metadata.modified().ok().map(SortKeyValue::Time),
+            SortKey::Created => metadata.created().ok().map(SortKeyValue::Time),
+        }
+    };
+    let ordering = match (value(a), value(b)) {
+        (Some(a), Some(b)) => match order {
+            Order::Asc => a.cmp(&b),
+            Order::Desc => b.cmp(&a),
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    ordering.then_with(|| a.file_name.cmp(&b.file_name))
+}
+
+/// A root of the walk with the values needed while reading its directories.
+pub(crate) struct WalkRoot {
+    pub(crate) path: PathBuf,
+    // device number of root if `same_file_system` is enabled
+    pub(crate) device: Option<u64>,
+    // canonical root if depth is counted with `LinkDepth::TargetPath`
+    pub(crate) target_depth_root: Option<PathBuf>,
+}
+
+// Returns the innermost root containing `path`.
+fn walk_root_of<'a>(walk_roots: &'a [WalkRoot], path: &Path) -> Option<&'a WalkRoot> {
+    walk_roots
+        .iter()
+        .filter(|walk_root| path.starts_with(&walk_root.path))
+        .max_by_key(|walk_root| walk_root.path.components().count())
+}
+
+// Returns `Some(true)` if `path` is inside of one of `include_paths`,
+// `Some(false)` if it leads to one of them and `None` otherwise.
+fn include_only_position(path: &Path, include_paths: &[PathBuf]) -> Option<bool> {
+    let mut position = None;
+    for include_path in include_paths {
+        if path.starts_with(include_path) {
+            return Some(true);
+        } else if include_path.starts_with(path) {
+            position = Some(false);
+        }
+    }
+    position
+}
+
+pub(crate) fn is_hidden(file_name: &OsStr) -> bool {
+    file_name
+        .to_str()
+        .map(|s| s.starts_with('.'))
+        .unwrap_or(false)
+}
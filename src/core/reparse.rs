@@ -0,0 +1,56 @@
+use std::ffi::c_void;
+use std::fs::OpenOptions;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+
+#[repr(C)]
+struct FileAttributeTagInfo {
+    file_attributes: u32,
+    reparse_tag: u32,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetFileInformationByHandleEx(
+        file: *mut c_void,
+        file_information_class: u32,
+        file_information: *mut c_void,
+        buffer_size: u32,
+    ) -> i32;
+}
+
+// Required to open directories.
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+// Opens the reparse point itself instead of its target.
+const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+// `FILE_INFO_BY_HANDLE_CLASS` returning `FILE_ATTRIBUTE_TAG_INFO`
+const FILE_ATTRIBUTE_TAG_INFO: u32 = 9;
+
+/// Returns the reparse tag of the reparse point at `path`, without opening
+/// its target or recalling cloud files.
+pub(crate) fn reparse_tag(path: &Path) -> io::Result<u32> {
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT)
+        .open(path)?;
+    let mut information = MaybeUninit::<FileAttributeTagInfo>::uninit();
+    // SAFETY: `file` is a valid open handle and `information` is large
+    // enough to hold the structure written by the call.
+    let information = unsafe {
+        if GetFileInformationByHandleEx(
+            file.as_raw_handle() as *mut c_void,
+            FILE_ATTRIBUTE_TAG_INFO,
+            information.as_mut_ptr() as *mut c_void,
+            std::mem::size_of::<FileAttributeTagInfo>() as u32,
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        information.assume_init()
+    };
+    Ok(information.reparse_tag)
+}
@@ -1,14 +1,22 @@
-use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::Arc;
 
-use super::{ClientState, Ordered, OrderedQueue, ReadDir, ReadDirCallback, ReadDirSpec};
-use crate::Result;
+use super::{
+    Backpressure, ClientState, Ordered, OrderedQueue, ReadDir, ReadDirCallback, ReadDirPacing,
+    ReadDirSpec, ReadDirSpecPriorityCallback,
+};
+use crate::{ReadDirOrder, Result};
 
 pub(crate) struct RunContext<C: ClientState> {
     pub(crate) stop: Arc<AtomicBool>,
     pub(crate) read_dir_spec_queue: OrderedQueue<ReadDirSpec<C>>,
     pub(crate) read_dir_result_queue: OrderedQueue<Result<ReadDir<C>>>,
     pub(crate) core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    pub(crate) read_dir_spec_priority: Option<Arc<ReadDirSpecPriorityCallback<C>>>,
+    pub(crate) pacing: ReadDirPacing,
+    pub(crate) enqueued: Arc<AtomicUsize>,
+    // blocks reads while too many results weren't consumed
+    pub(crate) backpressure: Option<Arc<Backpressure>>,
 }
 
 impl<C: ClientState> RunContext<C> {
@@ -17,6 +25,16 @@ impl<C: ClientState> RunContext<C> {
     }
 
     pub(crate) fn schedule_read_dir_spec(&self, ordered_read_dir: Ordered<ReadDirSpec<C>>) -> bool {
+        let priority = self
+            .read_dir_spec_priority
+            .as_ref()
+            .map_or(0, |priority| priority(&ordered_read_dir.value))
+            .max(ordered_read_dir.value.priority);
+        let mut ordered_read_dir = ordered_read_dir.with_priority(priority);
+        if self.pacing.order == ReadDirOrder::Fifo {
+            let sequence = self.enqueued.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+            ordered_read_dir = ordered_read_dir.with_sequence(sequence);
+        }
         self.read_dir_spec_queue.push(ordered_read_dir).is_ok()
     }
 
@@ -39,6 +57,10 @@ impl<C: ClientState> Clone for RunContext<C> {
             read_dir_spec_queue: self.read_dir_spec_queue.clone(),
             read_dir_result_queue: self.read_dir_result_queue.clone(),
             core_read_dir_callback: self.core_read_dir_callback.clone(),
+            read_dir_spec_priority: self.read_dir_spec_priority.clone(),
+            pacing: self.pacing,
+            enqueued: self.enqueued.clone(),
+            backpressure: self.backpressure.clone(),
         }
     }
 }
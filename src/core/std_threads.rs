@@ -0,0 +1,35 @@
+use std::sync::Mutex;
+use std::thread;
+
+use super::*;
+
+/// Reads pending directories on `num_threads` scoped `std` threads, as many
+/// as the system has cores if `0`, instead of a rayon pool.
+pub(crate) fn walk_on_std_threads<C: ClientState>(
+    read_dir_spec_iter: OrderedQueueIter<ReadDirSpec<C>>,
+    run_context: RunContext<C>,
+    num_threads: usize,
+) {
+    let num_threads = match num_threads {
+        0 => thread::available_parallelism().map_or(1, |num_threads| num_threads.get()),
+        num_threads => num_threads,
+    };
+    let read_dir_spec_iter = Mutex::new(read_dir_spec_iter);
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            let read_dir_spec_iter = &read_dir_spec_iter;
+            let mut run_context = run_context.clone();
+            scope.spawn(move || loop {
+                // Only one thread waits for the next spec, the others wait
+                // for the lock
+                let next = read_dir_spec_iter.lock().unwrap().next();
+                match next {
+                    Some(ordered_read_dir_spec) => {
+                        multi_threaded_walk_dir(ordered_read_dir_spec, &mut run_context)
+                    }
+                    None => return,
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use io_uring::{opcode, types, IoUring};
+
+use super::getdents::{self, stat_file_type_at, stat_file_types};
+use crate::file_system::{FileSystemEntry, FileType, FileTypeSource, SendReadDirEntries};
+
+// Number of stats submitted at once
+const RING_ENTRIES: u32 = 256;
+
+thread_local! {
+    // Ring of the thread reading directories, `None` if io_uring isn't
+    // available or failed
+    static RING: RefCell<Option<IoUring>> = RefCell::new(IoUring::new(RING_ENTRIES).ok());
+}
+
+/// Reads the entries of the directory at `path` with the `getdents64`
+/// syscall, and stats entries whose type isn't returned by the file system in
+/// batches on the thread's io_uring.
+pub(crate) fn read_dir(path: &Path) -> io::Result<SendReadDirEntries> {
+    getdents::read_dir_with(path, statx_file_types)
+}
+
+/// Reads the entries of the directory opened as `dir` at `path` like
+/// [`read_dir`](fn.read_dir.html).
+pub(crate) fn read_open_dir(dir: &File, path: &Path) -> io::Result<SendReadDirEntries> {
+    getdents::read_open_dir(dir, path, statx_file_types)
+}
+
+fn statx_file_types(dir: &File, path: &Path, entries: &mut [FileSystemEntry], unknown: &[usize]) {
+    RING.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        for chunk in unknown.chunks(RING_ENTRIES as usize) {
+            let statted = match ring.as_mut() {
+                Some(uring) => statx_chunk(uring, dir, entries, chunk),
+                None => Err(io::ErrorKind::Unsupported.into()),
+            };
+            if statted.is_err() {
+                // Operations of a failed submission may still complete, so the
+                // ring isn't reused
+                *ring = None;
+                stat_file_types(dir, path, entries, chunk);
+            }
+        }
+    })
+}
+
+// Stats the `entries` at `chunk` with one submission of up to `RING_ENTRIES`
+// operations.
+fn statx_chunk(
+    ring: &mut IoUring,
+    dir: &File,
+    entries: &mut [FileSystemEntry],
+    chunk: &[usize],
+) -> io::Result<()> {
+    let names = chunk
+        .iter()
+        .map(|&index| CString::new(entries[index].file_name.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    // SAFETY: `statx` only contains integers, for which zero is valid.
+    let mut buffers: Vec<libc::statx> =
+        (0..chunk.len()).map(|_| unsafe { mem::zeroed() }).collect();
+
+    let submitted = submit_and_wait(ring, dir, &names, &mut buffers);
+    if submitted.is_err() {
+        // The kernel may still write to them
+        mem::forget(names);
+        mem::forget(buffers);
+        return submitted;
+    }
+
+    for completion in ring.completion() {
+        let position = completion.user_data() as usize;
+        let entry = &mut entries[chunk[position]];
+        if completion.result() < 0 {
+            entry.file_type = Err(io::Error::from_raw_os_error(-completion.result()));
+            continue;
+        }
+        entry.file_type = match u32::from(buffers[position].stx_mode) & libc::S_IFMT {
+            libc::S_IFDIR => Ok(FileType::dir().with_source(FileTypeSource::Stat)),
+            libc::S_IFREG => Ok(FileType::file().with_source(FileTypeSource::Stat)),
+            libc::S_IFLNK => Ok(FileType::symlink().with_source(FileTypeSource::Stat)),
+            _ => stat_file_type_at(dir, &entry.file_name),
+        };
+    }
+    Ok(())
+}
+
+fn submit_and_wait(
+    ring: &mut IoUring,
+    dir: &File,
+    names: &[CString],
+    buffers: &mut [libc::statx],
+) -> io::Result<()> {
+    for (position, (name, buffer)) in names.iter().zip(buffers.iter_mut()).enumerate() {
+        let statx = opcode::Statx::new(
+            types::Fd(dir.as_raw_fd()),
+            name.as_ptr(),
+            buffer as *mut libc::statx as *mut types::statx,
+        )
+        .flags(libc::AT_SYMLINK_NOFOLLOW)
+        .mask(libc::STATX_TYPE)
+        .build()
+        .user_data(position as u64);
+        // SAFETY: `name` and `buffer` outlive the operation, the caller
+        // keeps them alive until it completed or if this fails.
+        unsafe { ring.submission().push(&statx) }.map_err(io::Error::other)?;
+    }
+    loop {
+        match ring.submit_and_wait(names.len()) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            submitted => return submitted.map(|_| ()),
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Compare two trees by relative path.
+//!
+//! [`diff`](fn.diff.html) walks both trees in parallel, sorted the same way,
+//! and yields a [`DiffEntry`](enum.DiffEntry.html) for each path relative to
+//! the roots, saying whether it exists in the left tree, the right tree or
+//! both. Comparing the entries found in both trees is left to the caller, such
+//! as a sync or backup tool deciding what to copy.
+//!
+//! ```no_run
+//! use jwalk::diff::DiffEntry;
+//!
+//! for diff_entry in jwalk::diff("/data", "/backup/data") {
+//!     match diff_entry? {
+//!         DiffEntry::OnlyLeft { path, .. } => println!("copy {}", path.display()),
+//!         DiffEntry::OnlyRight { path, .. } => println!("delete {}", path.display()),
+//!         DiffEntry::Both { path, left, right } => {
+//!             if left.metadata()?.len() != right.metadata()?.len() {
+//!                 println!("update {}", path.display());
+//!             }
+//!         }
+//!     }
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use crate::{DirEntry, DirEntryIter, Result, WalkDir};
+
+/// Entry of a path relative to the roots of a [`diff`](fn.diff.html).
+#[derive(Debug)]
+pub enum DiffEntry {
+    /// The path only exists in the left tree.
+    OnlyLeft {
+        /// Path relative to the roots.
+        path: PathBuf,
+        /// Entry in the left tree.
+        entry: DirEntry<((), ())>,
+    },
+    /// The path only exists in the right tree.
+    OnlyRight {
+        /// Path relative to the roots.
+        path: PathBuf,
+        /// Entry in the right tree.
+        entry: DirEntry<((), ())>,
+    },
+    /// The path exists in both trees, not necessarily with the same file type.
+    Both {
+        /// Path relative to the roots.
+        path: PathBuf,
+        /// Entry in the left tree.
+        left: Box<DirEntry<((), ())>>,
+        /// Entry in the right tree.
+        right: Box<DirEntry<((), ())>>,
+    },
+}
+
+/// Builder for comparing two trees, created by [`diff`](fn.diff.html).
+pub struct DiffTrees {
+    left: WalkDir,
+    right: WalkDir,
+}
+
+/// Iterator yielding the entries of two trees by relative path.
+pub struct DiffIter {
+    left: Side,
+    right: Side,
+}
+
+// Entry paired with its path relative to the root.
+type RelativeEntry = (PathBuf, DirEntry<((), ())>);
+
+// One of the walks of a diff.
+struct Side {
+    root: PathBuf,
+    dir_entry_iter: DirEntryIter<((), ())>,
+    peeked: Option<Result<RelativeEntry>>,
+}
+
+/// Compare the trees at `left` and `right`, see the [`diff`](diff/index.html)
+/// module.
+///
+/// Hidden files are included by default.
+pub fn diff<P: AsRef<Path>, Q: AsRef<Path>>(left: P, right: Q) -> DiffTrees {
+    DiffTrees {
+        left: WalkDir::new(left).skip_hidden(false),
+        right: WalkDir::new(right).skip_hidden(false),
+    }
+}
+
+impl DiffEntry {
+    /// Path of the entry, relative to the roots.
+    pub fn path(&self) -> &Path {
+        match self {
+            DiffEntry::OnlyLeft { path, .. }
+            | DiffEntry::OnlyRight { path, .. }
+            | DiffEntry::Both { path, .. } => path,
+        }
+    }
+}
+
+impl DiffTrees {
+    /// Configure both wrapped [`WalkDir`](../type.WalkDir.html)s the same
+    /// way, for example their parallelism or filters.
+    ///
+    /// Both walks are sorted by file name and yield directories before their
+    /// contents, so their [`sort_by`](../struct.WalkDirGeneric.html#method.sort_by)
+    /// and [`contents_first`](../struct.WalkDirGeneric.html#method.contents_first)
+    /// options are replaced. A
+    /// [`process_read_dir`](../struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback must not reorder children.
+    pub fn walk_dir<F>(mut self, configure: F) -> Self
+    where
+        F: Fn(WalkDir) -> WalkDir,
+    {
+        self.left = configure(self.left);
+        self.right = configure(self.right);
+        self
+    }
+}
+
+impl IntoIterator for DiffTrees {
+    type Item = Result<DiffEntry>;
+    type IntoIter = DiffIter;
+
+    fn into_iter(self) -> DiffIter {
+        // Start both walks before consuming either so they read ahead in
+        // parallel
+        DiffIter {
+            left: Side::new(self.left),
+            right: Side::new(self.right),
+        }
+    }
+}
+
+impl Side {
+    fn new(mut walk_dir: WalkDir) -> Side {
+        walk_dir.options.sort_by = None;
+        let walk_dir = walk_dir.sort(true).contents_first(false);
+        Side {
+            root: walk_dir.root().to_path_buf(),
+            dir_entry_iter: walk_dir.into_iter(),
+            peeked: None,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Result<RelativeEntry>> {
+        if self.peeked.is_none() {
+            let root = &self.root;
+            self.peeked = self.dir_entry_iter.next().map(|dir_entry_result| {
+                dir_entry_result.map(|dir_entry| {
+                    let path = dir_entry.path();
+                    let path = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+                    (path, dir_entry)
+                })
+            });
+        }
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Option<Result<RelativeEntry>> {
+        self.peek();
+        self.peeked.take()
+    }
+}
+
+impl Iterator for DiffIter {
+    type Item = Result<DiffEntry>;
+
+    fn next(&mut self) -> Option<Result<DiffEntry>> {
+        // Paths compare by component, which is the order of a sorted walk
+        // yielding directories before their contents
+        let ordering = match (self.left.peek(), self.right.peek()) {
+            (None, None) => return None,
+            (Some(Err(_)), _) | (Some(_), None) => Ordering::Less,
+            (_, Some(Err(_))) | (None, Some(_)) => Ordering::Greater,
+            (Some(Ok((left, _))), Some(Ok((right, _)))) => left.cmp(right),
+        };
+        let diff_entry = match ordering {
+            Ordering::Less => self
+                .left
+                .next()?
+                .map(|(path, entry)| DiffEntry::OnlyLeft { path, entry }),
+            Ordering::Greater => self
+                .right
+                .next()?
+                .map(|(path, entry)| DiffEntry::OnlyRight { path, entry }),
+            Ordering::Equal => {
+                let (path, left) = self.left.next()?.ok()?;
+                let (_, right) = self.right.next()?.ok()?;
+                Ok(DiffEntry::Both {
+                    path,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+        };
+        Some(diff_entry)
+    }
+}
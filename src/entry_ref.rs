@@ -0,0 +1,169 @@
+use std::ffi::OsStr;
+use std::fs::{self, FileType};
+use std::path::{Path, PathBuf};
+
+use crate::core::Operation;
+use crate::{Error, Parallelism, Result};
+
+/// A borrowed directory entry passed to
+/// [`for_each_ref`](struct.WalkDirGeneric.html#method.for_each_ref).
+///
+/// The entry borrows the path of its parent directory and its file name from
+/// the thread reading the directory, so it is only valid during the callback.
+#[derive(Clone, Copy, Debug)]
+pub struct DirEntryRef<'a> {
+    parent_path: &'a Path,
+    file_name: &'a OsStr,
+    file_type: FileType,
+    depth: usize,
+}
+
+impl<'a> DirEntryRef<'a> {
+    /// Path of the directory containing this entry.
+    pub fn parent_path(&self) -> &'a Path {
+        self.parent_path
+    }
+
+    /// The file name of this entry. For root this is the full path given to
+    /// the builder if it has no file name, such as `.`.
+    pub fn file_name(&self) -> &'a OsStr {
+        self.file_name
+    }
+
+    /// The file type of this entry, symbolic links are not followed.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// The depth of this entry relative to root.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Path of this entry, allocating a new path.
+    pub fn path(&self) -> PathBuf {
+        self.parent_path.join(self.file_name)
+    }
+}
+
+pub(crate) struct RefWalkOptions {
+    pub(crate) skip_hidden: bool,
+    pub(crate) min_depth: usize,
+    pub(crate) max_depth: usize,
+}
+
+type RefFunction<'f> = dyn Fn(Result<DirEntryRef<'_>>) + Send + Sync + 'f;
+
+pub(crate) fn walk_refs(
+    root: &Path,
+    parallelism: &Parallelism,
+    options: &RefWalkOptions,
+    f: &RefFunction<'_>,
+) {
+    let file_type = match fs::symlink_metadata(root) {
+        Ok(metadata) => metadata.file_type(),
+        Err(err) => {
+            return f(Err(
+                Error::from_path(0, root.to_owned(), err).during(Operation::ReadDir)
+            ))
+        }
+    };
+    if options.min_depth == 0 {
+        f(Ok(DirEntryRef {
+            parent_path: root.parent().unwrap_or_else(|| Path::new("")),
+            file_name: root.file_name().unwrap_or(root.as_os_str()),
+            file_type,
+            depth: 0,
+        }));
+    }
+    if !file_type.is_dir() {
+        return;
+    }
+
+    let root = root.to_path_buf();
+    match parallelism {
+        Parallelism::Serial => read_dir_refs(None, root, 1, options, f),
+        Parallelism::RayonDefaultPool { .. } | Parallelism::Adaptive { .. } => {
+            rayon::scope(|scope| read_dir_refs(Some(scope), root, 1, options, f))
+        }
+        Parallelism::StdThreads(_) => {
+            rayon::scope(|scope| read_dir_refs(Some(scope), root, 1, options, f))
+        }
+        Parallelism::RayonExistingPool { pool, .. } => {
+            pool.scope(|scope| read_dir_refs(Some(scope), root, 1, options, f))
+        }
+        Parallelism::RayonNewPool(num_threads) => {
+            let mut thread_pool = rayon::ThreadPoolBuilder::new();
+            if *num_threads > 0 {
+                thread_pool = thread_pool.num_threads(*num_threads);
+            }
+            match thread_pool.build() {
+                Ok(thread_pool) => {
+                    thread_pool.scope(|scope| read_dir_refs(Some(scope), root, 1, options, f))
+                }
+                Err(_) => rayon::scope(|scope| read_dir_refs(Some(scope), root, 1, options, f)),
+            }
+        }
+    }
+}
+
+// Passes the contents of `dir` to `f`, spawning reads of subdirectories onto
+// `scope` or reading them right away without a scope.
+fn read_dir_refs<'s>(
+    scope: Option<&rayon::Scope<'s>>,
+    dir: PathBuf,
+    depth: usize,
+    options: &'s RefWalkOptions,
+    f: &'s RefFunction<'s>,
+) {
+    if depth > options.max_depth {
+        return;
+    }
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) => {
+            return f(Err(
+                Error::from_path(depth - 1, dir, err).during(Operation::ReadDir)
+            ))
+        }
+    };
+    for fs_dir_entry in read_dir {
+        let fs_dir_entry = match fs_dir_entry {
+            Ok(fs_dir_entry) => fs_dir_entry,
+            Err(err) => {
+                f(Err(Error::from_io(depth, err).during(Operation::ReadDir)));
+                continue;
+            }
+        };
+        let file_name = fs_dir_entry.file_name();
+        if options.skip_hidden && crate::core::is_hidden(&file_name) {
+            continue;
+        }
+        let file_type = match fs_dir_entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                f(Err(
+                    Error::from_path(depth, fs_dir_entry.path(), err).during(Operation::Metadata)
+                ));
+                continue;
+            }
+        };
+        if depth >= options.min_depth {
+            f(Ok(DirEntryRef {
+                parent_path: &dir,
+                file_name: &file_name,
+                file_type,
+                depth,
+            }));
+        }
+        if file_type.is_dir() && depth < options.max_depth {
+            let child_dir = dir.join(&file_name);
+            match scope {
+                Some(scope) => scope.spawn(move |scope| {
+                    read_dir_refs(Some(scope), child_dir, depth + 1, options, f)
+                }),
+                None => read_dir_refs(None, child_dir, depth + 1, options, f),
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Walks as a sequence of enter and leave directory events.
+//!
+//! [`WalkDirGeneric::into_events`](../struct.WalkDirGeneric.html#method.into_events)
+//! yields an [`Event`](enum.Event.html) per entry, and an additional
+//! [`Event::LeaveDir`](enum.Event.html#variant.LeaveDir) after the contents of
+//! each directory. This allows keeping a stack of open directories, such as
+//! for writing indented trees or archives.
+//!
+//! ```no_run
+//! use jwalk::events::Event;
+//! use jwalk::WalkDir;
+//!
+//! let mut indent = 0;
+//! for event in WalkDir::new(".").sort(true).into_events() {
+//!     match event? {
+//!         Event::EnterDir(dir_entry) => {
+//!             println!("{:indent$}{}/", "", dir_entry.file_name.to_string_lossy());
+//!             indent += 2;
+//!         }
+//!         Event::File(dir_entry) => {
+//!             println!("{:indent$}{}", "", dir_entry.file_name.to_string_lossy());
+//!         }
+//!         Event::LeaveDir { .. } => indent -= 2,
+//!     }
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::path::PathBuf;
+
+use crate::{ClientState, DirEntry, DirEntryIter, Result, WalkDirGeneric};
+
+/// Item of [`DirEventIter`](struct.DirEventIter.html).
+#[derive(Debug)]
+pub enum Event<C: ClientState> {
+    /// A directory, followed by the events of its contents and a
+    /// `LeaveDir` event.
+    EnterDir(DirEntry<C>),
+    /// Any entry that isn't a directory, including symlinks that aren't
+    /// followed.
+    File(DirEntry<C>),
+    /// All contents of the directory at `path` were yielded.
+    LeaveDir {
+        /// Path of the directory, as returned by
+        /// [`DirEntry::path`](../struct.DirEntry.html#method.path).
+        path: PathBuf,
+        /// Depth of the directory.
+        depth: usize,
+    },
+}
+
+/// Event iterator from
+/// [`WalkDirGeneric::into_events`](../struct.WalkDirGeneric.html#method.into_events).
+pub struct DirEventIter<C: ClientState> {
+    dir_entry_iter: DirEntryIter<C>,
+    // Paths and depths of the directories that were entered but not left
+    open_dirs: Vec<(PathBuf, usize)>,
+    // Entry to yield once the directories it isn't inside of were left
+    next_dir_entry: Option<Result<DirEntry<C>>>,
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Create an iterator yielding the entries of
+    /// [`into_iter`](struct.WalkDirGeneric.html#method.into_iter) as events,
+    /// see the [`events`](events/index.html) module.
+    ///
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first)
+    /// is ignored, directories are always entered before their contents.
+    pub fn into_events(self) -> DirEventIter<C> {
+        DirEventIter {
+            dir_entry_iter: self.contents_first(false).into_iter(),
+            open_dirs: Vec::new(),
+            next_dir_entry: None,
+        }
+    }
+}
+
+impl<C: ClientState> Iterator for DirEventIter<C> {
+    type Item = Result<Event<C>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_dir_entry.is_none() {
+            self.next_dir_entry = self.dir_entry_iter.next();
+        }
+        let left_open_dir = match self.next_dir_entry.as_ref() {
+            Some(Ok(dir_entry)) => self
+                .open_dirs
+                .last()
+                .is_some_and(|(path, _)| !dir_entry.parent_path.starts_with(path)),
+            Some(Err(_)) => false,
+            None => true,
+        };
+        if left_open_dir {
+            return self
+                .open_dirs
+                .pop()
+                .map(|(path, depth)| Ok(Event::LeaveDir { path, depth }));
+        }
+        self.next_dir_entry.take().map(|dir_entry_result| {
+            dir_entry_result.map(|dir_entry| {
+                if dir_entry.file_type.is_dir() {
+                    self.open_dirs.push((dir_entry.path(), dir_entry.depth));
+                    Event::EnterDir(dir_entry)
+                } else {
+                    Event::File(dir_entry)
+                }
+            })
+        })
+    }
+}
@@ -0,0 +1,564 @@
+//! Walking sources other than the local file system.
+//!
+//! Walks read directories through a [`FileSystem`](trait.FileSystem.html),
+//! [`StdFileSystem`](struct.StdFileSystem.html) unless another one is passed
+//! to [`WalkDirGeneric::file_system`](../struct.WalkDirGeneric.html#method.file_system).
+//! Other implementations can walk virtual trees in tests or sources that
+//! aren't local, with the same scheduling, filtering and sorting.
+//! [`Latency`](struct.Latency.html) wraps another one to inject delays and
+//! failures.
+//!
+//! Options that inspect the local file system directly, such as
+//! [`same_file_system`](../struct.WalkDirGeneric.html#method.same_file_system),
+//! [`exclude_paths`](../struct.WalkDirGeneric.html#method.exclude_paths) and
+//! [`git_ignore`](../struct.WalkDirGeneric.html#method.git_ignore), as well as
+//! [`for_each_ref`](../struct.WalkDirGeneric.html#method.for_each_ref), keep
+//! using `std::fs`.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Source of the directories of a walk.
+///
+/// Methods are called from the threads reading directories.
+pub trait FileSystem: Send + Sync + 'static {
+    /// Read the entries of the directory at `path`, in any order.
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>>;
+
+    /// The type of the entry at `path`, of the target of a symlink if
+    /// `follow_links` is `true`.
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType>;
+
+    /// The metadata of the entry at `path`, of the target of a symlink if
+    /// `follow_links` is `true`, for
+    /// [`DirEntry::metadata`](../struct.DirEntry.html#method.metadata).
+    ///
+    /// Fails with `io::ErrorKind::Unsupported` by default, as `std::fs::Metadata`
+    /// can only be read from the local file system.
+    fn metadata(&self, path: &Path, follow_links: bool) -> io::Result<fs::Metadata> {
+        let _ = (path, follow_links);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "file system doesn't provide metadata",
+        ))
+    }
+
+    /// The target of the symlink at `path`, used to detect loops when
+    /// following links.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// The absolute path of `path` with all symlinks resolved, used to look
+    /// up entries in
+    /// [`exclude_paths`](../struct.WalkDirGeneric.html#method.exclude_paths).
+    ///
+    /// Fails with `io::ErrorKind::Unsupported` by default, so no entries are
+    /// excluded by path.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "file system doesn't canonicalize paths",
+        ))
+    }
+
+    /// The contents of the file at `path`, used to read the per-directory
+    /// files of [filter rules](../filter/index.html), such as `.gitignore`.
+    ///
+    /// Fails with `io::ErrorKind::Unsupported` by default, which is treated
+    /// like a missing file.
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "file system doesn't read files",
+        ))
+    }
+}
+
+/// Entries returned by [`FileSystem::read_dir`](trait.FileSystem.html#tymethod.read_dir).
+pub type ReadDirEntries<'a> = Box<dyn Iterator<Item = io::Result<FileSystemEntry>> + 'a>;
+
+// Entries read by `jwalk` itself, which can be sent to the thread reading
+// the next chunk of their directory
+pub(crate) type SendReadDirEntries = Box<dyn Iterator<Item = io::Result<FileSystemEntry>> + Send>;
+
+/// Entry of a directory read by a [`FileSystem`](trait.FileSystem.html).
+#[derive(Debug)]
+pub struct FileSystemEntry {
+    /// File name of the entry without leading path component.
+    pub file_name: OsString,
+    /// Type of the entry, of the symlink itself for symlinks.
+    pub file_type: io::Result<FileType>,
+}
+
+/// Type of an entry, like `std::fs::FileType` but also available for entries
+/// that aren't read from the local file system.
+///
+/// Types compare equal regardless of their [`source`](struct.FileType.html#method.source)
+/// and [`reparse_tag`](struct.FileType.html#method.reparse_tag).
+#[derive(Clone, Copy, Debug)]
+pub struct FileType {
+    kind: FileTypeKind,
+    source: FileTypeSource,
+    reparse_tag: Option<ReparseTag>,
+}
+
+/// How the type of an entry was read, see
+/// [`FileType::source`](struct.FileType.html#method.source).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileTypeSource {
+    /// Read with the directory entry, such as its `d_type` on Unix, without
+    /// a system call per entry.
+    DirEntry,
+    /// Read with a system call per entry, such as `lstat`, because the
+    /// directory entry doesn't include it, for roots or for followed links.
+    Stat,
+    /// Read with `std::fs::DirEntry::file_type`, which uses the directory
+    /// entry where possible and stats the entry otherwise. Use
+    /// [`ReadDirBackend::Getdents`](../enum.ReadDirBackend.html#variant.Getdents)
+    /// on Linux to know which.
+    Std,
+}
+
+/// Kind of a reparse point on Windows, see
+/// [`FileType::reparse_tag`](struct.FileType.html#method.reparse_tag).
+///
+/// `std::fs::FileType::is_symlink` is `true` for both symlinks and mount
+/// points, entries with other tags are files or directories.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReparseTag {
+    /// A symlink, `IO_REPARSE_TAG_SYMLINK`.
+    Symlink,
+    /// A directory junction or a volume mount point,
+    /// `IO_REPARSE_TAG_MOUNT_POINT`. See
+    /// [`WalkDirGeneric::follow_junctions`](../struct.WalkDirGeneric.html#method.follow_junctions).
+    MountPoint,
+    /// An app execution alias, such as the `python.exe` aliases of the
+    /// Microsoft Store, `IO_REPARSE_TAG_APPEXECLINK`.
+    AppExecLink,
+    /// Any other tag, such as of cloud file placeholders or deduplicated
+    /// files.
+    Other(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum FileTypeKind {
+    Std(fs::FileType),
+    Dir,
+    File,
+    Symlink,
+}
+
+/// The local file system, read with `std::fs`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdFileSystem;
+
+impl FileType {
+    /// The type of a directory.
+    pub fn dir() -> FileType {
+        FileType {
+            kind: FileTypeKind::Dir,
+            source: FileTypeSource::DirEntry,
+            reparse_tag: None,
+        }
+    }
+
+    /// The type of a regular file.
+    pub fn file() -> FileType {
+        FileType {
+            kind: FileTypeKind::File,
+            source: FileTypeSource::DirEntry,
+            reparse_tag: None,
+        }
+    }
+
+    /// The type of a symlink.
+    pub fn symlink() -> FileType {
+        FileType {
+            kind: FileTypeKind::Symlink,
+            source: FileTypeSource::DirEntry,
+            reparse_tag: None,
+        }
+    }
+
+    /// Returns `true` for directories.
+    pub fn is_dir(&self) -> bool {
+        match self.kind {
+            FileTypeKind::Std(file_type) => file_type.is_dir(),
+            kind => kind == FileTypeKind::Dir,
+        }
+    }
+
+    /// Returns `true` for regular files.
+    pub fn is_file(&self) -> bool {
+        match self.kind {
+            FileTypeKind::Std(file_type) => file_type.is_file(),
+            kind => kind == FileTypeKind::File,
+        }
+    }
+
+    /// Returns `true` for symlinks.
+    pub fn is_symlink(&self) -> bool {
+        match self.kind {
+            FileTypeKind::Std(file_type) => file_type.is_symlink(),
+            kind => kind == FileTypeKind::Symlink,
+        }
+    }
+
+    /// How this type was read. Defaults to
+    /// [`FileTypeSource::DirEntry`](enum.FileTypeSource.html#variant.DirEntry)
+    /// for types created with [`dir`](struct.FileType.html#method.dir),
+    /// [`file`](struct.FileType.html#method.file) and
+    /// [`symlink`](struct.FileType.html#method.symlink), and to
+    /// [`FileTypeSource::Std`](enum.FileTypeSource.html#variant.Std) for
+    /// types converted from `std::fs::FileType`.
+    pub fn source(&self) -> FileTypeSource {
+        self.source
+    }
+
+    /// This type read with `source`.
+    pub fn with_source(mut self, source: FileTypeSource) -> FileType {
+        self.source = source;
+        self
+    }
+
+    /// The tag of the entry if it's a reparse point on Windows, `None` for
+    /// other entries and on other platforms.
+    ///
+    /// Read with the directory entry by
+    /// [`ReadDirBackend::NtQueryDirectoryFile`](../enum.ReadDirBackend.html#variant.NtQueryDirectoryFile),
+    /// otherwise with an extra system call per reparse point.
+    pub fn reparse_tag(&self) -> Option<ReparseTag> {
+        self.reparse_tag
+    }
+
+    /// This type with the tag of a reparse point.
+    pub fn with_reparse_tag(mut self, reparse_tag: Option<ReparseTag>) -> FileType {
+        self.reparse_tag = reparse_tag;
+        self
+    }
+
+    /// The `std::fs::FileType` this type was read as, `None` if it wasn't
+    /// read from the local file system. Allows checking platform specific
+    /// types, such as with `std::os::unix::fs::FileTypeExt`.
+    pub fn as_std(&self) -> Option<fs::FileType> {
+        match self.kind {
+            FileTypeKind::Std(file_type) => Some(file_type),
+            _ => None,
+        }
+    }
+}
+
+impl ReparseTag {
+    const SYMLINK: u32 = 0xA000_000C;
+    const MOUNT_POINT: u32 = 0xA000_0003;
+    const APPEXECLINK: u32 = 0x8000_001B;
+
+    /// The kind of the reparse point with the raw tag `tag`.
+    pub fn from_raw(tag: u32) -> ReparseTag {
+        match tag {
+            ReparseTag::SYMLINK => ReparseTag::Symlink,
+            ReparseTag::MOUNT_POINT => ReparseTag::MountPoint,
+            ReparseTag::APPEXECLINK => ReparseTag::AppExecLink,
+            tag => ReparseTag::Other(tag),
+        }
+    }
+
+    /// The raw tag, an `IO_REPARSE_TAG_*` value.
+    pub fn as_raw(&self) -> u32 {
+        match *self {
+            ReparseTag::Symlink => ReparseTag::SYMLINK,
+            ReparseTag::MountPoint => ReparseTag::MOUNT_POINT,
+            ReparseTag::AppExecLink => ReparseTag::APPEXECLINK,
+            ReparseTag::Other(tag) => tag,
+        }
+    }
+}
+
+impl PartialEq for FileType {
+    fn eq(&self, other: &FileType) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for FileType {}
+
+impl std::hash::Hash for FileType {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.kind.hash(state);
+    }
+}
+
+impl From<fs::FileType> for FileType {
+    fn from(file_type: fs::FileType) -> FileType {
+        FileType {
+            kind: FileTypeKind::Std(file_type),
+            source: FileTypeSource::Std,
+            reparse_tag: None,
+        }
+    }
+}
+
+impl FileSystem for StdFileSystem {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        Ok(read_std_dir(path)?)
+    }
+
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType> {
+        self.metadata(path, follow_links)
+            .map(|metadata| with_reparse_tag(metadata.file_type().into(), &metadata, path))
+    }
+
+    fn metadata(&self, path: &Path, follow_links: bool) -> io::Result<fs::Metadata> {
+        if follow_links {
+            fs::metadata(path)
+        } else {
+            fs::symlink_metadata(path)
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// A [`FileSystem`](trait.FileSystem.html) delaying and failing reads of
+/// scripted paths of another one, to test timeouts, retries and backpressure
+/// deterministically.
+///
+/// Delays and failures apply to
+/// [`read_dir`](trait.FileSystem.html#tymethod.read_dir) and
+/// [`file_type`](trait.FileSystem.html#tymethod.file_type) calls for exactly
+/// the scripted path, and add up if a path is scripted several times. Clones
+/// share the count of failures left.
+///
+/// ```
+/// use jwalk::file_system::Latency;
+/// use jwalk::memfs::MemoryFs;
+/// use jwalk::WalkDir;
+/// use std::io;
+/// use std::path::Path;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let mut memory_fs = MemoryFs::new();
+/// memory_fs.add_file("/slow/file");
+/// memory_fs.add_file("/flaky/file");
+///
+/// let latency = Latency::new(memory_fs)
+///     .delay_read_dir("/slow", Duration::from_millis(10))
+///     .fail_read_dir("/flaky", io::ErrorKind::TimedOut, 1);
+/// for dir_entry in WalkDir::new("/").file_system(Some(Arc::new(latency))) {
+///     let dir_entry = dir_entry.unwrap();
+///     if let Some(err) = &dir_entry.read_children_error {
+///         assert_eq!(dir_entry.path(), Path::new("/flaky"));
+///         assert_eq!(err.io_error().unwrap().kind(), io::ErrorKind::TimedOut);
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Latency<F> {
+    file_system: F,
+    read_dir_delays: Vec<(PathBuf, Duration)>,
+    file_type_delays: Vec<(PathBuf, Duration)>,
+    read_dir_failures: Vec<Failure>,
+    file_type_failures: Vec<Failure>,
+}
+
+// Failures left for reads of a path
+#[derive(Clone, Debug)]
+struct Failure {
+    path: PathBuf,
+    kind: io::ErrorKind,
+    remaining: Arc<AtomicUsize>,
+}
+
+impl<F: FileSystem> Latency<F> {
+    /// Wrap `file_system` without any delays or failures.
+    pub fn new(file_system: F) -> Latency<F> {
+        Latency {
+            file_system,
+            read_dir_delays: Vec::new(),
+            file_type_delays: Vec::new(),
+            read_dir_failures: Vec::new(),
+            file_type_failures: Vec::new(),
+        }
+    }
+
+    /// Delay reading the directory at `path` by `duration`.
+    pub fn delay_read_dir<P: AsRef<Path>>(mut self, path: P, duration: Duration) -> Latency<F> {
+        self.read_dir_delays
+            .push((path.as_ref().to_path_buf(), duration));
+        self
+    }
+
+    /// Delay reading the type of the entry at `path` by `duration`.
+    pub fn delay_file_type<P: AsRef<Path>>(mut self, path: P, duration: Duration) -> Latency<F> {
+        self.file_type_delays
+            .push((path.as_ref().to_path_buf(), duration));
+        self
+    }
+
+    /// Fail the next `times` reads of the directory at `path` with an error
+    /// of `kind`, after their delay. Later reads succeed, use `usize::MAX` to
+    /// fail all of them.
+    pub fn fail_read_dir<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        kind: io::ErrorKind,
+        times: usize,
+    ) -> Latency<F> {
+        self.read_dir_failures
+            .push(Failure::new(path.as_ref(), kind, times));
+        self
+    }
+
+    /// Fail the next `times` reads of the type of the entry at `path` with an
+    /// error of `kind`, after their delay. Later reads succeed, use
+    /// `usize::MAX` to fail all of them.
+    pub fn fail_file_type<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        kind: io::ErrorKind,
+        times: usize,
+    ) -> Latency<F> {
+        self.file_type_failures
+            .push(Failure::new(path.as_ref(), kind, times));
+        self
+    }
+
+    /// The wrapped file system.
+    pub fn get_ref(&self) -> &F {
+        &self.file_system
+    }
+}
+
+impl<F: FileSystem> FileSystem for Latency<F> {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        inject(path, &self.read_dir_delays, &self.read_dir_failures)?;
+        self.file_system.read_dir(path)
+    }
+
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType> {
+        inject(path, &self.file_type_delays, &self.file_type_failures)?;
+        self.file_system.file_type(path, follow_links)
+    }
+
+    fn metadata(&self, path: &Path, follow_links: bool) -> io::Result<fs::Metadata> {
+        self.file_system.metadata(path, follow_links)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.file_system.read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.file_system.canonicalize(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.file_system.read_to_string(path)
+    }
+}
+
+impl Failure {
+    fn new(path: &Path, kind: io::ErrorKind, times: usize) -> Failure {
+        Failure {
+            path: path.to_path_buf(),
+            kind,
+            remaining: Arc::new(AtomicUsize::new(times)),
+        }
+    }
+
+    // Takes one of the failures left, if any
+    fn take(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+// Sleeps for the delays scripted for `path`, then takes the first failure
+// left for it.
+fn inject(path: &Path, delays: &[(PathBuf, Duration)], failures: &[Failure]) -> io::Result<()> {
+    for (delayed_path, duration) in delays {
+        if delayed_path == path {
+            thread::sleep(*duration);
+        }
+    }
+    match failures
+        .iter()
+        .find(|failure| failure.path == path && failure.take())
+    {
+        Some(failure) => Err(io::Error::new(
+            failure.kind,
+            format!("injected failure for {}", path.display()),
+        )),
+        None => Ok(()),
+    }
+}
+
+// `file_type` with the tag of the entry at `path` if its `metadata` is of a
+// reparse point.
+#[cfg(windows)]
+fn with_reparse_tag(file_type: FileType, metadata: &fs::Metadata, path: &Path) -> FileType {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+    if metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+        return file_type;
+    }
+    file_type.with_reparse_tag(
+        crate::core::reparse_tag(path)
+            .ok()
+            .map(ReparseTag::from_raw),
+    )
+}
+
+#[cfg(not(windows))]
+fn with_reparse_tag(file_type: FileType, _metadata: &fs::Metadata, _path: &Path) -> FileType {
+    file_type
+}
+
+// The file system of entries without one, which are read with `std::fs`.
+/// Reads the directory at `path` like
+/// [`StdFileSystem`](struct.StdFileSystem.html), with entries that can be
+/// kept between reads of its chunks.
+pub(crate) fn read_std_dir(path: &Path) -> io::Result<SendReadDirEntries> {
+    let read_dir = fs::read_dir(path)?;
+    Ok(Box::new(read_dir.map(|fs_dir_entry| {
+        let fs_dir_entry = fs_dir_entry?;
+        let file_type = fs_dir_entry.file_type().map(FileType::from);
+        // Free on Windows, the metadata is read with the directory entry
+        #[cfg(windows)]
+        let file_type = file_type.map(|file_type| match fs_dir_entry.metadata() {
+            Ok(metadata) => with_reparse_tag(file_type, &metadata, &fs_dir_entry.path()),
+            Err(_) => file_type,
+        });
+        Ok(FileSystemEntry {
+            file_name: fs_dir_entry.file_name(),
+            file_type,
+        })
+    })))
+}
+
+pub(crate) fn or_std(file_system: Option<&dyn FileSystem>) -> &dyn FileSystem {
+    file_system.unwrap_or(&StdFileSystem)
+}
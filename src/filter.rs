@@ -0,0 +1,462 @@
+//! `rsync` style filter rules, available with the `filter` feature.
+//!
+//! [`FilterRules`](struct.FilterRules.html) holds an ordered list of include
+//! and exclude rules. Each entry is checked against the rules in order and the
+//! first matching rule decides if it is included or excluded. Entries that
+//! don't match any rule are included. Excluded directories are not descended
+//! into.
+//!
+//! Rules can be parsed from the text of `rsync` filter files. Each line holds
+//! one rule, blank lines and lines starting with `#` or `;` are ignored:
+//!
+//! ```text
+//! # Rules as accepted by rsync's --filter option
+//! + /src/**.rs
+//! - *.o
+//! exclude /target/
+//! hide .cache/
+//! # Read per directory rules from .rsync-filter files
+//! : .rsync-filter
+//! ```
+//!
+//! Supported rules are `include`/`+`, `exclude`/`-`, `show`/`S`, `hide`/`H`,
+//! `dir-merge`/`:` (with the `n` and `e` modifiers) and `clear`/`!`. The
+//! receiver side `protect`/`P` and `risk`/`R` rules are accepted and ignored.
+//! Rules from `dir-merge` files found in a directory take precedence over the
+//! inherited rules and apply to that directory and its descendants, anchored
+//! patterns in them are anchored at the directory holding the file.
+//!
+//! Exclude lists as used by `tar --exclude-from` can be loaded with
+//! [`FilterRules::parse_tar_excludes`](struct.FilterRules.html#method.parse_tar_excludes)
+//! and `.gitignore` files with
+//! [`FilterRules::parse_git_ignore`](struct.FilterRules.html#method.parse_git_ignore).
+//!
+//! See the [`pattern`](../pattern/index.html) module for the pattern syntax.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::file_system::FileSystem;
+use crate::pattern::{slash_separated, Pattern};
+
+/// Action taken for entries that match a filter rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Yield matching entries and descend into matching directories.
+    Include,
+    /// Skip matching entries and don't descend into matching directories.
+    Exclude,
+}
+
+/// A single filter rule.
+#[derive(Clone, Debug)]
+pub enum FilterRule {
+    /// Include or exclude entries matching the pattern.
+    Match {
+        /// Action to take for matching entries.
+        action: FilterAction,
+        /// Pattern matched against entry paths relative to the root of the
+        /// walk.
+        pattern: Pattern,
+    },
+    /// Read additional rules from a file with this name in every directory.
+    DirMerge {
+        /// Name of the file holding the per directory rules.
+        file_name: String,
+        /// If `false` the rules of a file only apply to the entries of the
+        /// directory holding the file, not to its descendants.
+        inherit: bool,
+    },
+}
+
+/// An ordered list of filter rules.
+///
+/// See the [module documentation](index.html) for details.
+#[derive(Clone, Debug, Default)]
+pub struct FilterRules {
+    rules: Vec<FilterRule>,
+}
+
+/// An error produced when parsing invalid filter rules.
+#[derive(Clone, Debug)]
+pub struct FilterError {
+    line: usize,
+    message: String,
+}
+
+impl FilterRules {
+    /// Create an empty list of rules which includes all entries.
+    pub fn new() -> FilterRules {
+        FilterRules::default()
+    }
+
+    /// Parse rules from the text of an `rsync` filter file.
+    pub fn parse(text: &str) -> Result<FilterRules, FilterError> {
+        let mut rules = FilterRules::new();
+        for (i, line) in text.lines().enumerate() {
+            rules.parse_line(line).map_err(|message| FilterError {
+                line: i + 1,
+                message,
+            })?;
+        }
+        Ok(rules)
+    }
+
+    /// Read and parse rules from an `rsync` filter file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<FilterRules> {
+        let text = fs::read_to_string(path)?;
+        FilterRules::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Parse a GNU `tar` style exclude list, as read by `tar --exclude-from`.
+    ///
+    /// Each line holds one pattern which is parsed with
+    /// [`Pattern::tar`](../pattern/struct.Pattern.html#method.tar) and added
+    /// as an exclude rule. Blank lines and lines starting with `#` are
+    /// ignored.
+    pub fn parse_tar_excludes(text: &str) -> Result<FilterRules, FilterError> {
+        let mut rules = FilterRules::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let pattern = Pattern::tar(line).map_err(|err| FilterError {
+                line: i + 1,
+                message: err.to_string(),
+            })?;
+            rules = rules.exclude(pattern);
+        }
+        Ok(rules)
+    }
+
+    /// Read and parse a GNU `tar` style exclude file, see
+    /// [`parse_tar_excludes`](struct.FilterRules.html#method.parse_tar_excludes).
+    pub fn from_tar_exclude_file<P: AsRef<Path>>(path: P) -> io::Result<FilterRules> {
+        let text = fs::read_to_string(path)?;
+        FilterRules::parse_tar_excludes(&text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Parse the text of a `.gitignore` file.
+    ///
+    /// As in git, later patterns take precedence over earlier ones, a leading
+    /// `!` includes entries excluded by an earlier pattern and patterns with
+    /// a `/` at the start or in the middle are anchored at root, while a
+    /// leading `**/` matches in all directories. The rules are stored in
+    /// reverse order so the first matching rule decides.
+    ///
+    /// Use [`WalkDirGeneric::git_ignore`](../struct.WalkDirGeneric.html#method.git_ignore)
+    /// to apply the `.gitignore` files found while walking.
+    pub fn parse_git_ignore(text: &str) -> Result<FilterRules, FilterError> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let mut line = line.trim_end_matches('\r');
+            while line.ends_with(' ') && !line.ends_with("\\ ") {
+                line = &line[..line.len() - 1];
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (action, mut body) = match line.strip_prefix('!') {
+                Some(body) => (FilterAction::Include, body),
+                None => (FilterAction::Exclude, line),
+            };
+            let mut unanchored = false;
+            while let Some(rest) = body.strip_prefix("**/") {
+                body = rest;
+                unanchored = true;
+            }
+            let anchor =
+                !unanchored && !body.starts_with('/') && body.trim_end_matches('/').contains('/');
+            let body = if anchor {
+                format!("/{}", body)
+            } else {
+                body.to_owned()
+            };
+            let pattern = Pattern::new(&body).map_err(|err| FilterError {
+                line: i + 1,
+                message: err.to_string(),
+            })?;
+            rules.push(FilterRule::Match { action, pattern });
+        }
+        rules.reverse();
+        Ok(FilterRules { rules })
+    }
+
+    /// Append a rule, like `rsync --filter`.
+    pub fn push(&mut self, rule: FilterRule) {
+        self.rules.push(rule);
+    }
+
+    /// Append an include rule, like `rsync --include`.
+    pub fn include(mut self, pattern: Pattern) -> FilterRules {
+        self.push(FilterRule::Match {
+            action: FilterAction::Include,
+            pattern,
+        });
+        self
+    }
+
+    /// Append an exclude rule, like `rsync --exclude`.
+    pub fn exclude(mut self, pattern: Pattern) -> FilterRules {
+        self.push(FilterRule::Match {
+            action: FilterAction::Exclude,
+            pattern,
+        });
+        self
+    }
+
+    /// Append all rules of `other`.
+    pub fn extend(mut self, other: FilterRules) -> FilterRules {
+        self.rules.extend(other.rules);
+        self
+    }
+
+    /// The rules in the order they are checked.
+    pub fn rules(&self) -> &[FilterRule] {
+        &self.rules
+    }
+
+    /// Returns `true` if there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the action of the first rule matching `path`, relative to the
+    /// root of the walk. `is_dir` tells if `path` refers to a directory.
+    ///
+    /// `dir-merge` rules are ignored, they only take effect while walking.
+    pub fn action(&self, path: &Path, is_dir: bool) -> Option<FilterAction> {
+        self.rules.iter().find_map(|rule| match rule {
+            FilterRule::Match { action, pattern } if pattern.matches(path, is_dir) => Some(*action),
+            _ => None,
+        })
+    }
+
+    /// Returns `true` if `path`, relative to the root of the walk, is
+    /// excluded by these rules.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.action(path, is_dir) == Some(FilterAction::Exclude)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return Ok(());
+        }
+
+        let (name, modifiers, argument) = split_rule(line)?;
+        let pattern = || Pattern::new(argument).map_err(|err| err.to_string());
+        match name {
+            "include" | "+" | "show" | "S" => {
+                check_modifiers(name, modifiers, "")?;
+                self.push(FilterRule::Match {
+                    action: FilterAction::Include,
+                    pattern: pattern()?,
+                })
+            }
+            "exclude" | "-" | "hide" | "H" => {
+                check_modifiers(name, modifiers, "")?;
+                self.push(FilterRule::Match {
+                    action: FilterAction::Exclude,
+                    pattern: pattern()?,
+                })
+            }
+            "protect" | "P" | "risk" | "R" => {}
+            "dir-merge" | ":" => {
+                check_modifiers(name, modifiers, "ne")?;
+                if argument.is_empty() {
+                    return Err("missing dir-merge file name".into());
+                }
+                if modifiers.contains('e') {
+                    self.push(FilterRule::Match {
+                        action: FilterAction::Exclude,
+                        pattern: Pattern::new(argument).map_err(|err| err.to_string())?,
+                    });
+                }
+                self.push(FilterRule::DirMerge {
+                    file_name: argument.to_owned(),
+                    inherit: !modifiers.contains('n'),
+                })
+            }
+            "clear" | "!" => self.rules.clear(),
+            _ => return Err(format!("unsupported filter rule {:?}", name)),
+        }
+        Ok(())
+    }
+}
+
+impl FilterError {
+    /// The line number, starting at 1, of the invalid rule.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl error::Error for FilterError {}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid filter rule on line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+// Splits a rule into its name, modifiers and argument.
+fn split_rule(line: &str) -> Result<(&str, &str, &str), String> {
+    let (head, argument) = match line.find([' ', '_']) {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => (line, ""),
+    };
+    let (name, modifiers) = match head.find(',') {
+        Some(i) => (&head[..i], &head[i + 1..]),
+        None if head.len() > 1 && !head.chars().next().unwrap().is_ascii_alphabetic() => {
+            head.split_at(1)
+        }
+        None => (head, ""),
+    };
+    if name.is_empty() {
+        return Err("missing filter rule".into());
+    }
+    Ok((name, modifiers, argument))
+}
+
+fn check_modifiers(name: &str, modifiers: &str, supported: &str) -> Result<(), String> {
+    match modifiers.chars().find(|&c| !supported.contains(c)) {
+        Some(modifier) => Err(format!(
+            "unsupported modifier {:?} for filter rule {:?}",
+            modifier, name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Filter rules in effect for a directory during the walk.
+#[derive(Debug, Default)]
+pub(crate) struct DirFilterRules {
+    rules: Vec<ScopedFilterRule>,
+}
+
+#[derive(Clone, Debug)]
+struct ScopedFilterRule {
+    rule: FilterRule,
+    // Number of leading path components to strip before matching, which
+    // anchors rules from dir-merge files at their directory.
+    base_depth: usize,
+    inherit: bool,
+    // dir-merge files in .gitignore instead of rsync syntax
+    git_ignore: bool,
+}
+
+impl DirFilterRules {
+    pub(crate) fn new(rules: Option<&FilterRules>, git_ignore: bool) -> Arc<DirFilterRules> {
+        let mut scoped_rules: Vec<_> = rules
+            .iter()
+            .flat_map(|rules| rules.rules.iter())
+            .map(|rule| ScopedFilterRule {
+                rule: rule.clone(),
+                base_depth: 0,
+                inherit: true,
+                git_ignore: false,
+            })
+            .collect();
+        if git_ignore {
+            scoped_rules.push(ScopedFilterRule {
+                rule: FilterRule::DirMerge {
+                    file_name: ".gitignore".into(),
+                    inherit: true,
+                },
+                base_depth: 0,
+                inherit: true,
+                git_ignore: true,
+            });
+        }
+        Arc::new(DirFilterRules {
+            rules: scoped_rules,
+        })
+    }
+
+    /// Merges the rules of `dir-merge` files found in `dir`, at `depth`
+    /// relative to root, read from `file_system`. Returns the rules for the entries of `dir` and the
+    /// rules inherited by its subdirectories.
+    pub(crate) fn enter_dir(
+        self: &Arc<Self>,
+        dir: &Path,
+        depth: usize,
+        file_system: &dyn FileSystem,
+    ) -> io::Result<(Arc<DirFilterRules>, Arc<DirFilterRules>)> {
+        let mut merged = Vec::new();
+        let mut clears_inherited = false;
+        for scoped in &self.rules {
+            if let FilterRule::DirMerge { file_name, inherit } = &scoped.rule {
+                let text = match file_system.read_to_string(&dir.join(file_name)) {
+                    Ok(text) => text,
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::NotFound | io::ErrorKind::Unsupported
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(err) => return Err(err),
+                };
+                let rules = if scoped.git_ignore {
+                    FilterRules::parse_git_ignore(&text)
+                } else {
+                    clears_inherited |= text
+                        .lines()
+                        .any(|line| matches!(line.trim_end_matches('\r'), "!" | "clear"));
+                    FilterRules::parse(&text)
+                }
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                merged.extend(rules.rules.into_iter().map(|rule| ScopedFilterRule {
+                    rule,
+                    base_depth: depth,
+                    inherit: *inherit,
+                    git_ignore: false,
+                }));
+            }
+        }
+        if merged.is_empty() {
+            return Ok((self.clone(), self.clone()));
+        }
+
+        let mut rules = merged;
+        if !clears_inherited {
+            rules.extend(self.rules.iter().cloned());
+        }
+        let inherited = DirFilterRules {
+            rules: rules.iter().filter(|rule| rule.inherit).cloned().collect(),
+        };
+        Ok((Arc::new(DirFilterRules { rules }), Arc::new(inherited)))
+    }
+
+    /// Returns `true` if `path`, relative to root, is excluded.
+    pub(crate) fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let path = slash_separated(path);
+        for scoped in &self.rules {
+            if let FilterRule::Match { action, pattern } = &scoped.rule {
+                if pattern.is_dir_only() && !is_dir {
+                    continue;
+                }
+                let scoped_path = path
+                    .splitn(scoped.base_depth + 1, '/')
+                    .nth(scoped.base_depth)
+                    .unwrap_or("");
+                if pattern.matches_str(scoped_path) {
+                    return *action == FilterAction::Exclude;
+                }
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,139 @@
+//! Tracking of hardlinked files seen while walking.
+
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Files with several hardlinks seen so far, shared between the threads of a
+/// walk.
+#[derive(Debug, Default)]
+pub(crate) struct HardlinkSet {
+    // device and inode numbers, or volume serial number and file index
+    seen: Mutex<HashSet<(u64, u64)>>,
+}
+
+impl HardlinkSet {
+    /// Records the file with `metadata`, returns whether another link to it
+    /// was recorded before.
+    ///
+    /// Always `false` for directories and on platforms other than Unix.
+    #[cfg(feature = "size")]
+    pub(crate) fn is_duplicate(&self, metadata: &Metadata) -> bool {
+        if metadata.is_dir() {
+            return false;
+        }
+        self.record(hardlinked_file_id(metadata))
+    }
+
+    /// Like [`is_duplicate`](struct.HardlinkSet.html#method.is_duplicate)
+    /// for the file at `path`, also identifying files on Windows by opening
+    /// them.
+    pub(crate) fn is_duplicate_at(&self, path: &Path, metadata: &Metadata) -> bool {
+        if metadata.is_dir() {
+            return false;
+        }
+        #[cfg(windows)]
+        let file_id = hardlinked_file_id_at(path, metadata);
+        #[cfg(not(windows))]
+        let file_id = {
+            let _ = path;
+            hardlinked_file_id(metadata)
+        };
+        self.record(file_id)
+    }
+
+    fn record(&self, file_id: Option<(u64, u64)>) -> bool {
+        match file_id {
+            Some(file_id) => !self.seen.lock().unwrap().insert(file_id),
+            None => false,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hardlinked_file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    if metadata.nlink() > 1 {
+        Some((metadata.dev(), metadata.ino()))
+    } else {
+        None
+    }
+}
+
+#[cfg(all(not(unix), any(feature = "size", not(windows))))]
+fn hardlinked_file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// The volume serial number and file index of the file at `path` if it has
+// several links. `metadata` tells whether `path` is a symlink that wasn't
+// followed.
+#[cfg(windows)]
+fn hardlinked_file_id_at(path: &Path, metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::ffi::c_void;
+    use std::fs::OpenOptions;
+    use std::mem::MaybeUninit;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+
+    // `BY_HANDLE_FILE_INFORMATION`, only some fields are read
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct ByHandleFileInformation {
+        file_attributes: u32,
+        creation_time: [u32; 2],
+        last_access_time: [u32; 2],
+        last_write_time: [u32; 2],
+        volume_serial_number: u32,
+        file_size_high: u32,
+        file_size_low: u32,
+        number_of_links: u32,
+        file_index_high: u32,
+        file_index_low: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetFileInformationByHandle(
+            file: *mut c_void,
+            file_information: *mut ByHandleFileInformation,
+        ) -> i32;
+    }
+
+    // Required to open directories.
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    // Opens the reparse point itself instead of its target.
+    const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+    let mut flags = FILE_FLAG_BACKUP_SEMANTICS;
+    if metadata.file_type().is_symlink() {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT;
+    }
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(flags)
+        .open(path)
+        .ok()?;
+    let mut information = MaybeUninit::<ByHandleFileInformation>::uninit();
+    // SAFETY: `file` is a valid open handle and `information` is large
+    // enough to hold the structure written by the call.
+    let information = unsafe {
+        if GetFileInformationByHandle(
+            file.as_raw_handle() as *mut c_void,
+            information.as_mut_ptr(),
+        ) == 0
+        {
+            return None;
+        }
+        information.assume_init()
+    };
+    if information.number_of_links > 1 {
+        let file_index =
+            ((information.file_index_high as u64) << 32) | information.file_index_low as u64;
+        Some((information.volume_serial_number as u64, file_index))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,122 @@
+//! Hash file contents while walking, available with the `hash` feature.
+//!
+//! [`WalkDirGeneric::hash_files`](../struct.WalkDirGeneric.html#method.hash_files)
+//! reads and hashes every file on the threads reading directories, and stores
+//! the digest in the entry's `client_state`. Dedupe and integrity tools get
+//! the digests with the same parallelism as the walk.
+//!
+//! Any [`Hasher`](trait.Hasher.html) can be used. Implementations for
+//! `blake3::Hasher` and `sha2::Sha256` are available with the `blake3` and
+//! `sha2` features.
+//!
+//! ```no_run
+//! # #[cfg(feature = "blake3")]
+//! # fn main() -> Result<(), jwalk::Error> {
+//! use jwalk::hash::HashState;
+//! use jwalk::WalkDirGeneric;
+//!
+//! for dir_entry in WalkDirGeneric::<HashState>::new(".").hash_files::<blake3::Hasher>() {
+//!     let dir_entry = dir_entry?;
+//!     if let Some(digest) = dir_entry.client_state.as_ref() {
+//!         println!("{:02x?} {}", digest, dir_entry.path().display());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "blake3"))]
+//! # fn main() {}
+//! ```
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::{Error, WalkDirGeneric};
+
+/// Client state of a walk hashing files, each file entry carries the digest
+/// of its contents.
+pub type HashState = ((), Option<Vec<u8>>);
+
+// Size of the buffer files are read with
+const READ_BUFFER: usize = 64 * 1024;
+
+/// Incremental hash function for
+/// [`hash_files`](../struct.WalkDirGeneric.html#method.hash_files).
+///
+/// A new hasher is created with `Default` for every file.
+pub trait Hasher: Default {
+    /// Add the next chunk of the file contents.
+    fn update(&mut self, data: &[u8]);
+
+    /// The digest of all chunks passed to
+    /// [`update`](trait.Hasher.html#tymethod.update).
+    fn finish(self) -> Vec<u8>;
+}
+
+#[cfg(feature = "blake3")]
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.finalize().as_bytes().to_vec()
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl Hasher for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        sha2::Digest::finalize(self).to_vec()
+    }
+}
+
+impl WalkDirGeneric<HashState> {
+    /// Hash the contents of every file with a new `H` and store the digest in
+    /// its [`client_state`](struct.DirEntry.html#structfield.client_state),
+    /// see the [`hash`](hash/index.html) module.
+    ///
+    /// Files are hashed on the thread reading their directory, after the
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback set before this. Setting `process_read_dir` afterwards
+    /// replaces the hashing. Directories and other entries that aren't
+    /// regular files keep `None`, files that can't be read are yielded as
+    /// errors in place of them.
+    pub fn hash_files<H: Hasher>(self) -> Self {
+        let process_read_dir = self.options.process_read_dir.clone();
+        self.process_read_dir(move |depth, path, read_dir_state, children| {
+            if let Some(process_read_dir) = process_read_dir.as_ref() {
+                process_read_dir(depth, path, read_dir_state, children);
+            }
+            let mut buffer = Vec::new();
+            for dir_entry_result in children.iter_mut() {
+                let dir_entry = match dir_entry_result {
+                    Ok(dir_entry) if dir_entry.file_type.is_file() => dir_entry,
+                    _ => continue,
+                };
+                buffer.resize(READ_BUFFER, 0);
+                match hash_file::<H>(&dir_entry.path(), &mut buffer) {
+                    Ok(digest) => dir_entry.client_state = Some(digest),
+                    Err(err) => *dir_entry_result = Err(Error::from_entry(dir_entry, err)),
+                }
+            }
+        })
+    }
+}
+
+fn hash_file<H: Hasher>(path: &Path, buffer: &mut [u8]) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut hasher = H::default();
+    loop {
+        match file.read(buffer) {
+            Ok(0) => return Ok(hasher.finish()),
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
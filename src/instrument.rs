@@ -0,0 +1,262 @@
+//! Hooks for profiling and accounting of walks.
+//!
+//! Implement [`Instrumentation`](trait.Instrumentation.html) and pass it to
+//! [`WalkDirGeneric::instrumentation`](../struct.WalkDirGeneric.html#method.instrumentation)
+//! to be told about every directory read of a walk. Hooks are called from the
+//! threads reading directories, so they should return quickly.
+//!
+//! [`SlowReadDirs`](struct.SlowReadDirs.html) is an implementation finding
+//! directories that take long to read, like a dead network mount or a huge
+//! mail directory. [`ProgressReports`](struct.ProgressReports.html)
+//! periodically reports the [`Progress`](struct.Progress.html) of a walk.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::core::ReadDirCallback;
+use crate::{ClientState, Error};
+
+/// Hooks called while walking, all of which do nothing by default.
+pub trait Instrumentation: Send + Sync + 'static {
+    /// Called when the directory at `path` is scheduled to be read.
+    fn on_spec_queued(&self, _path: &Path, _depth: usize) {}
+
+    /// Called before reading the directory at `path`.
+    fn on_read_dir_start(&self, _path: &Path, _depth: usize) {}
+
+    /// Called after reading the directory at `path`, successfully or not,
+    /// with the number of entries and errors it produced.
+    fn on_read_dir_finish(&self, _path: &Path, _depth: usize, _entries: usize, _elapsed: Duration) {
+    }
+
+    /// Called for every error produced while reading directories, including
+    /// errors of individual entries.
+    fn on_error(&self, _error: &Error) {}
+}
+
+/// A directory that took longer to read than the threshold of
+/// [`SlowReadDirs`](struct.SlowReadDirs.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlowReadDir {
+    /// Path of the directory.
+    pub path: PathBuf,
+    /// Depth of the directory relative to root.
+    pub depth: usize,
+    /// Number of entries and errors read from the directory.
+    pub entries: usize,
+    /// Time it took to read the directory.
+    pub elapsed: Duration,
+}
+
+type SlowReadDirFunction = dyn Fn(&SlowReadDir) + Send + Sync + 'static;
+
+/// Instrumentation reporting directories that take longer to read than a
+/// threshold.
+///
+/// Slow directories are passed to the
+/// [`on_slow_read_dir`](struct.SlowReadDirs.html#method.on_slow_read_dir)
+/// callback as they are found and collected for a summary available from
+/// [`slow_read_dirs`](struct.SlowReadDirs.html#method.slow_read_dirs).
+pub struct SlowReadDirs {
+    threshold: Duration,
+    on_slow_read_dir: Option<Box<SlowReadDirFunction>>,
+    slow_read_dirs: Mutex<Vec<SlowReadDir>>,
+}
+
+impl SlowReadDirs {
+    /// Report directories taking longer than `threshold` to read.
+    pub fn new(threshold: Duration) -> SlowReadDirs {
+        SlowReadDirs {
+            threshold,
+            on_slow_read_dir: None,
+            slow_read_dirs: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Call `on_slow_read_dir` for every slow directory right after it was
+    /// read.
+    pub fn on_slow_read_dir<F>(mut self, on_slow_read_dir: F) -> SlowReadDirs
+    where
+        F: Fn(&SlowReadDir) + Send + Sync + 'static,
+    {
+        self.on_slow_read_dir = Some(Box::new(on_slow_read_dir));
+        self
+    }
+
+    /// The slow directories found so far, slowest first.
+    pub fn slow_read_dirs(&self) -> Vec<SlowReadDir> {
+        let mut slow_read_dirs = self.slow_read_dirs.lock().unwrap().clone();
+        slow_read_dirs.sort_by_key(|slow_read_dir| std::cmp::Reverse(slow_read_dir.elapsed));
+        slow_read_dirs
+    }
+}
+
+impl Instrumentation for SlowReadDirs {
+    fn on_read_dir_finish(&self, path: &Path, depth: usize, entries: usize, elapsed: Duration) {
+        if elapsed <= self.threshold {
+            return;
+        }
+        let slow_read_dir = SlowReadDir {
+            path: path.to_path_buf(),
+            depth,
+            entries,
+            elapsed,
+        };
+        if let Some(on_slow_read_dir) = self.on_slow_read_dir.as_ref() {
+            on_slow_read_dir(&slow_read_dir);
+        }
+        self.slow_read_dirs.lock().unwrap().push(slow_read_dir);
+    }
+}
+
+impl fmt::Debug for SlowReadDirs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowReadDirs")
+            .field("threshold", &self.threshold)
+            .field("slow_read_dirs", &self.slow_read_dirs)
+            .finish()
+    }
+}
+
+/// Progress of a walk, counted on the threads reading directories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of entries and errors read from directories.
+    pub entries: u64,
+    /// Number of directories scheduled to be read, including root.
+    pub dirs_queued: u64,
+    /// Number of directories read, successfully or not.
+    pub dirs_completed: u64,
+    /// Number of errors, including errors of individual entries.
+    pub errors: u64,
+}
+
+impl Progress {
+    /// Number of directories scheduled but not read yet.
+    pub fn dirs_pending(&self) -> u64 {
+        self.dirs_queued.saturating_sub(self.dirs_completed)
+    }
+}
+
+type ProgressFunction = dyn Fn(&Progress) + Send + Sync + 'static;
+
+/// Instrumentation reporting the [`Progress`](struct.Progress.html) of a walk
+/// at most once per interval.
+///
+/// Reports are made after reading a directory once the interval has passed
+/// since the previous report, and after reading the last directory. Reports
+/// are never made concurrently.
+pub struct ProgressReports {
+    interval: Duration,
+    on_progress: Box<ProgressFunction>,
+    entries: AtomicU64,
+    dirs_queued: AtomicU64,
+    dirs_completed: AtomicU64,
+    errors: AtomicU64,
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl ProgressReports {
+    /// Pass the progress to `on_progress` at most once per `interval`.
+    pub fn new<F>(interval: Duration, on_progress: F) -> ProgressReports
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        ProgressReports {
+            interval,
+            on_progress: Box::new(on_progress),
+            entries: AtomicU64::new(0),
+            dirs_queued: AtomicU64::new(0),
+            dirs_completed: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            last_report: Mutex::new(None),
+        }
+    }
+
+    /// The progress counted so far.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            entries: self.entries.load(Ordering::SeqCst),
+            dirs_queued: self.dirs_queued.load(Ordering::SeqCst),
+            dirs_completed: self.dirs_completed.load(Ordering::SeqCst),
+            errors: self.errors.load(Ordering::SeqCst),
+        }
+    }
+}
+
+impl Instrumentation for ProgressReports {
+    fn on_spec_queued(&self, _path: &Path, _depth: usize) {
+        self.dirs_queued.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn on_read_dir_finish(&self, _path: &Path, _depth: usize, entries: usize, _elapsed: Duration) {
+        self.entries.fetch_add(entries as u64, Ordering::SeqCst);
+        self.dirs_completed.fetch_add(1, Ordering::SeqCst);
+
+        let mut last_report = self.last_report.lock().unwrap();
+        let progress = self.progress();
+        let due = last_report.is_none_or(|last_report| last_report.elapsed() >= self.interval);
+        if due || progress.dirs_pending() == 0 {
+            (self.on_progress)(&progress);
+            *last_report = Some(Instant::now());
+        }
+    }
+
+    fn on_error(&self, _error: &Error) {
+        self.errors.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for ProgressReports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressReports")
+            .field("interval", &self.interval)
+            .field("progress", &self.progress())
+            .finish()
+    }
+}
+
+// Wraps `read_dir_callback` to call the hooks of `instrumentation`.
+pub(crate) fn instrument_read_dir<C: ClientState>(
+    instrumentation: Arc<dyn Instrumentation>,
+    read_dir_callback: Arc<ReadDirCallback<C>>,
+) -> Arc<ReadDirCallback<C>> {
+    Arc::new(move |read_dir_spec| {
+        let path = read_dir_spec.path.clone();
+        let depth = read_dir_spec.depth;
+        instrumentation.on_read_dir_start(&path, depth);
+        let start = Instant::now();
+        let read_dir_result = read_dir_callback(read_dir_spec);
+        let elapsed = start.elapsed();
+
+        match read_dir_result.as_ref() {
+            Ok(read_dir) => {
+                for dir_entry_result in &read_dir.results_list {
+                    match dir_entry_result {
+                        Ok(dir_entry) => {
+                            if let Some(read_children_path) = dir_entry.read_children_path.as_ref()
+                            {
+                                instrumentation.on_spec_queued(read_children_path, dir_entry.depth);
+                            }
+                        }
+                        Err(err) => instrumentation.on_error(err),
+                    }
+                }
+                instrumentation.on_read_dir_finish(
+                    &path,
+                    depth,
+                    read_dir.results_list.len(),
+                    elapsed,
+                );
+            }
+            Err(err) => {
+                instrumentation.on_error(err);
+                instrumentation.on_read_dir_finish(&path, depth, 0, elapsed);
+            }
+        }
+        read_dir_result
+    })
+}
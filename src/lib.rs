@@ -113,22 +113,121 @@
 //! Wraps a `ReadDirIter` and yields individual `DirEntry` results in strict
 //! depth first order.
 
+#[cfg(any(feature = "zip", feature = "tar"))]
+pub mod archive;
+#[cfg(feature = "checkpoint")]
+pub mod checkpoint;
+pub mod classify;
+mod collate;
+pub mod compat;
 mod core;
+pub mod diff;
+#[cfg(feature = "rayon")]
+mod entry_ref;
+pub mod events;
+pub mod file_system;
+#[cfg(feature = "filter")]
+pub mod filter;
+mod hardlinks;
+#[cfg(feature = "hash")]
+pub mod hash;
+pub mod instrument;
+#[cfg(all(unix, feature = "locate"))]
+pub mod locate;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+pub mod memfs;
+#[cfg(all(windows, feature = "mft"))]
+pub mod mft;
+#[cfg(feature = "object-store")]
+pub mod object_store;
+pub mod pattern;
+#[cfg(feature = "indicatif")]
+pub mod progress;
+pub mod rollup;
+pub mod scheduler;
+mod scratch;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+#[cfg(feature = "size")]
+pub mod size;
+pub mod sorted;
+pub mod stats;
+#[cfg(target_os = "linux")]
+pub mod statx;
+#[cfg(feature = "tokio")]
+pub mod stream;
+#[cfg(feature = "camino")]
+pub mod utf8;
+#[cfg(feature = "rayon")]
+pub mod visit;
+#[cfg(feature = "watch")]
+pub mod watch;
+#[cfg(feature = "webdav")]
+pub mod webdav;
 
+#[cfg(feature = "rayon")]
+use rayon::iter::ParallelIterator;
+#[cfg(feature = "rayon")]
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
+#[cfg(feature = "rayon")]
 use std::ffi::OsStr;
+use std::ffi::OsString;
 use std::fmt::Debug;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use std::vec;
 
-use crate::core::{ReadDir, ReadDirSpec};
+use crate::collate::NameOrder;
+#[cfg(feature = "rayon")]
+use crate::entry_ref::{walk_refs, RefWalkOptions};
+use crate::file_system::FileSystem;
+#[cfg(feature = "filter")]
+use crate::filter::{DirFilterRules, FilterRules};
+use crate::hardlinks::HardlinkSet;
+use crate::instrument::{instrument_read_dir, Instrumentation, Progress, ProgressReports};
+use crate::pattern::Pattern;
+use crate::scheduler::Scheduler;
+use crate::scratch::ScratchKey;
+#[cfg(feature = "size")]
+use crate::size::SizeCounter;
+use crate::stats::{count_read_dir, StatsCollector};
+#[cfg(target_os = "linux")]
+use crate::statx::StatxFields;
 
-pub use crate::core::{DirEntry, DirEntryIter, Error};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::core::dir_handle_path;
+#[cfg(feature = "rayon")]
+use crate::core::par_entries;
+use crate::core::{
+    device_num, to_verbatim, DirFd, EntryFilter, EntryPreload, EntrySort, FollowLinks,
+    MetadataFilter, NewSchedulerCallback, Operation, ReadDirCallback, ReadDirLimits, ReadDirPacing,
+    ReadDirQueueing, ReadDirSchedule, ReadDirSource, ReadDirSpec, ReadDirSpecPriorityCallback,
+    ReadDirSteps, SpawnWalk, WalkRoot,
+};
+
+pub use crate::core::{
+    BufferLimit, CancelToken, ConcurrencyLimit, DirEntry, DirEntryIter, Error, ErrorKind,
+    LoopDetected,
+};
+pub use crate::diff::diff;
+#[cfg(feature = "rayon")]
+pub use crate::entry_ref::DirEntryRef;
+pub use crate::file_system::{FileType, FileTypeSource, ReparseTag};
+#[cfg(feature = "rayon")]
 pub use rayon;
 
+const DEFAULT_VCS_DIR_NAMES: &[&str] = &[".git", ".hg", ".svn"];
+const DEFAULT_BUILD_DIR_NAMES: &[&str] = &["node_modules", "target", ".venv", "build", "dist"];
+
 /// Builder for walking a directory.
 pub type WalkDir = WalkDirGeneric<((), ())>;
 
@@ -161,6 +260,12 @@ pub trait ClientState: Send + Default + Debug + 'static {
 /// into yeilded DirEntries.
 pub struct WalkDirGeneric<C: ClientState> {
     root: PathBuf,
+    // handle `root` is read through, see `from_dir_handle`
+    root_dir_fd: Option<Arc<DirFd>>,
+    additional_roots: Vec<PathBuf>,
+    // entries to start from instead of the roots, see `resume`
+    #[cfg(feature = "checkpoint")]
+    resume_from: Option<Vec<(PathBuf, usize)>>,
     options: WalkDirOptions<C>,
 }
 
@@ -169,6 +274,30 @@ type ProcessReadDirFunction<C> = dyn Fn(Option<usize>, &Path, &mut <C as ClientS
     + Sync
     + 'static;
 
+type ReduceReadDirFunction<C> = dyn Fn(&mut <C as ClientState>::ReadDirState, &mut DirEntry<C>, <C as ClientState>::ReadDirState)
+    + Send
+    + Sync
+    + 'static;
+
+type InitClientStateFunction<C> =
+    dyn Fn(&DirEntry<C>) -> <C as ClientState>::DirEntryState + Send + Sync + 'static;
+
+type ProjectReadDirStateFunction<C> = dyn Fn(&<C as ClientState>::ReadDirState, &DirEntry<C>) -> <C as ClientState>::ReadDirState
+    + Send
+    + Sync
+    + 'static;
+
+type MapEntryFunction<C> = dyn Fn(&mut DirEntry<C>) + Send + Sync + 'static;
+
+type SkippedBuildDirFunction<C> = dyn Fn(&DirEntry<C>) + Send + Sync + 'static;
+
+type FilterEntryFunction<C> = dyn Fn(&DirEntry<C>) -> bool + Send + Sync + 'static;
+
+type SortByFunction<C> = dyn Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering + Send + Sync + 'static;
+
+type ScheduleRootChildrenFunction<C> =
+    dyn Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering + Send + Sync + 'static;
+
 /// Degree of parallelism to use when performing walk.
 ///
 /// Parallelism happens at the directory level. It will help when walking deep
@@ -176,6 +305,12 @@ type ProcessReadDirFunction<C> = dyn Fn(Option<usize>, &Path, &mut <C as ClientS
 /// directory with many files.
 ///
 /// If you plan to perform lots of per file processing you might want to use Rayon to
+///
+/// Without the `rayon` feature, which is enabled by default, the rayon
+/// variants read directories on `std` threads like
+/// [`Parallelism::StdThreads`](enum.Parallelism.html#variant.StdThreads),
+/// with as many threads as `RayonNewPool` or `Adaptive` are given, and
+/// `RayonExistingPool` can't be created.
 #[derive(Clone)]
 pub enum Parallelism {
     /// Run on calling thread, similar to what happens in the `walkdir` crate.
@@ -199,17 +334,254 @@ pub enum Parallelism {
     },
     /// Run in new rayon thread pool with # threads
     RayonNewPool(usize),
+    /// Run in default rayon thread pool, reading directories with only as
+    /// many tasks as there are directories waiting to be read.
+    ///
+    /// Tasks are added while the queue of pending directories grows, up to
+    /// `max_threads`, and end again near the frontier of the walk where few
+    /// directories are left, so a walk embedded in an application sharing
+    /// the pool doesn't occupy more of it than it can use.
+    Adaptive {
+        /// Maximal number of directories read at the same time, as many as
+        /// the pool has threads if `0`.
+        max_threads: usize,
+        /// Like [`Parallelism::RayonDefaultPool::busy_timeout`].
+        busy_timeout: std::time::Duration,
+    },
+    /// Run on # scoped `std` threads, as many as the system has cores if
+    /// `0`, instead of a rayon pool, so the walk never waits for a busy
+    /// pool. The default without the `rayon` feature.
+    ///
+    /// Only the iterators read directories this way,
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries),
+    /// [`visit`](struct.WalkDirGeneric.html#method.visit) and
+    /// [`for_each_ref`](struct.WalkDirGeneric.html#method.for_each_ref)
+    /// still run on the default rayon pool.
+    StdThreads(usize),
+}
+
+/// Stand-in for `rayon::ThreadPool` without the `rayon` feature, so
+/// [`Parallelism::RayonExistingPool`](enum.Parallelism.html#variant.RayonExistingPool)
+/// exists in every build. It has no values, so that variant can't be created.
+#[cfg(not(feature = "rayon"))]
+#[derive(Debug)]
+pub enum ThreadPool {}
+
+/// What to do if the rayon pool is too busy to start a walk, see
+/// [`WalkDirGeneric::busy_policy`](struct.WalkDirGeneric.html#method.busy_policy).
+///
+/// Only walks on a pool with a `busy_timeout` can find it busy, the policy
+/// is ignored for other [`Parallelism`](enum.Parallelism.html) variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusyPolicy {
+    /// Fail with an [`Error`](struct.Error.html) that
+    /// [`is_busy`](struct.Error.html#method.is_busy).
+    Error,
+    /// Continue the walk on the calling thread, like
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial).
+    FallbackSerial,
+    /// Continue the walk on a new rayon pool with the given number of
+    /// threads, like
+    /// [`Parallelism::RayonNewPool`](enum.Parallelism.html#variant.RayonNewPool).
+    FallbackNewPool(usize),
+    /// Never give up waiting for the pool, ignoring `busy_timeout`. This
+    /// deadlocks if the walk is started from the only thread of the pool.
+    WaitForever,
+}
+
+impl BusyPolicy {
+    pub(crate) fn fallback_parallelism(&self) -> Option<Parallelism> {
+        match self {
+            BusyPolicy::Error | BusyPolicy::WaitForever => None,
+            BusyPolicy::FallbackSerial => Some(Parallelism::Serial),
+            BusyPolicy::FallbackNewPool(num_threads) => {
+                Some(Parallelism::RayonNewPool(*num_threads))
+            }
+        }
+    }
+}
+
+/// Order in which pending directories are read by the rayon pool, see
+/// [`WalkDirGeneric::read_dir_order`](struct.WalkDirGeneric.html#method.read_dir_order).
+///
+/// Entries are yielded in the same order either way, this only changes which
+/// directories are read ahead of the iterator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadDirOrder {
+    /// Read the pending directory that comes first in walk order, so the
+    /// directories the iterator needs next are read first.
+    DepthFirst,
+    /// Read pending directories in the order they were discovered. Siblings
+    /// are read close together, which can improve cache and disk locality.
+    Fifo,
+}
+
+/// How directories are read, see
+/// [`WalkDirGeneric::read_dir_backend`](struct.WalkDirGeneric.html#method.read_dir_backend).
+///
+/// The `IoUring` variant is only available with the `io-uring` feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadDirBackend {
+    /// Read with `std::fs::read_dir`.
+    Std,
+    /// Read with the `getdents64` syscall into a large buffer, so large
+    /// directories are read with fewer syscalls. Only supported on Linux and
+    /// Android, other platforms read with `std::fs::read_dir`.
+    ///
+    /// Types are read from `d_type`, only entries the file system returns
+    /// without one are stat'ed, see
+    /// [`FileType::source`](struct.FileType.html#method.source).
+    /// [`FileType::as_std`](struct.FileType.html#method.as_std) returns `None`
+    /// for directories, regular files and symlinks read this way.
+    Getdents,
+    /// Read with the `getdents64` syscall like
+    /// [`Getdents`](enum.ReadDirBackend.html#variant.Getdents), and get the
+    /// types of entries the file system doesn't return with `getdents64`,
+    /// such as on some network file systems, with `statx` operations
+    /// submitted in batches to an io_uring per thread. Falls back to a stat
+    /// per entry if io_uring isn't available. Only supported on Linux and
+    /// Android, other platforms read with `std::fs::read_dir`.
+    ///
+    /// Experimental, available with the `io-uring` feature. Linux has no
+    /// io_uring operations reading directories or symlinks, so those are
+    /// still read with a syscall each.
+    #[cfg(feature = "io-uring")]
+    IoUring,
+    /// Read with `NtQueryDirectoryFile` into a large buffer, returning many
+    /// entries with their attributes per call, which is fastest for NTFS
+    /// directories with many entries. Only supported on Windows, other
+    /// platforms read with `std::fs::read_dir`.
+    ///
+    /// Entry names and attributes are read in bulk, sizes and other metadata
+    /// are still read per entry by
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata).
+    /// [`FileType::as_std`](struct.FileType.html#method.as_std) returns
+    /// `None` for entries read this way.
+    NtQueryDirectoryFile,
+}
+
+/// Metadata to sort the entries of each directory by, see
+/// [`WalkDirGeneric::sort_by_key`](struct.WalkDirGeneric.html#method.sort_by_key).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// The size in bytes, as returned by `Metadata::len`.
+    Size,
+    /// The last modification time.
+    Modified,
+    /// The creation time, which isn't available on all platforms and file
+    /// systems.
+    Created,
+}
+
+/// Direction of a sort, see
+/// [`WalkDirGeneric::sort_by_key`](struct.WalkDirGeneric.html#method.sort_by_key).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Smallest or oldest first.
+    Asc,
+    /// Largest or newest first.
+    Desc,
+}
+
+/// Which directories are leaves, see
+/// [`WalkDirGeneric::leaf_dirs_only`](struct.WalkDirGeneric.html#method.leaf_dirs_only).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeafDirs {
+    /// Directories that contain no directories.
+    WithoutSubdirs,
+    /// Directories that contain no entries at all.
+    Empty,
+}
+
+/// How depth is counted for entries reached by following symbolic links.
+///
+/// This affects [`DirEntry::depth`](struct.DirEntry.html#structfield.depth)
+/// and thereby [`min_depth`](struct.WalkDirGeneric.html#method.min_depth)
+/// and [`max_depth`](struct.WalkDirGeneric.html#method.max_depth).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkDepth {
+    /// Count depth along the walked path, a followed link is one level deeper
+    /// than the directory containing it, regardless of where it points.
+    LinkPath,
+    /// Count depth along the resolved target path. A followed link whose
+    /// target is inside of root gets the depth of its target relative to
+    /// root, and its descendants are counted from there. Links pointing
+    /// outside of root are counted along the walked path.
+    TargetPath,
 }
 
 struct WalkDirOptions<C: ClientState> {
     sort: bool,
+    name_order: NameOrder,
+    sort_by: Option<Arc<SortByFunction<C>>>,
+    sort_key: Option<(SortKey, Order)>,
+    contents_first: bool,
     min_depth: usize,
     max_depth: usize,
     skip_hidden: bool,
+    skip_vcs_dirs: bool,
+    vcs_dir_names: Arc<Vec<OsString>>,
+    skip_build_dirs: bool,
+    build_dir_names: Arc<Vec<OsString>>,
+    on_skipped_build_dir: Option<Arc<SkippedBuildDirFunction<C>>>,
+    skip_empty_dirs: bool,
+    leaf_dirs_only: Option<LeafDirs>,
     follow_links: bool,
+    follow_junctions: Option<bool>,
+    link_depth: LinkDepth,
     parallelism: Parallelism,
     root_read_dir_state: C::ReadDirState,
+    init_client_state: Option<Arc<InitClientStateFunction<C>>>,
     process_read_dir: Option<Arc<ProcessReadDirFunction<C>>>,
+    reduce_read_dir: Option<Arc<ReduceReadDirFunction<C>>>,
+    map_entries: Option<Arc<MapEntryFunction<C>>>,
+    project_read_dir_state: Option<Arc<ProjectReadDirStateFunction<C>>>,
+    filter_entry: Option<Arc<FilterEntryFunction<C>>>,
+    priority_paths: Vec<PathBuf>,
+    schedule_root_children_by: Option<Arc<ScheduleRootChildrenFunction<C>>>,
+    exclude_paths: Arc<HashSet<PathBuf>>,
+    exclude_devices: Arc<HashSet<u64>>,
+    same_file_system: bool,
+    hardlink_dedup: Option<HardlinkDedup>,
+    preload_metadata: bool,
+    #[cfg(target_os = "linux")]
+    preload_statx: Option<StatxFields>,
+    #[cfg(feature = "size")]
+    aggregate_sizes: Option<Arc<SizeCounter>>,
+    min_file_size: u64,
+    max_file_size: u64,
+    modified_after: Option<SystemTime>,
+    prune_unmodified_dirs: bool,
+    include_only: Vec<PathBuf>,
+    exclude_patterns: Arc<Vec<Pattern>>,
+    include_patterns: Arc<Vec<Pattern>>,
+    #[cfg(feature = "filter")]
+    filter_rules: Option<FilterRules>,
+    #[cfg(feature = "filter")]
+    git_ignore: bool,
+    check_root: bool,
+    #[cfg(feature = "rayon")]
+    yield_between_dirs: bool,
+    strict_order: bool,
+    read_dir_order: ReadDirOrder,
+    new_scheduler: Option<Arc<NewSchedulerCallback<C>>>,
+    read_dir_backend: ReadDirBackend,
+    read_dir_chunk_size: Option<usize>,
+    long_paths: bool,
+    normalize_long_paths: bool,
+    busy_policy: BusyPolicy,
+    concurrency_limit: Option<ConcurrencyLimit>,
+    buffer_limit: Option<BufferLimit>,
+    cancel_token: Option<CancelToken>,
+    max_entries: Option<usize>,
+    deadline: Option<Instant>,
+    timeout: Option<Duration>,
+    stats_collector: Option<StatsCollector>,
+    file_system: Option<Arc<dyn FileSystem>>,
+    dir_fds: bool,
+    instrumentation: Option<Arc<dyn Instrumentation>>,
+    progress_reports: Option<Arc<ProgressReports>>,
 }
 
 impl<C: ClientState> WalkDirGeneric<C> {
@@ -224,36 +596,284 @@ impl<C: ClientState> WalkDirGeneric<C> {
     pub fn new<P: AsRef<Path>>(root: P) -> Self {
         WalkDirGeneric {
             root: root.as_ref().to_path_buf(),
+            root_dir_fd: None,
+            additional_roots: Vec::new(),
+            #[cfg(feature = "checkpoint")]
+            resume_from: None,
             options: WalkDirOptions {
                 sort: false,
+                name_order: NameOrder::default(),
+                sort_by: None,
+                sort_key: None,
+                contents_first: false,
                 min_depth: 0,
                 max_depth: ::std::usize::MAX,
                 skip_hidden: true,
+                skip_vcs_dirs: false,
+                vcs_dir_names: Arc::new(DEFAULT_VCS_DIR_NAMES.iter().map(OsString::from).collect()),
+                skip_build_dirs: false,
+                build_dir_names: Arc::new(
+                    DEFAULT_BUILD_DIR_NAMES.iter().map(OsString::from).collect(),
+                ),
+                on_skipped_build_dir: None,
+                skip_empty_dirs: false,
+                leaf_dirs_only: None,
                 follow_links: false,
+                follow_junctions: None,
+                link_depth: LinkDepth::LinkPath,
+                #[cfg(feature = "rayon")]
                 parallelism: Parallelism::RayonDefaultPool {
                     busy_timeout: std::time::Duration::from_secs(1),
                 },
+                #[cfg(not(feature = "rayon"))]
+                parallelism: Parallelism::StdThreads(0),
                 root_read_dir_state: C::ReadDirState::default(),
+                init_client_state: None,
                 process_read_dir: None,
+                reduce_read_dir: None,
+                map_entries: None,
+                project_read_dir_state: None,
+                filter_entry: None,
+                priority_paths: Vec::new(),
+                schedule_root_children_by: None,
+                exclude_paths: Default::default(),
+                exclude_devices: Default::default(),
+                same_file_system: false,
+                hardlink_dedup: None,
+                preload_metadata: false,
+                #[cfg(target_os = "linux")]
+                preload_statx: None,
+                #[cfg(feature = "size")]
+                aggregate_sizes: None,
+                min_file_size: 0,
+                max_file_size: u64::MAX,
+                modified_after: None,
+                prune_unmodified_dirs: false,
+                include_only: Vec::new(),
+                exclude_patterns: Default::default(),
+                include_patterns: Default::default(),
+                #[cfg(feature = "filter")]
+                filter_rules: None,
+                #[cfg(feature = "filter")]
+                git_ignore: false,
+                check_root: false,
+                #[cfg(feature = "rayon")]
+                yield_between_dirs: false,
+                strict_order: true,
+                read_dir_order: ReadDirOrder::DepthFirst,
+                new_scheduler: None,
+                read_dir_backend: ReadDirBackend::Std,
+                read_dir_chunk_size: None,
+                long_paths: cfg!(windows),
+                normalize_long_paths: true,
+                busy_policy: BusyPolicy::Error,
+                concurrency_limit: None,
+                buffer_limit: None,
+                cancel_token: None,
+                max_entries: None,
+                deadline: None,
+                timeout: None,
+                stats_collector: None,
+                file_system: None,
+                dir_fds: false,
+                instrumentation: None,
+                progress_reports: None,
             },
         }
     }
 
+    /// Create a builder for a recursive directory iterator walking each of
+    /// `roots` in turn, see
+    /// [`add_root`](struct.WalkDirGeneric.html#method.add_root). Yields an
+    /// error like for an empty path if `roots` is empty.
+    pub fn new_multi<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut roots = roots.into_iter();
+        let walk_dir =
+            WalkDirGeneric::new(roots.next().as_ref().map_or(Path::new(""), AsRef::as_ref));
+        roots.fold(walk_dir, WalkDirGeneric::add_root)
+    }
+
+    /// Also walk `root` after the roots added before.
+    ///
+    /// All roots share one iterator and thread-pool, so directories of later
+    /// roots are read while the earlier roots are still being yielded.
+    /// Entries are yielded root by root in the order the roots were added,
+    /// with depths counted from their own root. Paths used by filters, such as
+    /// [`exclude_glob`](struct.WalkDirGeneric.html#method.exclude_glob), are
+    /// relative to the innermost root containing the entry.
+    pub fn add_root<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.additional_roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Create a builder for a recursive directory iterator starting at the
+    /// directory opened as `dir`.
+    ///
+    /// The walk reads `dir` itself, and opens its subdirectories relative to
+    /// their parents like [`dir_fds`](struct.WalkDirGeneric.html#method.dir_fds),
+    /// so it walks exactly the opened directory even if it, or its ancestors,
+    /// are renamed or replaced. This allows opening and validating a directory
+    /// before walking it. Yielded paths start at the path of the opened
+    /// directory when the builder is created, and fails if `dir` isn't a
+    /// directory or was removed by then. Options reading the root by path,
+    /// such as [`same_file_system`](struct.WalkDirGeneric.html#method.same_file_system),
+    /// use that path, and a
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system) replaces
+    /// reading through handles.
+    ///
+    /// Only available on Linux and Android.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn from_dir_handle(dir: fs::File) -> io::Result<Self> {
+        let root = dir_handle_path(&dir)?;
+        let mut walk_dir = WalkDirGeneric::new(root);
+        walk_dir.root_dir_fd = Some(Arc::new(DirFd::from_dir(dir)));
+        Ok(walk_dir)
+    }
+
     /// Try to create an iterator or fail if the rayon threadpool (in any configuration) is busy.
+    ///
+    /// If [`check_root`](struct.WalkDirGeneric.html#method.check_root) is
+    /// enabled this also fails if root can't be accessed.
     pub fn try_into_iter(self) -> Result<DirEntryIter<C>> {
+        if self.options.check_root {
+            for root in self.roots() {
+                check_root_access(root, self.options.file_system.as_deref())?;
+            }
+        }
         let iter = self.into_iter();
         if iter.read_dir_iter.is_none() {
-            Err(Error::busy())
+            Err(iter.parallelism.busy_error())
         } else {
             Ok(iter)
         }
     }
 
-    /// Root path of the walk.
+    /// Check that root exists and, if it is a directory, can be read before
+    /// any threads are spawned. Defaults to `false`.
+    ///
+    /// Only [`try_into_iter`](struct.WalkDirGeneric.html#method.try_into_iter)
+    /// honors this option, returning the error instead of an iterator. Use it
+    /// to report a missing or unreadable root right away instead of from the
+    /// first item of the iterator.
+    pub fn check_root(mut self, check_root: bool) -> Self {
+        self.options.check_root = check_root;
+        self
+    }
+
+    /// Create an iterator that runs the walk on `pool` instead of the
+    /// configured pool, for applications that own their pool and don't keep
+    /// it in an `Arc` for
+    /// [`Parallelism::RayonExistingPool`](enum.Parallelism.html#variant.RayonExistingPool).
+    ///
+    /// [`parallelism`](struct.WalkDirGeneric.html#method.parallelism) only
+    /// decides the `busy_timeout` and whether to walk serially, in which case
+    /// nothing is spawned onto `pool`.
+    ///
+    /// Available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn into_iter_in_pool(self, pool: &ThreadPool) -> DirEntryIter<C> {
+        self.walk(Some(&|op| pool.spawn(op)))
+    }
+
+    /// Walk as a rayon `ParallelIterator`, for processing entries in parallel
+    /// without funneling them through the single consumer of
+    /// `into_iter().par_bridge()`.
+    ///
+    /// Directories are read by the pool driving the returned iterator, which
+    /// also runs the per entry processing, instead of the pool configured
+    /// with [`parallelism`](struct.WalkDirGeneric.html#method.parallelism).
+    /// Entries are yielded in no particular order, though each directory is
+    /// still sorted and processed by
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// before its entries are yielded.
+    ///
+    /// Available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn par_entries(self) -> impl ParallelIterator<Item = Result<DirEntry<C>>> {
+        let PreparedWalk {
+            root_entry_results,
+            schedule,
+            min_depth,
+            root_read_dir_state,
+            read_dir_callback,
+            ..
+        } = self.prepare(None);
+        par_entries(
+            root_entry_results,
+            min_depth,
+            root_read_dir_state,
+            read_dir_callback,
+            schedule.concurrency_limit,
+        )
+    }
+
+    /// Walk without creating a [`DirEntry`](struct.DirEntry.html) per entry,
+    /// calling `f` with borrowed entries on the threads reading directories.
+    ///
+    /// This avoids most per entry allocations for consumers that don't keep
+    /// entries around, such as counting or matching. Entries are passed in no
+    /// particular order and only
+    /// [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden),
+    /// [`min_depth`](struct.WalkDirGeneric.html#method.min_depth),
+    /// [`max_depth`](struct.WalkDirGeneric.html#method.max_depth) and
+    /// [`parallelism`](struct.WalkDirGeneric.html#method.parallelism) are
+    /// honored, symbolic links are never followed. Returns once all entries
+    /// were passed to `f`.
+    ///
+    /// Available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn for_each_ref<F>(self, f: F)
+    where
+        F: Fn(Result<DirEntryRef<'_>>) + Send + Sync,
+    {
+        let options = RefWalkOptions {
+            skip_hidden: self.options.skip_hidden,
+            min_depth: self.options.min_depth,
+            max_depth: self.options.max_depth,
+        };
+        for root in self.roots() {
+            walk_refs(root, &self.options.parallelism, &options, &f);
+        }
+    }
+
+    /// Like [`for_each_ref`](struct.WalkDirGeneric.html#method.for_each_ref),
+    /// but only passes the parent path, file name and file type of each entry
+    /// and silently skips errors, for the hottest loops such as counting or
+    /// matching names.
+    ///
+    /// Available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn for_each_raw<F>(self, f: F)
+    where
+        F: Fn(&Path, &OsStr, fs::FileType) + Send + Sync,
+    {
+        self.for_each_ref(|dir_entry| {
+            if let Ok(dir_entry) = dir_entry {
+                f(
+                    dir_entry.parent_path(),
+                    dir_entry.file_name(),
+                    dir_entry.file_type(),
+                );
+            }
+        })
+    }
+
+    /// Root path of the walk, the first root if there are
+    /// [several](struct.WalkDirGeneric.html#method.add_root).
     pub fn root(&self) -> &Path {
         &self.root
     }
 
+    /// All root paths of the walk, in the order they are walked.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.root.as_path())
+            .chain(self.additional_roots.iter().map(PathBuf::as_path))
+    }
+
     /// Sort entries by `file_name` per directory. Defaults to `false`. Use
     /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir) for custom
     /// sorting or filtering.
@@ -262,12 +882,471 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
+    /// Sort entries by `file_name` per directory like
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort), but compare runs of
+    /// digits by their numeric value, so `file2` comes before `file10`.
+    /// Defaults to `false`.
+    ///
+    /// Names that only differ in leading zeros, like `file01` and `file1`,
+    /// are ordered by their bytes.
+    pub fn natural_sort(mut self, natural_sort: bool) -> Self {
+        self.options.name_order.natural = natural_sort;
+        self
+    }
+
+    /// Sort entries by `file_name` per directory like
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort), but ignoring case,
+    /// like file managers do. Defaults to `false`.
+    ///
+    /// Only ASCII letters are compared case-insensitively unless the
+    /// `unicode-case` feature is enabled, which lowercases all of the
+    /// letters of names that are valid Unicode. Names only differing in
+    /// case are ordered by their bytes, so `B` comes before `b`. Combines
+    /// with [`natural_sort`](struct.WalkDirGeneric.html#method.natural_sort).
+    pub fn sort_case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.options.name_order.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Sort entries per directory with `compare`, which takes precedence
+    /// over [`sort`](struct.WalkDirGeneric.html#method.sort). Errors are
+    /// placed after the entries. Defaults to no custom sorting.
+    pub fn sort_by<F>(mut self, compare: F) -> Self
+    where
+        F: Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering + Send + Sync + 'static,
+    {
+        self.options.sort_by = Some(Arc::new(compare));
+        self
+    }
+
+    /// Sort entries per directory by `key` of their metadata in `order`,
+    /// and by `file_name` if their keys are equal. Defaults to not sorting
+    /// by metadata.
+    ///
+    /// The metadata is read on the threads reading directories like with
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata),
+    /// and cached for
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata). Entries
+    /// whose key can't be read are placed after the others, and errors after
+    /// all entries. Takes precedence over
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort), while
+    /// [`sort_by`](struct.WalkDirGeneric.html#method.sort_by) takes
+    /// precedence over this.
+    pub fn sort_by_key(mut self, key: SortKey, order: Order) -> Self {
+        self.options.sort_key = Some((key, order));
+        self
+    }
+
+    /// Yield directories after their contents instead of before. Defaults
+    /// to `false`.
+    ///
+    /// This allows processing a tree bottom up, such as removing it or
+    /// summing up sizes. Directories whose contents couldn't be read are
+    /// yielded right away with
+    /// [`read_children_error`](struct.DirEntry.html#structfield.read_children_error)
+    /// set. Has no effect on
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.options.contents_first = contents_first;
+        self
+    }
+
     /// Skip hidden entries. Enabled by default.
     pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
         self.options.skip_hidden = skip_hidden;
         self
     }
 
+    /// Skip version control metadata directories such as `.git`, `.hg` and
+    /// `.svn`, and don't descend into them. Defaults to `false`.
+    ///
+    /// Directories are pruned by name while reading their parent directory,
+    /// files with these names such as the `.git` file of a worktree or
+    /// submodule are still yielded. Note that these names are also skipped by
+    /// [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden), this
+    /// is mostly useful in combination with `skip_hidden(false)`. Use
+    /// [`vcs_dir_names`](struct.WalkDirGeneric.html#method.vcs_dir_names) to
+    /// change the names that are skipped.
+    pub fn skip_vcs_dirs(mut self, skip_vcs_dirs: bool) -> Self {
+        self.options.skip_vcs_dirs = skip_vcs_dirs;
+        self
+    }
+
+    /// Names skipped by
+    /// [`skip_vcs_dirs`](struct.WalkDirGeneric.html#method.skip_vcs_dirs).
+    /// Defaults to `.git`, `.hg` and `.svn`.
+    pub fn vcs_dir_names<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.options.vcs_dir_names = Arc::new(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Skip directories holding build output or installed dependencies, such
+    /// as `node_modules`, `target`, `.venv`, `build` and `dist`, and don't
+    /// descend into them. Defaults to `false`.
+    ///
+    /// Directories are pruned by name while reading their parent directory.
+    /// Use [`build_dir_names`](struct.WalkDirGeneric.html#method.build_dir_names)
+    /// to change the names that are skipped and
+    /// [`on_skipped_build_dir`](struct.WalkDirGeneric.html#method.on_skipped_build_dir)
+    /// to find out what was skipped.
+    pub fn skip_build_dirs(mut self, skip_build_dirs: bool) -> Self {
+        self.options.skip_build_dirs = skip_build_dirs;
+        self
+    }
+
+    /// Directory names skipped by
+    /// [`skip_build_dirs`](struct.WalkDirGeneric.html#method.skip_build_dirs).
+    /// Defaults to `node_modules`, `target`, `.venv`, `build` and `dist`.
+    pub fn build_dir_names<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.options.build_dir_names = Arc::new(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// A callback invoked with each directory skipped by
+    /// [`skip_build_dirs`](struct.WalkDirGeneric.html#method.skip_build_dirs).
+    ///
+    /// The callback is invoked from the threads reading directories, it can be
+    /// used to report or to measure what was skipped.
+    pub fn on_skipped_build_dir<F>(mut self, on_skipped: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) + Send + Sync + 'static,
+    {
+        self.options.on_skipped_build_dir = Some(Arc::new(on_skipped));
+        self
+    }
+
+    /// Don't yield directories that contain no entries after filtering,
+    /// including roots. Defaults to `false`.
+    ///
+    /// A directory is empty if its read returned no entries or errors once
+    /// [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry),
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// and the other filters were applied. Directories only containing empty
+    /// directories aren't empty, and directories that couldn't be read are
+    /// yielded with their error. As directories are yielded once their
+    /// contents were read, this doesn't hold back the iterator. Has no effect
+    /// on [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn skip_empty_dirs(mut self, skip_empty_dirs: bool) -> Self {
+        self.options.skip_empty_dirs = skip_empty_dirs;
+        self
+    }
+
+    /// Only yield leaf directories, so work can be split up per directory
+    /// without looking ahead. Defaults to `None`, yielding all entries.
+    ///
+    /// Leaves are recognized once their contents were read and filtered
+    /// like with [`skip_empty_dirs`](struct.WalkDirGeneric.html#method.skip_empty_dirs),
+    /// the other entries are still walked but not yielded. Directories whose
+    /// contents aren't read, such as at
+    /// [`max_depth`](struct.WalkDirGeneric.html#method.max_depth), aren't
+    /// leaves, and directories that couldn't be read are yielded with their
+    /// error. Disables
+    /// [`read_dir_chunk_size`](struct.WalkDirGeneric.html#method.read_dir_chunk_size),
+    /// and has no effect on
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn leaf_dirs_only(mut self, leaf_dirs: Option<LeafDirs>) -> Self {
+        self.options.leaf_dirs_only = leaf_dirs;
+        self
+    }
+
+    /// Skip entries whose canonical path is contained in `paths`, and don't
+    /// descend into them. Defaults to an empty set.
+    ///
+    /// Each directory is canonicalized once when it is read, the canonical
+    /// path of an entry is then looked up in `paths` without further system
+    /// calls. Use this instead of
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// based filtering for large machine generated exclude lists. The given
+    /// paths must be absolute and canonical, as returned by
+    /// [`std::fs::canonicalize`]. With a
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system),
+    /// directories are canonicalized with
+    /// [`FileSystem::canonicalize`](file_system/trait.FileSystem.html#method.canonicalize).
+    ///
+    /// [`std::fs::canonicalize`]: https://doc.rust-lang.org/stable/std/fs/fn.canonicalize.html
+    pub fn exclude_paths(mut self, paths: HashSet<PathBuf>) -> Self {
+        self.options.exclude_paths = Arc::new(paths);
+        self
+    }
+
+    /// Skip directories located on any of the given devices, and don't
+    /// descend into them. Defaults to an empty set.
+    ///
+    /// Devices are identified by the `st_dev` device number on Unix and by
+    /// the volume serial number on Windows. This excludes a volume wherever
+    /// it is mounted in the walked tree. Each directory entry is checked with
+    /// an additional system call while walking, files are never checked since
+    /// they are on the device of their parent directory.
+    pub fn exclude_devices<I>(mut self, devices: I) -> Self
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        self.options.exclude_devices = Arc::new(devices.into_iter().collect());
+        self
+    }
+
+    /// Don't descend into directories on another device than root, such as
+    /// mount points of other file systems. Defaults to `false`.
+    ///
+    /// Such directories are still yielded, but their contents are not read.
+    /// Devices are identified like with
+    /// [`exclude_devices`](struct.WalkDirGeneric.html#method.exclude_devices),
+    /// at the cost of an additional system call per directory.
+    pub fn same_file_system(mut self, same_file_system: bool) -> Self {
+        self.options.same_file_system = same_file_system;
+        self
+    }
+
+    /// Skip further links to files with several hardlinks, so each file is
+    /// only yielded for the first link that is read. Defaults to `false`.
+    ///
+    /// Links are identified by their device and inode numbers, at the cost of
+    /// reading the metadata of every entry that isn't a directory, like
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata)
+    /// does. On Windows they are identified by their volume serial number and
+    /// file index, at the cost of also opening every such entry. Entries are
+    /// checked after
+    /// [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry) and
+    /// sorting, and before sizes are counted for
+    /// [`aggregate_sizes`](struct.WalkDirGeneric.html#method.aggregate_sizes)
+    /// and [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called. With parallel walks, which directory is read first and so
+    /// which link is kept isn't deterministic. Entries whose metadata can't
+    /// be read are yielded.
+    ///
+    /// Only available on Unix and Windows, other platforms yield every link.
+    /// Replaces
+    /// [`flag_duplicate_hardlinks`](struct.WalkDirGeneric.html#method.flag_duplicate_hardlinks).
+    pub fn dedup_hardlinks(mut self, dedup_hardlinks: bool) -> Self {
+        self.options.hardlink_dedup = dedup_hardlinks.then_some(HardlinkDedup::Skip);
+        self
+    }
+
+    /// Like [`dedup_hardlinks`](struct.WalkDirGeneric.html#method.dedup_hardlinks),
+    /// but yield further links to a file and mark them with
+    /// [`DirEntry::is_duplicate_hardlink`](struct.DirEntry.html#method.is_duplicate_hardlink).
+    /// Marked links add nothing to the sizes of
+    /// [`aggregate_sizes`](struct.WalkDirGeneric.html#method.aggregate_sizes).
+    /// Defaults to `false`.
+    pub fn flag_duplicate_hardlinks(mut self, flag_duplicate_hardlinks: bool) -> Self {
+        self.options.hardlink_dedup = flag_duplicate_hardlinks.then_some(HardlinkDedup::Flag);
+        self
+    }
+
+    /// Read the metadata of every entry while walking and cache it, so that
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata) doesn't
+    /// need a system call. Defaults to `false`.
+    ///
+    /// The metadata is read on the threads reading directories, so it is read
+    /// in parallel instead of one by one while iterating. Entries that are
+    /// filtered out before being yielded are still read.
+    ///
+    /// Available with the `preload-metadata` feature.
+    #[cfg(feature = "preload-metadata")]
+    pub fn preload_metadata(mut self, preload_metadata: bool) -> Self {
+        self.options.preload_metadata = preload_metadata;
+        self
+    }
+
+    /// Read only the metadata `fields` of every entry with `statx` while
+    /// walking, and cache it for
+    /// [`DirEntry::statx`](struct.DirEntry.html#method.statx), see the
+    /// [`statx`](statx/index.html) module. Defaults to not reading it.
+    ///
+    /// Like [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata),
+    /// the metadata is read in parallel and entries that are filtered out
+    /// before being yielded are still read. Ignored with a
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system).
+    ///
+    /// Available with the `preload-metadata` feature.
+    #[cfg(all(target_os = "linux", feature = "preload-metadata"))]
+    pub fn preload_metadata_ext(mut self, fields: StatxFields) -> Self {
+        self.options.preload_statx = Some(fields);
+        self
+    }
+
+    /// Sum up the sizes under each directory with `size_counter`, see
+    /// [`DirEntry::subtree_size`](struct.DirEntry.html#method.subtree_size).
+    ///
+    /// Sizes are read on the threads reading directories. Directories are
+    /// yielded after their contents like with
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first),
+    /// so their totals are complete when they are yielded. Only entries that
+    /// pass the walk's filters are counted, including entries below
+    /// [`min_depth`](struct.WalkDirGeneric.html#method.min_depth). Has no
+    /// effect on [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    #[cfg(feature = "size")]
+    pub fn aggregate_sizes(mut self, size_counter: SizeCounter) -> Self {
+        self.options.aggregate_sizes = Some(Arc::new(size_counter));
+        self
+    }
+
+    /// Skip files smaller than `min_file_size` bytes. Defaults to `0`.
+    ///
+    /// Sizes are read on the threads reading directories, and the metadata
+    /// is cached if
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata)
+    /// is enabled. Only applies to regular files, errors reading their
+    /// metadata are yielded in place of them.
+    pub fn min_file_size(mut self, min_file_size: u64) -> Self {
+        self.options.min_file_size = min_file_size;
+        self
+    }
+
+    /// Skip files larger than `max_file_size` bytes. Defaults to `u64::MAX`.
+    ///
+    /// See [`min_file_size`](struct.WalkDirGeneric.html#method.min_file_size).
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.options.max_file_size = max_file_size;
+        self
+    }
+
+    /// Skip files that weren't modified after `modified_after`. Defaults to
+    /// yielding files of any age.
+    ///
+    /// Modification times are read like sizes with
+    /// [`min_file_size`](struct.WalkDirGeneric.html#method.min_file_size).
+    /// Directories are always yielded.
+    pub fn modified_after(mut self, modified_after: SystemTime) -> Self {
+        self.options.modified_after = Some(modified_after);
+        self
+    }
+
+    /// Yield files of any age again after
+    /// [`modified_after`](struct.WalkDirGeneric.html#method.modified_after).
+    pub fn clear_modified_after(mut self) -> Self {
+        self.options.modified_after = None;
+        self
+    }
+
+    /// Don't descend into directories that weren't changed after
+    /// [`modified_after`](struct.WalkDirGeneric.html#method.modified_after).
+    /// Defaults to `false`.
+    ///
+    /// This is a heuristic: a directory's modification time (and status
+    /// change time on Unix) only changes when entries are added, removed or
+    /// renamed, not when files inside of it are modified in place. Only
+    /// enable it if files are replaced rather than rewritten, for example by
+    /// tools writing to a temporary file and renaming it.
+    pub fn prune_unmodified_dirs(mut self, prune_unmodified_dirs: bool) -> Self {
+        self.options.prune_unmodified_dirs = prune_unmodified_dirs;
+        self
+    }
+
+    /// Only yield entries inside of the given paths. Defaults to yielding all
+    /// entries.
+    ///
+    /// Directories leading from root to the given paths are read to reach
+    /// them, but are not yielded themselves. Siblings that don't lead to any
+    /// of the given paths are skipped without being read. Paths are compared
+    /// component wise against paths built from `root`, so they must be
+    /// spelled with the same prefix as `root`.
+    pub fn include_only<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.options.include_only = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        self
+    }
+
+    /// Skip entries matching any of the given patterns, and don't descend
+    /// into them. Defaults to no patterns.
+    ///
+    /// Patterns are matched against entry paths relative to root, with
+    /// `rsync` style anchoring: `/build/` only matches the `build` directory
+    /// directly inside of root, while `build/` matches `build` directories at
+    /// any depth. See the [`pattern`](pattern/index.html) module for the
+    /// full syntax.
+    pub fn exclude_patterns<I>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = Pattern>,
+    {
+        self.options.exclude_patterns = Arc::new(patterns.into_iter().collect());
+        self
+    }
+
+    /// Add a glob pattern to the
+    /// [`exclude_patterns`](struct.WalkDirGeneric.html#method.exclude_patterns),
+    /// skipping matching entries and pruning matching directories.
+    ///
+    /// ```
+    /// # use jwalk::{pattern::Pattern, WalkDir};
+    /// let walk_dir = WalkDir::new(".")
+    ///     .exclude_glob(Pattern::new("target/").unwrap())
+    ///     .exclude_glob(Pattern::new("*.o").unwrap());
+    /// ```
+    pub fn exclude_glob(mut self, pattern: Pattern) -> Self {
+        Arc::make_mut(&mut self.options.exclude_patterns).push(pattern);
+        self
+    }
+
+    /// Only yield files matching at least one of the glob patterns added
+    /// with this method. Defaults to yielding all files.
+    ///
+    /// Include patterns are only matched against entries that aren't
+    /// directories, directories are still yielded and descended into unless
+    /// excluded, so `src/**.rs` finds the Rust files anywhere below `src`.
+    /// Patterns are matched like
+    /// [`exclude_patterns`](struct.WalkDirGeneric.html#method.exclude_patterns),
+    /// and exclusions take precedence.
+    pub fn include_glob(mut self, pattern: Pattern) -> Self {
+        Arc::make_mut(&mut self.options.include_patterns).push(pattern);
+        self
+    }
+
+    /// Filter entries with `rsync` style filter rules, including per
+    /// directory `dir-merge` rule files. Defaults to no rules.
+    ///
+    /// Excluded directories are not descended into. Errors reading or
+    /// parsing a `dir-merge` file are reported as the
+    /// [`read_children_error`](struct.DirEntry.html#structfield.read_children_error)
+    /// of the directory holding it. Rule files are read with the
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system), see
+    /// [`FileSystem::read_to_string`](file_system/trait.FileSystem.html#method.read_to_string).
+    /// See the [`filter`](filter/index.html) module for details.
+    ///
+    /// Available with the `filter` feature.
+    #[cfg(feature = "filter")]
+    pub fn filter_rules(mut self, rules: FilterRules) -> Self {
+        self.options.filter_rules = Some(rules);
+        self
+    }
+
+    /// Skip entries ignored by the `.gitignore` files found while walking,
+    /// and don't descend into ignored directories. Defaults to `false`.
+    ///
+    /// Rules of a `.gitignore` file apply to the directory holding it and its
+    /// descendants and take precedence over rules from parent directories
+    /// and [`filter_rules`](struct.WalkDirGeneric.html#method.filter_rules).
+    /// Entries are filtered before they are passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    /// `.git/info/exclude` and global excludes are not read. Errors reading
+    /// or parsing a `.gitignore` file are reported as the
+    /// [`read_children_error`](struct.DirEntry.html#structfield.read_children_error)
+    /// of the directory holding it. `.gitignore` files are read with the
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system). See
+    /// [`FilterRules::parse_git_ignore`](filter/struct.FilterRules.html#method.parse_git_ignore)
+    /// for the syntax.
+    ///
+    /// Available with the `filter` feature.
+    #[cfg(feature = "filter")]
+    pub fn git_ignore(mut self, git_ignore: bool) -> Self {
+        self.options.git_ignore = git_ignore;
+        self
+    }
+
     /// Follow symbolic links. By default, this is disabled.
     ///
     /// When `yes` is `true`, symbolic links are followed as if they were normal
@@ -284,6 +1363,348 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
+    /// Follow directory junctions and volume mount points on Windows.
+    /// Defaults to the [`follow_links`] setting.
+    ///
+    /// `std::fs` reports junctions as symbolic links, so they are otherwise
+    /// followed together with symbolic links. This allows following only one
+    /// of them, such as following junctions to stay inside of a volume while
+    /// skipping symbolic links to network shares. Junctions that aren't
+    /// followed are yielded as symbolic links, see
+    /// [`DirEntry::reparse_tag`](struct.DirEntry.html#method.reparse_tag).
+    ///
+    /// [`follow_links`]: struct.WalkDirGeneric.html#method.follow_links
+    pub fn follow_junctions(mut self, follow_junctions: bool) -> Self {
+        self.options.follow_junctions = Some(follow_junctions);
+        self
+    }
+
+    /// How depth is counted for entries reached by following symbolic links.
+    /// Defaults to [`LinkDepth::LinkPath`](enum.LinkDepth.html#variant.LinkPath).
+    ///
+    /// Only has an effect if
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+    /// enabled.
+    pub fn link_depth(mut self, link_depth: LinkDepth) -> Self {
+        self.options.link_depth = link_depth;
+        self
+    }
+
+    /// What to do if the rayon pool is too busy to start the walk within its
+    /// `busy_timeout`. Defaults to
+    /// [`BusyPolicy::Error`](enum.BusyPolicy.html#variant.Error).
+    ///
+    /// Only has an effect with
+    /// [`Parallelism::RayonDefaultPool`](enum.Parallelism.html#variant.RayonDefaultPool),
+    /// [`Parallelism::RayonExistingPool`](enum.Parallelism.html#variant.RayonExistingPool)
+    /// with a `busy_timeout` and [`Parallelism::Adaptive`](enum.Parallelism.html#variant.Adaptive),
+    /// with the `rayon` feature. Other walks never wait for a busy pool, so
+    /// they ignore the policy: a
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) walk
+    /// with [`BusyPolicy::FallbackNewPool`](enum.BusyPolicy.html#variant.FallbackNewPool)
+    /// still runs on the calling thread.
+    pub fn busy_policy(mut self, busy_policy: BusyPolicy) -> Self {
+        self.options.busy_policy = busy_policy;
+        self
+    }
+
+    /// Limit the number of directories read at the same time with a handle
+    /// that can be changed while the walk is running. Defaults to `None`,
+    /// reading as many directories at the same time as the thread-pool has
+    /// threads.
+    pub fn concurrency_limit(mut self, concurrency_limit: Option<ConcurrencyLimit>) -> Self {
+        self.options.concurrency_limit = concurrency_limit;
+        self
+    }
+
+    /// Stop reading directories while `buffer_limit` of read directories
+    /// haven't been consumed by the iterator yet. Defaults to `None`,
+    /// reading ahead as far as the thread-pool gets, which can use a lot of
+    /// memory when the iterator is consumed slower than directories are
+    /// read.
+    ///
+    /// This is a soft bound: while the iterator waits for the next directory
+    /// in order, other directories are still read, so the limit can be
+    /// exceeded by those. Has no effect with
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) and
+    /// for [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn buffer_limit(mut self, buffer_limit: Option<BufferLimit>) -> Self {
+        self.options.buffer_limit = buffer_limit;
+        self
+    }
+
+    /// Cancel the walk when `cancel_token` is canceled. Defaults to `None`,
+    /// walking until all entries were yielded or the iterator is dropped.
+    ///
+    /// Canceling stops reading directories that weren't read yet, and the
+    /// iterator ends at the next call to `next`.
+    pub fn cancel_token(mut self, cancel_token: Option<CancelToken>) -> Self {
+        self.options.cancel_token = cancel_token;
+        self
+    }
+
+    /// Stop the walk once `max_entries` entries were yielded. Defaults to
+    /// `None`, yielding all entries.
+    ///
+    /// Unlike `take(max_entries)` on the iterator, this also stops reading
+    /// directories that weren't read yet, like canceling a
+    /// [`cancel_token`](struct.WalkDirGeneric.html#method.cancel_token), so
+    /// the thread-pool doesn't keep reading the rest of the tree. Errors
+    /// aren't counted. Has no effect on
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn max_entries(mut self, max_entries: Option<usize>) -> Self {
+        self.options.max_entries = max_entries;
+        self
+    }
+
+    /// Stop the walk once `deadline` passed. Defaults to `None`, walking
+    /// until all entries were yielded.
+    ///
+    /// Directories aren't read anymore once the deadline passed, and the
+    /// iterator yields an [`Error`](struct.Error.html) whose
+    /// [`kind`](struct.Error.html#method.kind) is
+    /// [`ErrorKind::DeadlineExceeded`](enum.ErrorKind.html#variant.DeadlineExceeded)
+    /// at the next call to `next`, then ends. Entries that were already read
+    /// but not yielded yet are dropped.
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries) and
+    /// [`visit`](struct.WalkDirGeneric.html#method.visit) stop reading
+    /// directories without yielding the error.
+    pub fn deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.options.deadline = deadline;
+        self
+    }
+
+    /// Stop the walk once `timeout` passed since it was started, like a
+    /// [`deadline`](struct.WalkDirGeneric.html#method.deadline). Defaults to
+    /// `None`. The earlier of both applies if both are set.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    /// Create a new [`CancelToken`](struct.CancelToken.html) canceling this
+    /// walk, see [`cancel_token`](struct.WalkDirGeneric.html#method.cancel_token).
+    pub fn cancelable(self) -> (Self, CancelToken) {
+        let cancel_token = CancelToken::new();
+        (self.cancel_token(Some(cancel_token.clone())), cancel_token)
+    }
+
+    /// Count the entries of the walk with `stats_collector`, see the
+    /// [`stats`](stats/index.html) module. Defaults to `None`.
+    pub fn stats_collector(mut self, stats_collector: Option<StatsCollector>) -> Self {
+        self.options.stats_collector = stats_collector;
+        self
+    }
+
+    /// Create a new [`StatsCollector`](stats/struct.StatsCollector.html)
+    /// counting the entries of this walk, see
+    /// [`stats_collector`](struct.WalkDirGeneric.html#method.stats_collector).
+    pub fn with_stats(self) -> (Self, StatsCollector) {
+        let stats_collector = StatsCollector::new();
+        (
+            self.stats_collector(Some(stats_collector.clone())),
+            stats_collector,
+        )
+    }
+
+    /// Read directories from `file_system` instead of the local file system,
+    /// see the [`file_system`](file_system/index.html) module. Defaults to
+    /// `None`, reading with `std::fs`.
+    pub fn file_system(mut self, file_system: Option<Arc<dyn FileSystem>>) -> Self {
+        self.options.file_system = file_system;
+        self
+    }
+
+    /// Keep each directory open until its subdirectories are read, and open
+    /// them relative to it instead of by their full paths. Defaults to `false`.
+    ///
+    /// Opening a directory then costs the same at any depth, and a walk keeps
+    /// reading a subtree whose ancestors are renamed while it runs. Yielded
+    /// paths are unchanged, so they can be stale after a rename. Every
+    /// directory with subdirectories waiting to be read holds a file
+    /// descriptor, directories are opened by path when no more can be opened.
+    ///
+    /// Subdirectories are opened with `openat` without following symlinks,
+    /// followed symlinks are opened by path. Directories are read through
+    /// their handles with `getdents64` and `fstatat`, like
+    /// [`ReadDirBackend::Getdents`](enum.ReadDirBackend.html#variant.Getdents),
+    /// unless [`ReadDirBackend::IoUring`](enum.ReadDirBackend.html#variant.IoUring)
+    /// is used.
+    ///
+    /// Only supported on Linux and Android, and ignored with a
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system).
+    pub fn dir_fds(mut self, dir_fds: bool) -> Self {
+        self.options.dir_fds = dir_fds;
+        self
+    }
+
+    /// Call the hooks of `instrumentation` while walking, see the
+    /// [`instrument`](instrument/index.html) module. Defaults to `None`.
+    pub fn instrumentation(mut self, instrumentation: Option<Arc<dyn Instrumentation>>) -> Self {
+        self.options.instrumentation = instrumentation;
+        self
+    }
+
+    /// Call `on_progress` with the [`Progress`](instrument/struct.Progress.html)
+    /// of the walk at most once per `interval`, and once more after the last
+    /// directory was read. Defaults to no progress reports.
+    ///
+    /// Called from the threads reading directories, so it also counts
+    /// directories that were read ahead of the iterator. See
+    /// [`ProgressReports`](instrument/struct.ProgressReports.html).
+    pub fn on_progress<F>(mut self, interval: std::time::Duration, on_progress: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.options.progress_reports = Some(Arc::new(ProgressReports::new(interval, on_progress)));
+        self
+    }
+
+    /// Let other work waiting on the rayon pool run before reading each
+    /// directory. Defaults to `false`.
+    ///
+    /// Use this for background walks on a pool that is shared with the rest
+    /// of the application, such as the default rayon pool, so the walk doesn't
+    /// starve the application's own parallel work. The walk gets slower while
+    /// the pool is busy. Has no effect with
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial).
+    /// Available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn yield_between_dirs(mut self, yield_between_dirs: bool) -> Self {
+        self.options.yield_between_dirs = yield_between_dirs;
+        self
+    }
+
+    /// Set the order in which the rayon pool reads pending directories.
+    /// Defaults to [`ReadDirOrder::DepthFirst`](enum.ReadDirOrder.html#variant.DepthFirst).
+    ///
+    /// Has no effect with
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) or on
+    /// directories with a higher priority, such as
+    /// [`priority_paths`](struct.WalkDirGeneric.html#method.priority_paths).
+    pub fn read_dir_order(mut self, read_dir_order: ReadDirOrder) -> Self {
+        self.options.read_dir_order = read_dir_order;
+        self
+    }
+
+    /// Set the [`Scheduler`](scheduler/trait.Scheduler.html) deciding which
+    /// pending directory the rayon pool reads next, created by
+    /// `new_scheduler` for each walk, see the [`scheduler`](scheduler/index.html)
+    /// module. Defaults to
+    /// [`DepthFirst`](scheduler/struct.DepthFirst.html).
+    ///
+    /// Has no effect with
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) or
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn scheduler<F, S>(mut self, new_scheduler: F) -> Self
+    where
+        F: Fn() -> S + Send + Sync + 'static,
+        S: Scheduler<C> + 'static,
+    {
+        self.options.new_scheduler = Some(Arc::new(move || {
+            Box::new(new_scheduler()) as Box<dyn Scheduler<C>>
+        }));
+        self
+    }
+
+    /// Yield the entries of each directory as soon as it was read, instead
+    /// of in depth first order, if `false`. Defaults to `true`.
+    ///
+    /// In depth first order, directories read ahead wait until the iterator
+    /// gets to them, which serializes walks whose consumer doesn't care
+    /// about the order, such as counting or indexing. Directories are still
+    /// yielded before their contents, entries of the same directory are
+    /// yielded together, and errors reading directories are yielded on
+    /// their own instead of in
+    /// [`DirEntry::read_children_error`](struct.DirEntry.html#structfield.read_children_error).
+    ///
+    /// Ordering the contents of directories relative to them isn't possible
+    /// anymore, so
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first),
+    /// [`reduce_read_dir`](struct.WalkDirGeneric.html#method.reduce_read_dir),
+    /// the subtree sizes of directories with
+    /// [`aggregate_sizes`](struct.WalkDirGeneric.html#method.aggregate_sizes),
+    /// [`skip_empty_dirs`](struct.WalkDirGeneric.html#method.skip_empty_dirs)
+    /// and [`leaf_dirs_only`](struct.WalkDirGeneric.html#method.leaf_dirs_only)
+    /// are ignored, and
+    /// [`DirEntryIter::checkpoint`](struct.DirEntryIter.html#method.checkpoint)
+    /// doesn't record directories that are being read.
+    pub fn strict_order(mut self, strict_order: bool) -> Self {
+        self.options.strict_order = strict_order;
+        self
+    }
+
+    /// Set how directories are read. Defaults to
+    /// [`ReadDirBackend::Std`](enum.ReadDirBackend.html#variant.Std).
+    ///
+    /// Backends not supported on the current platform read with
+    /// `std::fs::read_dir` instead, so the same walk runs everywhere.
+    /// Ignored with a [`file_system`](struct.WalkDirGeneric.html#method.file_system).
+    pub fn read_dir_backend(mut self, read_dir_backend: ReadDirBackend) -> Self {
+        self.options.read_dir_backend = read_dir_backend;
+        self
+    }
+
+    /// Process and yield the entries of directories in chunks of at most
+    /// `read_dir_chunk_size` entries. Defaults to `None`, processing all
+    /// entries of a directory before any of them is yielded.
+    ///
+    /// A directory's entries are read from it as its chunks are taken, so the
+    /// first entries of a directory with millions of them are yielded sooner
+    /// and fewer entries are held at once. Entries read by a
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system) are
+    /// read in full before the first chunk, and the `getdents64` and
+    /// `NtQueryDirectoryFile` [backends](enum.ReadDirBackend.html) read all
+    /// names and types up front. Chunks are filtered, sorted
+    /// and passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// one by one, with the `ReadDirState` left by the previous chunk, so
+    /// entries are only sorted within their chunk. The thread-pool reads the
+    /// next chunk ahead of the iterator, use
+    /// [`buffer_limit`](struct.WalkDirGeneric.html#method.buffer_limit) to
+    /// bound how far.
+    ///
+    /// The remaining entries of a directory aren't recorded in
+    /// [checkpoints](struct.DirEntryIter.html#method.checkpoint), and
+    /// [`reduce_read_dir`](struct.WalkDirGeneric.html#method.reduce_read_dir)
+    /// is passed the `ReadDirState` of a directory's first chunk.
+    pub fn read_dir_chunk_size(mut self, read_dir_chunk_size: Option<usize>) -> Self {
+        self.options.read_dir_chunk_size = read_dir_chunk_size;
+        self
+    }
+
+    /// Walk from the extended-length form of the roots on Windows, starting
+    /// with `\\?\`, so trees deeper than `MAX_PATH` characters can be read
+    /// without enabling long paths for the whole process. Defaults to `true`
+    /// on Windows.
+    ///
+    /// Relative roots are made absolute with the current directory when the
+    /// walk starts, and `.` and `..` are removed from them.
+    /// [`include_only`](struct.WalkDirGeneric.html#method.include_only) and
+    /// [`priority_paths`](struct.WalkDirGeneric.html#method.priority_paths)
+    /// are converted the same way. Ignored on other platforms and with a
+    /// [`file_system`](struct.WalkDirGeneric.html#method.file_system).
+    pub fn long_paths(mut self, long_paths: bool) -> Self {
+        self.options.long_paths = long_paths;
+        self
+    }
+
+    /// Yield paths below the roots as given to the builder instead of their
+    /// extended-length form when
+    /// [`long_paths`](struct.WalkDirGeneric.html#method.long_paths) is
+    /// enabled. Defaults to `true`.
+    ///
+    /// Normalized paths are easier to display and compare, but `std::fs`
+    /// calls on them, including
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata), fail for
+    /// paths longer than `MAX_PATH` unless long paths are enabled for the
+    /// process. Disable this to yield extended-length paths that work at any
+    /// length.
+    pub fn normalize_long_paths(mut self, normalize_long_paths: bool) -> Self {
+        self.options.normalize_long_paths = normalize_long_paths;
+        self
+    }
+
     /// Set the minimum depth of entries yielded by the iterator.
     ///
     /// The smallest depth is `0` and always corresponds to the path given
@@ -323,7 +1744,9 @@ impl<C: ClientState> WalkDirGeneric<C> {
     }
 
     /// Degree of parallelism to use when performing walk. Defaults to
-    /// [`Parallelism::RayonDefaultPool`](enum.Parallelism.html#variant.RayonDefaultPool).
+    /// [`Parallelism::RayonDefaultPool`](enum.Parallelism.html#variant.RayonDefaultPool),
+    /// or to [`Parallelism::StdThreads`](enum.Parallelism.html#variant.StdThreads)
+    /// without the `rayon` feature.
     pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
         self.options.parallelism = parallelism;
         self
@@ -337,6 +1760,24 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
+    /// A callback function creating the initial
+    /// [`entry.client_state`](struct.DirEntry.html#field.client_state) of
+    /// each entry, instead of `C::DirEntryState::default()`.
+    ///
+    /// It's called on the threads reading directories, once an entry was
+    /// created and symbolic links were followed, and before the entry is
+    /// passed to [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry)
+    /// and [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    /// Use this to seed every entry with shared context, such as an `Arc`,
+    /// without an extra pass over all entries.
+    pub fn init_client_state<F>(mut self, init: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) -> C::DirEntryState + Send + Sync + 'static,
+    {
+        self.options.init_client_state = Some(Arc::new(init));
+        self
+    }
+
     /// A callback function to process (sort/filter/skip/state) each directory
     /// of entries before they are yielded. Modify the given array to
     /// sort/filter entries. Use [`entry.read_children_path =
@@ -354,165 +1795,601 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self.options.process_read_dir = Some(Arc::new(process_by));
         self
     }
-}
 
-fn process_dir_entry_result<C: ClientState>(
-    dir_entry_result: Result<DirEntry<C>>,
-    follow_links: bool,
-) -> Result<DirEntry<C>> {
-    match dir_entry_result {
-        Ok(mut dir_entry) => {
-            if follow_links && dir_entry.file_type.is_symlink() {
-                dir_entry = dir_entry.follow_symlink()?;
-            }
+    /// A callback function to reduce the state of a directory's contents into
+    /// the directory and its parent, once all of its descendants were
+    /// yielded.
+    ///
+    /// It's passed the `ReadDirState` of the directory containing the
+    /// directory, the directory entry and the `ReadDirState` its contents were
+    /// processed with by
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// which includes what was reduced from its subdirectories. This allows
+    /// computing results bottom up, such as hashes of trees, while
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// does the per-entry work in parallel.
+    ///
+    /// Directories are yielded after their contents like with
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first), so
+    /// their state is reduced when they are yielded. The callback runs on the
+    /// thread iterating, and isn't called for directories whose contents
+    /// weren't read. Has no effect on
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries).
+    pub fn reduce_read_dir<F>(mut self, reduce_by: F) -> Self
+    where
+        F: Fn(&mut C::ReadDirState, &mut DirEntry<C>, C::ReadDirState) + Send + Sync + 'static,
+    {
+        self.options.reduce_read_dir = Some(Arc::new(reduce_by));
+        self
+    }
 
-            if dir_entry.depth == 0 && dir_entry.file_type.is_symlink() {
-                // As a special case, if we are processing a root entry, then we
-                // always follow it even if it's a symlink and follow_links is
-                // false. We are careful to not let this change the semantics of
-                // the DirEntry however. Namely, the DirEntry should still
-                // respect the follow_links setting. When it's disabled, it
-                // should report itself as a symlink. When it's enabled, it
-                // should always report itself as the target.
-                let metadata = fs::metadata(dir_entry.path())
-                    .map_err(|err| Error::from_path(0, dir_entry.path(), err))?;
-                if metadata.file_type().is_dir() {
-                    dir_entry.read_children_path = Some(Arc::from(dir_entry.path()));
-                }
+    /// A callback function called with each entry before it is yielded, to
+    /// do per-entry work like detecting file types from contents and store
+    /// the result in
+    /// [`entry.client_state`](struct.DirEntry.html#field.client_state).
+    ///
+    /// It's called once
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// processed the entries of a directory, so entries removed there are
+    /// skipped. The entries of a directory are mapped in parallel on the
+    /// thread pool of the walk, and one by one with
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial).
+    pub fn map_entries<F>(mut self, map_by: F) -> Self
+    where
+        F: Fn(&mut DirEntry<C>) + Send + Sync + 'static,
+    {
+        self.options.map_entries = Some(Arc::new(map_by));
+        self
+    }
+
+    /// A callback function deriving the state each entry gets from the
+    /// `ReadDirState` of the directory containing it, so context like being
+    /// inside of `node_modules` or accumulated ignore rules flows down the
+    /// tree.
+    ///
+    /// It's called with the state of each directory once
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// processed its entries, and with each of them. The result is available
+    /// from [`DirEntry::parent_read_dir_state`](struct.DirEntry.html#method.parent_read_dir_state),
+    /// and the contents of directories are processed starting from it,
+    /// instead of from a clone of the parent's state. Roots get a projection
+    /// of the `ReadDirState` passed to
+    /// [`root_read_dir_state`](struct.WalkDirGeneric.html#method.root_read_dir_state).
+    pub fn project_read_dir_state<F>(mut self, project_by: F) -> Self
+    where
+        F: Fn(&C::ReadDirState, &DirEntry<C>) -> C::ReadDirState + Send + Sync + 'static,
+    {
+        self.options.project_read_dir_state = Some(Arc::new(project_by));
+        self
+    }
+
+    /// Like [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// but also passes a scratch value for reusable buffers or matchers.
+    ///
+    /// Each thread processing directories keeps its own scratch value,
+    /// created with `init` the first time it processes a directory of this
+    /// walk and reused for later ones.
+    ///
+    /// A `process_read_dir` callback set before this one is called before
+    /// `process_by`, setting one afterwards replaces both.
+    pub fn process_read_dir_with_scratch<S, I, F>(mut self, init: I, process_by: F) -> Self
+    where
+        S: Send + 'static,
+        I: Fn() -> S + Send + Sync + 'static,
+        F: Fn(&mut S, Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+            + Send
+            + Sync
+            + 'static,
+    {
+        let process_before = self.options.process_read_dir.take();
+        let scratch_key = ScratchKey::default();
+        self.process_read_dir(move |depth, path, read_dir_state, children| {
+            if let Some(process_before) = process_before.as_ref() {
+                process_before(depth, path, read_dir_state, children);
             }
+            let mut scratch = scratch_key.take().unwrap_or_else(&init);
+            process_by(&mut scratch, depth, path, read_dir_state, children);
+            scratch_key.put(scratch);
+        })
+    }
 
-            Ok(dir_entry)
-        }
-        Err(err) => Err(err),
+    /// Only yield entries for which `predicate` returns `true`, and don't
+    /// descend into directories for which it returns `false`. Defaults to
+    /// yielding all entries.
+    ///
+    /// The predicate is called on the threads reading directories, before
+    /// sorting and
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    /// Root is filtered too. Errors are always yielded.
+    pub fn filter_entry<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) -> bool + Send + Sync + 'static,
+    {
+        self.options.filter_entry = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Directories that should be read before the rest of the tree. Defaults
+    /// to none.
+    ///
+    /// When walking in parallel, pending directory reads on the way to (and
+    /// below) these paths are scheduled ahead of all other directories. This
+    /// only changes the order in which directories are read, entries are
+    /// still yielded in the usual depth first order. Paths are compared
+    /// component wise against paths built from `root`, so they must be
+    /// spelled with the same prefix as `root`.
+    pub fn priority_paths<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.options.priority_paths = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        self
+    }
+
+    /// Order in which the subtrees of root's child directories should be
+    /// read. Defaults to reading them in the order they are yielded.
+    ///
+    /// When walking in parallel, directories below a root child that
+    /// compares as `Less` are scheduled ahead of directories below root
+    /// children that compare as `Greater`. This is useful to start with the
+    /// subtrees most likely to contain interesting entries. Like
+    /// [`priority_paths`](struct.WalkDirGeneric.html#method.priority_paths) it
+    /// doesn't change the order in which entries are yielded, use
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// for that.
+    pub fn schedule_root_children_by<F>(mut self, compare: F) -> Self
+    where
+        F: Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering + Send + Sync + 'static,
+    {
+        self.options.schedule_root_children_by = Some(Arc::new(compare));
+        self
     }
 }
 
+// What to do with further links to a hardlinked file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HardlinkDedup {
+    Skip,
+    Flag,
+}
+
+fn check_root_access(root: &Path, file_system: Option<&dyn FileSystem>) -> Result<()> {
+    let file_system = file_system::or_std(file_system);
+    let file_type = file_system
+        .file_type(root, true)
+        .map_err(|err| Error::from_path(0, root.to_owned(), err).during(Operation::Metadata))?;
+    if file_type.is_dir() {
+        let _entries = file_system
+            .read_dir(root)
+            .map_err(|err| Error::from_path(0, root.to_owned(), err).during(Operation::ReadDir))?;
+    }
+    Ok(())
+}
+
 impl<C: ClientState> IntoIterator for WalkDirGeneric<C> {
     type Item = Result<DirEntry<C>>;
     type IntoIter = DirEntryIter<C>;
 
     fn into_iter(self) -> DirEntryIter<C> {
-        let sort = self.options.sort;
+        self.walk(None)
+    }
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    fn walk(mut self, spawn_walk: Option<&SpawnWalk<'_>>) -> DirEntryIter<C> {
+        #[cfg(feature = "checkpoint")]
+        let roots = self.roots().map(Path::to_path_buf).collect();
+        let long_path_roots = self.use_long_paths();
+        let reduce_read_dir = self.options.reduce_read_dir.clone();
+        let skip_empty_dirs = self.options.skip_empty_dirs;
+        let leaf_dirs_only = self.options.leaf_dirs_only;
+        if leaf_dirs_only.is_some() {
+            // Leaves are only known with all of the contents of a directory
+            self.options.read_dir_chunk_size = None;
+        }
+        let contents_first = self.options.contents_first || reduce_read_dir.is_some();
+        #[cfg(feature = "size")]
+        let contents_first = contents_first || self.options.aggregate_sizes.is_some();
+        let PreparedWalk {
+            root_entry_results,
+            schedule,
+            min_depth,
+            root_read_dir_state,
+            read_dir_callback,
+            max_entries,
+            deadline,
+        } = self.prepare(spawn_walk);
+        let mut dir_entry_iter = DirEntryIter::new(
+            root_entry_results,
+            schedule,
+            min_depth,
+            contents_first,
+            root_read_dir_state,
+            read_dir_callback,
+        );
+        #[cfg(feature = "checkpoint")]
+        {
+            dir_entry_iter.roots = roots;
+        }
+        dir_entry_iter.reduce_read_dir = reduce_read_dir;
+        dir_entry_iter.long_path_roots = long_path_roots;
+        dir_entry_iter.max_entries = max_entries;
+        dir_entry_iter.deadline = deadline;
+        dir_entry_iter.skip_empty_dirs = skip_empty_dirs;
+        dir_entry_iter.leaf_dirs_only = leaf_dirs_only;
+        dir_entry_iter
+    }
+
+    // Replaces the roots and the paths compared against entry paths with their
+    // extended-length form if enabled, returning the roots to normalize
+    // yielded paths to.
+    fn use_long_paths(&mut self) -> Vec<(PathBuf, PathBuf)> {
+        if !cfg!(windows) || !self.options.long_paths || self.options.file_system.is_some() {
+            return Vec::new();
+        }
+        let mut long_path_roots = Vec::new();
+        for root in std::iter::once(&mut self.root).chain(&mut self.additional_roots) {
+            let long_path_root = to_verbatim(root);
+            if long_path_root != *root {
+                long_path_roots.push((long_path_root.clone(), std::mem::take(root)));
+                *root = long_path_root;
+            }
+        }
+        #[cfg(feature = "checkpoint")]
+        for (path, _) in self.resume_from.iter_mut().flatten() {
+            *path = to_verbatim(path);
+        }
+        for path in self
+            .options
+            .include_only
+            .iter_mut()
+            .chain(&mut self.options.priority_paths)
+        {
+            *path = to_verbatim(path);
+        }
+        if self.options.normalize_long_paths {
+            long_path_roots
+        } else {
+            Vec::new()
+        }
+    }
+
+    // Processes the root entry and creates the callback reading directories.
+    fn prepare<'a>(self, spawn_walk: Option<&'a SpawnWalk<'a>>) -> PreparedWalk<'a, C> {
         let max_depth = self.options.max_depth;
         let min_depth = self.options.min_depth;
         let parallelism = self.options.parallelism;
-        let skip_hidden = self.options.skip_hidden;
-        let follow_links = self.options.follow_links;
-        let process_read_dir = self.options.process_read_dir.clone();
-        let mut root_read_dir_state = self.options.root_read_dir_state;
-        let follow_link_ancestors = if follow_links {
-            Arc::new(vec![Arc::from(self.root.clone()) as Arc<Path>])
+        let busy_policy = self.options.busy_policy;
+        let concurrency_limit = self
+            .options
+            .concurrency_limit
+            .as_ref()
+            .map(ConcurrencyLimit::start_walk);
+        let cancel_token = self.options.cancel_token;
+        let max_entries = self.options.max_entries.map(|max_entries| {
+            let reached = CancelToken::new();
+            if max_entries == 0 {
+                reached.cancel();
+            }
+            (max_entries, reached)
+        });
+        let deadline = self
+            .options
+            .timeout
+            .map(|timeout| Instant::now() + timeout)
+            .into_iter()
+            .chain(self.options.deadline)
+            .min();
+        let stats_collector = self.options.stats_collector;
+        let instrumentations: Vec<Arc<dyn Instrumentation>> = self
+            .options
+            .instrumentation
+            .into_iter()
+            .chain(
+                self.options
+                    .progress_reports
+                    .map(|progress_reports| progress_reports as Arc<dyn Instrumentation>),
+            )
+            .collect();
+        let pacing = ReadDirPacing {
+            #[cfg(feature = "rayon")]
+            yield_between_dirs: self.options.yield_between_dirs,
+            order: self.options.read_dir_order,
+            buffer_limit: self.options.buffer_limit,
+            strict_order: self.options.strict_order,
+        };
+        let follow_links = FollowLinks {
+            symlinks: self.options.follow_links,
+            junctions: self
+                .options
+                .follow_junctions
+                .unwrap_or(self.options.follow_links),
+        };
+        let walk_roots: Arc<Vec<WalkRoot>> = Arc::new(
+            std::iter::once(&self.root)
+                .chain(&self.additional_roots)
+                .map(|root| WalkRoot {
+                    path: root.to_path_buf(),
+                    device: if self.options.same_file_system {
+                        device_num(root).ok()
+                    } else {
+                        None
+                    },
+                    target_depth_root: if follow_links.any()
+                        && self.options.link_depth == LinkDepth::TargetPath
+                    {
+                        fs::canonicalize(root).ok()
+                    } else {
+                        None
+                    },
+                })
+                .collect(),
+        );
+        #[cfg(feature = "filter")]
+        let root_filter_rules = if self.options.filter_rules.is_some() || self.options.git_ignore {
+            Some(DirFilterRules::new(
+                self.options.filter_rules.as_ref(),
+                self.options.git_ignore,
+            ))
         } else {
-            Arc::new(vec![])
+            None
         };
+        let file_system = self.options.file_system.clone();
+        let root_dir_fd = self
+            .root_dir_fd
+            .filter(|_| file_system.is_none())
+            .map(|dir_fd| (Arc::<Path>::from(self.root.as_path()), dir_fd));
+        let root_is_handle = root_dir_fd.is_some();
+        let priority_paths = self.options.priority_paths;
+        let root_children_priorities: Arc<RwLock<HashMap<PathBuf, usize>>> = Default::default();
+        let read_dir_spec_priority: Option<Arc<ReadDirSpecPriorityCallback<C>>> =
+            if priority_paths.is_empty() && self.options.schedule_root_children_by.is_none() {
+                None
+            } else {
+                let root_children_priorities = root_children_priorities.clone();
+                Some(Arc::new(move |read_dir_spec: &ReadDirSpec<C>| {
+                    if is_priority_path(&read_dir_spec.path, &priority_paths) {
+                        return usize::MAX;
+                    }
+                    ReadDirSteps::root_children_priority(&root_children_priorities, read_dir_spec)
+                }))
+            };
+        let read_dir_steps = Arc::new(ReadDirSteps {
+            limits: ReadDirLimits {
+                max_depth,
+                cancel_token: cancel_token.clone(),
+                max_entries_reached: max_entries.as_ref().map(|(_, reached)| reached.clone()),
+                deadline,
+            },
+            concurrency_limit: concurrency_limit.clone(),
+            source: ReadDirSource {
+                file_system: file_system.clone(),
+                dir_fds: (self.options.dir_fds || root_is_handle) && file_system.is_none(),
+                root_dir_fd,
+                backend: self.options.read_dir_backend,
+                chunk_size: self.options.read_dir_chunk_size.map(|size| size.max(1)),
+            },
+            follow_links,
+            walk_roots: walk_roots.clone(),
+            entry_filter: EntryFilter {
+                skip_hidden: self.options.skip_hidden,
+                skip_vcs_dirs: self.options.skip_vcs_dirs,
+                vcs_dir_names: self.options.vcs_dir_names.clone(),
+                skip_build_dirs: self.options.skip_build_dirs,
+                build_dir_names: self.options.build_dir_names.clone(),
+                on_skipped_build_dir: self.options.on_skipped_build_dir.clone(),
+                exclude_paths: self.options.exclude_paths.clone(),
+                exclude_devices: self.options.exclude_devices.clone(),
+                exclude_patterns: self.options.exclude_patterns.clone(),
+                include_patterns: self.options.include_patterns.clone(),
+                include_only: self.options.include_only.clone(),
+            },
+            entry_preload: EntryPreload {
+                metadata: self.options.preload_metadata
+                    || (self.options.sort_by.is_none() && self.options.sort_key.is_some()),
+                #[cfg(target_os = "linux")]
+                statx: self.options.preload_statx,
+                init_client_state: self.options.init_client_state.clone(),
+            },
+            metadata_filter: MetadataFilter {
+                file_sizes: self.options.min_file_size..=self.options.max_file_size,
+                modified_after: self.options.modified_after,
+                prune_unmodified_dirs: self.options.prune_unmodified_dirs,
+            },
+            filter_entry: self.options.filter_entry.clone(),
+            #[cfg(feature = "size")]
+            aggregate_sizes: self.options.aggregate_sizes.clone(),
+            entry_sort: EntrySort {
+                sort: self.options.sort || self.options.name_order.is_custom(),
+                name_order: self.options.name_order,
+                sort_by: self.options.sort_by.clone(),
+                sort_key: self.options.sort_key,
+            },
+            hardlink_dedup: self.options.hardlink_dedup,
+            hardlinks: Arc::new(HardlinkSet::default()),
+            process_read_dir: self.options.process_read_dir.clone(),
+            map_entries: self.options.map_entries.clone(),
+            project_read_dir_state: self.options.project_read_dir_state.clone(),
+            schedule_root_children_by: self.options.schedule_root_children_by.clone(),
+            root_children_priorities,
+        });
 
-        let root_entry = DirEntry::from_path(0, &self.root, false, follow_link_ancestors);
-        let root_parent_path = root_entry
-            .as_ref()
-            .map(|root| root.parent_path().to_owned())
-            .unwrap_or_default();
-        let mut root_entry_results = vec![process_dir_entry_result(root_entry, follow_links)];
-        if let Some(process_read_dir) = process_read_dir.as_ref() {
-            process_read_dir(
-                None,
-                &root_parent_path,
+        let mut root_read_dir_state = self.options.root_read_dir_state;
+        let root_entries = || {
+            walk_roots
+                .iter()
+                .map(|walk_root| (walk_root.path.clone(), 0))
+                .collect()
+        };
+        #[cfg(feature = "checkpoint")]
+        let start_entries: Vec<_> = self.resume_from.unwrap_or_else(root_entries);
+        #[cfg(not(feature = "checkpoint"))]
+        let start_entries: Vec<_> = root_entries();
+        let mut root_entry_results = Vec::new();
+        for (start_path, start_depth) in start_entries {
+            let follow_link_ancestors = if follow_links.any() {
+                Arc::new(vec![Arc::from(start_path.clone()) as Arc<Path>])
+            } else {
+                Arc::new(vec![])
+            };
+            // The root of a walk through a handle is known to be a directory
+            let root_entry = if root_is_handle && start_path == self.root {
+                Ok(DirEntry::from_path_and_type(
+                    start_depth,
+                    &start_path,
+                    FileType::dir().with_source(FileTypeSource::Stat),
+                    false,
+                    follow_link_ancestors,
+                    file_system.clone(),
+                ))
+            } else {
+                DirEntry::from_path(
+                    start_depth,
+                    &start_path,
+                    false,
+                    follow_link_ancestors,
+                    file_system.clone(),
+                )
+            };
+            root_entry_results.extend(read_dir_steps.root_entry(
+                root_entry,
+                &start_path,
+                #[cfg(feature = "filter")]
+                root_filter_rules.as_ref(),
                 &mut root_read_dir_state,
-                &mut root_entry_results,
-            );
+            ));
         }
 
-        DirEntryIter::new(
-            root_entry_results,
-            parallelism,
-            min_depth,
-            root_read_dir_state,
-            Arc::new(move |read_dir_spec| {
-                let ReadDirSpec {
-                    path,
-                    depth,
-                    mut client_read_state,
-                    mut follow_link_ancestors,
-                } = read_dir_spec;
-
-                let read_dir_depth = depth;
-                let read_dir_contents_depth = depth + 1;
-
-                if read_dir_contents_depth > max_depth {
-                    return Ok(ReadDir::new(client_read_state, Vec::new()));
-                }
+        let mut read_dir_callback: Arc<ReadDirCallback<C>> =
+            Arc::new(move |read_dir_spec| read_dir_steps.read_dir(read_dir_spec));
 
-                follow_link_ancestors = if follow_links {
-                    let mut ancestors = Vec::with_capacity(follow_link_ancestors.len() + 1);
-                    ancestors.extend(follow_link_ancestors.iter().cloned());
-                    ancestors.push(path.clone());
-                    Arc::new(ancestors)
-                } else {
-                    follow_link_ancestors
-                };
-
-                let mut dir_entry_results: Vec<_> = fs::read_dir(path.as_ref())
-                    .map_err(|err| Error::from_path(0, path.to_path_buf(), err))?
-                    .filter_map(|dir_entry_result| {
-                        let fs_dir_entry = match dir_entry_result {
-                            Ok(fs_dir_entry) => fs_dir_entry,
-                            Err(err) => {
-                                return Some(Err(Error::from_io(read_dir_contents_depth, err)))
-                            }
-                        };
-
-                        let dir_entry = match DirEntry::from_entry(
-                            read_dir_contents_depth,
-                            path.clone(),
-                            &fs_dir_entry,
-                            follow_link_ancestors.clone(),
-                        ) {
-                            Ok(dir_entry) => dir_entry,
-                            Err(err) => return Some(Err(err)),
-                        };
-
-                        if skip_hidden && is_hidden(&dir_entry.file_name) {
-                            return None;
-                        }
-
-                        Some(process_dir_entry_result(Ok(dir_entry), follow_links))
-                    })
-                    .collect();
-
-                if sort {
-                    dir_entry_results.sort_by(|a, b| match (a, b) {
-                        (Ok(a), Ok(b)) => a.file_name.cmp(&b.file_name),
-                        (Ok(_), Err(_)) => Ordering::Less,
-                        (Err(_), Ok(_)) => Ordering::Greater,
-                        (Err(_), Err(_)) => Ordering::Equal,
-                    });
-                }
+        if let Some(stats_collector) = stats_collector {
+            stats_collector.count(&root_entry_results, min_depth);
+            read_dir_callback = count_read_dir(stats_collector, min_depth, read_dir_callback);
+        }
 
-                if let Some(process_read_dir) = process_read_dir.as_ref() {
-                    process_read_dir(
-                        Some(read_dir_depth),
-                        path.as_ref(),
-                        &mut client_read_state,
-                        &mut dir_entry_results,
-                    );
+        for instrumentation in instrumentations {
+            for root_entry in root_entry_results.iter().flatten() {
+                if let Some(read_children_path) = root_entry.read_children_path.as_ref() {
+                    instrumentation.on_spec_queued(read_children_path, root_entry.depth);
                 }
+            }
+            read_dir_callback = instrument_read_dir(instrumentation, read_dir_callback);
+        }
 
-                Ok(ReadDir::new(client_read_state, dir_entry_results))
-            }),
-        )
+        PreparedWalk {
+            root_entry_results,
+            schedule: ReadDirSchedule {
+                parallelism,
+                busy_policy,
+                spawn_walk,
+                pacing,
+                concurrency_limit,
+                cancel_token,
+                queueing: ReadDirQueueing {
+                    read_dir_spec_priority,
+                    new_scheduler: self.options.new_scheduler,
+                },
+            },
+            min_depth,
+            root_read_dir_state,
+            read_dir_callback,
+            max_entries,
+            deadline,
+        }
     }
 }
 
+/// Root entries and read dir callback of a walk that is about to start.
+struct PreparedWalk<'a, C: ClientState> {
+    root_entry_results: Vec<Result<DirEntry<C>>>,
+    schedule: ReadDirSchedule<'a, C>,
+    min_depth: usize,
+    root_read_dir_state: C::ReadDirState,
+    read_dir_callback: Arc<ReadDirCallback<C>>,
+    // see `max_entries`, canceled once they were yielded
+    max_entries: Option<(usize, CancelToken)>,
+    // earliest of `deadline` and `timeout`
+    deadline: Option<Instant>,
+}
+
 impl<C: ClientState> Clone for WalkDirOptions<C> {
     fn clone(&self) -> WalkDirOptions<C> {
         WalkDirOptions {
             sort: false,
+            name_order: self.name_order,
+            sort_by: self.sort_by.clone(),
+            sort_key: self.sort_key,
+            contents_first: self.contents_first,
             min_depth: self.min_depth,
             max_depth: self.max_depth,
             skip_hidden: self.skip_hidden,
+            skip_vcs_dirs: self.skip_vcs_dirs,
+            vcs_dir_names: self.vcs_dir_names.clone(),
+            skip_build_dirs: self.skip_build_dirs,
+            build_dir_names: self.build_dir_names.clone(),
+            on_skipped_build_dir: self.on_skipped_build_dir.clone(),
+            skip_empty_dirs: self.skip_empty_dirs,
+            leaf_dirs_only: self.leaf_dirs_only,
             follow_links: self.follow_links,
+            follow_junctions: self.follow_junctions,
+            link_depth: self.link_depth,
             parallelism: self.parallelism.clone(),
             root_read_dir_state: self.root_read_dir_state.clone(),
+            init_client_state: self.init_client_state.clone(),
             process_read_dir: self.process_read_dir.clone(),
+            reduce_read_dir: self.reduce_read_dir.clone(),
+            map_entries: self.map_entries.clone(),
+            project_read_dir_state: self.project_read_dir_state.clone(),
+            filter_entry: self.filter_entry.clone(),
+            priority_paths: self.priority_paths.clone(),
+            schedule_root_children_by: self.schedule_root_children_by.clone(),
+            exclude_paths: self.exclude_paths.clone(),
+            exclude_devices: self.exclude_devices.clone(),
+            same_file_system: self.same_file_system,
+            hardlink_dedup: self.hardlink_dedup,
+            preload_metadata: self.preload_metadata,
+            #[cfg(target_os = "linux")]
+            preload_statx: self.preload_statx,
+            #[cfg(feature = "size")]
+            aggregate_sizes: self.aggregate_sizes.clone(),
+            min_file_size: self.min_file_size,
+            max_file_size: self.max_file_size,
+            modified_after: self.modified_after,
+            prune_unmodified_dirs: self.prune_unmodified_dirs,
+            include_only: self.include_only.clone(),
+            exclude_patterns: self.exclude_patterns.clone(),
+            include_patterns: self.include_patterns.clone(),
+            #[cfg(feature = "filter")]
+            filter_rules: self.filter_rules.clone(),
+            #[cfg(feature = "filter")]
+            git_ignore: self.git_ignore,
+            check_root: self.check_root,
+            #[cfg(feature = "rayon")]
+            yield_between_dirs: self.yield_between_dirs,
+            strict_order: self.strict_order,
+            read_dir_order: self.read_dir_order,
+            new_scheduler: self.new_scheduler.clone(),
+            read_dir_backend: self.read_dir_backend,
+            read_dir_chunk_size: self.read_dir_chunk_size,
+            long_paths: self.long_paths,
+            normalize_long_paths: self.normalize_long_paths,
+            busy_policy: self.busy_policy,
+            concurrency_limit: self.concurrency_limit.clone(),
+            buffer_limit: self.buffer_limit,
+            cancel_token: self.cancel_token.clone(),
+            max_entries: self.max_entries,
+            deadline: self.deadline,
+            timeout: self.timeout,
+            stats_collector: self.stats_collector.clone(),
+            file_system: self.file_system.clone(),
+            dir_fds: self.dir_fds,
+            instrumentation: self.instrumentation.clone(),
+            progress_reports: self.progress_reports.clone(),
         }
     }
 }
@@ -524,7 +2401,9 @@ impl Parallelism {
     {
         match self {
             Parallelism::Serial => op(),
+            #[cfg(feature = "rayon")]
             Parallelism::RayonDefaultPool { .. } => rayon::spawn(op),
+            #[cfg(feature = "rayon")]
             Parallelism::RayonNewPool(num_threads) => {
                 let mut thread_pool = ThreadPoolBuilder::new();
                 if *num_threads > 0 {
@@ -536,24 +2415,72 @@ impl Parallelism {
                     rayon::spawn(op);
                 }
             }
+            #[cfg(feature = "rayon")]
             Parallelism::RayonExistingPool { pool, .. } => pool.spawn(op),
+            #[cfg(feature = "rayon")]
+            Parallelism::Adaptive { .. } => rayon::spawn(op),
+            #[cfg(not(feature = "rayon"))]
+            Parallelism::RayonExistingPool { pool, .. } => match **pool {},
+            #[cfg(not(feature = "rayon"))]
+            Parallelism::RayonDefaultPool { .. }
+            | Parallelism::RayonNewPool(_)
+            | Parallelism::Adaptive { .. } => {
+                std::thread::spawn(op);
+            }
+            Parallelism::StdThreads(_) => {
+                std::thread::spawn(op);
+            }
         }
     }
 
+    // Number of `std` threads reading directories, `None` if they are read on
+    // the calling thread or a rayon pool.
+    pub(crate) fn std_threads(&self) -> Option<usize> {
+        match self {
+            Parallelism::StdThreads(num_threads) => Some(*num_threads),
+            #[cfg(not(feature = "rayon"))]
+            Parallelism::RayonNewPool(num_threads)
+            | Parallelism::Adaptive {
+                max_threads: num_threads,
+                ..
+            } => Some(*num_threads),
+            #[cfg(not(feature = "rayon"))]
+            Parallelism::RayonDefaultPool { .. } => Some(0),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn busy_error(&self) -> Error {
+        let num_threads = match self {
+            #[cfg(feature = "rayon")]
+            Parallelism::RayonDefaultPool { .. } | Parallelism::Adaptive { .. } => {
+                Some(rayon::current_num_threads())
+            }
+            #[cfg(feature = "rayon")]
+            Parallelism::RayonExistingPool { pool, .. } => Some(pool.current_num_threads()),
+            _ => None,
+        };
+        Error::busy(self.timeout(), num_threads)
+    }
+
     pub(crate) fn timeout(&self) -> Option<std::time::Duration> {
         match self {
-            Parallelism::Serial | Parallelism::RayonNewPool(_) => None,
+            #[cfg(feature = "rayon")]
             Parallelism::RayonDefaultPool { busy_timeout } => Some(*busy_timeout),
+            #[cfg(feature = "rayon")]
             Parallelism::RayonExistingPool { busy_timeout, .. } => *busy_timeout,
+            #[cfg(feature = "rayon")]
+            Parallelism::Adaptive { busy_timeout, .. } => Some(*busy_timeout),
+            // `std` threads are never busy
+            _ => None,
         }
     }
 }
 
-fn is_hidden(file_name: &OsStr) -> bool {
-    file_name
-        .to_str()
-        .map(|s| s.starts_with('.'))
-        .unwrap_or(false)
+fn is_priority_path(path: &Path, priority_paths: &[PathBuf]) -> bool {
+    priority_paths
+        .iter()
+        .any(|priority_path| priority_path.starts_with(path) || path.starts_with(priority_path))
 }
 
 impl<B, E> ClientState for (B, E)
@@ -564,3 +2491,55 @@ where
     type ReadDirState = B;
     type DirEntryState = E;
 }
+
+/// Define a named [`ClientState`](trait.ClientState.html) type instead of
+/// using a `(ReadDirState, DirEntryState)` tuple.
+///
+/// ```
+/// # use jwalk::{client_state, WalkDirGeneric};
+/// #[derive(Clone, Debug, Default)]
+/// struct DirState {
+///     inherited_tag: Option<String>,
+/// }
+///
+/// #[derive(Debug, Default)]
+/// struct EntryState {
+///     size: u64,
+/// }
+///
+/// client_state! {
+///     /// State of our walk.
+///     struct MyState {
+///         read_dir: DirState,
+///         dir_entry: EntryState,
+///     }
+/// }
+///
+/// let walk_dir = WalkDirGeneric::<MyState>::new(".").process_read_dir(
+///     |_depth, _path, dir_state, children| {
+///         dir_state.inherited_tag = Some("checked".to_string());
+///         for dir_entry in children.iter_mut().flatten() {
+///             dir_entry.client_state.size = dir_entry.metadata().map_or(0, |m| m.len());
+///         }
+///     },
+/// );
+/// ```
+#[macro_export]
+macro_rules! client_state {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            read_dir: $read_dir_state:ty,
+            dir_entry: $dir_entry_state:ty $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Default)]
+        $vis struct $name;
+
+        impl $crate::ClientState for $name {
+            type ReadDirState = $read_dir_state;
+            type DirEntryState = $dir_entry_state;
+        }
+    };
+}
@@ -0,0 +1,248 @@
+//! Warm-start from an `mlocate` database, available with the `locate`
+//! feature on Unix.
+//!
+//! `updatedb` stores the listing of every directory it scanned, along with
+//! the directory's change time at that moment. [`LocateDb`] reads that
+//! listing, which answers a walk of a huge filesystem near instantly, and
+//! [`LocateDir::is_fresh`] checks lazily whether a listing still matches the
+//! directory on disk. Tools can show the database results first and rewalk
+//! stale directories with [`WalkDir`](../type.WalkDir.html).
+//!
+//! ```no_run
+//! use jwalk::locate::LocateDb;
+//!
+//! let db = LocateDb::open("/var/lib/mlocate/mlocate.db")?;
+//! for dir in db.dirs_under("/home") {
+//!     let dir = dir?;
+//!     if dir.is_fresh() {
+//!         for path in dir.paths() {
+//!             println!("{}", path.display());
+//!         }
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Only the `mlocate` format is supported, `plocate` databases are
+//! compressed and need to be converted first.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8] = b"\0mlocate";
+
+const ENTRY_FILE: u8 = 0;
+const ENTRY_DIR: u8 = 1;
+const ENTRY_END: u8 = 2;
+
+/// An `mlocate` database read into memory.
+#[derive(Debug)]
+pub struct LocateDb {
+    data: Vec<u8>,
+    root: PathBuf,
+    dirs_offset: usize,
+}
+
+/// The listing of one directory in a [`LocateDb`](struct.LocateDb.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocateDir {
+    /// Path of the directory.
+    pub path: PathBuf,
+    /// The later of the change and modification time of the directory when
+    /// it was scanned.
+    pub time: SystemTime,
+    /// Entries of the directory in the order they were stored.
+    pub entries: Vec<LocateEntry>,
+}
+
+/// An entry of a [`LocateDir`](struct.LocateDir.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocateEntry {
+    /// File name of the entry.
+    pub file_name: PathBuf,
+    /// Whether the entry was a directory, in which case it has its own
+    /// listing in the database.
+    pub is_dir: bool,
+}
+
+/// Iterator over the directory listings of a [`LocateDb`](struct.LocateDb.html).
+#[derive(Debug)]
+pub struct LocateDirs<'a> {
+    data: &'a [u8],
+    offset: usize,
+    under: Option<&'a Path>,
+}
+
+impl LocateDb {
+    /// Read the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<LocateDb> {
+        LocateDb::from_bytes(fs::read(path)?)
+    }
+
+    /// Parse the header of a database already read into memory. Directory
+    /// listings are parsed as they are iterated.
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<LocateDb> {
+        let mut reader = Reader {
+            data: &data,
+            offset: 0,
+        };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid_data("not an mlocate database"));
+        }
+        let config_size = u32::from_be_bytes(reader.array()?) as usize;
+        let [version, _visibility, _, _] = reader.array()?;
+        if version != 0 {
+            return Err(invalid_data("unsupported mlocate database version"));
+        }
+        let root = PathBuf::from(reader.os_str()?);
+        reader.take(config_size)?;
+        let dirs_offset = reader.offset;
+        Ok(LocateDb {
+            data,
+            root,
+            dirs_offset,
+        })
+    }
+
+    /// The root path `updatedb` scanned.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Iterate over all directory listings in the database.
+    pub fn dirs(&self) -> LocateDirs<'_> {
+        LocateDirs {
+            data: &self.data,
+            offset: self.dirs_offset,
+            under: None,
+        }
+    }
+
+    /// Iterate over the listings of `root` and the directories below it.
+    /// `root` must be absolute, like the paths stored in the database.
+    pub fn dirs_under<'a>(&'a self, root: &'a (impl AsRef<Path> + ?Sized)) -> LocateDirs<'a> {
+        LocateDirs {
+            under: Some(root.as_ref()),
+            ..self.dirs()
+        }
+    }
+}
+
+impl LocateDir {
+    /// Whether the directory on disk still has the time recorded in the
+    /// database, meaning no entries were added, removed or renamed since.
+    /// Changes inside of subdirectories are checked by their own listing.
+    pub fn is_fresh(&self) -> bool {
+        fs::symlink_metadata(&self.path)
+            .map(|metadata| dir_time(&metadata) == self.time)
+            .unwrap_or(false)
+    }
+
+    /// Paths of the entries of this directory.
+    pub fn paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.entries
+            .iter()
+            .map(move |entry| self.path.join(&entry.file_name))
+    }
+}
+
+impl<'a> Iterator for LocateDirs<'a> {
+    type Item = io::Result<LocateDir>;
+    fn next(&mut self) -> Option<io::Result<LocateDir>> {
+        loop {
+            if self.offset >= self.data.len() {
+                return None;
+            }
+            let mut reader = Reader {
+                data: self.data,
+                offset: self.offset,
+            };
+            let dir = reader.dir();
+            // Stop after an error instead of yielding it over and over
+            self.offset = if dir.is_ok() {
+                reader.offset
+            } else {
+                self.data.len()
+            };
+            match (dir, self.under) {
+                (Ok(dir), Some(under)) if !dir.path.starts_with(under) => continue,
+                (dir, _) => return Some(dir),
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| invalid_data("truncated mlocate database"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn os_str(&mut self) -> io::Result<&'a OsStr> {
+        let len = self.data[self.offset..]
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| invalid_data("truncated mlocate database"))?;
+        let bytes = self.take(len)?;
+        self.offset += 1;
+        Ok(OsStr::from_bytes(bytes))
+    }
+
+    fn dir(&mut self) -> io::Result<LocateDir> {
+        let secs = u64::from_be_bytes(self.array()?);
+        let nanos = u32::from_be_bytes(self.array()?);
+        self.take(4)?;
+        let time = UNIX_EPOCH + Duration::new(secs, nanos);
+        let path = PathBuf::from(self.os_str()?);
+        let mut entries = Vec::new();
+        loop {
+            let [kind] = self.array()?;
+            let is_dir = match kind {
+                ENTRY_FILE => false,
+                ENTRY_DIR => true,
+                ENTRY_END => break,
+                _ => return Err(invalid_data("invalid mlocate entry type")),
+            };
+            entries.push(LocateEntry {
+                file_name: PathBuf::from(self.os_str()?),
+                is_dir,
+            });
+        }
+        Ok(LocateDir {
+            path,
+            time,
+            entries,
+        })
+    }
+}
+
+// updatedb records the later of ctime and mtime
+fn dir_time(metadata: &fs::Metadata) -> SystemTime {
+    let (secs, nanos) = std::cmp::max(
+        (metadata.ctime(), metadata.ctime_nsec()),
+        (metadata.mtime(), metadata.mtime_nsec()),
+    );
+    UNIX_EPOCH + Duration::new(secs.max(0) as u64, nanos as u32)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,305 @@
+//! Verify a tree against a manifest of paths, sizes and hashes, available with
+//! the `manifest` feature.
+//!
+//! [`Manifest::verify`](struct.Manifest.html#method.verify) walks the tree,
+//! comparing sizes and hashes on the walk's thread-pool while directories are
+//! read, and yields only the differences: files that were added, removed or
+//! changed.
+//!
+//! ```no_run
+//! use jwalk::manifest::{Manifest, ManifestEntry};
+//!
+//! let mut manifest = Manifest::new();
+//! manifest.insert("bin/tool", ManifestEntry::new(1024).hash(vec![0xab; 32]));
+//! for diff in manifest.verify("/opt/tool").hash(|path| {
+//!     // Hash the file at `path` with the same algorithm as the manifest
+//!     # let _ = path;
+//!     Ok(vec![0xab; 32])
+//! }) {
+//!     println!("{:?}", diff?);
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::{DirEntry, DirEntryIter, Error, Result, WalkDirGeneric};
+
+/// Client state of the walk verifying a manifest, each entry carries its
+/// difference from the manifest.
+pub type VerifyState = ((), Option<ManifestDiff>);
+
+type HashFunction = dyn Fn(&Path) -> io::Result<Vec<u8>> + Send + Sync + 'static;
+
+/// Expected files of a tree, by path relative to the root of the tree.
+///
+/// Directories aren't listed, they are expected to exist as long as they
+/// contain listed files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Arc<BTreeMap<PathBuf, ManifestEntry>>,
+}
+
+/// Expected size and hash of a file in a [`Manifest`](struct.Manifest.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Hash of the file contents, compared with the result of the
+    /// [`hash`](struct.VerifyManifest.html#method.hash) function if set.
+    pub hash: Option<Vec<u8>>,
+}
+
+/// Difference between a tree and its manifest. Paths are relative to the
+/// root of the tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ManifestDiff {
+    /// The file exists but isn't listed in the manifest.
+    Added(PathBuf),
+    /// The file is listed in the manifest but doesn't exist.
+    Removed(PathBuf),
+    /// The file has a different size than listed in the manifest.
+    SizeChanged {
+        /// Path of the file.
+        path: PathBuf,
+        /// Size listed in the manifest.
+        expected: u64,
+        /// Size of the file on disk.
+        actual: u64,
+    },
+    /// The file has the listed size but a different hash.
+    HashChanged(PathBuf),
+}
+
+/// Builder for verifying a tree against a manifest, created by
+/// [`Manifest::verify`](struct.Manifest.html#method.verify).
+pub struct VerifyManifest {
+    manifest: Manifest,
+    walk_dir: WalkDirGeneric<VerifyState>,
+    hash: Option<Arc<HashFunction>>,
+}
+
+/// Iterator yielding the differences between a tree and its manifest.
+pub struct ManifestDiffIter {
+    manifest: Manifest,
+    seen: Arc<Mutex<HashSet<PathBuf>>>,
+    dir_entry_iter: DirEntryIter<VerifyState>,
+    removed: Option<Vec<PathBuf>>,
+}
+
+impl Manifest {
+    /// Create an empty manifest.
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    /// Add the file at `path`, relative to the root of the tree.
+    pub fn insert<P: Into<PathBuf>>(&mut self, path: P, entry: ManifestEntry) {
+        Arc::make_mut(&mut self.entries).insert(path.into(), entry);
+    }
+
+    /// The entry of the file at `path`, relative to the root of the tree.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&ManifestEntry> {
+        self.entries.get(path.as_ref())
+    }
+
+    /// Iterate over the files in the manifest, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &ManifestEntry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_path(), entry))
+    }
+
+    /// The number of files in the manifest.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the manifest lists no files.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Verify the tree at `root` against this manifest.
+    pub fn verify<P: AsRef<Path>>(&self, root: P) -> VerifyManifest {
+        VerifyManifest {
+            manifest: self.clone(),
+            walk_dir: WalkDirGeneric::new(root).skip_hidden(false),
+            hash: None,
+        }
+    }
+}
+
+impl<P: Into<PathBuf>> FromIterator<(P, ManifestEntry)> for Manifest {
+    fn from_iter<I: IntoIterator<Item = (P, ManifestEntry)>>(iter: I) -> Manifest {
+        Manifest {
+            entries: Arc::new(
+                iter.into_iter()
+                    .map(|(path, entry)| (path.into(), entry))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl ManifestEntry {
+    /// Create an entry of a file with `size` bytes and no hash.
+    pub fn new(size: u64) -> ManifestEntry {
+        ManifestEntry { size, hash: None }
+    }
+
+    /// Set the expected hash of the file.
+    pub fn hash(mut self, hash: Vec<u8>) -> ManifestEntry {
+        self.hash = Some(hash);
+        self
+    }
+}
+
+impl ManifestDiff {
+    /// Path of the differing file, relative to the root of the tree.
+    pub fn path(&self) -> &Path {
+        match self {
+            ManifestDiff::Added(path)
+            | ManifestDiff::Removed(path)
+            | ManifestDiff::HashChanged(path)
+            | ManifestDiff::SizeChanged { path, .. } => path,
+        }
+    }
+}
+
+impl VerifyManifest {
+    /// Hash files with `hash` and compare the result with the hashes in the
+    /// manifest. Files are only hashed if their size matches. Without a hash
+    /// function only sizes are compared.
+    pub fn hash<F>(mut self, hash: F) -> Self
+    where
+        F: Fn(&Path) -> io::Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.hash = Some(Arc::new(hash));
+        self
+    }
+
+    /// Configure the wrapped [`WalkDirGeneric`](../struct.WalkDirGeneric.html),
+    /// for example its parallelism. Hidden files are included by default.
+    ///
+    /// Its [`process_read_dir`](../struct.WalkDirGeneric.html#method.process_read_dir)
+    /// callback is replaced to compare entries with the manifest.
+    pub fn walk_dir<F>(mut self, configure: F) -> Self
+    where
+        F: FnOnce(WalkDirGeneric<VerifyState>) -> WalkDirGeneric<VerifyState>,
+    {
+        self.walk_dir = configure(self.walk_dir);
+        self
+    }
+}
+
+impl IntoIterator for VerifyManifest {
+    type Item = Result<ManifestDiff>;
+    type IntoIter = ManifestDiffIter;
+
+    fn into_iter(self) -> ManifestDiffIter {
+        let VerifyManifest {
+            manifest,
+            walk_dir,
+            hash,
+        } = self;
+        let root = walk_dir.root().to_path_buf();
+        let seen: Arc<Mutex<HashSet<PathBuf>>> = Default::default();
+        let read_dir_manifest = manifest.clone();
+        let read_dir_seen = seen.clone();
+        let walk_dir = walk_dir.process_read_dir(move |_, _, _, children| {
+            for dir_entry_result in children.iter_mut() {
+                let checked = match dir_entry_result {
+                    Ok(dir_entry) => {
+                        check_entry(dir_entry, &root, &read_dir_manifest, &read_dir_seen, &hash)
+                    }
+                    Err(_) => continue,
+                };
+                match checked {
+                    Ok(diff) => dir_entry_result.as_mut().unwrap().client_state = diff,
+                    Err(err) => *dir_entry_result = Err(err),
+                }
+            }
+        });
+        ManifestDiffIter {
+            manifest,
+            seen,
+            dir_entry_iter: walk_dir.into_iter(),
+            removed: None,
+        }
+    }
+}
+
+impl Iterator for ManifestDiffIter {
+    type Item = Result<ManifestDiff>;
+    fn next(&mut self) -> Option<Result<ManifestDiff>> {
+        if self.removed.is_none() {
+            for dir_entry_result in &mut self.dir_entry_iter {
+                let mut dir_entry = match dir_entry_result {
+                    Ok(dir_entry) => dir_entry,
+                    Err(err) => return Some(Err(err)),
+                };
+                if let Some(err) = dir_entry.read_children_error.take() {
+                    return Some(Err(err));
+                }
+                if let Some(diff) = dir_entry.client_state.take() {
+                    return Some(Ok(diff));
+                }
+            }
+            let seen = self.seen.lock().unwrap();
+            let mut removed = self
+                .manifest
+                .entries
+                .keys()
+                .filter(|path| !seen.contains(*path))
+                .cloned()
+                .collect::<Vec<_>>();
+            removed.reverse();
+            self.removed = Some(removed);
+        }
+        self.removed
+            .as_mut()
+            .unwrap()
+            .pop()
+            .map(|path| Ok(ManifestDiff::Removed(path)))
+    }
+}
+
+// Compare a read entry with the manifest, on the walk's thread-pool
+fn check_entry(
+    dir_entry: &DirEntry<VerifyState>,
+    root: &Path,
+    manifest: &Manifest,
+    seen: &Mutex<HashSet<PathBuf>>,
+    hash: &Option<Arc<HashFunction>>,
+) -> Result<Option<ManifestDiff>> {
+    if dir_entry.file_type.is_dir() {
+        return Ok(None);
+    }
+    let path = dir_entry.path();
+    let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+    let expected = match manifest.get(&relative) {
+        Some(expected) => expected,
+        None => return Ok(Some(ManifestDiff::Added(relative))),
+    };
+    seen.lock().unwrap().insert(relative.clone());
+    let actual = dir_entry.metadata()?.len();
+    if actual != expected.size {
+        return Ok(Some(ManifestDiff::SizeChanged {
+            path: relative,
+            expected: expected.size,
+            actual,
+        }));
+    }
+    match (hash, &expected.hash) {
+        (Some(hash), Some(expected_hash)) => {
+            let actual_hash = hash(&path).map_err(|err| Error::from_entry(dir_entry, err))?;
+            Ok((actual_hash != *expected_hash).then_some(ManifestDiff::HashChanged(relative)))
+        }
+        _ => Ok(None),
+    }
+}
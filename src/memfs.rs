@@ -0,0 +1,216 @@
+//! A file system in memory, for testing walks.
+//!
+//! [`MemoryFs`](struct.MemoryFs.html) is a [`FileSystem`](../file_system/trait.FileSystem.html)
+//! that is populated programmatically and walked with the same scheduler as
+//! the local file system. Tests of
+//! [`process_read_dir`](../struct.WalkDirGeneric.html#method.process_read_dir)
+//! callbacks and other walk logic don't need temporary directories, and
+//! directories are always read in the same order.
+//!
+//! ```
+//! use jwalk::memfs::MemoryFs;
+//! use jwalk::WalkDir;
+//!
+//! let mut memory_fs = MemoryFs::new();
+//! memory_fs.add_file("/project/src/main.rs");
+//! memory_fs.add_dir("/project/target");
+//!
+//! let paths: Vec<_> = memory_fs
+//!     .walk_dir::<((), ())>("/project")
+//!     .sort(true)
+//!     .into_iter()
+//!     .map(|dir_entry| dir_entry.unwrap().path())
+//!     .collect();
+//! assert_eq!(paths.len(), 4);
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::file_system::{FileSystem, FileSystemEntry, FileType, ReadDirEntries};
+use crate::{ClientState, WalkDirGeneric};
+
+// Symlinks followed to resolve a path before failing like a loop
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// File system holding a tree of directories, files and symlinks in memory.
+///
+/// Paths are used as given, without resolving `.` or `..`, so they should be
+/// added and walked in the same form. Cloning is cheap until either clone is
+/// modified.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFs {
+    nodes: Arc<BTreeMap<PathBuf, Node>>,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Dir(BTreeSet<OsString>),
+    File,
+    Symlink(PathBuf),
+}
+
+impl MemoryFs {
+    /// Create an empty file system.
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Add a directory at `path`, and any missing parent directories.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if !matches!(self.nodes.get(path), Some(Node::Dir(_))) {
+            self.insert(path, Node::Dir(BTreeSet::new()));
+        }
+    }
+
+    /// Add an empty file at `path`, and any missing parent directories.
+    /// Replaces an existing entry at `path`.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) {
+        self.insert(path.as_ref(), Node::File);
+    }
+
+    /// Add a symlink at `path` pointing to `target`, and any missing parent
+    /// directories. Relative targets are relative to the parent of `path`.
+    /// Replaces an existing entry at `path`.
+    pub fn add_symlink<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, path: P, target: Q) {
+        self.insert(path.as_ref(), Node::Symlink(target.as_ref().to_path_buf()));
+    }
+
+    /// Create a builder walking `root` in this file system, see
+    /// [`WalkDirGeneric::file_system`](../struct.WalkDirGeneric.html#method.file_system).
+    pub fn walk_dir<C: ClientState>(&self, root: impl AsRef<Path>) -> WalkDirGeneric<C> {
+        WalkDirGeneric::new(root).file_system(Some(Arc::new(self.clone())))
+    }
+
+    fn insert(&mut self, path: &Path, node: Node) {
+        let nodes = Arc::make_mut(&mut self.nodes);
+        if let Some(Node::Dir(children)) = nodes.get(path) {
+            // Replacing a directory removes its contents
+            let children: Vec<PathBuf> = children.iter().map(|name| path.join(name)).collect();
+            for child in children {
+                nodes.remove(&child);
+            }
+        }
+        nodes.insert(path.to_path_buf(), node);
+
+        let mut child = path;
+        while let (Some(parent), Some(name)) = (child.parent(), child.file_name()) {
+            if parent.as_os_str().is_empty() {
+                break;
+            }
+            match nodes.get_mut(parent) {
+                Some(Node::Dir(children)) => {
+                    children.insert(name.to_os_string());
+                    break;
+                }
+                _ => {
+                    let children = BTreeSet::from([name.to_os_string()]);
+                    nodes.insert(parent.to_path_buf(), Node::Dir(children));
+                }
+            }
+            child = parent;
+        }
+    }
+
+    // The node at `path`, see `resolve`.
+    fn node(&self, path: &Path, follow_links: bool) -> io::Result<&Node> {
+        let path = self.resolve(path, follow_links)?;
+        self.nodes
+            .get(&path)
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    // Resolves symlinks in the parents of `path`, and in its last component
+    // if `follow_links` is `true`.
+    fn resolve(&self, path: &Path, follow_links: bool) -> io::Result<PathBuf> {
+        let mut components: Vec<OsString> = path
+            .components()
+            .rev()
+            .map(|component| component.as_os_str().to_os_string())
+            .collect();
+        let mut resolved = PathBuf::new();
+        let mut hops = 0;
+        while let Some(component) = components.pop() {
+            if component == ".." {
+                resolved.pop();
+                continue;
+            }
+            resolved.push(&component);
+            let target = match self.nodes.get(&resolved) {
+                Some(Node::Symlink(target)) if follow_links || !components.is_empty() => target,
+                _ => continue,
+            };
+            hops += 1;
+            if hops > MAX_SYMLINK_HOPS {
+                return Err(io::Error::other("too many levels of symbolic links"));
+            }
+            // Absolute targets replace `resolved` when pushed
+            resolved.pop();
+            components.extend(
+                target
+                    .components()
+                    .rev()
+                    .map(|component| component.as_os_str().to_os_string()),
+            );
+        }
+        Ok(resolved)
+    }
+}
+
+impl Node {
+    fn file_type(&self) -> FileType {
+        match self {
+            Node::Dir(_) => FileType::dir(),
+            Node::File => FileType::file(),
+            Node::Symlink(_) => FileType::symlink(),
+        }
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        let path = self.resolve(path, true)?;
+        let children = match self.nodes.get(&path) {
+            Some(Node::Dir(children)) => children,
+            None => return Err(io::ErrorKind::NotFound.into()),
+            _ => return Err(io::Error::other("not a directory")),
+        };
+        Ok(Box::new(children.iter().map(move |file_name| {
+            let file_type = self
+                .nodes
+                .get(&path.join(file_name))
+                .map(Node::file_type)
+                .ok_or_else(|| io::ErrorKind::NotFound.into());
+            Ok(FileSystemEntry {
+                file_name: file_name.clone(),
+                file_type,
+            })
+        })))
+    }
+
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType> {
+        self.node(path, follow_links).map(Node::file_type)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.node(path, false)? {
+            Node::Symlink(target) => Ok(target.clone()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a symbolic link",
+            )),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = self.resolve(path, true)?;
+        match self.nodes.get(&path) {
+            Some(_) => Ok(path),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
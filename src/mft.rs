@@ -0,0 +1,519 @@
+//! Enumerating NTFS volumes from the master file table, available on Windows
+//! with the `mft` feature.
+//!
+//! [`MftIndex::open`](struct.MftIndex.html#method.open) reads the name,
+//! parent and attributes of every file of a volume with
+//! `FSCTL_ENUM_USN_DATA`, which reads the MFT sequentially instead of
+//! opening each directory. The index is a
+//! [`FileSystem`](../file_system/trait.FileSystem.html), so walks of the
+//! volume are answered from memory with the usual filtering and sorting,
+//! orders of magnitude faster than reading directories for full volume
+//! scans. [`MftIndex::update`](struct.MftIndex.html#method.update) applies
+//! the changes recorded in the USN journal since, for incremental runs.
+//!
+//! ```no_run
+//! use jwalk::mft::MftIndex;
+//!
+//! let mut index = MftIndex::open("C:\\")?;
+//! for dir_entry in index.walk_dir::<((), ())>("C:\\Users") {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//!
+//! // Later, only read what changed
+//! index.update()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Opening a volume needs administrator rights and a USN journal, which NTFS
+//! volumes have by default. Each file is indexed under one of its names, so
+//! further hardlinks aren't yielded. Metadata and the targets of reparse
+//! points are read from the file system.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{c_void, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::windows::ffi::OsStringExt;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::Arc;
+
+use crate::file_system::{FileSystem, FileSystemEntry, FileType, ReadDirEntries, StdFileSystem};
+use crate::{ClientState, WalkDirGeneric};
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn DeviceIoControl(
+        device: *mut c_void,
+        io_control_code: u32,
+        in_buffer: *const c_void,
+        in_buffer_size: u32,
+        out_buffer: *mut c_void,
+        out_buffer_size: u32,
+        bytes_returned: *mut u32,
+        overlapped: *mut c_void,
+    ) -> i32;
+
+    fn GetFileInformationByHandle(
+        file: *mut c_void,
+        file_information: *mut ByHandleFileInformation,
+    ) -> i32;
+}
+
+// `BY_HANDLE_FILE_INFORMATION`, only the file index is read
+#[allow(dead_code)]
+#[repr(C)]
+struct ByHandleFileInformation {
+    file_attributes: u32,
+    creation_time: [u32; 2],
+    last_access_time: [u32; 2],
+    last_write_time: [u32; 2],
+    volume_serial_number: u32,
+    file_size_high: u32,
+    file_size_low: u32,
+    number_of_links: u32,
+    file_index_high: u32,
+    file_index_low: u32,
+}
+
+// `USN_JOURNAL_DATA_V0`, only the id and next USN are read
+#[allow(dead_code)]
+#[repr(C)]
+struct UsnJournalData {
+    usn_journal_id: u64,
+    first_usn: i64,
+    next_usn: i64,
+    lowest_valid_usn: i64,
+    max_usn: i64,
+    maximum_size: u64,
+    allocation_delta: u64,
+}
+
+// `MFT_ENUM_DATA_V0`
+#[repr(C)]
+struct MftEnumData {
+    start_file_reference_number: u64,
+    low_usn: i64,
+    high_usn: i64,
+}
+
+// `READ_USN_JOURNAL_DATA_V0`
+#[repr(C)]
+struct ReadUsnJournalData {
+    start_usn: i64,
+    reason_mask: u32,
+    return_only_on_close: u32,
+    timeout: u64,
+    bytes_to_wait_for: u64,
+    usn_journal_id: u64,
+}
+
+const FSCTL_QUERY_USN_JOURNAL: u32 = 0x0009_00f4;
+const FSCTL_ENUM_USN_DATA: u32 = 0x0009_00b3;
+const FSCTL_READ_USN_JOURNAL: u32 = 0x0009_00bb;
+
+// Returned by `FSCTL_ENUM_USN_DATA` once all records were read.
+const ERROR_HANDLE_EOF: i32 = 38;
+
+const FILE_SHARE_READ: u32 = 0x1;
+const FILE_SHARE_WRITE: u32 = 0x2;
+// Required to open directories.
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+
+const USN_REASON_FILE_DELETE: u32 = 0x200;
+const USN_REASON_RENAME_OLD_NAME: u32 = 0x1000;
+
+// Size of the buffer records are read into
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Files of an NTFS volume read from its master file table, see the
+/// [`mft`](index.html) module.
+///
+/// Cloning is cheap until either clone is updated.
+#[derive(Clone, Debug)]
+pub struct MftIndex {
+    volume: Arc<File>,
+    root: PathBuf,
+    journal_id: u64,
+    // USN of the first change not applied yet
+    next_usn: i64,
+    files: Arc<Files>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Files {
+    root_id: u64,
+    nodes: HashMap<u64, Node>,
+    // Children of each directory by lowercase name
+    children: HashMap<u64, BTreeMap<String, u64>>,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    parent_id: u64,
+    name: OsString,
+    attributes: u32,
+}
+
+// A `USN_RECORD_V2`
+struct UsnRecord {
+    id: u64,
+    reason: u32,
+    node: Node,
+}
+
+impl MftIndex {
+    /// Read the files of the volume whose root directory is `root`, such as
+    /// `C:\`.
+    pub fn open<P: AsRef<Path>>(root: P) -> io::Result<MftIndex> {
+        let root = root.as_ref();
+        let volume = OpenOptions::new()
+            .read(true)
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+            .open(volume_device(root)?)?;
+        let root_id = file_id(root)?;
+
+        let mut journal = MaybeUninit::<UsnJournalData>::uninit();
+        // SAFETY: `volume` is a valid open handle and `journal` is large
+        // enough to hold the structure written by the call.
+        let journal = unsafe {
+            device_io_control(
+                &volume,
+                FSCTL_QUERY_USN_JOURNAL,
+                &(),
+                journal.as_mut_ptr() as *mut u8,
+                std::mem::size_of::<UsnJournalData>(),
+            )?;
+            journal.assume_init()
+        };
+
+        let mut files = Files {
+            root_id,
+            ..Files::default()
+        };
+        let mut enum_data = MftEnumData {
+            start_file_reference_number: 0,
+            low_usn: 0,
+            high_usn: journal.next_usn,
+        };
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            // SAFETY: `volume` is a valid open handle and `buffer` is
+            // `BUFFER_SIZE` bytes long.
+            let read = unsafe {
+                device_io_control(
+                    &volume,
+                    FSCTL_ENUM_USN_DATA,
+                    &enum_data,
+                    buffer.as_mut_ptr(),
+                    BUFFER_SIZE,
+                )
+            };
+            let read = match read {
+                Ok(read) => read,
+                Err(err) if err.raw_os_error() == Some(ERROR_HANDLE_EOF) => break,
+                Err(err) => return Err(err),
+            };
+            enum_data.start_file_reference_number = read_u64(&buffer, 0);
+            for record in usn_records(&buffer[..read]) {
+                files.insert(record.id, record.node);
+            }
+        }
+
+        Ok(MftIndex {
+            volume: Arc::new(volume),
+            root: root.to_path_buf(),
+            journal_id: journal.usn_journal_id,
+            next_usn: journal.next_usn,
+            files: Arc::new(files),
+        })
+    }
+
+    /// Apply the changes recorded in the USN journal since the volume was
+    /// read or last updated.
+    ///
+    /// Fails if the journal was deleted or recreated, or if the changes were
+    /// already purged from it. [`open`](struct.MftIndex.html#method.open)
+    /// the volume again in that case.
+    pub fn update(&mut self) -> io::Result<()> {
+        let mut read_data = ReadUsnJournalData {
+            start_usn: self.next_usn,
+            reason_mask: u32::MAX,
+            return_only_on_close: 0,
+            timeout: 0,
+            bytes_to_wait_for: 0,
+            usn_journal_id: self.journal_id,
+        };
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            // SAFETY: `volume` is a valid open handle and `buffer` is
+            // `BUFFER_SIZE` bytes long.
+            let read = unsafe {
+                device_io_control(
+                    &self.volume,
+                    FSCTL_READ_USN_JOURNAL,
+                    &read_data,
+                    buffer.as_mut_ptr(),
+                    BUFFER_SIZE,
+                )?
+            };
+            let files = Arc::make_mut(&mut self.files);
+            for record in usn_records(&buffer[..read]) {
+                if record.reason & USN_REASON_FILE_DELETE != 0 {
+                    files.remove(record.id);
+                } else if record.reason & USN_REASON_RENAME_OLD_NAME == 0 {
+                    files.insert(record.id, record.node);
+                }
+            }
+            let next_usn = read_u64(&buffer, 0) as i64;
+            // Only the next USN is returned once the journal was read
+            if read <= 8 || next_usn == read_data.start_usn {
+                self.next_usn = next_usn;
+                return Ok(());
+            }
+            read_data.start_usn = next_usn;
+        }
+    }
+
+    /// Create a builder for a walk of this volume starting at `root`.
+    pub fn walk_dir<C: ClientState>(&self, root: impl AsRef<Path>) -> WalkDirGeneric<C> {
+        WalkDirGeneric::new(root).file_system(Some(Arc::new(self.clone())))
+    }
+
+    // Id of the file at `path`, if it is on this volume and indexed.
+    fn resolve(&self, path: &Path) -> Option<u64> {
+        let relative_path = path.strip_prefix(&self.root).ok()?;
+        let mut id = self.files.root_id;
+        for component in relative_path.components() {
+            match component {
+                Component::Normal(name) => {
+                    let name = name.to_str()?.to_lowercase();
+                    id = *self.files.children.get(&id)?.get(&name)?;
+                }
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        Some(id)
+    }
+}
+
+impl FileSystem for MftIndex {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        let id = match self.resolve(path) {
+            Some(id) => id,
+            None => return StdFileSystem.read_dir(path),
+        };
+        let children = self.files.children.get(&id);
+        let path = path.to_path_buf();
+        Ok(Box::new(children.into_iter().flatten().filter_map(
+            move |(_, child_id)| {
+                let node = self.files.nodes.get(child_id)?;
+                let file_type = match node.file_type() {
+                    Some(file_type) => Ok(file_type),
+                    None => StdFileSystem.file_type(&path.join(&node.name), false),
+                };
+                Some(Ok(FileSystemEntry {
+                    file_name: node.name.clone(),
+                    file_type,
+                }))
+            },
+        )))
+    }
+
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType> {
+        let node = self.resolve(path).and_then(|id| self.files.nodes.get(&id));
+        match node.and_then(Node::file_type) {
+            Some(file_type) => Ok(file_type),
+            _ if path == self.root => Ok(FileType::dir()),
+            _ => StdFileSystem.file_type(path, follow_links),
+        }
+    }
+
+    fn metadata(&self, path: &Path, follow_links: bool) -> io::Result<fs::Metadata> {
+        StdFileSystem.metadata(path, follow_links)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+}
+
+impl Files {
+    fn insert(&mut self, id: u64, node: Node) {
+        // The root directory is its own parent
+        if id == node.parent_id {
+            return;
+        }
+        self.unlink(id);
+        if let Some(name) = node.name.to_str() {
+            self.children
+                .entry(node.parent_id)
+                .or_default()
+                .insert(name.to_lowercase(), id);
+        }
+        self.nodes.insert(id, node);
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.unlink(id);
+        self.nodes.remove(&id);
+        self.children.remove(&id);
+    }
+
+    // Removes `id` from the children of its parent
+    fn unlink(&mut self, id: u64) {
+        let node = match self.nodes.get(&id) {
+            Some(node) => node,
+            None => return,
+        };
+        if let (Some(children), Some(name)) =
+            (self.children.get_mut(&node.parent_id), node.name.to_str())
+        {
+            children.remove(&name.to_lowercase());
+        }
+    }
+}
+
+impl Node {
+    // The type of the file, unless it is a reparse point whose type depends
+    // on its tag.
+    fn file_type(&self) -> Option<FileType> {
+        if self.attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 {
+            None
+        } else if self.attributes & FILE_ATTRIBUTE_DIRECTORY != 0 {
+            Some(FileType::dir())
+        } else {
+            Some(FileType::file())
+        }
+    }
+}
+
+// Sends the control code `code` with `input` to `device`, returning the
+// number of bytes written to `output`.
+//
+// SAFETY: `output` must be valid for writes of `output_size` bytes.
+unsafe fn device_io_control<T>(
+    device: &File,
+    code: u32,
+    input: &T,
+    output: *mut u8,
+    output_size: usize,
+) -> io::Result<usize> {
+    let mut bytes_returned = 0;
+    if DeviceIoControl(
+        device.as_raw_handle() as *mut c_void,
+        code,
+        input as *const T as *const c_void,
+        std::mem::size_of::<T>() as u32,
+        output as *mut c_void,
+        output_size as u32,
+        &mut bytes_returned,
+        std::ptr::null_mut(),
+    ) == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(bytes_returned as usize)
+}
+
+// The file reference number of the file at `path`.
+fn file_id(path: &Path) -> io::Result<u64> {
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)?;
+    let mut information = MaybeUninit::<ByHandleFileInformation>::uninit();
+    // SAFETY: `file` is a valid open handle and `information` is large
+    // enough to hold the structure written by the call.
+    let information = unsafe {
+        if GetFileInformationByHandle(
+            file.as_raw_handle() as *mut c_void,
+            information.as_mut_ptr(),
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        information.assume_init()
+    };
+    Ok((u64::from(information.file_index_high) << 32) | u64::from(information.file_index_low))
+}
+
+// The device path of the volume of `root`, such as `\\.\C:` for `C:\`.
+fn volume_device(root: &Path) -> io::Result<PathBuf> {
+    match root.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                Ok(PathBuf::from(format!(r"\\.\{}:", letter as char)))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only volumes with a drive letter can be read",
+            )),
+        },
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path has no drive letter",
+        )),
+    }
+}
+
+// The version 2 records following the 8 bytes of a USN or file reference
+// number in `buffer`.
+fn usn_records(buffer: &[u8]) -> impl Iterator<Item = UsnRecord> + '_ {
+    let mut offset = 8;
+    std::iter::from_fn(move || loop {
+        let record = buffer.get(offset..)?;
+        let length = read_u32(record, 0)? as usize;
+        if length == 0 || record.len() < length {
+            return None;
+        }
+        offset += length;
+        let record = &record[..length];
+        // Version 3 records with 128 bit ids are only used by ReFS
+        if read_u16(record, 4) != Some(2) {
+            continue;
+        }
+        let name_length = read_u16(record, 56)? as usize;
+        let name_offset = read_u16(record, 58)? as usize;
+        let name: Vec<u16> = record
+            .get(name_offset..name_offset + name_length)?
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        return Some(UsnRecord {
+            id: read_u64(record, 8),
+            reason: read_u32(record, 40)?,
+            node: Node {
+                parent_id: read_u64(record, 16),
+                name: OsString::from_wide(&name),
+                attributes: read_u32(record, 52)?,
+            },
+        });
+    })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// Records are at least 60 bytes long, the buffers start with 8 bytes.
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    bytes
+        .get(offset..offset + 8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u64::from_le_bytes)
+}
@@ -0,0 +1,236 @@
+//! Walking object stores, available with the `object-store` feature.
+//!
+//! Object stores such as S3, GCS and Azure Blob storage have no directories,
+//! but list the keys under a prefix with `/` as delimiter: objects directly
+//! under the prefix, and the longer common prefixes, which act as
+//! subdirectories. [`ObjectStoreFs`](struct.ObjectStoreFs.html) is a
+//! [`FileSystem`](../file_system/trait.FileSystem.html) over such listings, so
+//! buckets are walked with the same sorting, filtering and depth limits as
+//! local trees, and several prefixes are listed in parallel to hide the
+//! latency of list calls.
+//!
+//! Stores are plugged in by implementing
+//! [`ObjectLister`](trait.ObjectLister.html) for their client. With the
+//! `opendal` feature, [`OpenDalLister`](struct.OpenDalLister.html) lists
+//! through any OpenDAL operator.
+//!
+//! Paths of a walk are keys with a leading `/`, the walk of `/logs` lists
+//! the prefix `logs/`. Objects have no metadata and no symlinks.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::file_system::{FileSystem, FileSystemEntry, FileType, ReadDirEntries};
+use crate::{ClientState, WalkDirGeneric};
+
+/// Client of an object store, listing keys by prefix.
+///
+/// Called from the threads reading directories, so clients that are
+/// asynchronous have to block on their listings.
+pub trait ObjectLister: Send + Sync + 'static {
+    /// List the objects and common prefixes directly under `prefix`, with
+    /// `/` as delimiter. `prefix` is empty for the root of the store, and
+    /// ends with `/` otherwise.
+    ///
+    /// Listings hold full keys, listings of other prefixes are ignored. An
+    /// object whose key is `prefix` itself, as created by some tools to mark
+    /// directories, is ignored.
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectListing>>;
+}
+
+/// A key listed by an [`ObjectLister`](trait.ObjectLister.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ObjectListing {
+    /// Key of an object, walked as a file.
+    Object(String),
+    /// A common prefix of longer keys, ending with `/`, walked as a
+    /// directory.
+    Prefix(String),
+}
+
+/// [`ObjectLister`](trait.ObjectLister.html) of an OpenDAL operator, available
+/// with the `opendal` feature.
+///
+/// The operator blocks on the runtime it was created in, which has to
+/// outlive the walks:
+///
+/// ```
+/// use jwalk::object_store::{ObjectStoreFs, OpenDalLister};
+/// use opendal::{blocking, services, Operator};
+/// use std::path::PathBuf;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let runtime = tokio::runtime::Runtime::new()?;
+/// let operator = {
+///     let _guard = runtime.enter();
+///     blocking::Operator::new(Operator::new(services::Memory::default())?.finish())?
+/// };
+/// operator.write("logs/2024/01.log", "started")?;
+/// operator.write("logs/latest.log", "started")?;
+///
+/// let store = ObjectStoreFs::new(OpenDalLister::new(operator));
+/// let paths = store
+///     .walk_dir::<((), ())>("/logs")
+///     .sort(true)
+///     .into_iter()
+///     .map(|dir_entry| dir_entry.map(|dir_entry| dir_entry.path()))
+///     .collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(
+///     paths,
+///     ["/logs", "/logs/2024", "/logs/2024/01.log", "/logs/latest.log"].map(PathBuf::from)
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "opendal")]
+#[derive(Clone, Debug)]
+pub struct OpenDalLister(opendal::blocking::Operator);
+
+#[cfg(feature = "opendal")]
+impl OpenDalLister {
+    /// List the keys of `operator`.
+    pub fn new(operator: opendal::blocking::Operator) -> OpenDalLister {
+        OpenDalLister(operator)
+    }
+}
+
+#[cfg(feature = "opendal")]
+impl ObjectLister for OpenDalLister {
+    fn list(&self, prefix: &str) -> io::Result<Vec<ObjectListing>> {
+        Ok(self
+            .0
+            .list(prefix)?
+            .into_iter()
+            .map(|entry| {
+                let key = entry.path().to_string();
+                if entry.metadata().is_dir() {
+                    ObjectListing::Prefix(key)
+                } else {
+                    ObjectListing::Object(key)
+                }
+            })
+            .collect())
+    }
+}
+
+/// File system listing the keys of an object store, see the
+/// [`object_store`](index.html) module.
+#[derive(Debug)]
+pub struct ObjectStoreFs<L> {
+    lister: Arc<L>,
+}
+
+impl<L> Clone for ObjectStoreFs<L> {
+    fn clone(&self) -> Self {
+        ObjectStoreFs {
+            lister: self.lister.clone(),
+        }
+    }
+}
+
+impl<L: ObjectLister> ObjectStoreFs<L> {
+    /// Walk the store listed by `lister`.
+    pub fn new(lister: L) -> ObjectStoreFs<L> {
+        ObjectStoreFs {
+            lister: Arc::new(lister),
+        }
+    }
+
+    /// Create a builder for a walk of this store starting at `root`.
+    pub fn walk_dir<C: ClientState>(&self, root: impl AsRef<Path>) -> WalkDirGeneric<C> {
+        WalkDirGeneric::new(root).file_system(Some(Arc::new(self.clone())))
+    }
+
+    // Names of the keys directly under `prefix`, and whether they are
+    // prefixes. A key that is both an object and a prefix is a prefix.
+    fn children(&self, prefix: &str) -> io::Result<BTreeMap<String, bool>> {
+        let mut children = BTreeMap::new();
+        for listing in self.lister.list(prefix)? {
+            let (key, is_dir) = match &listing {
+                ObjectListing::Object(key) => (key.as_str(), false),
+                ObjectListing::Prefix(key) => (key.as_str(), true),
+            };
+            let name = match key.strip_prefix(prefix) {
+                Some(name) => name.strip_suffix('/').unwrap_or(name),
+                None => continue,
+            };
+            if !name.is_empty() && !name.contains('/') {
+                *children.entry(name.to_string()).or_default() |= is_dir;
+            }
+        }
+        Ok(children)
+    }
+}
+
+impl<L: ObjectLister> FileSystem for ObjectStoreFs<L> {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        let prefix = prefix_of(path)?;
+        let children = self.children(&prefix)?;
+        // Prefixes only exist as long as they have keys under them
+        if children.is_empty() && !prefix.is_empty() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(Box::new(children.into_iter().map(|(name, is_dir)| {
+            Ok(FileSystemEntry {
+                file_name: OsString::from(name),
+                file_type: Ok(if is_dir {
+                    FileType::dir()
+                } else {
+                    FileType::file()
+                }),
+            })
+        })))
+    }
+
+    fn file_type(&self, path: &Path, _follow_links: bool) -> io::Result<FileType> {
+        let prefix = prefix_of(path)?;
+        let (parent, name) = match prefix.strip_suffix('/') {
+            Some(key) => match key.rsplit_once('/') {
+                Some((parent, name)) => (format!("{}/", parent), name),
+                None => (String::new(), key),
+            },
+            None => return Ok(FileType::dir()),
+        };
+        match self.children(&parent)?.get(name) {
+            Some(true) => Ok(FileType::dir()),
+            Some(false) => Ok(FileType::file()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "object stores have no symlinks",
+        ))
+    }
+}
+
+// The prefix listing the children of `path`, empty for the root and ending
+// with `/` otherwise. Keys have no `.` or `..`, so paths holding them are
+// rejected rather than listing a prefix they do not name.
+fn prefix_of(path: &Path) -> io::Result<String> {
+    let mut prefix = String::new();
+    for component in path.components() {
+        match component {
+            Component::RootDir => {}
+            Component::Normal(name) => {
+                let name = name.to_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "object keys are valid unicode")
+                })?;
+                prefix.push_str(name);
+                prefix.push('/');
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "object keys have no `.`, `..` or prefix components",
+                ))
+            }
+        }
+    }
+    Ok(prefix)
+}
@@ -0,0 +1,294 @@
+//! Path patterns used to filter entries during a walk.
+//!
+//! Patterns follow the rules used by `rsync`:
+//!
+//! - `*` matches any sequence of characters except `/`.
+//! - `**` matches any sequence of characters including `/`.
+//! - `?` matches any single character except `/`.
+//! - `[...]` matches a single character from a set such as `[a-z_]`, `[!...]`
+//!   or `[^...]` negate the set.
+//! - `\` escapes the following character.
+//! - A pattern starting with `/` is anchored at the root of the walk, so
+//!   `/build` only matches `build` directly inside of root. Other patterns
+//!   match at the start of any path component, so `build` matches both
+//!   `build` and `src/build`.
+//! - A pattern ending in `/` only matches directories.
+//!
+//! Patterns are matched against entry paths relative to the root of the walk
+//! using `/` as separator on all platforms.
+//!
+//! Patterns created with [`Pattern::tar`](struct.Pattern.html#method.tar)
+//! follow the defaults GNU `tar` uses for exclude patterns instead: wildcards
+//! also match `/`, patterns are never anchored and trailing slashes are
+//! ignored.
+
+use std::error;
+use std::fmt;
+use std::path::{Component, Path};
+
+/// A compiled path pattern.
+///
+/// See the [module documentation](index.html) for the supported syntax.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    source: String,
+    tokens: Vec<Token>,
+    anchored: bool,
+    dir_only: bool,
+    wildcards_match_slash: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyChars,
+    AnyPath,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// An error produced when parsing an invalid [`Pattern`](struct.Pattern.html).
+#[derive(Clone, Debug)]
+pub struct PatternError {
+    pattern: String,
+    reason: &'static str,
+}
+
+impl Pattern {
+    /// Parse `pattern`, failing if it contains an unterminated character
+    /// class or a trailing escape.
+    pub fn new(pattern: &str) -> Result<Pattern, PatternError> {
+        let mut body = pattern;
+        let anchored = body.starts_with('/');
+        if anchored {
+            body = &body[1..];
+        }
+        let dir_only = body.ends_with('/') && !body.ends_with("\\/");
+        if dir_only {
+            body = &body[..body.len() - 1];
+        }
+        Pattern::parse(pattern, body, anchored, dir_only, false)
+    }
+
+    /// Parse `pattern` with GNU `tar` exclude semantics, where wildcards
+    /// also match `/` and the pattern may match at the start of any path
+    /// component. A leading `./` and trailing slashes are ignored.
+    pub fn tar(pattern: &str) -> Result<Pattern, PatternError> {
+        let mut body = pattern;
+        while let Some(rest) = body.strip_prefix("./") {
+            body = rest;
+        }
+        while body.len() > 1 && body.ends_with('/') && !body.ends_with("\\/") {
+            body = &body[..body.len() - 1];
+        }
+        Pattern::parse(pattern, body, false, false, true)
+    }
+
+    fn parse(
+        pattern: &str,
+        body: &str,
+        anchored: bool,
+        dir_only: bool,
+        wildcards_match_slash: bool,
+    ) -> Result<Pattern, PatternError> {
+        let error = |reason| PatternError {
+            pattern: pattern.to_owned(),
+            reason,
+        };
+        let mut tokens = Vec::new();
+        let mut chars = body.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::AnyChar,
+                '*' => {
+                    if chars.peek() == Some(&'*') {
+                        while chars.peek() == Some(&'*') {
+                            chars.next();
+                        }
+                        Token::AnyPath
+                    } else {
+                        Token::AnyChars
+                    }
+                }
+                '\\' => Token::Char(chars.next().ok_or_else(|| error("trailing escape"))?),
+                '[' => {
+                    let negated = matches!(chars.peek(), Some('!') | Some('^'));
+                    if negated {
+                        chars.next();
+                    }
+                    let mut ranges = Vec::new();
+                    let mut first = true;
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !first => break,
+                            Some('\\') => chars.next(),
+                            start => start,
+                        }
+                        .ok_or_else(|| error("unterminated character class"))?;
+                        first = false;
+                        let mut lookahead = chars.clone();
+                        let end = match (lookahead.next(), lookahead.next()) {
+                            (Some('-'), Some(end)) if end != ']' => {
+                                chars.next();
+                                chars.next();
+                                end
+                            }
+                            _ => start,
+                        };
+                        ranges.push((start, end));
+                    }
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Pattern {
+            source: pattern.to_owned(),
+            tokens,
+            anchored,
+            dir_only,
+            wildcards_match_slash,
+        })
+    }
+
+    /// The pattern as it was passed to [`new`](struct.Pattern.html#method.new).
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns `true` if this pattern is anchored at the root of the walk.
+    pub fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+
+    /// Returns `true` if this pattern only matches directories.
+    pub fn is_dir_only(&self) -> bool {
+        self.dir_only
+    }
+
+    /// Returns `true` if `path`, relative to the root of the walk, matches
+    /// this pattern. `is_dir` tells if `path` refers to a directory.
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let path = slash_separated(path);
+        self.matches_str(&path)
+    }
+
+    /// Returns `true` if `path`, a `/` separated path relative to the root of
+    /// the walk, matches this pattern ignoring whether it is a directory.
+    pub(crate) fn matches_str(&self, path: &str) -> bool {
+        let path: Vec<char> = path.chars().collect();
+        let matcher = Matcher {
+            wildcards_match_slash: self.wildcards_match_slash,
+        };
+        let matching = matcher.matching_starts(&self.tokens, &path);
+        if self.anchored {
+            return matching[0];
+        }
+        // Unanchored patterns may start at any path component.
+        matching[0]
+            || path
+                .iter()
+                .enumerate()
+                .any(|(i, &c)| c == '/' && matching[i + 1])
+    }
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(pattern: &str) -> Result<Pattern, PatternError> {
+        Pattern::new(pattern)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PatternError {
+    /// The pattern that failed to parse.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl error::Error for PatternError {}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern {:?}: {}", self.pattern, self.reason)
+    }
+}
+
+// Joins the normal components of `path` with `/`.
+pub(crate) fn slash_separated(path: &Path) -> String {
+    let mut joined = String::new();
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            if !joined.is_empty() {
+                joined.push('/');
+            }
+            joined.push_str(&name.to_string_lossy());
+        }
+    }
+    joined
+}
+
+struct Matcher {
+    wildcards_match_slash: bool,
+}
+
+impl Matcher {
+    // Whether `tokens` match `path[start..]`, for each `start` from `0` to
+    // `path.len()`. Tokens are applied from the last one, so each pair of
+    // token and start is only checked once instead of backtracking over the
+    // lengths matched by every wildcard.
+    fn matching_starts(&self, tokens: &[Token], path: &[char]) -> Vec<bool> {
+        // No tokens only match the end of the path
+        let mut matching: Vec<bool> = (0..=path.len()).map(|start| start == path.len()).collect();
+        for token in tokens.iter().rev() {
+            let rest = matching;
+            matching = vec![false; path.len() + 1];
+            for start in (0..=path.len()).rev() {
+                let next = path.get(start).copied();
+                let matches = match token {
+                    // Matches nothing, or the next character and then the
+                    // same as from the following start
+                    Token::AnyPath => rest[start] || (next.is_some() && matching[start + 1]),
+                    Token::AnyChars => {
+                        rest[start]
+                            || next.is_some_and(|c| {
+                                (c != '/' || self.wildcards_match_slash) && matching[start + 1]
+                            })
+                    }
+                    _ => next.is_some_and(|c| self.matches_char(token, c) && rest[start + 1]),
+                };
+                matching[start] = matches;
+            }
+        }
+        matching
+    }
+
+    fn matches_char(&self, token: &Token, c: char) -> bool {
+        let wildcard_matches_char = c != '/' || self.wildcards_match_slash;
+        match token {
+            Token::Char(expected) => *expected == c,
+            Token::AnyChar => wildcard_matches_char,
+            Token::Class { negated, ranges } => {
+                wildcard_matches_char
+                    && ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+            }
+            Token::AnyChars | Token::AnyPath => false,
+        }
+    }
+}
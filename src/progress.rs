@@ -0,0 +1,76 @@
+//! Progress reporting with [`indicatif`], available with the `indicatif`
+//! feature.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! use jwalk::progress::WalkProgress;
+//! use jwalk::WalkDir;
+//!
+//! let progress = Arc::new(WalkProgress::spinner());
+//! for _ in WalkDir::new(".").instrumentation(Some(progress.clone())) {}
+//! progress.finish();
+//! ```
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::instrument::Instrumentation;
+
+const DEFAULT_TEMPLATE: &str =
+    "{spinner} {elapsed} {pos} entries ({per_sec}), {prefix} dirs scanned, {wide_msg}";
+
+/// [`Instrumentation`](../instrument/trait.Instrumentation.html) updating a
+/// progress bar with the number of entries, entries per second, directories
+/// scanned and the directory read last.
+#[derive(Debug)]
+pub struct WalkProgress {
+    progress_bar: ProgressBar,
+    dirs: AtomicU64,
+}
+
+impl WalkProgress {
+    /// Report progress with a spinner drawn to stderr.
+    pub fn spinner() -> WalkProgress {
+        let progress_bar = ProgressBar::new_spinner()
+            .with_style(ProgressStyle::with_template(DEFAULT_TEMPLATE).unwrap());
+        progress_bar.enable_steady_tick(Duration::from_millis(100));
+        WalkProgress::new(progress_bar)
+    }
+
+    /// Report progress with `progress_bar`, which may use a custom style.
+    /// The position is the number of entries, the prefix the number of
+    /// directories scanned and the message the directory read last.
+    pub fn new(progress_bar: ProgressBar) -> WalkProgress {
+        WalkProgress {
+            progress_bar,
+            dirs: AtomicU64::new(0),
+        }
+    }
+
+    /// The progress bar updated by this instrumentation.
+    pub fn progress_bar(&self) -> &ProgressBar {
+        &self.progress_bar
+    }
+
+    /// The number of directories scanned so far.
+    pub fn dirs(&self) -> u64 {
+        self.dirs.load(Ordering::Relaxed)
+    }
+
+    /// Finish the progress bar, leaving the final counts visible.
+    pub fn finish(&self) {
+        self.progress_bar.finish();
+    }
+}
+
+impl Instrumentation for WalkProgress {
+    fn on_read_dir_finish(&self, path: &Path, _depth: usize, entries: usize, _elapsed: Duration) {
+        let dirs = self.dirs.fetch_add(1, Ordering::Relaxed) + 1;
+        self.progress_bar.inc(entries as u64);
+        self.progress_bar.set_prefix(dirs.to_string());
+        self.progress_bar.set_message(path.display().to_string());
+    }
+}
@@ -0,0 +1,100 @@
+//! Latest modification time under each directory.
+//!
+//! [`MtimeRollup`](struct.MtimeRollup.html) records, for every directory of a
+//! walk, the newest modification time of the directory itself and all of its
+//! descendants. Sync and cache tools can then compare top-level folders with
+//! a previous run without walking them again.
+//!
+//! ```no_run
+//! use jwalk::rollup::MtimeRollup;
+//! use jwalk::WalkDir;
+//!
+//! let rollup = MtimeRollup::from_walk(WalkDir::new("."))?;
+//! for (dir, latest) in rollup.iter() {
+//!     println!("{} {:?}", dir.display(), latest);
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::core::Operation;
+use crate::{ClientState, DirEntry, Error, Result, WalkDirGeneric};
+
+/// Newest modification time under each directory of a walk.
+#[derive(Clone, Debug)]
+pub struct MtimeRollup {
+    root: PathBuf,
+    latest: HashMap<PathBuf, SystemTime>,
+}
+
+impl MtimeRollup {
+    /// Create an empty rollup for a walk starting at `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> MtimeRollup {
+        MtimeRollup {
+            root: root.into(),
+            latest: HashMap::new(),
+        }
+    }
+
+    /// Walk `walk_dir` and record the modification time of every yielded
+    /// entry. Fails with the first error of the walk.
+    pub fn from_walk<C: ClientState>(walk_dir: WalkDirGeneric<C>) -> Result<MtimeRollup> {
+        let mut rollup = MtimeRollup::new(walk_dir.root());
+        for dir_entry in walk_dir {
+            rollup.add_entry(&dir_entry?)?;
+        }
+        Ok(rollup)
+    }
+
+    /// Record the modification time of `dir_entry`, for use while iterating
+    /// a walk. Symlinks are counted with their own time unless the walk
+    /// follows links.
+    pub fn add_entry<C: ClientState>(&mut self, dir_entry: &DirEntry<C>) -> Result<()> {
+        let modified = dir_entry
+            .metadata()?
+            .modified()
+            .map_err(|err| Error::from_entry(dir_entry, err).during(Operation::Metadata))?;
+        let path = dir_entry.path();
+        if dir_entry.file_type.is_dir() {
+            self.add_dir(&path, modified);
+        } else if let Some(parent) = path.parent() {
+            self.add_dir(parent, modified);
+        }
+        Ok(())
+    }
+
+    /// Record `modified` for directory `dir` and all of its ancestors up to
+    /// the root.
+    pub fn add_dir(&mut self, dir: &Path, modified: SystemTime) {
+        for ancestor in dir.ancestors() {
+            if !ancestor.starts_with(&self.root) {
+                break;
+            }
+            match self.latest.get_mut(ancestor) {
+                // Ancestors of a directory are never older than it
+                Some(latest) if *latest >= modified => break,
+                Some(latest) => *latest = modified,
+                None => {
+                    self.latest.insert(ancestor.to_path_buf(), modified);
+                }
+            }
+        }
+    }
+
+    /// The newest modification time of `dir` and its descendants, `None` if
+    /// no entries under `dir` were recorded.
+    pub fn latest<P: AsRef<Path>>(&self, dir: P) -> Option<SystemTime> {
+        self.latest.get(dir.as_ref()).copied()
+    }
+
+    /// Iterate over all directories with their newest modification time, in
+    /// no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, SystemTime)> {
+        self.latest
+            .iter()
+            .map(|(dir, latest)| (dir.as_path(), *latest))
+    }
+}
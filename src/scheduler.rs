@@ -0,0 +1,179 @@
+//! Order in which pending directories are read.
+//!
+//! While walking in parallel, the directories found by the walk wait in a
+//! queue until a thread of the rayon pool is free to read them. The
+//! [`Scheduler`](trait.Scheduler.html) set with
+//! [`WalkDirGeneric::scheduler`](../struct.WalkDirGeneric.html#method.scheduler)
+//! decides which of them is read next:
+//!
+//! ```no_run
+//! use jwalk::scheduler::BreadthFirst;
+//! use jwalk::WalkDir;
+//!
+//! for dir_entry in WalkDir::new(".").scheduler(BreadthFirst::default) {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+//!
+//! Entries are yielded in the same order either way, the scheduler only
+//! changes which directories are read ahead of the iterator. Reading far
+//! ahead holds more results in memory, see
+//! [`buffer_limit`](../struct.WalkDirGeneric.html#method.buffer_limit).
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::core::{Ordered, ReceiveBuffer};
+use crate::{ClientState, ReadDirSpec};
+
+/// Queue of the directories waiting to be read by a parallel walk.
+///
+/// Each walk gets its own scheduler, which is only used by one thread at a
+/// time.
+pub trait Scheduler<C: ClientState>: Send {
+    /// Add a directory that was found by the walk.
+    fn push(&mut self, read_dir: ScheduledReadDir<C>);
+    /// Remove the directory to read next, `None` if there are no pending
+    /// directories.
+    fn pop(&mut self) -> Option<ScheduledReadDir<C>>;
+}
+
+/// Directory waiting to be read, see [`Scheduler`](trait.Scheduler.html).
+///
+/// Ordered like [`DepthFirst`](struct.DepthFirst.html) reads them, the
+/// greatest is read first.
+pub struct ScheduledReadDir<C: ClientState> {
+    pub(crate) ordered: Ordered<ReadDirSpec<C>>,
+}
+
+impl<C: ClientState> ScheduledReadDir<C> {
+    /// Spec of the read, including its depth and path.
+    pub fn read_dir_spec(&self) -> &ReadDirSpec<C> {
+        &self.ordered.value
+    }
+
+    /// Position of the directory in walk order, the index of each of its
+    /// ancestors among the entries of their parent and its own index.
+    pub fn index_path(&self) -> &[usize] {
+        &self.ordered.index_path.indices
+    }
+
+    /// Priority of the directory, such as its
+    /// [`DirEntry::read_children_priority`](../struct.DirEntry.html#structfield.read_children_priority)
+    /// or given to
+    /// [`priority_paths`](../struct.WalkDirGeneric.html#method.priority_paths).
+    /// Directories with a higher priority are meant to be read first.
+    pub fn priority(&self) -> usize {
+        self.ordered.priority
+    }
+}
+
+impl<C: ClientState> PartialEq for ScheduledReadDir<C> {
+    fn eq(&self, o: &Self) -> bool {
+        self.ordered.eq(&o.ordered)
+    }
+}
+
+impl<C: ClientState> Eq for ScheduledReadDir<C> {}
+
+impl<C: ClientState> PartialOrd for ScheduledReadDir<C> {
+    fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
+        Some(self.cmp(o))
+    }
+}
+
+impl<C: ClientState> Ord for ScheduledReadDir<C> {
+    fn cmp(&self, o: &Self) -> Ordering {
+        self.ordered.cmp(&o.ordered)
+    }
+}
+
+/// Reads the pending directory with the highest priority, then the one that
+/// comes first in walk order, so the directories the iterator needs next are
+/// read first. The default.
+///
+/// With [`ReadDirOrder::Fifo`](../enum.ReadDirOrder.html#variant.Fifo)
+/// directories with the same priority are read in the order they were found
+/// instead.
+pub struct DepthFirst<C: ClientState> {
+    pending: BinaryHeap<ScheduledReadDir<C>>,
+}
+
+impl<C: ClientState> Default for DepthFirst<C> {
+    fn default() -> Self {
+        DepthFirst {
+            pending: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<C: ClientState> Scheduler<C> for DepthFirst<C> {
+    fn push(&mut self, read_dir: ScheduledReadDir<C>) {
+        self.pending.push(read_dir);
+    }
+
+    fn pop(&mut self) -> Option<ScheduledReadDir<C>> {
+        self.pending.pop()
+    }
+}
+
+/// Reads the pending directory with the highest priority, then the least
+/// deep one, so each level of the tree is read before the next.
+pub struct BreadthFirst<C: ClientState> {
+    pending: BinaryHeap<(usize, Reverse<usize>, ScheduledReadDir<C>)>,
+}
+
+impl<C: ClientState> Default for BreadthFirst<C> {
+    fn default() -> Self {
+        BreadthFirst {
+            pending: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<C: ClientState> Scheduler<C> for BreadthFirst<C> {
+    fn push(&mut self, read_dir: ScheduledReadDir<C>) {
+        let depth = read_dir.read_dir_spec().depth;
+        self.pending
+            .push((read_dir.priority(), Reverse(depth), read_dir));
+    }
+
+    fn pop(&mut self) -> Option<ScheduledReadDir<C>> {
+        self.pending.pop().map(|(_, _, read_dir)| read_dir)
+    }
+}
+
+/// Reads pending directories in the order they were found, ignoring their
+/// priorities.
+pub struct Fifo<C: ClientState> {
+    pending: VecDeque<ScheduledReadDir<C>>,
+}
+
+impl<C: ClientState> Default for Fifo<C> {
+    fn default() -> Self {
+        Fifo {
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<C: ClientState> Scheduler<C> for Fifo<C> {
+    fn push(&mut self, read_dir: ScheduledReadDir<C>) {
+        self.pending.push_back(read_dir);
+    }
+
+    fn pop(&mut self) -> Option<ScheduledReadDir<C>> {
+        self.pending.pop_front()
+    }
+}
+
+impl<C: ClientState> ReceiveBuffer<ReadDirSpec<C>> for Box<dyn Scheduler<C>> {
+    fn push(&mut self, ordered: Ordered<ReadDirSpec<C>>) {
+        Scheduler::push(self.as_mut(), ScheduledReadDir { ordered });
+    }
+
+    fn pop(&mut self) -> Option<Ordered<ReadDirSpec<C>>> {
+        Scheduler::pop(self.as_mut()).map(|read_dir| read_dir.ordered)
+    }
+}
@@ -0,0 +1,47 @@
+//! Scratch values of `process_read_dir_with_scratch`, kept per thread.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::{Arc, Weak};
+
+// A scratch value with the key of the callback it belongs to
+type KeyedScratch = (Weak<()>, Box<dyn Any>);
+
+thread_local! {
+    // Scratch values of the current thread
+    static SCRATCH: RefCell<Vec<KeyedScratch>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Identifies the scratch values of one callback on each thread.
+///
+/// Values of a dropped key are dropped the next time the thread holding them
+/// stores a scratch value, or when it exits.
+#[derive(Debug, Default)]
+pub(crate) struct ScratchKey(Arc<()>);
+
+impl ScratchKey {
+    /// Takes the current thread's scratch value of this key.
+    pub(crate) fn take<S: 'static>(&self) -> Option<S> {
+        SCRATCH.with(|scratch| {
+            let mut scratch = scratch.borrow_mut();
+            let position = scratch.iter().position(|(key, _)| self.is(key))?;
+            let (_, value) = scratch.swap_remove(position);
+            value.downcast().ok().map(|value| *value)
+        })
+    }
+
+    /// Stores `value` as the current thread's scratch value of this key.
+    pub(crate) fn put<S: 'static>(&self, value: S) {
+        SCRATCH.with(|scratch| {
+            let mut scratch = scratch.borrow_mut();
+            scratch.retain(|(key, _)| key.strong_count() > 0);
+            scratch.push((Arc::downgrade(&self.0), Box::new(value)));
+        })
+    }
+
+    // The `Weak` keeps the allocation of a dropped key alive, so no other key
+    // can have its address
+    fn is(&self, key: &Weak<()>) -> bool {
+        std::ptr::eq(key.as_ptr(), Arc::as_ptr(&self.0))
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{ClientState, DirEntry, Error, ErrorKind, FileType};
+
+/// Serializes the path, depth and file type of the entry, its metadata if
+/// it was read with
+/// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata),
+/// and its `client_state`.
+///
+/// Fails for paths that aren't valid UTF-8, like `serde` does for `Path`.
+impl<C: ClientState> Serialize for DirEntry<C>
+where
+    C::DirEntryState: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("DirEntry", 5)?;
+        state.serialize_field("path", &self.path())?;
+        state.serialize_field("depth", &self.depth)?;
+        state.serialize_field("file_type", &SerializeFileType(self.file_type))?;
+        state.serialize_field(
+            "metadata",
+            &self.preloaded_metadata().map(SerializeMetadata),
+        )?;
+        state.serialize_field("client_state", &self.client_state)?;
+        state.end()
+    }
+}
+
+/// Serializes the depth, path, kind and message of the error.
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Error", 4)?;
+        state.serialize_field("depth", &self.depth())?;
+        state.serialize_field("path", &self.path())?;
+        state.serialize_field("kind", &self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// Serializes the kind as the name of its variant.
+impl Serialize for ErrorKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (index, variant) = match self {
+            ErrorKind::NotFound => (0, "NotFound"),
+            ErrorKind::PermissionDenied => (1, "PermissionDenied"),
+            ErrorKind::Loop => (2, "Loop"),
+            ErrorKind::ReadDirFailed => (3, "ReadDirFailed"),
+            ErrorKind::MetadataFailed => (4, "MetadataFailed"),
+            ErrorKind::ThreadpoolBusy => (5, "ThreadpoolBusy"),
+            ErrorKind::Other => (6, "Other"),
+            ErrorKind::DeadlineExceeded => (7, "DeadlineExceeded"),
+        };
+        serializer.serialize_unit_variant("ErrorKind", index, variant)
+    }
+}
+
+// A file type serialized as `"dir"`, `"file"`, `"symlink"` or `"other"`.
+struct SerializeFileType(FileType);
+
+impl Serialize for SerializeFileType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(if self.0.is_dir() {
+            "dir"
+        } else if self.0.is_file() {
+            "file"
+        } else if self.0.is_symlink() {
+            "symlink"
+        } else {
+            "other"
+        })
+    }
+}
+
+// The portable fields of metadata.
+struct SerializeMetadata<'a>(&'a fs::Metadata);
+
+impl Serialize for SerializeMetadata<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Metadata", 4)?;
+        state.serialize_field("len", &self.0.len())?;
+        state.serialize_field("readonly", &self.0.permissions().readonly())?;
+        state.serialize_field("modified", &self.0.modified().ok())?;
+        state.serialize_field("created", &self.0.created().ok())?;
+        state.end()
+    }
+}
@@ -0,0 +1,129 @@
+//! Walking remote trees over SFTP, available with the `sftp` feature.
+//!
+//! [`SftpFs`](struct.SftpFs.html) is a
+//! [`FileSystem`](../file_system/trait.FileSystem.html) reading directories
+//! of a server through `ssh2` SFTP channels, so remote trees are walked with
+//! the same builder and iterator types as local ones.
+//!
+//! Channels of one SSH session share the session's lock, so their requests
+//! are sent one at a time. To read several directories at once, open a
+//! session per channel and pass all of them to
+//! [`SftpFs::with_channels`](struct.SftpFs.html#method.with_channels).
+//!
+//! ```no_run
+//! use jwalk::sftp::SftpFs;
+//! use jwalk::Parallelism;
+//! use ssh2::Session;
+//! use std::net::TcpStream;
+//!
+//! let mut channels = Vec::new();
+//! for _ in 0..4 {
+//!     let mut session = Session::new()?;
+//!     session.set_tcp_stream(TcpStream::connect("example.com:22")?);
+//!     session.handshake()?;
+//!     session.userauth_agent("deploy")?;
+//!     channels.push(session.sftp()?);
+//! }
+//! for dir_entry in SftpFs::with_channels(channels)
+//!     .walk_dir::<((), ())>("/srv/www")
+//!     .parallelism(Parallelism::RayonNewPool(4))
+//! {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Metadata isn't available, as `std::fs::Metadata` can't be built from SFTP
+//! attributes.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use ssh2::{FileStat, Sftp};
+
+use crate::file_system::{FileSystem, FileSystemEntry, FileType, ReadDirEntries};
+use crate::{ClientState, WalkDirGeneric};
+
+/// File system of an SFTP server, see the [`sftp`](index.html) module.
+#[derive(Clone)]
+pub struct SftpFs {
+    channels: Arc<Vec<Sftp>>,
+    // Channel of the next request
+    next: Arc<AtomicUsize>,
+}
+
+impl SftpFs {
+    /// Read directories through `sftp`.
+    pub fn new(sftp: Sftp) -> SftpFs {
+        SftpFs::with_channels(vec![sftp])
+    }
+
+    /// Read directories through `channels`, taking turns.
+    ///
+    /// # Panics
+    ///
+    /// If `channels` is empty.
+    pub fn with_channels(channels: Vec<Sftp>) -> SftpFs {
+        assert!(!channels.is_empty(), "SftpFs needs at least one channel");
+        SftpFs {
+            channels: Arc::new(channels),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Create a builder for a walk of this server starting at `root`.
+    pub fn walk_dir<C: ClientState>(&self, root: impl AsRef<Path>) -> WalkDirGeneric<C> {
+        WalkDirGeneric::new(root).file_system(Some(Arc::new(self.clone())))
+    }
+
+    // The channel to send the next request with
+    fn channel(&self) -> &Sftp {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.channels[next % self.channels.len()]
+    }
+}
+
+impl FileSystem for SftpFs {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        // Paths are joined to `path`, `.` and `..` are left out
+        let entries = self.channel().readdir(path)?;
+        Ok(Box::new(entries.into_iter().map(|(path, stat)| {
+            let file_name = path
+                .file_name()
+                .map(OsString::from)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "entry without name"))?;
+            Ok(FileSystemEntry {
+                file_name,
+                file_type: Ok(file_type(&stat)),
+            })
+        })))
+    }
+
+    fn file_type(&self, path: &Path, follow_links: bool) -> io::Result<FileType> {
+        let stat = if follow_links {
+            self.channel().stat(path)?
+        } else {
+            self.channel().lstat(path)?
+        };
+        Ok(file_type(&stat))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        Ok(self.channel().readlink(path)?)
+    }
+}
+
+// Entries that are neither directories nor symlinks are walked as files.
+fn file_type(stat: &FileStat) -> FileType {
+    let file_type = stat.file_type();
+    if file_type.is_dir() {
+        FileType::dir()
+    } else if file_type.is_symlink() {
+        FileType::symlink()
+    } else {
+        FileType::file()
+    }
+}
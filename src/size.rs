@@ -0,0 +1,121 @@
+//! Size semantics for disk usage computations.
+//!
+//! [`SizeCounter`](struct.SizeCounter.html) turns entry metadata into the
+//! number of bytes an entry contributes to a total, with the same choices `du`
+//! offers: apparent sizes (`du --apparent-size`) or allocated blocks (`du`'s
+//! default), counting hardlinked files once (`du`'s default) or once per link
+//! (`du --count-links`). The difference matters a lot on sparse and
+//! hardlinked trees.
+//!
+//! [`WalkDirGeneric::aggregate_sizes`](../struct.WalkDirGeneric.html#method.aggregate_sizes)
+//! uses a counter to sum up the sizes under each directory while walking:
+//!
+//! ```no_run
+//! use jwalk::size::{SizeCounter, SizeKind};
+//! use jwalk::WalkDir;
+//!
+//! let walk_dir = WalkDir::new(".").aggregate_sizes(SizeCounter::new(SizeKind::Allocated));
+//! for dir_entry in walk_dir {
+//!     let dir_entry = dir_entry?;
+//!     if dir_entry.file_type.is_dir() {
+//!         let subtree_size = dir_entry.subtree_size().unwrap_or_default();
+//!         println!("{}\t{}", subtree_size.bytes, dir_entry.path().display());
+//!     }
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::fs::Metadata;
+use std::ops::AddAssign;
+
+use crate::hardlinks::HardlinkSet;
+
+/// What size of a file to count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SizeKind {
+    /// The length of the file in bytes, as reported by `ls -l`.
+    #[default]
+    Apparent,
+    /// The space allocated for the file on disk, which is smaller than the
+    /// apparent size for sparse files and larger for small files.
+    ///
+    /// Only available on Unix, other platforms count the apparent size.
+    Allocated,
+}
+
+/// Total size of an entry and its descendants, see
+/// [`DirEntry::subtree_size`](../struct.DirEntry.html#method.subtree_size).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct SubtreeSize {
+    /// Bytes counted by the walk's [`SizeCounter`](struct.SizeCounter.html),
+    /// including the sizes of directories themselves.
+    pub bytes: u64,
+    /// Number of entries that aren't directories.
+    pub files: u64,
+}
+
+impl AddAssign for SubtreeSize {
+    fn add_assign(&mut self, other: SubtreeSize) {
+        self.bytes += other.bytes;
+        self.files += other.files;
+    }
+}
+
+/// Computes sizes of entries, shared between the threads of a walk.
+#[derive(Debug, Default)]
+pub struct SizeCounter {
+    kind: SizeKind,
+    dedup_hardlinks: bool,
+    hardlinks: HardlinkSet,
+}
+
+impl SizeCounter {
+    /// Create a counter for sizes of the given kind, counting hardlinked files
+    /// once per link.
+    pub fn new(kind: SizeKind) -> SizeCounter {
+        SizeCounter {
+            kind,
+            dedup_hardlinks: false,
+            hardlinks: HardlinkSet::default(),
+        }
+    }
+
+    /// Count files with several hardlinks only once, for the first link that
+    /// is passed to [`size`](struct.SizeCounter.html#method.size). Defaults
+    /// to `false`.
+    ///
+    /// Only available on Unix, other platforms count every link.
+    pub fn dedup_hardlinks(mut self, dedup_hardlinks: bool) -> SizeCounter {
+        self.dedup_hardlinks = dedup_hardlinks;
+        self
+    }
+
+    /// The kind of sizes this counter computes.
+    pub fn kind(&self) -> SizeKind {
+        self.kind
+    }
+
+    /// Returns the number of bytes an entry with `metadata` contributes to a
+    /// total, `0` if it's another link to an already counted file.
+    pub fn size(&self, metadata: &Metadata) -> u64 {
+        if self.dedup_hardlinks && self.hardlinks.is_duplicate(metadata) {
+            return 0;
+        }
+        match self.kind {
+            SizeKind::Apparent => metadata.len(),
+            SizeKind::Allocated => allocated_size(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn allocated_size(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
@@ -0,0 +1,130 @@
+//! Walks sorted by full path across the whole tree.
+//!
+//! [`WalkDirGeneric::into_sorted_iter`](../struct.WalkDirGeneric.html#method.into_sorted_iter)
+//! yields the entries of a walk in the order of their paths, compared as
+//! strings, like `find | sort` does. Directories are still read in parallel:
+//! the sorted contents of each directory are merged with the entries of the
+//! other directories whose paths fall between them, such as `a-b` coming
+//! before `a/c`.
+//!
+//! ```no_run
+//! use jwalk::WalkDir;
+//!
+//! for dir_entry in WalkDir::new(".").into_sorted_iter() {
+//!     println!("{}", dir_entry?.path().display());
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+//!
+//! Only entries sorting before all entries that weren't read yet are held
+//! back, which usually are few. With
+//! [`contents_first`](../struct.WalkDirGeneric.html#method.contents_first)
+//! the contents of each directory are held back until the directory is
+//! yielded, as the directory sorts before them.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::path::PathBuf;
+
+use crate::{ClientState, DirEntry, DirEntryIter, Result, WalkDirGeneric};
+
+/// Iterator from
+/// [`WalkDirGeneric::into_sorted_iter`](../struct.WalkDirGeneric.html#method.into_sorted_iter).
+pub struct SortedDirEntryIter<C: ClientState> {
+    dir_entry_iter: DirEntryIter<C>,
+    // Entries yielded by `dir_entry_iter`, smallest path first
+    held_back: BinaryHeap<HeldBack<C>>,
+}
+
+// Entry ordered so the smallest path is at the top of the heap.
+struct HeldBack<C: ClientState> {
+    path: PathBuf,
+    dir_entry: DirEntry<C>,
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Create an iterator yielding the entries of the walk sorted by their
+    /// full paths, see the [`sorted`](sorted/index.html) module.
+    ///
+    /// Paths are compared as strings, not component by component, so `a-b`
+    /// comes before `a/c`. Entries of each directory are sorted by file name,
+    /// replacing [`sort_by`](struct.WalkDirGeneric.html#method.sort_by),
+    /// [`sort_by_key`](struct.WalkDirGeneric.html#method.sort_by_key),
+    /// [`natural_sort`](struct.WalkDirGeneric.html#method.natural_sort) and
+    /// [`sort_case_insensitive`](struct.WalkDirGeneric.html#method.sort_case_insensitive), and
+    /// [`read_dir_chunk_size`](struct.WalkDirGeneric.html#method.read_dir_chunk_size)
+    /// and [`strict_order`](struct.WalkDirGeneric.html#method.strict_order)
+    /// are ignored. Errors are yielded as soon as they occur. Paths of
+    /// directories changed by
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// aren't taken into account.
+    pub fn into_sorted_iter(mut self) -> SortedDirEntryIter<C> {
+        self.options.sort_by = None;
+        self.options.sort_key = None;
+        self.options.name_order = Default::default();
+        SortedDirEntryIter {
+            dir_entry_iter: self
+                .sort(true)
+                .read_dir_chunk_size(None)
+                .strict_order(true)
+                .into_iter(),
+            held_back: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<C: ClientState> Iterator for SortedDirEntryIter<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(smallest) = self.held_back.peek() {
+                let sorted = self
+                    .dir_entry_iter
+                    .min_pending_path()
+                    .is_none_or(|pending| {
+                        smallest.path.as_os_str() < pending.as_os_str()
+                    });
+                if sorted {
+                    return self
+                        .held_back
+                        .pop()
+                        .map(|held_back| Ok(held_back.dir_entry));
+                }
+            }
+            match self.dir_entry_iter.next() {
+                Some(Ok(dir_entry)) => self.held_back.push(HeldBack {
+                    path: dir_entry.path(),
+                    dir_entry,
+                }),
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    return self
+                        .held_back
+                        .pop()
+                        .map(|held_back| Ok(held_back.dir_entry))
+                }
+            }
+        }
+    }
+}
+
+impl<C: ClientState> PartialEq for HeldBack<C> {
+    fn eq(&self, o: &Self) -> bool {
+        self.path.as_os_str() == o.path.as_os_str()
+    }
+}
+
+impl<C: ClientState> Eq for HeldBack<C> {}
+
+impl<C: ClientState> PartialOrd for HeldBack<C> {
+    fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
+        Some(self.cmp(o))
+    }
+}
+
+impl<C: ClientState> Ord for HeldBack<C> {
+    fn cmp(&self, o: &Self) -> Ordering {
+        o.path.as_os_str().cmp(self.path.as_os_str())
+    }
+}
@@ -0,0 +1,146 @@
+//! Counts of the entries of a walk.
+//!
+//! [`WalkDirGeneric::with_stats`](../struct.WalkDirGeneric.html#method.with_stats)
+//! returns a [`StatsCollector`](struct.StatsCollector.html) that counts files,
+//! directories, symlinks, errors and bytes while the walk reads directories.
+//! Its [`WalkStats`](struct.WalkStats.html) can be read from any thread
+//! during or after the walk.
+//!
+//! ```no_run
+//! use jwalk::WalkDir;
+//!
+//! let (walk_dir, stats_collector) = WalkDir::new(".").with_stats();
+//! for dir_entry in walk_dir {
+//!     let _ = dir_entry?;
+//! }
+//! let stats = stats_collector.stats();
+//! println!("{} files, {} bytes", stats.files, stats.bytes);
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::core::ReadDirCallback;
+use crate::{ClientState, DirEntry, Result};
+
+/// Counts of the entries of a walk, from
+/// [`StatsCollector::stats`](struct.StatsCollector.html#method.stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WalkStats {
+    /// Number of regular files, including symlinks to files that are
+    /// followed.
+    pub files: u64,
+    /// Number of directories, including the roots if they are yielded.
+    pub dirs: u64,
+    /// Number of symlinks that aren't followed.
+    pub symlinks: u64,
+    /// Number of errors, including directories that couldn't be read and files
+    /// whose size couldn't be read.
+    pub errors: u64,
+    /// Sum of the apparent sizes of all files.
+    pub bytes: u64,
+    /// Largest depth of any entry.
+    pub max_depth: usize,
+}
+
+/// Handle collecting the [`WalkStats`](struct.WalkStats.html) of a walk.
+///
+/// Pass a clone to
+/// [`WalkDirGeneric::stats_collector`](../struct.WalkDirGeneric.html#method.stats_collector),
+/// or create both with
+/// [`WalkDirGeneric::with_stats`](../struct.WalkDirGeneric.html#method.with_stats),
+/// and keep another to read the stats.
+#[derive(Clone, Debug, Default)]
+pub struct StatsCollector {
+    counts: Arc<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    files: AtomicU64,
+    dirs: AtomicU64,
+    symlinks: AtomicU64,
+    errors: AtomicU64,
+    bytes: AtomicU64,
+    max_depth: AtomicUsize,
+}
+
+impl StatsCollector {
+    /// Create a collector with all counts at zero.
+    pub fn new() -> StatsCollector {
+        StatsCollector::default()
+    }
+
+    /// The stats counted so far.
+    ///
+    /// Entries are counted when their directory is read, which can be ahead
+    /// of the entries yielded so far. Entries skipped by the walk's options or
+    /// removed by
+    /// [`process_read_dir`](../struct.WalkDirGeneric.html#method.process_read_dir)
+    /// aren't counted.
+    pub fn stats(&self) -> WalkStats {
+        WalkStats {
+            files: self.counts.files.load(Ordering::SeqCst),
+            dirs: self.counts.dirs.load(Ordering::SeqCst),
+            symlinks: self.counts.symlinks.load(Ordering::SeqCst),
+            errors: self.counts.errors.load(Ordering::SeqCst),
+            bytes: self.counts.bytes.load(Ordering::SeqCst),
+            max_depth: self.counts.max_depth.load(Ordering::SeqCst),
+        }
+    }
+
+    // Counts the entries that will be yielded and the errors of
+    // `dir_entry_results`.
+    pub(crate) fn count<C: ClientState>(
+        &self,
+        dir_entry_results: &[Result<DirEntry<C>>],
+        min_depth: usize,
+    ) {
+        for dir_entry_result in dir_entry_results {
+            let dir_entry = match dir_entry_result {
+                Ok(dir_entry) => dir_entry,
+                Err(_) => {
+                    self.counts.errors.fetch_add(1, Ordering::SeqCst);
+                    continue;
+                }
+            };
+            if dir_entry.depth < min_depth || dir_entry.skip_yield {
+                continue;
+            }
+            let counts = &self.counts;
+            if dir_entry.file_type.is_dir() {
+                counts.dirs.fetch_add(1, Ordering::SeqCst);
+            } else if dir_entry.file_type.is_symlink() {
+                counts.symlinks.fetch_add(1, Ordering::SeqCst);
+            } else if dir_entry.file_type.is_file() {
+                counts.files.fetch_add(1, Ordering::SeqCst);
+                match dir_entry.metadata() {
+                    Ok(metadata) => counts.bytes.fetch_add(metadata.len(), Ordering::SeqCst),
+                    Err(_) => counts.errors.fetch_add(1, Ordering::SeqCst),
+                };
+            }
+            counts
+                .max_depth
+                .fetch_max(dir_entry.depth, Ordering::SeqCst);
+        }
+    }
+}
+
+// Wraps `read_dir_callback` to count the entries it reads.
+pub(crate) fn count_read_dir<C: ClientState>(
+    stats_collector: StatsCollector,
+    min_depth: usize,
+    read_dir_callback: Arc<ReadDirCallback<C>>,
+) -> Arc<ReadDirCallback<C>> {
+    Arc::new(move |read_dir_spec| {
+        let read_dir_result = read_dir_callback(read_dir_spec);
+        match read_dir_result.as_ref() {
+            Ok(read_dir) => stats_collector.count(&read_dir.results_list, min_depth),
+            Err(_) => {
+                stats_collector.counts.errors.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        read_dir_result
+    })
+}
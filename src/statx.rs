@@ -0,0 +1,235 @@
+//! Read only the metadata fields a walk needs, on Linux.
+//!
+//! [`WalkDirGeneric::preload_metadata_ext`](../struct.WalkDirGeneric.html#method.preload_metadata_ext)
+//! reads the metadata of every entry with `statx`, requesting only the
+//! [`StatxFields`](struct.StatxFields.html) the caller needs, and stores it as
+//! a [`Statx`](struct.Statx.html) on the entry. File systems can skip work for
+//! fields that aren't requested, which makes size-only scans cheaper than
+//! reading the full `std::fs::Metadata`.
+//!
+//! ```no_run
+//! # #[cfg(feature = "preload-metadata")]
+//! # fn main() -> Result<(), jwalk::Error> {
+//! use jwalk::statx::StatxFields;
+//! use jwalk::WalkDir;
+//!
+//! let mut total = 0;
+//! for dir_entry in WalkDir::new(".").preload_metadata_ext(StatxFields::SIZE.dont_sync(true)) {
+//!     total += dir_entry?.statx().and_then(|statx| statx.size()).unwrap_or(0);
+//! }
+//! println!("{} bytes", total);
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "preload-metadata"))]
+//! # fn main() {}
+//! ```
+
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::ops::BitOr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Fields requested from `statx`, combined with `|`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StatxFields {
+    mask: u32,
+    dont_sync: bool,
+}
+
+/// Metadata of an entry read with `statx`, see the [`statx`](index.html)
+/// module.
+///
+/// Accessors return `None` for fields that weren't returned, such as fields
+/// the file system doesn't provide. Fields that weren't requested may be
+/// returned anyway.
+#[derive(Clone, Copy)]
+pub struct Statx {
+    statx: libc::statx,
+}
+
+impl StatxFields {
+    /// Permissions of the entry, see [`Statx::mode`](struct.Statx.html#method.mode).
+    pub const MODE: StatxFields = StatxFields::new(libc::STATX_MODE);
+    /// Number of hard links.
+    pub const NLINK: StatxFields = StatxFields::new(libc::STATX_NLINK);
+    /// User id of the owner.
+    pub const UID: StatxFields = StatxFields::new(libc::STATX_UID);
+    /// Group id of the owner.
+    pub const GID: StatxFields = StatxFields::new(libc::STATX_GID);
+    /// Last access time.
+    pub const ATIME: StatxFields = StatxFields::new(libc::STATX_ATIME);
+    /// Last modification time.
+    pub const MTIME: StatxFields = StatxFields::new(libc::STATX_MTIME);
+    /// Last status change time.
+    pub const CTIME: StatxFields = StatxFields::new(libc::STATX_CTIME);
+    /// Inode number.
+    pub const INO: StatxFields = StatxFields::new(libc::STATX_INO);
+    /// Size in bytes.
+    pub const SIZE: StatxFields = StatxFields::new(libc::STATX_SIZE);
+    /// Number of 512 byte blocks allocated.
+    pub const BLOCKS: StatxFields = StatxFields::new(libc::STATX_BLOCKS);
+    /// Creation time.
+    pub const BTIME: StatxFields = StatxFields::new(libc::STATX_BTIME);
+    /// All fields of `std::fs::Metadata` on Linux, except the creation time.
+    pub const BASIC_STATS: StatxFields = StatxFields::new(libc::STATX_BASIC_STATS);
+
+    const fn new(mask: u32) -> StatxFields {
+        StatxFields {
+            mask,
+            dont_sync: false,
+        }
+    }
+
+    /// Returns `true` if all of `other`'s fields are requested.
+    pub fn contains(self, other: StatxFields) -> bool {
+        self.mask & other.mask == other.mask
+    }
+
+    /// Return cached fields without synchronizing with the server on network
+    /// file systems, which can be out of date. Defaults to `false`.
+    pub fn dont_sync(mut self, dont_sync: bool) -> StatxFields {
+        self.dont_sync = dont_sync;
+        self
+    }
+}
+
+impl BitOr for StatxFields {
+    type Output = StatxFields;
+
+    fn bitor(self, other: StatxFields) -> StatxFields {
+        StatxFields {
+            mask: self.mask | other.mask,
+            dont_sync: self.dont_sync || other.dont_sync,
+        }
+    }
+}
+
+impl Statx {
+    /// The fields that were returned.
+    pub fn fields(&self) -> StatxFields {
+        StatxFields::new(self.statx.stx_mask)
+    }
+
+    /// Permission bits, without the file type bits.
+    pub fn mode(&self) -> Option<u32> {
+        self.field(StatxFields::MODE, || {
+            u32::from(self.statx.stx_mode) & !libc::S_IFMT
+        })
+    }
+
+    /// Number of hard links.
+    pub fn nlink(&self) -> Option<u64> {
+        self.field(StatxFields::NLINK, || u64::from(self.statx.stx_nlink))
+    }
+
+    /// User id of the owner.
+    pub fn uid(&self) -> Option<u32> {
+        self.field(StatxFields::UID, || self.statx.stx_uid)
+    }
+
+    /// Group id of the owner.
+    pub fn gid(&self) -> Option<u32> {
+        self.field(StatxFields::GID, || self.statx.stx_gid)
+    }
+
+    /// Last access time.
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.field(StatxFields::ATIME, || system_time(&self.statx.stx_atime))
+    }
+
+    /// Last modification time.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.field(StatxFields::MTIME, || system_time(&self.statx.stx_mtime))
+    }
+
+    /// Last status change time.
+    pub fn changed(&self) -> Option<SystemTime> {
+        self.field(StatxFields::CTIME, || system_time(&self.statx.stx_ctime))
+    }
+
+    /// Creation time.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.field(StatxFields::BTIME, || system_time(&self.statx.stx_btime))
+    }
+
+    /// Inode number.
+    pub fn ino(&self) -> Option<u64> {
+        self.field(StatxFields::INO, || self.statx.stx_ino)
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> Option<u64> {
+        self.field(StatxFields::SIZE, || self.statx.stx_size)
+    }
+
+    /// Number of 512 byte blocks allocated.
+    pub fn blocks(&self) -> Option<u64> {
+        self.field(StatxFields::BLOCKS, || self.statx.stx_blocks)
+    }
+
+    /// Major and minor number of the device holding the entry, always
+    /// returned.
+    pub fn dev(&self) -> (u32, u32) {
+        (self.statx.stx_dev_major, self.statx.stx_dev_minor)
+    }
+
+    fn field<T>(&self, field: StatxFields, value: impl FnOnce() -> T) -> Option<T> {
+        if self.fields().contains(field) {
+            Some(value())
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Debug for Statx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Statx")
+            .field("mode", &self.mode())
+            .field("size", &self.size())
+            .field("modified", &self.modified())
+            .finish_non_exhaustive()
+    }
+}
+
+fn system_time(timestamp: &libc::statx_timestamp) -> SystemTime {
+    let nanos = Duration::from_nanos(u64::from(timestamp.tv_nsec));
+    if timestamp.tv_sec >= 0 {
+        UNIX_EPOCH + Duration::from_secs(timestamp.tv_sec as u64) + nanos
+    } else {
+        UNIX_EPOCH - Duration::from_secs(timestamp.tv_sec.unsigned_abs()) + nanos
+    }
+}
+
+// Reads `fields` of the entry at `path`, of the target of a symlink if
+// `follow_link` is `true`.
+pub(crate) fn statx(path: &Path, fields: StatxFields, follow_link: bool) -> io::Result<Statx> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut flags = 0;
+    if !follow_link {
+        flags |= libc::AT_SYMLINK_NOFOLLOW;
+    }
+    if fields.dont_sync {
+        flags |= libc::AT_STATX_DONT_SYNC;
+    }
+    let mut statx = MaybeUninit::<libc::statx>::uninit();
+    // SAFETY: `path` is a valid C string and `statx` is large enough to hold
+    // the structure written by the call.
+    let statx = unsafe {
+        if libc::statx(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            flags,
+            fields.mask,
+            statx.as_mut_ptr(),
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        statx.assume_init()
+    };
+    Ok(Statx { statx })
+}
@@ -0,0 +1,70 @@
+//! Async streams of entries, available with the `tokio` feature.
+//!
+//! [`WalkDirGeneric::into_stream`](../struct.WalkDirGeneric.html#method.into_stream)
+//! runs the walk on its own thread and passes entries to a
+//! [`DirEntryStream`](struct.DirEntryStream.html), so async code can consume
+//! them without blocking a runtime worker thread.
+//!
+//! ```no_run
+//! # async fn walk() {
+//! use futures_core::Stream;
+//! use jwalk::WalkDir;
+//! use std::pin::Pin;
+//!
+//! let mut stream = WalkDir::new(".").into_stream();
+//! while let Some(dir_entry) =
+//!     std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+//! {
+//!     println!("{}", dir_entry.unwrap().path().display());
+//! }
+//! # }
+//! ```
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::{ClientState, DirEntry, Result, WalkDirGeneric};
+
+// Entries buffered ahead of a slow consumer
+const STREAM_BUFFER: usize = 256;
+
+/// Stream of entries from
+/// [`WalkDirGeneric::into_stream`](../struct.WalkDirGeneric.html#method.into_stream).
+///
+/// Dropping the stream stops the walk.
+#[derive(Debug)]
+pub struct DirEntryStream<C: ClientState> {
+    receiver: mpsc::Receiver<Result<DirEntry<C>>>,
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Walk on a new thread and yield the entries of
+    /// [`into_iter`](struct.WalkDirGeneric.html#method.into_iter) as an async
+    /// stream, in the same order.
+    ///
+    /// Doesn't need to be called from within a runtime.
+    pub fn into_stream(self) -> DirEntryStream<C> {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        thread::spawn(move || {
+            for dir_entry_result in self {
+                if sender.blocking_send(dir_entry_result).is_err() {
+                    // The stream was dropped
+                    break;
+                }
+            }
+        });
+        DirEntryStream { receiver }
+    }
+}
+
+impl<C: ClientState> Stream for DirEntryStream<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
@@ -0,0 +1,173 @@
+//! Walks yielding UTF-8 paths, available with the `camino` feature.
+//!
+//! [`Utf8WalkDir`](type.Utf8WalkDir.html) wraps [`WalkDir`](../type.WalkDir.html)
+//! and yields [`Utf8DirEntry`](struct.Utf8DirEntry.html)s with
+//! [`camino::Utf8Path`] paths. Entries whose path isn't valid UTF-8 are
+//! reported as errors or skipped, see [`Utf8Policy`](enum.Utf8Policy.html).
+
+use std::io;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{ClientState, DirEntry, DirEntryIter, Error, FileType, Result, WalkDirGeneric};
+
+/// What to do with entries whose path isn't valid UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Utf8Policy {
+    /// Yield an error with the path of the entry. Contents of such directories
+    /// are still walked and produce errors as well.
+    Error,
+    /// Skip the entry and, for directories, all of their contents.
+    Skip,
+}
+
+/// Builder for walking a directory yielding UTF-8 paths.
+pub type Utf8WalkDir = Utf8WalkDirGeneric<((), ())>;
+
+/// Generic builder for walking a directory yielding UTF-8 paths.
+///
+/// Wraps a [`WalkDirGeneric`](../struct.WalkDirGeneric.html) which can be
+/// configured with
+/// [`walk_dir`](struct.Utf8WalkDirGeneric.html#method.walk_dir).
+pub struct Utf8WalkDirGeneric<C: ClientState> {
+    walk_dir: WalkDirGeneric<C>,
+    policy: Utf8Policy,
+}
+
+/// A directory entry with a UTF-8 path.
+#[derive(Debug)]
+pub struct Utf8DirEntry<C: ClientState> {
+    dir_entry: DirEntry<C>,
+    path: Utf8PathBuf,
+}
+
+/// Iterator yielding [`Utf8DirEntry`](struct.Utf8DirEntry.html)s.
+pub struct Utf8DirEntryIter<C: ClientState> {
+    dir_entry_iter: DirEntryIter<C>,
+    policy: Utf8Policy,
+}
+
+impl<C: ClientState> Utf8WalkDirGeneric<C> {
+    /// Create a builder for a recursive directory iterator starting at the
+    /// file path root, see
+    /// [`WalkDirGeneric::new`](../struct.WalkDirGeneric.html#method.new).
+    pub fn new<P: AsRef<Utf8Path>>(root: P) -> Self {
+        Utf8WalkDirGeneric::from(WalkDirGeneric::new(root.as_ref()))
+    }
+
+    /// What to do with entries whose path isn't valid UTF-8. Defaults to
+    /// [`Utf8Policy::Error`](enum.Utf8Policy.html#variant.Error).
+    pub fn policy(mut self, policy: Utf8Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Configure the wrapped [`WalkDirGeneric`](../struct.WalkDirGeneric.html).
+    pub fn walk_dir<F>(mut self, configure: F) -> Self
+    where
+        F: FnOnce(WalkDirGeneric<C>) -> WalkDirGeneric<C>,
+    {
+        self.walk_dir = configure(self.walk_dir);
+        self
+    }
+
+    /// Try to create an iterator, see
+    /// [`WalkDirGeneric::try_into_iter`](../struct.WalkDirGeneric.html#method.try_into_iter).
+    pub fn try_into_iter(self) -> Result<Utf8DirEntryIter<C>> {
+        Ok(Utf8DirEntryIter {
+            dir_entry_iter: self.walk_dir.try_into_iter()?,
+            policy: self.policy,
+        })
+    }
+}
+
+impl<C: ClientState> From<WalkDirGeneric<C>> for Utf8WalkDirGeneric<C> {
+    fn from(walk_dir: WalkDirGeneric<C>) -> Self {
+        Utf8WalkDirGeneric {
+            walk_dir,
+            policy: Utf8Policy::Error,
+        }
+    }
+}
+
+impl<C: ClientState> IntoIterator for Utf8WalkDirGeneric<C> {
+    type Item = Result<Utf8DirEntry<C>>;
+    type IntoIter = Utf8DirEntryIter<C>;
+
+    fn into_iter(self) -> Utf8DirEntryIter<C> {
+        Utf8DirEntryIter {
+            dir_entry_iter: self.walk_dir.into_iter(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<C: ClientState> Utf8DirEntry<C> {
+    /// Path to the file that this entry represents.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// The file name of this entry.
+    pub fn file_name(&self) -> &str {
+        self.path.file_name().unwrap_or_else(|| self.path.as_str())
+    }
+
+    /// Returns the depth at which this entry was created relative to the
+    /// root.
+    pub fn depth(&self) -> usize {
+        self.dir_entry.depth
+    }
+
+    /// Return the file type for the file that this entry points to.
+    pub fn file_type(&self) -> FileType {
+        self.dir_entry.file_type
+    }
+
+    /// The wrapped entry.
+    pub fn dir_entry(&self) -> &DirEntry<C> {
+        &self.dir_entry
+    }
+
+    /// Mutable access to the wrapped entry, for example to its
+    /// [`client_state`](../struct.DirEntry.html#structfield.client_state).
+    pub fn dir_entry_mut(&mut self) -> &mut DirEntry<C> {
+        &mut self.dir_entry
+    }
+
+    /// Consume this entry, returning the wrapped entry.
+    pub fn into_dir_entry(self) -> DirEntry<C> {
+        self.dir_entry
+    }
+
+    /// Consume this entry, returning its path.
+    pub fn into_path(self) -> Utf8PathBuf {
+        self.path
+    }
+}
+
+impl<C: ClientState> Iterator for Utf8DirEntryIter<C> {
+    type Item = Result<Utf8DirEntry<C>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let dir_entry = match self.dir_entry_iter.next()? {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => return Some(Err(err)),
+            };
+            match Utf8PathBuf::from_path_buf(dir_entry.path()) {
+                Ok(path) => return Some(Ok(Utf8DirEntry { dir_entry, path })),
+                Err(path) => match self.policy {
+                    Utf8Policy::Error => {
+                        return Some(Err(Error::from_path(
+                            dir_entry.depth,
+                            path,
+                            io::Error::new(io::ErrorKind::InvalidData, "path is not valid UTF-8"),
+                        )))
+                    }
+                    Utf8Policy::Skip => continue,
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Parallel visitors with per-thread state.
+//!
+//! [`WalkDirGeneric::visit`](../struct.WalkDirGeneric.html#method.visit)
+//! passes the entries of a walk to visitors on the threads reading
+//! directories, like `ignore::WalkParallel::visit`, instead of funneling them
+//! through the ordered iterator. Each thread gets its own
+//! [`ParallelVisitor`](trait.ParallelVisitor.html), created by a
+//! [`ParallelVisitorBuilder`](trait.ParallelVisitorBuilder.html) before the
+//! walk starts, so visitors can keep state without synchronizing.
+//! Available with the `rayon` feature:
+//!
+//! ```no_run
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! use jwalk::visit::{ParallelVisitor, WalkState};
+//! use jwalk::{DirEntry, Result, WalkDir};
+//!
+//! struct Counter<'a> {
+//!     files: usize,
+//!     total: &'a AtomicUsize,
+//! }
+//!
+//! impl ParallelVisitor<((), ())> for Counter<'_> {
+//!     fn visit(&mut self, dir_entry: Result<DirEntry<((), ())>>) -> WalkState {
+//!         if dir_entry.is_ok_and(|dir_entry| dir_entry.file_type.is_file()) {
+//!             self.files += 1;
+//!         }
+//!         WalkState::Continue
+//!     }
+//! }
+//!
+//! impl Drop for Counter<'_> {
+//!     fn drop(&mut self) {
+//!         self.total.fetch_add(self.files, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let total = AtomicUsize::new(0);
+//! WalkDir::new(".").visit(&mut || {
+//!     Box::new(Counter { files: 0, total: &total }) as Box<dyn ParallelVisitor<_>>
+//! });
+//! println!("{} files", total.into_inner());
+//! ```
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use rayon::iter::ParallelIterator;
+use rayon::ThreadPoolBuilder;
+
+use crate::core::{par_entries, ReadDir, ReadDirCallback};
+use crate::{
+    ClientState, DirEntry, DirEntryIter, Parallelism, PreparedWalk, ReadDirSpec, Result,
+    WalkDirGeneric,
+};
+
+/// What a [`ParallelVisitor`](trait.ParallelVisitor.html) wants the walk to
+/// do next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalkState {
+    /// Continue walking.
+    Continue,
+    /// Don't pass the contents of the visited directory to any visitor. Same
+    /// as `Continue` for entries that aren't directories.
+    Skip,
+    /// Stop the walk as soon as possible. Entries that are already being
+    /// visited on other threads are still visited.
+    Quit,
+}
+
+/// Visitor of the entries passed to one thread by
+/// [`WalkDirGeneric::visit`](../struct.WalkDirGeneric.html#method.visit).
+pub trait ParallelVisitor<C: ClientState>: Send {
+    /// Called with each entry, or error, passed to this visitor.
+    fn visit(&mut self, dir_entry: Result<DirEntry<C>>) -> WalkState;
+}
+
+impl<C, F> ParallelVisitor<C> for F
+where
+    C: ClientState,
+    F: FnMut(Result<DirEntry<C>>) -> WalkState + Send,
+{
+    fn visit(&mut self, dir_entry: Result<DirEntry<C>>) -> WalkState {
+        self(dir_entry)
+    }
+}
+
+/// Creates the [`ParallelVisitor`](trait.ParallelVisitor.html) of each thread
+/// of [`WalkDirGeneric::visit`](../struct.WalkDirGeneric.html#method.visit).
+pub trait ParallelVisitorBuilder<'s, C: ClientState> {
+    /// Create a visitor for another thread.
+    fn build(&mut self) -> Box<dyn ParallelVisitor<C> + 's>;
+}
+
+impl<'s, C, F> ParallelVisitorBuilder<'s, C> for F
+where
+    C: ClientState,
+    F: FnMut() -> Box<dyn ParallelVisitor<C> + 's>,
+{
+    fn build(&mut self) -> Box<dyn ParallelVisitor<C> + 's> {
+        self()
+    }
+}
+
+// Visitor of each thread of a pool, indexed by `rayon::current_thread_index`.
+type Visitors<'s, C> = Vec<Mutex<Box<dyn ParallelVisitor<C> + 's>>>;
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Pass the entries of the walk to visitors created by `builder`, one for
+    /// each thread of the pool configured with
+    /// [`parallelism`](struct.WalkDirGeneric.html#method.parallelism), see
+    /// the [`visit`](visit/index.html) module. Returns once the walk
+    /// finished or a visitor returned
+    /// [`WalkState::Quit`](visit/enum.WalkState.html#variant.Quit), and all
+    /// visitors were dropped.
+    ///
+    /// Entries are passed in no particular order, from the threads reading
+    /// directories, like with
+    /// [`par_entries`](struct.WalkDirGeneric.html#method.par_entries). With
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) a single
+    /// visitor is created and entries are passed in the order of the
+    /// iterator.
+    pub fn visit<'s>(self, builder: &mut dyn ParallelVisitorBuilder<'s, C>) {
+        match self.options.parallelism.clone() {
+            Parallelism::Serial => visit_serial(self.into_iter(), builder.build()),
+            Parallelism::RayonDefaultPool { .. } | Parallelism::Adaptive { .. } => {
+                let visitors = build_visitors(builder, rayon::current_num_threads());
+                self.visit_on_current_pool(&visitors);
+            }
+            Parallelism::StdThreads(_) => {
+                let visitors = build_visitors(builder, rayon::current_num_threads());
+                self.visit_on_current_pool(&visitors);
+            }
+            Parallelism::RayonExistingPool { pool, .. } => {
+                let visitors = build_visitors(builder, pool.current_num_threads());
+                pool.install(|| self.visit_on_current_pool(&visitors));
+            }
+            Parallelism::RayonNewPool(num_threads) => {
+                let mut thread_pool = ThreadPoolBuilder::new();
+                if num_threads > 0 {
+                    thread_pool = thread_pool.num_threads(num_threads);
+                }
+                match thread_pool.build() {
+                    Ok(thread_pool) => {
+                        let visitors = build_visitors(builder, thread_pool.current_num_threads());
+                        thread_pool.install(|| self.visit_on_current_pool(&visitors));
+                    }
+                    Err(_) => {
+                        let visitors = build_visitors(builder, rayon::current_num_threads());
+                        self.visit_on_current_pool(&visitors);
+                    }
+                }
+            }
+        }
+    }
+
+    // Walks like `par_entries`, passing entries to the visitor of the thread
+    // they are produced on.
+    fn visit_on_current_pool(self, visitors: &Visitors<'_, C>) {
+        let PreparedWalk {
+            root_entry_results,
+            schedule,
+            min_depth,
+            root_read_dir_state,
+            read_dir_callback,
+            ..
+        } = self.prepare(None);
+
+        // Directories skipped by a visitor aren't read anymore, their
+        // contents that were already read aren't visited
+        let skipped_dirs: Arc<RwLock<Vec<Arc<Path>>>> = Default::default();
+        let quit = Arc::new(AtomicBool::new(false));
+        let read_dir_callback: Arc<ReadDirCallback<C>> = {
+            let skipped_dirs = skipped_dirs.clone();
+            let quit = quit.clone();
+            Arc::new(move |read_dir_spec: ReadDirSpec<C>| {
+                if quit.load(Ordering::Relaxed) || is_skipped(&read_dir_spec.path, &skipped_dirs) {
+                    return Ok(ReadDir::new(read_dir_spec.client_read_state, Vec::new()));
+                }
+                read_dir_callback(read_dir_spec)
+            })
+        };
+
+        par_entries(
+            root_entry_results,
+            min_depth,
+            root_read_dir_state,
+            read_dir_callback,
+            schedule.concurrency_limit,
+        )
+        .for_each(|dir_entry_result| {
+            if quit.load(Ordering::Relaxed) {
+                return;
+            }
+            let read_children_path = match &dir_entry_result {
+                Ok(dir_entry) if is_skipped(&dir_entry.parent_path, &skipped_dirs) => return,
+                Ok(dir_entry) => dir_entry.read_children_path.clone(),
+                Err(_) => None,
+            };
+            let index = rayon::current_thread_index().unwrap_or(0) % visitors.len();
+            let walk_state = visitors[index].lock().unwrap().visit(dir_entry_result);
+            match walk_state {
+                WalkState::Continue => {}
+                WalkState::Skip => skipped_dirs.write().unwrap().extend(read_children_path),
+                WalkState::Quit => quit.store(true, Ordering::Relaxed),
+            }
+        });
+    }
+}
+
+fn build_visitors<'s, C: ClientState>(
+    builder: &mut dyn ParallelVisitorBuilder<'s, C>,
+    num_threads: usize,
+) -> Visitors<'s, C> {
+    (0..num_threads.max(1))
+        .map(|_| Mutex::new(builder.build()))
+        .collect()
+}
+
+fn visit_serial<C: ClientState>(
+    mut dir_entry_iter: DirEntryIter<C>,
+    mut visitor: Box<dyn ParallelVisitor<C> + '_>,
+) {
+    while let Some(dir_entry_result) = dir_entry_iter.next() {
+        let is_dir = dir_entry_result
+            .as_ref()
+            .is_ok_and(|dir_entry| dir_entry.read_children_path.is_some());
+        match visitor.visit(dir_entry_result) {
+            WalkState::Continue => {}
+            // Contents of directories yielded after them were already passed
+            WalkState::Skip if is_dir && !dir_entry_iter.contents_first => {
+                dir_entry_iter.skip_current_dir()
+            }
+            WalkState::Skip => {}
+            WalkState::Quit => break,
+        }
+    }
+}
+
+fn is_skipped(path: &Path, skipped_dirs: &RwLock<Vec<Arc<Path>>>) -> bool {
+    skipped_dirs
+        .read()
+        .unwrap()
+        .iter()
+        .any(|skipped_dir| path.starts_with(skipped_dir))
+}
@@ -0,0 +1,154 @@
+//! Walks that keep reporting changes, available with the `watch` feature.
+//!
+//! [`WalkDirGeneric::watch`](../struct.WalkDirGeneric.html#method.watch)
+//! starts watching the roots of a walk, then yields the entries of the walk
+//! as [`WatchEvent::Existing`](enum.WatchEvent.html#variant.Existing)
+//! followed by the changes reported by the file system as they happen. This
+//! gives indexers a single stream without missing changes made during the
+//! initial walk.
+//!
+//! ```no_run
+//! use jwalk::watch::WatchEvent;
+//! use jwalk::WalkDir;
+//!
+//! for event in WalkDir::new(".").watch()? {
+//!     match event? {
+//!         WatchEvent::Existing(dir_entry) | WatchEvent::Created(dir_entry) => {
+//!             println!("index {}", dir_entry.path().display())
+//!         }
+//!         WatchEvent::Modified(dir_entry) => println!("update {}", dir_entry.path().display()),
+//!         WatchEvent::Removed(path) => println!("remove {}", path.display()),
+//!     }
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{ClientState, DirEntry, DirEntryIter, Error, Result, WalkDirGeneric};
+
+/// Item of [`WatchIter`](struct.WatchIter.html).
+#[derive(Debug)]
+pub enum WatchEvent<C: ClientState> {
+    /// An entry yielded by the initial walk.
+    Existing(DirEntry<C>),
+    /// An entry was created or moved into the watched tree after the walk
+    /// started.
+    Created(DirEntry<C>),
+    /// The contents or metadata of an entry were changed.
+    Modified(DirEntry<C>),
+    /// The entry at this path was removed or moved out of the watched tree.
+    Removed(PathBuf),
+}
+
+/// Iterator from
+/// [`WalkDirGeneric::watch`](../struct.WalkDirGeneric.html#method.watch).
+///
+/// Blocks waiting for changes once the initial walk was yielded, and never
+/// ends. Dropping it stops watching.
+pub struct WatchIter<C: ClientState> {
+    dir_entry_iter: Option<DirEntryIter<C>>,
+    roots: Vec<PathBuf>,
+    follow_links: bool,
+    receiver: mpsc::Receiver<notify::Result<notify::Event>>,
+    pending: VecDeque<Result<WatchEvent<C>>>,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Watch all roots for changes, then walk them and yield the entries
+    /// followed by the changes, see the [`watch`](watch/index.html) module.
+    ///
+    /// Changes are reported for all paths in the watched trees, the options
+    /// of the walk only apply to the initial walk. Fails if a root can't be
+    /// watched.
+    pub fn watch(self) -> Result<WatchIter<C>> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(notify_error)?;
+        let roots: Vec<PathBuf> = self.roots().map(Path::to_path_buf).collect();
+        for root in &roots {
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .map_err(|err| Error::from_path(0, root.clone(), notify_io_error(err)))?;
+        }
+        let follow_links = self.options.follow_links;
+        Ok(WatchIter {
+            dir_entry_iter: Some(self.into_iter()),
+            roots,
+            follow_links,
+            receiver,
+            pending: VecDeque::new(),
+            _watcher: watcher,
+        })
+    }
+}
+
+impl<C: ClientState> WatchIter<C> {
+    // Queues the changes of `event`.
+    fn push_event(&mut self, event: notify::Event) {
+        for path in event.paths {
+            let exists = path.symlink_metadata().is_ok();
+            let watch_event = match event.kind {
+                EventKind::Create(_) if exists => self.entry(&path).map(WatchEvent::Created),
+                EventKind::Modify(ModifyKind::Name(_)) if exists => {
+                    self.entry(&path).map(WatchEvent::Created)
+                }
+                EventKind::Modify(ModifyKind::Name(_)) | EventKind::Remove(_) => {
+                    Ok(WatchEvent::Removed(path))
+                }
+                EventKind::Modify(_) if exists => self.entry(&path).map(WatchEvent::Modified),
+                // Access events, or the entry is already gone again
+                _ => continue,
+            };
+            self.pending.push_back(watch_event);
+        }
+    }
+
+    fn entry(&self, path: &Path) -> Result<DirEntry<C>> {
+        let depth = self
+            .roots
+            .iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .map(|relative_path| relative_path.components().count())
+            .min()
+            .unwrap_or(0);
+        DirEntry::from_path(depth, path, self.follow_links, Arc::new(Vec::new()), None)
+    }
+}
+
+impl<C: ClientState> Iterator for WatchIter<C> {
+    type Item = Result<WatchEvent<C>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(dir_entry_iter) = self.dir_entry_iter.as_mut() {
+            match dir_entry_iter.next() {
+                Some(dir_entry_result) => return Some(dir_entry_result.map(WatchEvent::Existing)),
+                None => self.dir_entry_iter = None,
+            }
+        }
+        loop {
+            if let Some(watch_event) = self.pending.pop_front() {
+                return Some(watch_event);
+            }
+            match self.receiver.recv().ok()? {
+                Ok(event) => self.push_event(event),
+                Err(err) => return Some(Err(notify_error(err))),
+            }
+        }
+    }
+}
+
+fn notify_io_error(err: notify::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+fn notify_error(err: notify::Error) -> Error {
+    Error::from_io(0, notify_io_error(err))
+}
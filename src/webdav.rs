@@ -0,0 +1,347 @@
+//! Walking WebDAV shares, available with the `webdav` feature.
+//!
+//! [`WebDavFs`](struct.WebDavFs.html) is a
+//! [`FileSystem`](../file_system/trait.FileSystem.html) listing collections
+//! with a `PROPFIND` request of depth 1 each, so shares such as Nextcloud or
+//! SharePoint are walked with the same builder and iterator types as local
+//! trees, reading several collections in parallel.
+//!
+//! ```no_run
+//! use jwalk::webdav::WebDavFs;
+//!
+//! let share = WebDavFs::new("https://cloud.example.com/remote.php/dav/files/alice")
+//!     .header("Authorization", "Bearer token");
+//! for dir_entry in share.walk_dir::<((), ())>("/Documents") {
+//!     let dir_entry = dir_entry?;
+//!     let properties = share.properties(&dir_entry.path());
+//!     println!("{} {:?}", dir_entry.path().display(), properties);
+//! }
+//! # Ok::<(), jwalk::Error>(())
+//! ```
+//!
+//! Paths of a walk are relative to the URL of the share, with a leading `/`.
+//! `std::fs::Metadata` can't be built from WebDAV properties, so the size and
+//! modification time listed for each entry are kept apart and read with
+//! [`WebDavFs::properties`](struct.WebDavFs.html#method.properties).
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::file_system::{FileSystem, FileSystemEntry, FileType, ReadDirEntries};
+use crate::{ClientState, WalkDirGeneric};
+
+// Properties requested for each entry
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop>
+</d:propfind>"#;
+
+/// File system of a WebDAV share, see the [`webdav`](index.html) module.
+#[derive(Clone)]
+pub struct WebDavFs {
+    // URL of the share without trailing `/`
+    url: String,
+    agent: ureq::Agent,
+    headers: Vec<(String, String)>,
+    properties: Arc<Mutex<HashMap<PathBuf, WebDavProperties>>>,
+}
+
+/// Properties of an entry listed by a [`WebDavFs`](struct.WebDavFs.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WebDavProperties {
+    /// Size of a file in bytes, `getcontentlength`.
+    pub content_length: Option<u64>,
+    /// Modification time, `getlastmodified`.
+    pub last_modified: Option<SystemTime>,
+}
+
+// Entry of a `PROPFIND` response
+#[derive(Debug, Default)]
+struct Response {
+    href: String,
+    is_collection: bool,
+    properties: WebDavProperties,
+}
+
+impl WebDavFs {
+    /// Walk the share at `url`.
+    pub fn new(url: &str) -> WebDavFs {
+        WebDavFs {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::Agent::new(),
+            headers: Vec::new(),
+            properties: Default::default(),
+        }
+    }
+
+    /// Send requests with `agent`, to configure timeouts, proxies or TLS.
+    pub fn agent(mut self, agent: ureq::Agent) -> WebDavFs {
+        self.agent = agent;
+        self
+    }
+
+    /// Send the header `name` with every request, such as `Authorization`.
+    pub fn header(mut self, name: &str, value: &str) -> WebDavFs {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Create a builder for a walk of this share starting at `root`.
+    pub fn walk_dir<C: ClientState>(&self, root: impl AsRef<Path>) -> WalkDirGeneric<C> {
+        WalkDirGeneric::new(root).file_system(Some(Arc::new(self.clone())))
+    }
+
+    /// Properties of the entry at `path`, if it was listed by a walk of this
+    /// share or one of its clones.
+    ///
+    /// Properties are kept for every listed entry until
+    /// [`clear_properties`](struct.WebDavFs.html#method.clear_properties) is
+    /// called.
+    pub fn properties(&self, path: &Path) -> Option<WebDavProperties> {
+        self.properties.lock().unwrap().get(path).cloned()
+    }
+
+    /// Forget the properties of the entries listed so far.
+    pub fn clear_properties(&self) {
+        self.properties.lock().unwrap().clear();
+    }
+
+    // The responses of a `PROPFIND` request for `path`, with `depth` 0 for
+    // the entry itself or 1 for its children as well.
+    fn propfind(&self, path: &Path, depth: &str) -> io::Result<Vec<Response>> {
+        let mut request = self
+            .agent
+            .request("PROPFIND", &format!("{}{}", self.url, encode_path(path)?))
+            .set("Depth", depth)
+            .set("Content-Type", "application/xml; charset=utf-8");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        let response = request
+            .send_string(PROPFIND_BODY)
+            .map_err(|err| match err {
+                ureq::Error::Status(status, _) => io::Error::new(
+                    match status {
+                        401 | 403 => io::ErrorKind::PermissionDenied,
+                        404 | 410 => io::ErrorKind::NotFound,
+                        _ => io::ErrorKind::Other,
+                    },
+                    format!("PROPFIND {} failed with status {}", path.display(), status),
+                ),
+                ureq::Error::Transport(transport) => io::Error::other(transport),
+            })?;
+        let mut body = String::new();
+        response.into_reader().read_to_string(&mut body)?;
+        parse_multistatus(&body)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+}
+
+impl FileSystem for WebDavFs {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+        let request_path = decoded_path(&format!("{}{}", self.url, encode_path(path)?));
+        let mut entries = Vec::new();
+        let mut properties = Vec::new();
+        for response in self.propfind(path, "1")? {
+            let href_path = decoded_path(&response.href);
+            // The collection itself is listed along with its children
+            if href_path == request_path {
+                continue;
+            }
+            let file_name = match href_path.rsplit('/').next() {
+                Some(file_name) if !file_name.is_empty() => OsString::from(file_name),
+                _ => continue,
+            };
+            properties.push((path.join(&file_name), response.properties));
+            entries.push(Ok(FileSystemEntry {
+                file_name,
+                file_type: Ok(if response.is_collection {
+                    FileType::dir()
+                } else {
+                    FileType::file()
+                }),
+            }));
+        }
+        self.properties.lock().unwrap().extend(properties);
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn file_type(&self, path: &Path, _follow_links: bool) -> io::Result<FileType> {
+        let response = self
+            .propfind(path, "0")?
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty PROPFIND response"))?;
+        let is_collection = response.is_collection;
+        self.properties
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), response.properties);
+        Ok(if is_collection {
+            FileType::dir()
+        } else {
+            FileType::file()
+        })
+    }
+
+    fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "WebDAV shares have no symlinks",
+        ))
+    }
+}
+
+// The responses of a `207 Multi-Status` body, ignoring namespace prefixes.
+fn parse_multistatus(body: &str) -> quick_xml::Result<Vec<Response>> {
+    let mut reader = Reader::from_str(body);
+    reader.trim_text(true);
+    let mut responses = Vec::new();
+    let mut response: Option<Response> = None;
+    // Innermost open element
+    let mut element = Vec::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let name = start.local_name();
+                match name.as_ref() {
+                    b"response" => response = Some(Response::default()),
+                    b"collection" => {
+                        if let Some(response) = response.as_mut() {
+                            response.is_collection = true;
+                        }
+                    }
+                    _ => {}
+                }
+                element = name.as_ref().to_vec();
+            }
+            Event::Empty(empty) if empty.local_name().as_ref() == b"collection" => {
+                if let Some(response) = response.as_mut() {
+                    response.is_collection = true;
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                if let Some(response) = response.as_mut() {
+                    match element.as_slice() {
+                        b"href" => response.href = text.into_owned(),
+                        b"getcontentlength" => {
+                            response.properties.content_length = text.trim().parse().ok()
+                        }
+                        b"getlastmodified" => {
+                            response.properties.last_modified = parse_http_date(&text)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::End(end) => {
+                if end.local_name().as_ref() == b"response" {
+                    responses.extend(response.take());
+                }
+                element.clear();
+            }
+            Event::Eof => return Ok(responses),
+            _ => {}
+        }
+    }
+}
+
+// `path` as the percent-encoded path of a URL, with a leading `/` for each
+// component.
+fn encode_path(path: &Path) -> io::Result<String> {
+    let mut encoded = String::new();
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            let name = name.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "WebDAV paths are valid unicode",
+                )
+            })?;
+            encoded.push('/');
+            for byte in name.bytes() {
+                match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        encoded.push(byte as char)
+                    }
+                    _ => encoded.push_str(&format!("%{:02X}", byte)),
+                }
+            }
+        }
+    }
+    Ok(encoded)
+}
+
+// The decoded path of `href`, an absolute URL or path, without trailing `/`.
+fn decoded_path(href: &str) -> String {
+    let path = match href.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => href,
+    };
+    let bytes = path.trim_end_matches('/').as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Parses an RFC 1123 date such as `Sun, 06 Nov 1994 08:49:37 GMT`, the
+// format of `getlastmodified`.
+fn parse_http_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts
+        .next()?
+        .split(':')
+        .map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if parts.next() != Some("GMT") || year < 1970 || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch of the civil date, from Howard Hinnant's
+    // `days_from_civil`
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+
+    let seconds = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
@@ -8,7 +8,7 @@ fn works() {
         .build_global()
         .expect("Failed to initialize worker thread pool");
     // Does not finish if jwalk uses shared pool with 1 thread, but we can detect this issue and signal this with an error.
-    (0..=1)
+    (0..=3)
         .collect::<Vec<usize>>()
         .par_iter()
         .for_each(|round| {
@@ -19,12 +19,24 @@ fn works() {
                 for entry in generic {
                     match entry {
                         Ok(_) => panic!("Must detect deadlock"),
-                        Err(err) if err.is_busy() => {}
+                        Err(err) if err.is_busy() => {
+                            assert_eq!(
+                                err.busy_timeout(),
+                                Some(std::time::Duration::from_millis(10))
+                            );
+                            assert_eq!(err.busy_pool_threads(), Some(1));
+                        }
                         Err(err) => panic!("Unexpected error: {:?}", err),
                     }
                 }
-            } else {
+            } else if *round == 1 {
                 assert!(matches!(generic.try_into_iter(), Err(err) if err.is_busy()));
+            } else if *round == 2 {
+                let generic = generic.busy_policy(jwalk::BusyPolicy::FallbackSerial);
+                assert!(generic.into_iter().all(|entry| entry.is_ok()));
+            } else {
+                let generic = generic.busy_policy(jwalk::BusyPolicy::FallbackNewPool(2));
+                assert!(generic.into_iter().all(|entry| entry.is_ok()));
             }
         });
 }
@@ -591,8 +591,17 @@ fn sym_loop_detect() {
     let expected = dir.join("a");
     assert_eq!(Some(&*expected), err.loop_ancestor());
 
+    let loop_detected = err.loop_detected().unwrap();
+    assert_eq!(loop_detected.ancestor, dir.join("a"));
+    assert_eq!(loop_detected.child, dir.join("a/b/c/a-link"));
+    assert_eq!(
+        loop_detected.chain,
+        vec![dir.join("a"), dir.join("a/b"), dir.join("a/b/c")]
+    );
+
     assert_eq!(4, err.depth());
     assert!(err.io_error().is_none());
+    assert_eq!(ErrorKind::Loop, err.kind());
 }
 
 #[test]
@@ -660,6 +669,88 @@ fn sym_dir_self_loop_io_error() {
     assert_eq!(1, err.depth());
     assert!(err.loop_ancestor().is_none());
     assert!(err.io_error().is_some());
+    assert_eq!(ErrorKind::MetadataFailed, err.kind());
+}
+
+#[test]
+fn error_kind_not_found() {
+    let dir = Dir::tmp();
+    let missing = dir.join("missing");
+
+    let err = WalkDir::new(&missing)
+        .into_iter()
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(ErrorKind::NotFound, err.kind());
+    assert_eq!(Some(missing.as_path()), err.path());
+    assert_eq!(0, err.depth());
+}
+
+#[test]
+fn multiple_roots() {
+    let dir = Dir::tmp();
+    dir.mkdirp("one/sub");
+    dir.touch("one/sub/a");
+    dir.mkdirp("two");
+    dir.touch("two/b");
+    dir.touch("two/c");
+
+    let wd = WalkDir::new_multi([dir.join("two"), dir.join("one")])
+        .exclude_glob(jwalk::pattern::Pattern::new("c").unwrap())
+        .sort(true);
+    assert_eq!(
+        wd.roots().collect::<Vec<_>>(),
+        vec![dir.join("two"), dir.join("one")]
+    );
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.join("two"),
+        dir.join("two/b"),
+        dir.join("one"),
+        dir.join("one/sub"),
+        dir.join("one/sub/a"),
+    ];
+    assert_eq!(expected, r.paths());
+    let depths: Vec<usize> = r.ents().iter().map(|dir_entry| dir_entry.depth).collect();
+    assert_eq!(depths, vec![0, 1, 0, 1, 2]);
+}
+
+#[test]
+#[cfg(feature = "checkpoint")]
+fn checkpoint_and_resume() {
+    use jwalk::checkpoint::WalkCheckpoint;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let all = local_paths(WalkDir::new(&test_dir).sort(true));
+    for taken in 0..=all.len() {
+        let mut iter = WalkDir::new(&test_dir).sort(true).into_iter();
+        let mut paths: Vec<_> = iter
+            .by_ref()
+            .take(taken)
+            .map(|e| e.unwrap().path())
+            .collect();
+
+        let mut bytes = Vec::new();
+        iter.checkpoint().write_to(&mut bytes).unwrap();
+        let checkpoint = WalkCheckpoint::read_from(&bytes[..]).unwrap();
+        assert_eq!(checkpoint, iter.checkpoint());
+        assert_eq!(checkpoint.roots(), std::slice::from_ref(&test_dir));
+        assert_eq!(checkpoint.is_complete(), taken == all.len());
+
+        let resumed = WalkDir::resume(checkpoint).sort(true);
+        paths.extend(resumed.into_iter().map(|e| e.unwrap().path()));
+        let paths: Vec<_> = paths
+            .iter()
+            .map(|path| path.strip_prefix(&test_dir).unwrap().to_str().unwrap())
+            .collect();
+        let expected: Vec<_> = all
+            .iter()
+            .map(|path| path.rsplit_once(" (").unwrap().0)
+            .collect();
+        assert_eq!(paths, expected);
+    }
 }
 
 #[test]
@@ -1141,3 +1232,3895 @@ fn test_read_linux() {
         }
     }
 }
+
+#[test]
+fn priority_paths_keep_yield_order() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .priority_paths([test_dir.join("group 2")])
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn schedule_root_children_by_keeps_yield_order() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .schedule_root_children_by(|a, b| b.file_name.cmp(&a.file_name))
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn exclude_paths() {
+    let (test_dir, _temp_dir) = test_dir();
+    let canonical_test_dir = fs::canonicalize(&test_dir).unwrap();
+    let exclude_paths = [
+        canonical_test_dir.join("b.txt"),
+        canonical_test_dir.join("group 1"),
+    ];
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .exclude_paths(exclude_paths.into_iter().collect())
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "c.txt (1)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+
+    // Directories are canonicalized by the file system they are read from
+    let mut memory_fs = jwalk::memfs::MemoryFs::new();
+    memory_fs.add_file("/virtual/real/a");
+    memory_fs.add_file("/virtual/real/b");
+    memory_fs.add_symlink("/virtual/link", "/virtual/real");
+    let paths: Vec<_> = memory_fs
+        .walk_dir::<((), ())>("/virtual/link")
+        .exclude_paths([PathBuf::from("/virtual/real/a")].into_iter().collect())
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("/virtual/link"),
+            PathBuf::from("/virtual/link/b")
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn exclude_devices() {
+    use std::os::unix::fs::MetadataExt;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let device = fs::metadata(&test_dir).unwrap().dev();
+    let paths = local_paths(WalkDir::new(&test_dir).exclude_devices([device]).sort(true));
+    assert_eq!(paths, vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)"]);
+}
+
+#[test]
+fn same_file_system() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let paths = local_paths(WalkDir::new(&test_dir).same_file_system(true).sort(true));
+    assert_eq!(paths, expected);
+}
+
+#[test]
+fn same_file_system_unknown_device() {
+    use jwalk::memfs::MemoryFs;
+
+    // Directories whose device can't be read, here because they only exist
+    // in memory, are still descended into
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    let mut memory_fs = MemoryFs::new();
+    memory_fs.add_file(root.join("memory/a.txt"));
+    let paths: Vec<_> = memory_fs
+        .walk_dir::<((), ())>(root)
+        .same_file_system(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            root.to_path_buf(),
+            root.join("memory"),
+            root.join("memory/a.txt"),
+        ]
+    );
+}
+
+#[test]
+fn file_size_filters() {
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    fs::write(dir.join("small"), "x").unwrap();
+    fs::write(dir.join("sub/medium"), "x".repeat(10)).unwrap();
+    fs::write(dir.join("large"), "x".repeat(100)).unwrap();
+
+    let wd = WalkDir::new(dir.path())
+        .min_file_size(2)
+        .max_file_size(50)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("sub"),
+        dir.join("sub/medium"),
+    ];
+    assert_eq!(expected, r.paths());
+
+    let wd = WalkDir::new(dir.path()).min_file_size(2).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("large"),
+        dir.join("sub"),
+        dir.join("sub/medium"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn modified_after() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    dir.touch("new");
+    dir.touch("sub/old");
+    let now = SystemTime::now();
+    fs::File::options()
+        .write(true)
+        .open(dir.join("sub/old"))
+        .unwrap()
+        .set_modified(now - Duration::from_secs(3600))
+        .unwrap();
+
+    let wd = WalkDir::new(dir.path())
+        .modified_after(now - Duration::from_secs(60))
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![dir.path().to_path_buf(), dir.join("new"), dir.join("sub")];
+    assert_eq!(expected, r.paths());
+
+    // Nothing changed after an hour from now, so sub isn't read
+    let wd = WalkDir::new(dir.path())
+        .modified_after(now + Duration::from_secs(3600))
+        .prune_unmodified_dirs(true)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join("sub")], r.paths());
+    assert!(r.ents()[1].read_children_path.is_none());
+
+    let wd = WalkDir::new(dir.path())
+        .modified_after(now + Duration::from_secs(3600))
+        .clear_modified_after()
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(4, r.paths().len());
+}
+
+#[test]
+fn cancelable() {
+    let (test_dir, _temp_dir) = test_dir();
+    let (walk_dir, cancel_token) = WalkDir::new(&test_dir).cancelable();
+    let mut iter = walk_dir.into_iter();
+    assert!(iter.next().is_some());
+    assert!(!cancel_token.is_canceled());
+    cancel_token.cancel();
+    assert!(iter.next().is_none());
+
+    // Canceled before walking, only root is yielded
+    let (walk_dir, cancel_token) = WalkDir::new(&test_dir).cancelable();
+    cancel_token.cancel();
+    assert_eq!(walk_dir.par_entries().count(), 1);
+}
+
+#[test]
+fn cancel_from_other_thread() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let dir = Dir::tmp();
+    for i in 0..100 {
+        dir.mkdirp(format!("{}/sub", i));
+    }
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted_reads = reads.clone();
+    let (walk_dir, cancel_token) = WalkDir::new(dir.path()).cancelable();
+    let mut iter = walk_dir
+        .parallelism(Parallelism::RayonNewPool(2))
+        .process_read_dir(move |_, _, _, _| {
+            counted_reads.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+        })
+        .into_iter();
+    assert!(iter.next().is_some());
+    std::thread::spawn(move || cancel_token.cancel())
+        .join()
+        .unwrap();
+    let reads_when_canceled = reads.load(Ordering::SeqCst);
+    assert!(iter.next().is_none());
+
+    // Only the reads already in flight on the two threads finish
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(reads.load(Ordering::SeqCst) <= reads_when_canceled + 2);
+    assert!(reads.load(Ordering::SeqCst) < 201);
+}
+
+#[test]
+fn max_entries() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = Dir::tmp();
+    for i in 0..100 {
+        dir.mkdirp(format!("{}/sub", i));
+    }
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted_reads = reads.clone();
+    let walk_dir = WalkDir::new(dir.path())
+        .parallelism(Parallelism::Serial)
+        .max_entries(Some(5))
+        .process_read_dir(move |_, _, _, _| {
+            counted_reads.fetch_add(1, Ordering::SeqCst);
+        });
+    assert_eq!(walk_dir.into_iter().count(), 5);
+    assert!(reads.load(Ordering::SeqCst) < 10);
+
+    assert_eq!(
+        WalkDir::new(dir.path())
+            .max_entries(Some(150))
+            .into_iter()
+            .count(),
+        150
+    );
+    assert_eq!(
+        WalkDir::new(dir.path())
+            .max_entries(Some(0))
+            .into_iter()
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn max_entries_stops_reading() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let dir = Dir::tmp();
+    for i in 0..100 {
+        dir.mkdirp(format!("{}/sub", i));
+    }
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted_reads = reads.clone();
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .max_entries(Some(5))
+        .process_read_dir(move |_, _, _, _| {
+            counted_reads.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+        })
+        .into_iter();
+    assert_eq!(iter.by_ref().count(), 5);
+    let reads_when_reached = reads.load(Ordering::SeqCst);
+
+    // Directories queued ahead of the iterator aren't read anymore
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(reads.load(Ordering::SeqCst) <= reads_when_reached + 2);
+    assert!(reads.load(Ordering::SeqCst) < 201);
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn deadline() {
+    use std::time::{Duration, Instant};
+
+    let dir = Dir::tmp();
+    for i in 0..100 {
+        dir.mkdirp(format!("{}/sub", i));
+    }
+
+    let deadline = Instant::now();
+    let mut iter = WalkDir::new(dir.path())
+        .deadline(Some(deadline))
+        .into_iter();
+    let err = iter.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::DeadlineExceeded);
+    assert_eq!(err.deadline_exceeded(), Some(deadline));
+    assert!(iter.next().is_none());
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let results: Vec<_> = WalkDir::new(dir.path())
+            .parallelism(parallelism)
+            .timeout(Some(Duration::from_millis(20)))
+            .process_read_dir(|_, _, _, _| std::thread::sleep(Duration::from_millis(2)))
+            .into_iter()
+            .collect();
+        assert!(results.len() < 201);
+        let (last, entries) = results.split_last().unwrap();
+        assert_eq!(
+            last.as_ref().unwrap_err().kind(),
+            ErrorKind::DeadlineExceeded
+        );
+        assert!(entries.iter().all(|dir_entry| dir_entry.is_ok()));
+    }
+
+    assert_eq!(
+        WalkDir::new(dir.path())
+            .timeout(Some(Duration::from_secs(60)))
+            .into_iter()
+            .filter(|dir_entry| dir_entry.is_ok())
+            .count(),
+        201
+    );
+}
+
+#[test]
+fn deadline_stops_reading() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let dir = Dir::tmp();
+    for i in 0..100 {
+        dir.mkdirp(format!("{}/sub", i));
+    }
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted_reads = reads.clone();
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .timeout(Some(Duration::from_millis(20)))
+        .process_read_dir(move |_, _, _, _| {
+            counted_reads.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+        })
+        .into_iter();
+    assert!(iter.next().unwrap().is_ok());
+
+    // Threads stop reading once the deadline passed, even while the
+    // iterator isn't advanced
+    std::thread::sleep(Duration::from_millis(100));
+    let reads_after_deadline = reads.load(Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(reads.load(Ordering::SeqCst), reads_after_deadline);
+    assert!(reads_after_deadline < 201);
+
+    let last = iter.last().unwrap();
+    assert_eq!(last.unwrap_err().kind(), ErrorKind::DeadlineExceeded);
+}
+
+#[test]
+fn buffer_limit() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let dir = Dir::tmp();
+    for i in 0..20 {
+        dir.mkdirp(i.to_string());
+        dir.touch(format!("{}/a.txt", i));
+    }
+    for limit in [BufferLimit::ReadDirs(1), BufferLimit::Entries(2)] {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let counted_reads = reads.clone();
+        let mut iter = WalkDir::new(dir.path())
+            .parallelism(Parallelism::RayonNewPool(2))
+            .buffer_limit(Some(limit))
+            .process_read_dir(move |_, _, _, _| {
+                counted_reads.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(1));
+            })
+            .into_iter();
+        // The root, the first directory and its file.
+        for _ in 0..3 {
+            iter.next().unwrap().unwrap();
+        }
+        thread::sleep(Duration::from_millis(100));
+        assert!(reads.load(Ordering::SeqCst) < 15);
+        assert_eq!(iter.count(), 38);
+    }
+
+    // Dropping a blocked walk doesn't hang
+    let iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .buffer_limit(Some(BufferLimit::ReadDirs(1)))
+        .into_iter()
+        .take(3);
+    assert_eq!(iter.count(), 3);
+}
+
+#[test]
+fn read_dir_chunk_size() {
+    use std::sync::{Arc, Mutex};
+
+    let dir = Dir::tmp();
+    for i in 0..10 {
+        dir.touch(format!("{}.txt", i));
+    }
+    dir.mkdirp("a/b");
+    dir.mkdirp("c");
+    dir.touch("a/b/d.txt");
+
+    let all_paths = |walk_dir: WalkDir| {
+        let mut paths: Vec<_> = walk_dir
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+    };
+    let expected = all_paths(WalkDir::new(dir.path()));
+    assert_eq!(expected.len(), 15);
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let chunk_sizes = Arc::new(Mutex::new(Vec::new()));
+        let walk_dir = {
+            let chunk_sizes = chunk_sizes.clone();
+            WalkDir::new(dir.path())
+                .parallelism(parallelism.clone())
+                .read_dir_chunk_size(Some(3))
+                .process_read_dir(move |depth, _, _, children| {
+                    if depth == Some(0) {
+                        chunk_sizes.lock().unwrap().push(children.len());
+                    }
+                })
+        };
+        assert_eq!(all_paths(walk_dir), expected);
+        assert_eq!(*chunk_sizes.lock().unwrap(), vec![3, 3, 3, 3]);
+
+        let mut paths: Vec<_> = WalkDir::new(dir.path())
+            .parallelism(parallelism.clone())
+            .read_dir_chunk_size(Some(3))
+            .par_entries()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, expected);
+
+        // Skipping the root's contents skips its remaining chunks
+        let mut iter = WalkDir::new(dir.path())
+            .parallelism(parallelism)
+            .read_dir_chunk_size(Some(3))
+            .into_iter();
+        iter.next().unwrap().unwrap();
+        iter.next().unwrap().unwrap();
+        iter.skip_current_dir();
+        assert!(iter.next().is_none());
+    }
+}
+
+#[test]
+fn custom_file_system() {
+    use jwalk::file_system::{FileSystem, FileSystemEntry, ReadDirEntries};
+    use jwalk::FileType;
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    // Directories with the names and types of their entries
+    struct VirtualFs(BTreeMap<PathBuf, Vec<(&'static str, FileType)>>);
+
+    impl FileSystem for VirtualFs {
+        fn read_dir(&self, path: &Path) -> io::Result<ReadDirEntries<'_>> {
+            let entries = self.0.get(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(Box::new(entries.iter().map(|(file_name, file_type)| {
+                Ok(FileSystemEntry {
+                    file_name: file_name.into(),
+                    file_type: Ok(*file_type),
+                })
+            })))
+        }
+
+        fn file_type(&self, path: &Path, _follow_links: bool) -> io::Result<FileType> {
+            if self.0.contains_key(path) {
+                Ok(FileType::dir())
+            } else {
+                Ok(FileType::file())
+            }
+        }
+
+        fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+            Err(io::ErrorKind::InvalidInput.into())
+        }
+    }
+
+    let virtual_fs = VirtualFs(BTreeMap::from([
+        (
+            PathBuf::from("/virtual"),
+            vec![("b", FileType::file()), ("a", FileType::dir())],
+        ),
+        (PathBuf::from("/virtual/a"), vec![("c", FileType::file())]),
+    ]));
+    let dir_entries: Vec<_> = WalkDir::new("/virtual")
+        .file_system(Some(Arc::new(virtual_fs)))
+        .parallelism(Parallelism::RayonNewPool(2))
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    let paths: Vec<_> = dir_entries
+        .iter()
+        .map(|dir_entry| dir_entry.path())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("/virtual"),
+            PathBuf::from("/virtual/a"),
+            PathBuf::from("/virtual/a/c"),
+            PathBuf::from("/virtual/b"),
+        ]
+    );
+    assert_eq!(dir_entries[1].file_type(), FileType::dir());
+    assert_eq!(dir_entries[1].file_type().as_std(), None);
+    assert_eq!(
+        dir_entries[2]
+            .metadata()
+            .unwrap_err()
+            .io_error()
+            .unwrap()
+            .kind(),
+        io::ErrorKind::Unsupported
+    );
+}
+
+#[test]
+fn memory_fs() {
+    use jwalk::memfs::MemoryFs;
+
+    let mut memory_fs = MemoryFs::new();
+    memory_fs.add_file("/memory/b.txt");
+    memory_fs.add_file("/memory/a/c.txt");
+    memory_fs.add_dir("/memory/a/empty");
+    memory_fs.add_symlink("/memory/link", "a");
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let paths: Vec<_> = memory_fs
+            .walk_dir::<((), ())>("/memory")
+            .parallelism(parallelism)
+            .follow_links(true)
+            .process_read_dir(|_, _, _, children| {
+                children.retain(|dir_entry_result| {
+                    dir_entry_result
+                        .as_ref()
+                        .map(|dir_entry| dir_entry.file_name != "empty")
+                        .unwrap_or(true)
+                });
+            })
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/memory"),
+                PathBuf::from("/memory/a"),
+                PathBuf::from("/memory/a/c.txt"),
+                PathBuf::from("/memory/b.txt"),
+                PathBuf::from("/memory/link"),
+                PathBuf::from("/memory/link/c.txt"),
+            ]
+        );
+    }
+
+    let dir_entries: Vec<_> = memory_fs
+        .walk_dir::<((), ())>("/memory/link")
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert!(dir_entries[0].file_type().is_symlink());
+    assert_eq!(dir_entries[1].path(), PathBuf::from("/memory/link/c.txt"));
+    assert_eq!(dir_entries.len(), 3);
+}
+
+#[test]
+#[cfg(feature = "object-store")]
+fn object_store() {
+    use jwalk::object_store::{ObjectLister, ObjectListing, ObjectStoreFs};
+    use std::io;
+
+    // Lists keys like S3 with `/` as delimiter
+    struct Bucket(Vec<&'static str>);
+
+    impl ObjectLister for Bucket {
+        fn list(&self, prefix: &str) -> io::Result<Vec<ObjectListing>> {
+            let mut listings = Vec::new();
+            for key in self.0.iter().filter_map(|key| key.strip_prefix(prefix)) {
+                let listing = match key.split_once('/') {
+                    Some((name, _)) => ObjectListing::Prefix(format!("{}{}/", prefix, name)),
+                    None => ObjectListing::Object(format!("{}{}", prefix, key)),
+                };
+                if !listings.contains(&listing) {
+                    listings.push(listing);
+                }
+            }
+            Ok(listings)
+        }
+    }
+
+    let store = ObjectStoreFs::new(Bucket(vec![
+        "logs/",
+        "logs/2024/01.log",
+        "logs/2024/02.log",
+        "logs/2024",
+        "logs/latest.log",
+        "readme.txt",
+    ]));
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let entries: Vec<_> = store
+            .walk_dir::<((), ())>("/")
+            .parallelism(parallelism)
+            .sort(true)
+            .into_iter()
+            .map(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                (dir_entry.path(), dir_entry.file_type().is_dir())
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("/"), true),
+                (PathBuf::from("/logs"), true),
+                (PathBuf::from("/logs/2024"), true),
+                (PathBuf::from("/logs/2024/01.log"), false),
+                (PathBuf::from("/logs/2024/02.log"), false),
+                (PathBuf::from("/logs/latest.log"), false),
+                (PathBuf::from("/readme.txt"), false),
+            ]
+        );
+    }
+
+    let paths: Vec<_> = store
+        .walk_dir::<((), ())>("/logs/2024")
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect();
+    assert_eq!(paths.len(), 3);
+    let file: Vec<_> = store
+        .walk_dir::<((), ())>("/readme.txt")
+        .into_iter()
+        .collect();
+    assert!(file[0].as_ref().unwrap().file_type().is_file());
+    let missing: Vec<_> = store.walk_dir::<((), ())>("/missing").into_iter().collect();
+    assert_eq!(
+        missing[0].as_ref().unwrap_err().io_error().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+    for path in ["/logs/../readme.txt", "./logs"] {
+        let rejected: Vec<_> = store.walk_dir::<((), ())>(path).into_iter().collect();
+        assert_eq!(
+            rejected[0].as_ref().unwrap_err().io_error().unwrap().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "webdav")]
+fn webdav() {
+    use jwalk::webdav::{WebDavFs, WebDavProperties};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Answers PROPFIND requests for a share at `/dav` holding `docs/` with
+    // `my notes.txt`, and `readme.md`
+    fn multistatus(path: &str, depth: &str) -> Option<String> {
+        let response = |href: &str, collection: bool, size: &str| {
+            let resource_type = if collection { "<D:collection/>" } else { "" };
+            format!(
+                "<D:response><D:href>{}</D:href><D:propstat><D:prop>\
+                 <D:resourcetype>{}</D:resourcetype>{}\
+                 <D:getlastmodified>Sun, 06 Nov 1994 08:49:37 GMT</D:getlastmodified>\
+                 </D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>",
+                href, resource_type, size
+            )
+        };
+        let (own, children) = match path {
+            "/dav" | "/dav/" => (
+                response("/dav/", true, ""),
+                response("/dav/docs/", true, "")
+                    + &response(
+                        "/dav/readme.md",
+                        false,
+                        "<D:getcontentlength>42</D:getcontentlength>",
+                    ),
+            ),
+            "/dav/docs" => (
+                response("/dav/docs/", true, ""),
+                response("http://localhost/dav/docs/my%20notes.txt", false, ""),
+            ),
+            "/dav/readme.md" => (response("/dav/readme.md", false, ""), String::new()),
+            _ => return None,
+        };
+        let children = if depth == "1" {
+            children
+        } else {
+            String::new()
+        };
+        Some(format!(
+            "<?xml version=\"1.0\"?><D:multistatus xmlns:D=\"DAV:\">{}{}</D:multistatus>",
+            own, children
+        ))
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/dav", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let path = request_line.split(' ').nth(1).unwrap().to_string();
+                    let (mut depth, mut content_length) = (String::new(), 0);
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).unwrap();
+                        let header = header.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        let (name, value) = header.split_once(": ").unwrap();
+                        match name.to_ascii_lowercase().as_str() {
+                            "depth" => depth = value.to_string(),
+                            "content-length" => content_length = value.parse().unwrap(),
+                            _ => {}
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    let response = match multistatus(&path, &depth) {
+                        Some(xml) => format!(
+                            "HTTP/1.1 207 Multi-Status\r\nContent-Type: application/xml\r\n\
+                             Content-Length: {}\r\n\r\n{}",
+                            xml.len(),
+                            xml
+                        ),
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+                    };
+                    stream.write_all(response.as_bytes()).unwrap();
+                }
+            });
+        }
+    });
+
+    let share = WebDavFs::new(&url);
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let entries: Vec<_> = share
+            .walk_dir::<((), ())>("/")
+            .parallelism(parallelism)
+            .sort(true)
+            .into_iter()
+            .map(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                (dir_entry.path(), dir_entry.file_type().is_dir())
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("/"), true),
+                (PathBuf::from("/docs"), true),
+                (PathBuf::from("/docs/my notes.txt"), false),
+                (PathBuf::from("/readme.md"), false),
+            ]
+        );
+    }
+    assert_eq!(
+        share.properties("/readme.md".as_ref()),
+        Some(WebDavProperties {
+            content_length: Some(42),
+            last_modified: Some(UNIX_EPOCH + Duration::from_secs(784111777)),
+        })
+    );
+
+    let file: Vec<_> = share
+        .walk_dir::<((), ())>("/readme.md")
+        .into_iter()
+        .collect();
+    assert!(file[0].as_ref().unwrap().file_type().is_file());
+    let missing: Vec<_> = share.walk_dir::<((), ())>("/missing").into_iter().collect();
+    assert_eq!(
+        missing[0].as_ref().unwrap_err().io_error().unwrap().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn dir_fds() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let paths = local_paths(WalkDir::new(&test_dir).dir_fds(true).sort(true));
+    assert_eq!(paths, expected);
+
+    // Subdirectories are still read after the root is renamed
+    let renamed_dir = test_dir.with_file_name("renamed");
+    let rename_from = test_dir.clone();
+    let rename_to = renamed_dir.clone();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::Serial)
+            .dir_fds(true)
+            .sort(true)
+            .process_read_dir(move |depth, _, _, _| {
+                if depth == Some(0) {
+                    fs::rename(&rename_from, &rename_to).unwrap();
+                }
+            }),
+    );
+    assert_eq!(paths, expected);
+    assert!(renamed_dir.join("group 1/d.txt").exists());
+}
+
+#[test]
+fn file_type_source() {
+    let (test_dir, _temp_dir) = test_dir();
+    let dir_entries: Vec<_> = WalkDir::new(&test_dir)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert_eq!(dir_entries[0].file_type().source(), FileTypeSource::Stat);
+    assert_eq!(dir_entries[1].file_type().source(), FileTypeSource::Std);
+
+    let dir_entries: Vec<_> = WalkDir::new(&test_dir)
+        .read_dir_backend(ReadDirBackend::Getdents)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        assert!(dir_entries[1..]
+            .iter()
+            .all(|dir_entry| dir_entry.file_type().source() != FileTypeSource::Std));
+    }
+    assert_eq!(
+        FileType::dir().with_source(FileTypeSource::Stat),
+        FileType::dir()
+    );
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_dir_backend_getdents() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .read_dir_backend(ReadDirBackend::Getdents)
+            .sort(true),
+    );
+    assert_eq!(paths, expected);
+
+    // More entries than fit in one read
+    let large_dir = test_dir.join("large");
+    fs::create_dir(&large_dir).unwrap();
+    for i in 0..3000 {
+        fs::File::create(large_dir.join(format!("{:0>80}", i))).unwrap();
+    }
+    let dir_entries: Vec<_> = WalkDir::new(&large_dir)
+        .read_dir_backend(ReadDirBackend::Getdents)
+        .dir_fds(true)
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert_eq!(dir_entries.len(), 3001);
+    assert_eq!(dir_entries[1].file_name, format!("{:0>80}", 0).as_str());
+    assert!(dir_entries[3000].file_type().is_file());
+}
+
+#[test]
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+fn read_dir_backend_io_uring() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .read_dir_backend(ReadDirBackend::IoUring)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .sort(true),
+    );
+    assert_eq!(paths, expected);
+}
+
+#[test]
+#[cfg(windows)]
+fn read_dir_backend_nt_query_directory_file() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .read_dir_backend(ReadDirBackend::NtQueryDirectoryFile)
+            .sort(true),
+    );
+    assert_eq!(paths, expected);
+
+    // More entries than fit in one read
+    let large_dir = test_dir.join("large");
+    fs::create_dir(&large_dir).unwrap();
+    for i in 0..3000 {
+        fs::File::create(large_dir.join(format!("{:0>80}", i))).unwrap();
+    }
+    let dir_entries: Vec<_> = WalkDir::new(&large_dir)
+        .read_dir_backend(ReadDirBackend::NtQueryDirectoryFile)
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert_eq!(dir_entries.len(), 3001);
+    assert!(dir_entries[3000].file_type().is_file());
+}
+
+#[test]
+fn read_dir_backend_fallback() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let backends = [
+        ReadDirBackend::Getdents,
+        #[cfg(feature = "io-uring")]
+        ReadDirBackend::IoUring,
+        ReadDirBackend::NtQueryDirectoryFile,
+    ];
+    for backend in backends {
+        let paths = local_paths(WalkDir::new(&test_dir).read_dir_backend(backend).sort(true));
+        assert_eq!(paths, expected, "{:?}", backend);
+
+        // Backends of other platforms read with `std::fs::read_dir`
+        let nt_query = matches!(backend, ReadDirBackend::NtQueryDirectoryFile);
+        let native = if cfg!(windows) {
+            nt_query
+        } else {
+            !nt_query && cfg!(any(target_os = "linux", target_os = "android"))
+        };
+        if !native {
+            let dir_entries: Vec<_> = WalkDir::new(&test_dir)
+                .read_dir_backend(backend)
+                .into_iter()
+                .map(|dir_entry| dir_entry.unwrap())
+                .collect();
+            assert!(dir_entries[1..]
+                .iter()
+                .all(|dir_entry| dir_entry.file_type().source() == FileTypeSource::Std));
+        }
+    }
+}
+
+#[test]
+#[cfg(windows)]
+fn long_paths() {
+    let (test_dir, _temp_dir) = test_dir();
+    let mut deep_dir = test_dir.join("deep");
+    for _ in 0..20 {
+        deep_dir.push("d".repeat(20));
+    }
+    // Canonical paths have the extended-length prefix on Windows
+    fs::create_dir_all(
+        fs::canonicalize(&test_dir)
+            .unwrap()
+            .join(deep_dir.strip_prefix(&test_dir).unwrap()),
+    )
+    .unwrap();
+
+    let dir_entries: Vec<_> = WalkDir::new(test_dir.join("deep"))
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert_eq!(dir_entries.len(), 21);
+    assert_eq!(dir_entries[0].path(), test_dir.join("deep"));
+    assert_eq!(dir_entries[20].path(), deep_dir);
+
+    let dir_entries: Vec<_> = WalkDir::new(test_dir.join("deep"))
+        .normalize_long_paths(false)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert!(dir_entries[20]
+        .path()
+        .to_str()
+        .unwrap()
+        .starts_with(r"\\?\"));
+    assert!(dir_entries[20].metadata().unwrap().is_dir());
+}
+
+#[test]
+#[cfg(windows)]
+fn follow_junctions() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/zzz");
+    dir.symlink_dir("a", "a-link");
+    let status = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(dir.join("a-junction"))
+        .arg(dir.join("a"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .follow_junctions(false)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let ents = r.sorted_ents();
+    let junction = ents
+        .iter()
+        .find(|ent| ent.file_name() == "a-junction")
+        .unwrap();
+    assert_eq!(junction.reparse_tag(), Some(ReparseTag::MountPoint));
+    assert!(junction.file_type().is_symlink());
+    let link = ents.iter().find(|ent| ent.file_name() == "a-link").unwrap();
+    assert_eq!(link.reparse_tag(), Some(ReparseTag::Symlink));
+    assert!(link.file_type().is_dir());
+    assert!(ents
+        .iter()
+        .any(|ent| ent.path() == dir.join("a-link").join("zzz")));
+    assert!(!ents
+        .iter()
+        .any(|ent| ent.path() == dir.join("a-junction").join("zzz")));
+    assert_eq!(
+        ReparseTag::from_raw(ReparseTag::AppExecLink.as_raw()),
+        ReparseTag::AppExecLink
+    );
+}
+
+#[test]
+#[cfg(all(feature = "serde", feature = "preload-metadata"))]
+fn serialize() {
+    let (test_dir, _temp_dir) = test_dir();
+    let dir_entry = WalkDir::new(&test_dir)
+        .preload_metadata(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .find(|dir_entry| dir_entry.file_name() == "a.txt")
+        .unwrap();
+    let json = serde_json::to_string(&dir_entry).unwrap();
+    assert!(json.contains(r#""depth":1"#));
+    assert!(json.contains(r#""file_type":"file""#));
+    assert!(json.contains(r#""len":2"#));
+    assert!(json.contains(r#""client_state":null"#));
+
+    let err = WalkDir::new(test_dir.join("missing"))
+        .into_iter()
+        .next()
+        .unwrap()
+        .unwrap_err();
+    let json = serde_json::to_string(&err).unwrap();
+    assert!(json.contains(r#""kind":"NotFound""#));
+    assert!(json.contains("missing"));
+}
+
+#[test]
+fn compat_walkdir() {
+    use jwalk::compat::walkdir;
+
+    let (test_dir, _temp_dir) = test_dir();
+    fs::File::create(test_dir.join("group 1/.hidden")).unwrap();
+    let paths: Vec<_> = walkdir::WalkDir::new(&test_dir)
+        .sort_by_file_name()
+        .contents_first(true)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "group 1")
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let path = entry.path().strip_prefix(&test_dir).unwrap().to_owned();
+            assert_eq!(entry.into_path(), test_dir.join(&path));
+            path
+        })
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("a.txt"),
+            PathBuf::from("b.txt"),
+            PathBuf::from("c.txt"),
+            PathBuf::from("group 1/.hidden"),
+            PathBuf::from("group 1/d.txt"),
+            PathBuf::from("group 2/.hidden_file.txt"),
+            PathBuf::from("group 2/e.txt"),
+            PathBuf::from("group 2"),
+            PathBuf::from(""),
+        ]
+    );
+
+    let depths: Vec<_> = walkdir::WalkDir::new(&test_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by_key(|entry| std::cmp::Reverse(entry.file_name().to_owned()))
+        .into_iter()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            assert!(!entry.path_is_symlink());
+            (entry.file_name().to_owned(), entry.depth())
+        })
+        .collect();
+    assert_eq!(depths.len(), 5);
+    assert_eq!(depths[0], ("group 2".into(), 1));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "preload-metadata"))]
+fn preload_metadata_ext() {
+    use jwalk::statx::StatxFields;
+
+    let (test_dir, _temp_dir) = test_dir();
+    for dir_entry in WalkDir::new(&test_dir)
+        .preload_metadata_ext(StatxFields::SIZE | StatxFields::MTIME)
+        .parallelism(Parallelism::RayonNewPool(2))
+    {
+        let dir_entry = dir_entry.unwrap();
+        let metadata = dir_entry.metadata().unwrap();
+        let statx = dir_entry.statx().unwrap();
+        assert!(statx.fields().contains(StatxFields::SIZE));
+        assert_eq!(statx.size(), Some(metadata.len()));
+        assert_eq!(statx.modified(), Some(metadata.modified().unwrap()));
+    }
+
+    let dir_entry = WalkDir::new(&test_dir).into_iter().next().unwrap().unwrap();
+    assert!(dir_entry.statx().is_none());
+}
+
+#[test]
+#[cfg(feature = "tar")]
+fn archive_fs() {
+    use jwalk::archive::ArchiveFs;
+    use std::fs::File;
+    use std::io;
+    use std::sync::Arc;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let mut builder = tar::Builder::new(File::create(test_dir.join("b.tar")).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::dir());
+    header.set_size(0);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "inner/", io::empty())
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "inner/file.txt", &b"data"[..])
+        .unwrap();
+    builder.into_inner().unwrap();
+
+    let local_paths = local_paths(
+        WalkDir::new(test_dir.join("b.tar"))
+            .file_system(Some(Arc::new(ArchiveFs::new())))
+            .sort(true),
+    );
+    assert_eq!(local_paths, vec![" (0)", "inner (1)", "inner/file.txt (2)"]);
+
+    let dir_entries: Vec<_> = WalkDir::new(&test_dir)
+        .file_system(Some(Arc::new(ArchiveFs::new())))
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .filter(|dir_entry| dir_entry.path().starts_with(test_dir.join("b.tar")))
+        .collect();
+    assert_eq!(dir_entries.len(), 3);
+    assert!(dir_entries[0].file_type().is_dir());
+    assert!(dir_entries[0].metadata().unwrap().is_file());
+    assert_eq!(dir_entries[2].depth, 3);
+    assert_eq!(
+        dir_entries[2]
+            .metadata()
+            .unwrap_err()
+            .io_error()
+            .unwrap()
+            .kind(),
+        io::ErrorKind::Unsupported
+    );
+}
+
+#[test]
+fn with_stats() {
+    use jwalk::stats::WalkStats;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("sub/deeper");
+    fs::write(dir.join("a"), "x".repeat(5)).unwrap();
+    fs::write(dir.join("sub/deeper/b"), "x".repeat(10)).unwrap();
+    dir.symlink_file(dir.join("a"), "link");
+
+    let (wd, stats_collector) = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .with_stats();
+    assert_eq!(stats_collector.stats(), WalkStats::default());
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        stats_collector.stats(),
+        WalkStats {
+            files: 2,
+            dirs: 3,
+            symlinks: 1,
+            errors: 0,
+            bytes: 15,
+            max_depth: 3,
+        }
+    );
+
+    // Entries that aren't yielded aren't counted
+    let (wd, stats_collector) = WalkDir::new(dir.path()).min_depth(2).with_stats();
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let stats = stats_collector.stats();
+    assert_eq!((stats.files, stats.dirs), (1, 1));
+}
+
+#[test]
+#[cfg(feature = "preload-metadata")]
+fn preload_metadata() {
+    let (test_dir, _temp_dir) = test_dir();
+    let dir_entries: Vec<_> = WalkDir::new(&test_dir)
+        .preload_metadata(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    assert_eq!(dir_entries.len(), 8);
+    let a_txt = dir_entries
+        .iter()
+        .find(|dir_entry| dir_entry.file_name == "a.txt")
+        .unwrap();
+    let len = a_txt.metadata().unwrap().len();
+    fs::write(a_txt.path(), "changed after walking").unwrap();
+    // Cached while walking, so the change isn't seen
+    assert_eq!(a_txt.metadata().unwrap().len(), len);
+    assert!(dir_entries
+        .iter()
+        .all(|dir_entry| dir_entry.metadata().is_ok()));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn same_file_system_skips_other_devices() {
+    use std::os::unix::fs::MetadataExt;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let proc_dir = std::path::Path::new("/proc/self");
+    match fs::metadata(proc_dir) {
+        Ok(metadata) if metadata.dev() != fs::metadata(&test_dir).unwrap().dev() => {}
+        _ => return,
+    }
+    std::os::unix::fs::symlink(proc_dir, test_dir.join("proc")).unwrap();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .follow_links(true)
+            .same_file_system(true)
+            .max_depth(1)
+            .sort(true),
+    );
+    assert!(paths.contains(&"proc (1)".to_string()));
+    let walk_dir = WalkDir::new(&test_dir)
+        .follow_links(true)
+        .same_file_system(true)
+        .min_depth(2);
+    assert!(walk_dir
+        .into_iter()
+        .all(|dir_entry| !dir_entry.unwrap().path().starts_with(test_dir.join("proc"))));
+}
+
+#[test]
+fn include_only() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .include_only([test_dir.join("group 2"), test_dir.join("b.txt")])
+            .sort(true),
+    );
+    assert_eq!(paths, vec!["b.txt (1)", "group 2 (1)", "group 2/e.txt (2)"]);
+}
+
+#[test]
+fn pattern_matching() {
+    use jwalk::pattern::Pattern;
+    use std::path::Path;
+
+    let matches = |pattern: &str, path: &str, is_dir: bool| {
+        Pattern::new(pattern)
+            .unwrap()
+            .matches(Path::new(path), is_dir)
+    };
+    assert!(matches("build", "build", true));
+    assert!(matches("build", "src/build", false));
+    assert!(matches("/build", "build", true));
+    assert!(!matches("/build", "src/build", true));
+    assert!(matches("build/", "src/build", true));
+    assert!(!matches("build/", "src/build", false));
+    assert!(matches("*.txt", "a/b/c.txt", false));
+    assert!(!matches("/*.txt", "a/c.txt", false));
+    assert!(matches("/**.txt", "a/c.txt", false));
+    assert!(matches("a/*/c", "x/a/b/c", false));
+    assert!(!matches("a/*/c", "a/b/b/c", false));
+    assert!(matches("a/**/c", "a/b/b/c", false));
+    assert!(matches("file?.[ch]", "file1.c", false));
+    assert!(!matches("file?.[!ch]", "file1.c", false));
+    assert!(matches("[a-c]\\*", "b*", false));
+    assert!(!matches("[a-c]\\*", "bx", false));
+    assert!(Pattern::new("[abc").is_err());
+
+    // Many wildcards are matched without backtracking exponentially
+    let path = format!("{}/{}", "a".repeat(60), "a".repeat(60));
+    assert!(matches(&"*a".repeat(30), &path, false));
+    assert!(!matches(&format!("{}b", "*a".repeat(30)), &path, false));
+    assert!(!matches(&format!("{}b", "**a".repeat(30)), &path, false));
+    let tar_pattern = Pattern::tar(&format!("{}b", "*a".repeat(30))).unwrap();
+    assert!(!tar_pattern.matches(Path::new(&path), false));
+}
+
+#[test]
+fn exclude_patterns_are_anchored_at_root() {
+    let dir = Dir::tmp();
+    dir.mkdirp("build/a");
+    dir.mkdirp("src/build/b");
+    dir.touch("src/main.rs");
+
+    let wd = WalkDir::new(dir.path())
+        .exclude_patterns(["/build/".parse().unwrap()])
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("src"),
+            dir.join("src/build"),
+            dir.join("src/build/b"),
+            dir.join("src/main.rs"),
+        ]
+    );
+
+    let wd = WalkDir::new(dir.path())
+        .exclude_patterns(["build/".parse().unwrap()])
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("src"),
+            dir.join("src/main.rs"),
+        ]
+    );
+}
+
+#[test]
+fn include_and_exclude_globs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("src/gen");
+    dir.mkdirp("target");
+    dir.touch_all(&[
+        "README.md",
+        "src/main.rs",
+        "src/notes.txt",
+        "src/gen/out.rs",
+        "target/lib.rs",
+    ]);
+
+    let wd = WalkDir::new(dir.path())
+        .include_glob("*.rs".parse().unwrap())
+        .include_glob("/README.md".parse().unwrap())
+        .exclude_glob("/target/".parse().unwrap())
+        .exclude_glob("gen/".parse().unwrap())
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("README.md"),
+            dir.join("src"),
+            dir.join("src/main.rs"),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "filter")]
+fn filter_rules_parse() {
+    use jwalk::filter::{FilterAction, FilterRules};
+    use std::path::Path;
+
+    let rules = FilterRules::parse(
+        "# comment\n\
+         + /keep.o\n\
+         - *.o\n\
+         exclude /target/\n\
+         hide .cache/\n\
+         P protected\n\
+         :n .rsync-filter\n",
+    )
+    .unwrap();
+    assert_eq!(rules.rules().len(), 5);
+    assert_eq!(
+        rules.action(Path::new("keep.o"), false),
+        Some(FilterAction::Include)
+    );
+    assert!(rules.is_excluded(Path::new("src/keep.o"), false));
+    assert!(rules.is_excluded(Path::new("target"), true));
+    assert!(!rules.is_excluded(Path::new("src/target"), true));
+    assert!(!rules.is_excluded(Path::new("main.rs"), false));
+
+    let err = FilterRules::parse("+ a\nbogus b\n").unwrap_err();
+    assert_eq!(err.line(), 2);
+    assert!(FilterRules::parse("exclude,/ a\n").is_err());
+    assert!(FilterRules::parse("- a\n!\n").unwrap().is_empty());
+}
+
+#[test]
+#[cfg(feature = "filter")]
+fn filter_rules_with_dir_merge() {
+    use jwalk::filter::FilterRules;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.mkdirp("c");
+    dir.touch_all(&["a/x.tmp", "a/b/y.tmp", "a/b/y.log", "c/z.tmp", "c/z.log"]);
+    fs::write(dir.join("a/rules"), "- *.tmp\n- /b/*.log\n").unwrap();
+
+    let rules = FilterRules::parse("- *.log\n- rules\n: rules\n").unwrap();
+    let wd = WalkDir::new(dir.path()).filter_rules(rules).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("a"),
+            dir.join("a/b"),
+            dir.join("c"),
+            dir.join("c/z.tmp"),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "filter")]
+fn git_ignore() {
+    use jwalk::filter::{FilterAction, FilterRules};
+    use std::path::Path;
+
+    let rules = FilterRules::parse_git_ignore(
+        "# comment\n\
+         *.log\n\
+         !keep.log\n\
+         docs/build/\n\
+         **/cache\n\
+         trailing \n",
+    )
+    .unwrap();
+    assert_eq!(rules.rules().len(), 5);
+    assert_eq!(
+        rules.action(Path::new("src/keep.log"), false),
+        Some(FilterAction::Include)
+    );
+    assert!(rules.is_excluded(Path::new("src/a.log"), false));
+    assert!(rules.is_excluded(Path::new("docs/build"), true));
+    assert!(!rules.is_excluded(Path::new("src/docs/build"), true));
+    assert!(rules.is_excluded(Path::new("src/cache"), false));
+    assert!(rules.is_excluded(Path::new("trailing"), false));
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.mkdirp("target");
+    dir.touch_all(&["x.log", "a/y.log", "a/b/z.log", "a/b/z.rs", "target/out"]);
+    fs::write(dir.join(".gitignore"), "*.log\n/target/\n").unwrap();
+    fs::write(dir.join("a/.gitignore"), "!*.log\n/b/*.rs\n").unwrap();
+
+    let wd = WalkDir::new(dir.path()).git_ignore(true).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("a"),
+            dir.join("a/b"),
+            dir.join("a/b/z.log"),
+            dir.join("a/y.log"),
+        ]
+    );
+
+    // `.gitignore` files are read from the walked file system
+    struct IgnoreLogsFs(jwalk::memfs::MemoryFs);
+
+    impl jwalk::file_system::FileSystem for IgnoreLogsFs {
+        fn read_dir(&self, path: &Path) -> std::io::Result<jwalk::file_system::ReadDirEntries<'_>> {
+            self.0.read_dir(path)
+        }
+
+        fn file_type(&self, path: &Path, follow_links: bool) -> std::io::Result<FileType> {
+            self.0.file_type(path, follow_links)
+        }
+
+        fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+            self.0.read_link(path)
+        }
+
+        fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+            match path.file_name() {
+                Some(file_name) if file_name == ".gitignore" => Ok("*.log\n".to_string()),
+                _ => Err(std::io::ErrorKind::NotFound.into()),
+            }
+        }
+    }
+
+    let mut memory_fs = jwalk::memfs::MemoryFs::new();
+    memory_fs.add_file("/virtual/a.log");
+    memory_fs.add_file("/virtual/b.txt");
+    memory_fs.add_file("/virtual/sub/c.log");
+    let paths: Vec<_> = WalkDir::new("/virtual")
+        .file_system(Some(std::sync::Arc::new(IgnoreLogsFs(memory_fs))))
+        .git_ignore(true)
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            PathBuf::from("/virtual"),
+            PathBuf::from("/virtual/b.txt"),
+            PathBuf::from("/virtual/sub"),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "filter")]
+fn tar_exclude_file() {
+    use jwalk::filter::FilterRules;
+    use jwalk::pattern::Pattern;
+    use std::path::Path;
+
+    assert!(Pattern::tar("a*c")
+        .unwrap()
+        .matches(Path::new("x/ab/bc"), false));
+    assert!(Pattern::tar("./build/")
+        .unwrap()
+        .matches(Path::new("src/build"), false));
+
+    let dir = Dir::tmp();
+    dir.mkdirp("src/build");
+    dir.mkdirp("logs");
+    dir.touch_all(&["src/main.rs", "src/build/out.o", "logs/a.log", "notes.txt"]);
+    fs::write(
+        dir.join("excludes"),
+        "# build output\n./build/\nlogs/*\n\nexcludes\n",
+    )
+    .unwrap();
+
+    let rules = FilterRules::from_tar_exclude_file(dir.join("excludes")).unwrap();
+    assert_eq!(rules.rules().len(), 3);
+    let wd = WalkDir::new(dir.path()).filter_rules(rules).sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("logs"),
+            dir.join("notes.txt"),
+            dir.join("src"),
+            dir.join("src/main.rs"),
+        ]
+    );
+}
+
+#[test]
+fn skip_vcs_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp(".git/objects");
+    dir.mkdirp(".svn");
+    dir.mkdirp("CVS");
+    dir.touch_all(&[".gitignore", "CVS/Entries"]);
+
+    let wd = WalkDir::new(dir.path())
+        .skip_hidden(false)
+        .skip_vcs_dirs(true)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join(".gitignore"),
+            dir.join("CVS"),
+            dir.join("CVS/Entries"),
+        ]
+    );
+
+    let wd = WalkDir::new(dir.path())
+        .skip_hidden(false)
+        .skip_vcs_dirs(true)
+        .vcs_dir_names([".git", "CVS"])
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join(".gitignore"),
+            dir.join(".svn"),
+        ]
+    );
+}
+
+#[test]
+fn skip_vcs_dirs_keeps_files() {
+    let dir = Dir::tmp();
+    dir.mkdirp("submodule/.git");
+    dir.mkdirp("worktree");
+    dir.touch_all(&["submodule/.git/HEAD", "worktree/.git"]);
+
+    let wd = WalkDir::new(dir.path())
+        .skip_hidden(false)
+        .skip_vcs_dirs(true)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("submodule"),
+            dir.join("worktree"),
+            dir.join("worktree/.git"),
+        ]
+    );
+}
+
+#[test]
+fn skip_build_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("web/node_modules/react");
+    dir.mkdirp("crate/target/debug");
+    dir.mkdirp("out");
+    dir.touch_all(&["build", "web/index.js"]);
+
+    let skipped = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let skipped_clone = skipped.clone();
+    let wd = WalkDir::new(dir.path())
+        .skip_build_dirs(true)
+        .on_skipped_build_dir(move |dir_entry| {
+            skipped_clone.lock().unwrap().push(dir_entry.path());
+        })
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![
+            dir.path().to_path_buf(),
+            dir.join("build"),
+            dir.join("crate"),
+            dir.join("out"),
+            dir.join("web"),
+            dir.join("web/index.js"),
+        ]
+    );
+    let mut skipped = skipped.lock().unwrap().clone();
+    skipped.sort();
+    assert_eq!(
+        skipped,
+        vec![dir.join("crate/target"), dir.join("web/node_modules")]
+    );
+
+    let wd = WalkDir::new(dir.path())
+        .skip_build_dirs(true)
+        .build_dir_names(["out"])
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert!(!r.paths().contains(&dir.join("out")));
+    assert!(r.paths().contains(&dir.join("crate/target")));
+}
+
+#[test]
+fn skip_empty_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("empty");
+    dir.mkdirp("filtered");
+    dir.mkdirp("nested/empty");
+    dir.touch_all(&["a.txt", "filtered/b.log"]);
+
+    for contents_first in [false, true] {
+        for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+            let walk_dir = WalkDir::new(dir.path())
+                .parallelism(parallelism)
+                .contents_first(contents_first)
+                .skip_empty_dirs(true)
+                .process_read_dir(|_, _, _, children| {
+                    children.retain(|dir_entry_result| {
+                        dir_entry_result.as_ref().map_or(true, |dir_entry| {
+                            dir_entry.file_name.to_str() != Some("b.log")
+                        })
+                    });
+                });
+            let mut paths = local_paths(walk_dir);
+            paths.sort();
+            assert_eq!(paths, vec![" (0)", "a.txt (1)", "nested (1)"]);
+        }
+    }
+
+    let empty = Dir::tmp();
+    assert!(WalkDir::new(empty.path())
+        .skip_empty_dirs(true)
+        .into_iter()
+        .next()
+        .is_none());
+}
+
+#[test]
+fn leaf_dirs_only() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.mkdirp("a/d");
+    dir.mkdirp("e");
+    dir.touch_all(&["a/d/file.txt", "a/file.txt", "e/file.txt"]);
+
+    for contents_first in [false, true] {
+        for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+            let walk_dir = || {
+                WalkDir::new(dir.path())
+                    .parallelism(parallelism.clone())
+                    .contents_first(contents_first)
+            };
+            let mut paths = local_paths(walk_dir().leaf_dirs_only(Some(LeafDirs::WithoutSubdirs)));
+            paths.sort();
+            assert_eq!(paths, vec!["a/b/c (3)", "a/d (2)", "e (1)"]);
+
+            let paths = local_paths(walk_dir().leaf_dirs_only(Some(LeafDirs::Empty)));
+            assert_eq!(paths, vec!["a/b/c (3)"]);
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn dedup_hardlinks() {
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    dir.touch_all(&["a", "c"]);
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    fs::hard_link(dir.join("a"), dir.join("sub/a")).unwrap();
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = || {
+            WalkDir::new(dir.path())
+                .parallelism(parallelism.clone())
+                .sort(true)
+        };
+        let files: Vec<_> = walk_dir()
+            .dedup_hardlinks(true)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap())
+            .filter(|dir_entry| dir_entry.file_type.is_file())
+            .map(|dir_entry| dir_entry.file_name)
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files.contains(&"c".into()));
+
+        let duplicates = walk_dir()
+            .flag_duplicate_hardlinks(true)
+            .into_iter()
+            .filter(|dir_entry| dir_entry.as_ref().unwrap().is_duplicate_hardlink())
+            .count();
+        assert_eq!(duplicates, 2);
+    }
+
+    let serial_files: Vec<_> = WalkDir::new(dir.path())
+        .parallelism(Parallelism::Serial)
+        .sort(true)
+        .dedup_hardlinks(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().file_name)
+        .collect();
+    assert_eq!(serial_files[1..], ["a", "c", "sub"]);
+}
+
+#[cfg(all(unix, feature = "size"))]
+#[test]
+fn size_counter() {
+    use jwalk::size::{SizeCounter, SizeKind};
+    use std::io::{Seek, SeekFrom, Write};
+
+    let dir = Dir::tmp();
+    fs::write(dir.join("a"), vec![1u8; 5000]).unwrap();
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    let mut sparse = fs::File::create(dir.join("sparse")).unwrap();
+    sparse.seek(SeekFrom::Start(1 << 24)).unwrap();
+    sparse.write_all(b"x").unwrap();
+    drop(sparse);
+
+    let metadata = |name: &str| fs::metadata(dir.join(name)).unwrap();
+
+    let apparent = SizeCounter::new(SizeKind::Apparent);
+    assert_eq!(apparent.size(&metadata("a")), 5000);
+    assert_eq!(apparent.size(&metadata("b")), 5000);
+    assert_eq!(apparent.size(&metadata("sparse")), (1 << 24) + 1);
+
+    let allocated = SizeCounter::new(SizeKind::Allocated).dedup_hardlinks(true);
+    assert!(allocated.size(&metadata("a")) >= 5000);
+    assert_eq!(allocated.size(&metadata("b")), 0);
+    assert!(allocated.size(&metadata("sparse")) < 1 << 24);
+}
+
+#[cfg(all(unix, feature = "size"))]
+#[test]
+fn aggregate_sizes_dedup_hardlinks() {
+    use jwalk::size::{SizeCounter, SizeKind, SubtreeSize};
+
+    let dir = Dir::tmp();
+    fs::write(dir.join("a"), "x".repeat(5000)).unwrap();
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    fs::write(dir.join("c"), "x".repeat(10)).unwrap();
+    let root_size = fs::metadata(dir.path()).unwrap().len();
+
+    for flag in [false, true] {
+        let walk_dir = WalkDir::new(dir.path())
+            .aggregate_sizes(SizeCounter::new(SizeKind::Apparent))
+            .sort(true);
+        let walk_dir = if flag {
+            walk_dir.flag_duplicate_hardlinks(true)
+        } else {
+            walk_dir.dedup_hardlinks(true)
+        };
+        let root = walk_dir
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap())
+            .find(|dir_entry| dir_entry.depth == 0)
+            .unwrap();
+        assert_eq!(
+            root.subtree_size(),
+            Some(SubtreeSize {
+                bytes: root_size + 5000 + 10,
+                files: 2
+            })
+        );
+    }
+}
+
+#[cfg(feature = "size")]
+#[test]
+fn aggregate_sizes() {
+    use jwalk::size::{SizeCounter, SizeKind, SubtreeSize};
+
+    let dir = Dir::tmp();
+    dir.mkdirp("sub/deeper");
+    fs::write(dir.join("a"), "x".repeat(5)).unwrap();
+    fs::write(dir.join("sub/b"), "x".repeat(10)).unwrap();
+    fs::write(dir.join("sub/deeper/c"), "x".repeat(100)).unwrap();
+    let dir_size = |path: &str| fs::metadata(dir.join(path)).unwrap().len();
+
+    let wd = WalkDir::new(dir.path())
+        .aggregate_sizes(SizeCounter::new(SizeKind::Apparent))
+        .min_depth(1)
+        .sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.join("a"),
+        dir.join("sub/b"),
+        dir.join("sub/deeper/c"),
+        dir.join("sub/deeper"),
+        dir.join("sub"),
+    ];
+    assert_eq!(expected, r.paths());
+    let subtree_sizes: Vec<_> = r
+        .ents()
+        .iter()
+        .map(|dir_entry| dir_entry.subtree_size().unwrap())
+        .collect();
+    assert_eq!(
+        subtree_sizes,
+        vec![
+            SubtreeSize { bytes: 5, files: 1 },
+            SubtreeSize {
+                bytes: 10,
+                files: 1
+            },
+            SubtreeSize {
+                bytes: 100,
+                files: 1
+            },
+            SubtreeSize {
+                bytes: 100 + dir_size("sub/deeper"),
+                files: 1
+            },
+            SubtreeSize {
+                bytes: 110 + dir_size("sub/deeper") + dir_size("sub"),
+                files: 2
+            },
+        ]
+    );
+}
+
+#[test]
+fn reduce_read_dir() {
+    let dir = Dir::tmp();
+    dir.mkdirp("sub/deeper");
+    dir.mkdirp("empty");
+    dir.touch("a");
+    dir.touch("sub/b");
+    dir.touch("sub/c");
+    dir.touch("sub/deeper/d");
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        // Count the files under each directory
+        let wd = WalkDirGeneric::<(usize, usize)>::new(dir.path())
+            .parallelism(parallelism)
+            .sort(true)
+            .process_read_dir(|_, _, file_count, children| {
+                *file_count = 0;
+                for dir_entry in children.iter_mut().flatten() {
+                    if !dir_entry.file_type.is_dir() {
+                        dir_entry.client_state = 1;
+                        *file_count += 1;
+                    }
+                }
+            })
+            .reduce_read_dir(|parent_file_count, dir_entry, file_count| {
+                dir_entry.client_state = file_count;
+                *parent_file_count += file_count;
+            });
+        let file_counts: Vec<_> = wd
+            .into_iter()
+            .map(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                let path = dir_entry.path();
+                let path = path.strip_prefix(dir.path()).unwrap().to_path_buf();
+                (path, dir_entry.client_state)
+            })
+            .collect();
+        assert_eq!(
+            file_counts,
+            vec![
+                (PathBuf::from("a"), 1),
+                (PathBuf::from("empty"), 0),
+                (PathBuf::from("sub/b"), 1),
+                (PathBuf::from("sub/c"), 1),
+                (PathBuf::from("sub/deeper/d"), 1),
+                (PathBuf::from("sub/deeper"), 1),
+                (PathBuf::from("sub"), 3),
+                (PathBuf::from(""), 4),
+            ]
+        );
+    }
+}
+
+#[test]
+fn init_client_state() {
+    use std::sync::Arc;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    dir.touch_all(&["a", "sub/b"]);
+
+    let context: Arc<str> = Arc::from("context");
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let context = context.clone();
+        let states: Vec<_> = WalkDirGeneric::<((), Option<(Arc<str>, usize)>)>::new(dir.path())
+            .parallelism(parallelism)
+            .sort(true)
+            .init_client_state(move |dir_entry| Some((context.clone(), dir_entry.depth)))
+            .process_read_dir(|_, _, _, children| {
+                for dir_entry in children.iter_mut().flatten() {
+                    assert!(dir_entry.client_state.is_some());
+                }
+            })
+            .into_iter()
+            .map(|dir_entry| {
+                let (context, depth) = dir_entry.unwrap().client_state.unwrap();
+                (context.to_string(), depth)
+            })
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("context".to_string(), 0),
+                ("context".to_string(), 1),
+                ("context".to_string(), 1),
+                ("context".to_string(), 2),
+            ]
+        );
+    }
+}
+
+#[test]
+fn project_read_dir_state() {
+    let dir = Dir::tmp();
+    dir.mkdirp("node_modules/pkg/lib");
+    dir.mkdirp("src");
+    dir.touch_all(&["node_modules/pkg/lib/a.js", "src/b.js"]);
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let node_modules = dir.join("node_modules");
+        let inside: Vec<_> = WalkDirGeneric::<(bool, ())>::new(dir.path())
+            .parallelism(parallelism)
+            .sort(true)
+            .min_depth(1)
+            .process_read_dir(move |_, path, inside, _| {
+                assert_eq!(*inside, path.starts_with(&node_modules));
+            })
+            .project_read_dir_state(|inside, dir_entry| {
+                *inside || dir_entry.file_name == "node_modules"
+            })
+            .into_iter()
+            .map(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                (
+                    dir_entry.file_name().to_str().unwrap().to_string(),
+                    *dir_entry.parent_read_dir_state().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            inside,
+            vec![
+                ("node_modules".to_string(), true),
+                ("pkg".to_string(), true),
+                ("lib".to_string(), true),
+                ("a.js".to_string(), true),
+                ("src".to_string(), false),
+                ("b.js".to_string(), false),
+            ]
+        );
+    }
+}
+
+#[test]
+fn map_entries() {
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    fs::write(dir.join("a"), "abc").unwrap();
+    fs::write(dir.join("sub/b"), "abcde").unwrap();
+    dir.touch("sub/skipped");
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let lens: Vec<_> = WalkDirGeneric::<((), Option<u64>)>::new(dir.path())
+            .parallelism(parallelism)
+            .sort(true)
+            .min_depth(1)
+            .process_read_dir(|_, _, _, children| {
+                children.retain(|dir_entry_result| {
+                    dir_entry_result.as_ref().map_or(true, |dir_entry| {
+                        dir_entry.file_name.to_str() != Some("skipped")
+                    })
+                });
+            })
+            .map_entries(|dir_entry| {
+                assert!(dir_entry.file_name.to_str() != Some("skipped"));
+                if dir_entry.file_type.is_file() {
+                    dir_entry.client_state = Some(fs::read(dir_entry.path()).unwrap().len() as u64);
+                }
+            })
+            .into_iter()
+            .map(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                (dir_entry.file_name, dir_entry.client_state)
+            })
+            .collect();
+        assert_eq!(
+            lens,
+            vec![
+                ("a".into(), Some(3)),
+                ("sub".into(), None),
+                ("b".into(), Some(5)),
+            ]
+        );
+    }
+}
+
+#[test]
+fn link_depth_target_path() {
+    let dir = Dir::tmp();
+    dir.mkdirp("deep/er/est");
+    dir.touch("deep/er/est/a");
+    dir.symlink_dir(dir.join("deep/er/est"), "link");
+
+    let depths = |link_depth| {
+        let wd = WalkDir::new(dir.path())
+            .follow_links(true)
+            .link_depth(link_depth)
+            .sort(true);
+        let r = dir.run_recursive(wd);
+        r.assert_no_errors();
+        r.ents()
+            .iter()
+            .filter(|dir_entry| dir_entry.path().starts_with(dir.join("link")))
+            .map(|dir_entry| dir_entry.depth())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(depths(LinkDepth::LinkPath), vec![1, 2]);
+    assert_eq!(depths(LinkDepth::TargetPath), vec![3, 4]);
+
+    let wd = WalkDir::new(dir.path())
+        .follow_links(true)
+        .link_depth(LinkDepth::TargetPath)
+        .max_depth(3);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert!(!r.paths().contains(&dir.join("link/a")));
+}
+
+#[test]
+fn check_root() {
+    let dir = Dir::tmp();
+    let missing = dir.join("missing");
+
+    let err = WalkDir::new(&missing)
+        .check_root(true)
+        .try_into_iter()
+        .err()
+        .unwrap();
+    assert_eq!(err.path(), Some(missing.as_path()));
+    assert_eq!(err.depth(), 0);
+    assert_eq!(err.io_error().unwrap().kind(), std::io::ErrorKind::NotFound);
+
+    assert!(WalkDir::new(&missing).try_into_iter().is_ok());
+    assert!(WalkDir::new(dir.path())
+        .check_root(true)
+        .try_into_iter()
+        .is_ok());
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn from_dir_handle() {
+    let dir = Dir::tmp();
+    dir.mkdirp("foo/bar");
+    dir.touch("foo/a");
+    dir.mkdirp("gone");
+
+    let handle = std::fs::File::open(dir.join("foo")).unwrap();
+    let wd = WalkDir::from_dir_handle(handle).unwrap().sort(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![dir.join("foo"), dir.join("foo/a"), dir.join("foo/bar")]
+    );
+
+    // The walk reads the opened directory, even if it's replaced after the
+    // walk is created.
+    let handle = std::fs::File::open(dir.join("foo")).unwrap();
+    let wd = WalkDir::from_dir_handle(handle).unwrap().sort(true);
+    std::fs::rename(dir.join("foo"), dir.join("moved")).unwrap();
+    dir.mkdirp("foo/other");
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(
+        r.paths(),
+        vec![dir.join("foo"), dir.join("foo/a"), dir.join("foo/bar")]
+    );
+
+    // The walk follows the opened directory, not the path used to open it.
+    let handle = std::fs::File::open(dir.join("foo")).unwrap();
+    std::fs::rename(dir.join("foo"), dir.join("renamed")).unwrap();
+    let wd = WalkDir::from_dir_handle(handle).unwrap();
+    assert_eq!(wd.root(), dir.join("renamed"));
+
+    let handle = std::fs::File::open(dir.join("gone")).unwrap();
+    std::fs::remove_dir(dir.join("gone")).unwrap();
+    assert!(WalkDir::from_dir_handle(handle).is_err());
+
+    let file = std::fs::File::open(dir.join("moved/a")).unwrap();
+    assert!(WalkDir::from_dir_handle(file).is_err());
+}
+
+#[test]
+fn sort_by() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .sort_by(|a, b| b.file_name.cmp(&a.file_name)),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "c.txt (1)",
+            "b.txt (1)",
+            "a.txt (1)",
+        ]
+    );
+}
+
+#[test]
+fn contents_first() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(&test_dir).contents_first(true).sort(true));
+    assert_eq!(
+        paths,
+        vec![
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1/d.txt (2)",
+            "group 1 (1)",
+            "group 2/e.txt (2)",
+            "group 2 (1)",
+            " (0)",
+        ]
+    );
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .contents_first(true)
+            .min_depth(1)
+            .max_depth(1)
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 2 (1)",
+        ]
+    );
+}
+
+#[test]
+fn into_events() {
+    use jwalk::events::Event;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let events: Vec<String> = WalkDir::new(&test_dir)
+        .sort(true)
+        .into_events()
+        .map(|event| match event.unwrap() {
+            Event::EnterDir(dir_entry) => {
+                format!("enter {}", dir_entry.file_name.to_str().unwrap())
+            }
+            Event::File(dir_entry) => format!("file {}", dir_entry.file_name.to_str().unwrap()),
+            Event::LeaveDir { path, depth } => format!(
+                "leave {} ({})",
+                path.file_name().unwrap().to_str().unwrap(),
+                depth
+            ),
+        })
+        .collect();
+    let root_name = test_dir.file_name().unwrap().to_str().unwrap();
+    assert_eq!(
+        events,
+        vec![
+            format!("enter {}", root_name),
+            "file a.txt".to_string(),
+            "file b.txt".to_string(),
+            "file c.txt".to_string(),
+            "enter group 1".to_string(),
+            "file d.txt".to_string(),
+            "leave group 1 (1)".to_string(),
+            "enter group 2".to_string(),
+            "file e.txt".to_string(),
+            "leave group 2 (1)".to_string(),
+            format!("leave {} (0)", root_name),
+        ]
+    );
+}
+
+#[test]
+fn into_events_errors() {
+    use jwalk::events::Event;
+
+    // Errors are yielded inside the directory they were found in
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    dir.symlink_file("missing", "sub/broken");
+    let events: Vec<String> = WalkDir::new(dir.path())
+        .follow_links(true)
+        .into_events()
+        .map(|event| match event {
+            Ok(Event::EnterDir(dir_entry)) => format!("enter {}", dir_entry.depth),
+            Ok(Event::File(dir_entry)) => format!("file {}", dir_entry.depth),
+            Ok(Event::LeaveDir { depth, .. }) => format!("leave {}", depth),
+            Err(err) => format!("error {}", err.depth()),
+        })
+        .collect();
+    assert_eq!(
+        events,
+        vec!["enter 0", "enter 1", "error 2", "leave 1", "leave 0"]
+    );
+}
+
+#[test]
+fn sort_by_key() {
+    use std::time::{Duration, SystemTime};
+
+    let dir = Dir::tmp();
+    let now = SystemTime::now();
+    for (i, name) in ["a", "b", "c"].iter().enumerate() {
+        fs::write(dir.join(name), vec![0; [2, 3, 1][i]]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(dir.join(name))
+            .unwrap()
+            .set_modified(now - Duration::from_secs([60, 180, 120][i]))
+            .unwrap();
+    }
+    fs::write(dir.join("d"), vec![0; 3]).unwrap();
+
+    let names = |walk_dir: WalkDir| -> Vec<String> {
+        walk_dir
+            .min_depth(1)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().file_name.into_string().unwrap())
+            .collect()
+    };
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = || WalkDir::new(dir.path()).parallelism(parallelism.clone());
+        assert_eq!(
+            names(walk_dir().sort_by_key(SortKey::Size, Order::Desc)),
+            vec!["b", "d", "a", "c"]
+        );
+        assert_eq!(
+            names(walk_dir().sort_by_key(SortKey::Size, Order::Asc)),
+            vec!["c", "a", "b", "d"]
+        );
+        assert_eq!(
+            names(walk_dir().sort_by_key(SortKey::Modified, Order::Desc)),
+            vec!["d", "a", "c", "b"]
+        );
+        assert_eq!(
+            names(
+                walk_dir()
+                    .sort_by_key(SortKey::Size, Order::Desc)
+                    .sort_by(|a, b| a.file_name.cmp(&b.file_name))
+            ),
+            vec!["a", "b", "c", "d"]
+        );
+    }
+}
+
+#[test]
+fn natural_sort() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["file10", "file2", "file1", "file01", "file1a", "a9b"]);
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let names: Vec<String> = WalkDir::new(dir.path())
+            .parallelism(parallelism)
+            .natural_sort(true)
+            .min_depth(1)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().file_name.into_string().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["a9b", "file01", "file1", "file1a", "file2", "file10"]
+        );
+    }
+}
+
+#[test]
+fn sort_case_insensitive() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["b", "C", "a", "B", "file10", "File2"]);
+
+    let names = |walk_dir: WalkDir| -> Vec<String> {
+        walk_dir
+            .min_depth(1)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().file_name.into_string().unwrap())
+            .collect()
+    };
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = || {
+            WalkDir::new(dir.path())
+                .parallelism(parallelism.clone())
+                .sort_case_insensitive(true)
+        };
+        assert_eq!(
+            names(walk_dir()),
+            vec!["a", "B", "b", "C", "file10", "File2"]
+        );
+        assert_eq!(
+            names(walk_dir().natural_sort(true)),
+            vec!["a", "B", "b", "C", "File2", "file10"]
+        );
+    }
+}
+
+#[cfg(feature = "unicode-case")]
+#[test]
+fn sort_case_insensitive_unicode() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["Éa", "éb", "z"]);
+
+    let names: Vec<String> = WalkDir::new(dir.path())
+        .sort_case_insensitive(true)
+        .min_depth(1)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().file_name.into_string().unwrap())
+        .collect();
+    assert_eq!(names, vec!["z", "Éa", "éb"]);
+}
+
+#[test]
+fn into_sorted_iter() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    dir.mkdirp("b");
+    dir.touch_all(&["a/b/c/d", "a/b-c", "a/b.txt", "a-b", "a.txt", "a0", "b/x"]);
+
+    let local_paths = |walk_dir: WalkDir| -> Vec<String> {
+        walk_dir
+            .into_sorted_iter()
+            .map(|dir_entry| {
+                let path = dir_entry.unwrap().path();
+                let path = path.strip_prefix(dir.path()).unwrap();
+                path.to_str().unwrap().replace('\\', "/")
+            })
+            .collect()
+    };
+    let expected = vec![
+        "", "a", "a-b", "a.txt", "a/b", "a/b-c", "a/b.txt", "a/b/c", "a/b/c/d", "a0", "b", "b/x",
+    ];
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = || WalkDir::new(dir.path()).parallelism(parallelism.clone());
+        assert_eq!(local_paths(walk_dir()), expected);
+        assert_eq!(local_paths(walk_dir().contents_first(true)), expected);
+        assert_eq!(
+            local_paths(walk_dir().sort_by(|a, b| b.file_name.cmp(&a.file_name))),
+            expected
+        );
+    }
+
+    // Roots are merged too
+    let paths = local_paths(WalkDir::new(dir.path().join("b")).add_root(dir.path().join("a/b")));
+    assert_eq!(paths, vec!["a/b", "a/b/c", "a/b/c/d", "b", "b/x"]);
+}
+
+#[test]
+fn into_sorted_iter_streams() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = Dir::tmp();
+    for i in 0..100 {
+        dir.mkdirp(format!("{}/sub", i));
+    }
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counted_reads = reads.clone();
+    let mut iter = WalkDir::new(dir.path())
+        .parallelism(Parallelism::Serial)
+        .process_read_dir(move |_, _, _, _| {
+            counted_reads.fetch_add(1, Ordering::SeqCst);
+        })
+        .into_sorted_iter();
+
+    // Entries sorting before all pending entries are yielded right away,
+    // without reading the rest of the tree
+    assert_eq!(iter.next().unwrap().unwrap().path(), dir.path());
+    assert_eq!(iter.next().unwrap().unwrap().path(), dir.join("0"));
+    assert!(reads.load(Ordering::SeqCst) < 10);
+
+    // Held back entries are yielded once nothing is pending anymore
+    let paths: Vec<_> = iter.map(|dir_entry| dir_entry.unwrap().path()).collect();
+    assert_eq!(paths.len(), 199);
+    assert_eq!(paths.last(), Some(&dir.join("99/sub")));
+}
+
+#[test]
+fn filter_entry() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .filter_entry(|dir_entry| {
+                dir_entry.file_name != "b.txt" && dir_entry.file_name != "group 1"
+            })
+            .sort(true),
+    );
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "c.txt (1)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn skip_current_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = WalkDir::new(&test_dir)
+            .parallelism(parallelism.clone())
+            .sort(true);
+        let mut iter = walk_dir.into_iter();
+        let mut file_names = Vec::new();
+        while let Some(dir_entry) = iter.next() {
+            let dir_entry = dir_entry.unwrap();
+            if dir_entry.file_name == "group 1" || dir_entry.file_name == "e.txt" {
+                iter.skip_current_dir();
+            }
+            if dir_entry.depth > 0 {
+                file_names.push(dir_entry.file_name.to_str().unwrap().to_string());
+            }
+        }
+        assert_eq!(
+            file_names,
+            vec!["a.txt", "b.txt", "c.txt", "group 1", "group 2", "e.txt"]
+        );
+
+        // Skips the rest of root, including the pending reads of both groups
+        let mut iter = WalkDir::new(&test_dir)
+            .parallelism(parallelism)
+            .sort(true)
+            .into_iter();
+        iter.next();
+        let a_txt = iter.next().unwrap().unwrap();
+        assert_eq!(a_txt.file_name, "a.txt");
+        iter.skip_current_dir();
+        assert!(iter.next().is_none());
+    }
+}
+
+#[test]
+fn yield_between_dirs() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let local_paths = local_paths(WalkDir::new(&test_dir).sort(true).yield_between_dirs(true));
+    assert_eq!(local_paths, expected);
+}
+
+#[test]
+fn read_dir_order_fifo() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    for parallelism in [Parallelism::RayonNewPool(2), Parallelism::Serial] {
+        let local_paths = local_paths(
+            WalkDir::new(&test_dir)
+                .sort(true)
+                .parallelism(parallelism)
+                .read_dir_order(ReadDirOrder::Fifo),
+        );
+        assert_eq!(local_paths, expected);
+    }
+}
+
+#[test]
+fn scheduler() {
+    use jwalk::scheduler::{BreadthFirst, DepthFirst, Fifo, ScheduledReadDir, Scheduler};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Reads the most recently found directory first, counting reads
+    struct Lifo(Vec<ScheduledReadDir<((), ())>>, Arc<AtomicUsize>);
+
+    impl Scheduler<((), ())> for Lifo {
+        fn push(&mut self, read_dir: ScheduledReadDir<((), ())>) {
+            assert_eq!(
+                read_dir.index_path().len(),
+                read_dir.read_dir_spec().depth + 1
+            );
+            self.0.push(read_dir);
+        }
+
+        fn pop(&mut self) -> Option<ScheduledReadDir<((), ())>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.pop()
+        }
+    }
+
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = || {
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::RayonNewPool(2))
+    };
+    let expected = local_paths(walk_dir());
+    assert_eq!(
+        local_paths(walk_dir().scheduler(DepthFirst::default)),
+        expected
+    );
+    assert_eq!(
+        local_paths(walk_dir().scheduler(BreadthFirst::default)),
+        expected
+    );
+    assert_eq!(local_paths(walk_dir().scheduler(Fifo::default)), expected);
+
+    let pops = Arc::new(AtomicUsize::new(0));
+    let walk_dir = walk_dir().scheduler({
+        let pops = pops.clone();
+        move || Lifo(Vec::new(), pops.clone())
+    });
+    assert_eq!(local_paths(walk_dir), expected);
+    assert!(pops.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn read_children_priority() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/a");
+    dir.mkdirp("cache/a");
+    dir.mkdirp("docs/a");
+
+    let read_dirs = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let walk_dir = WalkDir::new(dir.path())
+        .sort(true)
+        .parallelism(Parallelism::RayonNewPool(1))
+        .process_read_dir({
+            let read_dirs = read_dirs.clone();
+            move |depth, path, _, children| {
+                if depth == Some(1) {
+                    let name = path.file_name().unwrap().to_str().unwrap();
+                    read_dirs.lock().unwrap().push(name.to_string());
+                }
+                for dir_entry in children.iter_mut().flatten() {
+                    if dir_entry.file_name == "docs" {
+                        dir_entry.read_children_priority = 2;
+                    } else if dir_entry.file_name != "cache" {
+                        dir_entry.read_children_priority = 1;
+                    }
+                }
+            }
+        });
+    // Entries are still yielded in walk order
+    let file_names: Vec<_> = walk_dir
+        .into_iter()
+        .skip(1)
+        .map(|dir_entry| dir_entry.unwrap().file_name().to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(file_names, vec!["a", "a", "cache", "a", "docs", "a"]);
+    assert_eq!(*read_dirs.lock().unwrap(), vec!["docs", "a", "cache"]);
+}
+
+#[test]
+fn parallelism_adaptive() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    for max_threads in [0, 1, 2] {
+        let reading = Arc::new(AtomicUsize::new(0));
+        let max_reading = Arc::new(AtomicUsize::new(0));
+        let walk_dir = WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::Adaptive {
+                max_threads,
+                busy_timeout: std::time::Duration::from_secs(1),
+            })
+            .process_read_dir({
+                let reading = reading.clone();
+                let max_reading = max_reading.clone();
+                move |_, _, _, _| {
+                    let now_reading = reading.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_reading.fetch_max(now_reading, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    reading.fetch_sub(1, Ordering::SeqCst);
+                }
+            });
+        assert_eq!(local_paths(walk_dir), expected);
+        if max_threads > 0 {
+            assert!(max_reading.load(Ordering::SeqCst) <= max_threads);
+        }
+    }
+}
+
+#[test]
+fn parallelism_adaptive_busy_timeout() {
+    let (test_dir, _temp_dir) = test_dir();
+    let busy_timeout = std::time::Duration::from_millis(20);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    // The only thread of the pool is blocked waiting for the walk to start
+    let err = pool
+        .install(|| {
+            WalkDir::new(&test_dir)
+                .parallelism(Parallelism::Adaptive {
+                    max_threads: 0,
+                    busy_timeout,
+                })
+                .try_into_iter()
+        })
+        .err()
+        .unwrap();
+    assert!(err.is_busy());
+    assert_eq!(err.busy_timeout(), Some(busy_timeout));
+}
+
+#[test]
+fn parallelism_std_threads() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    for num_threads in [0, 1, 2] {
+        let walk_dir = WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::StdThreads(num_threads));
+        assert_eq!(local_paths(walk_dir), expected);
+    }
+
+    // Reads run on threads of their own instead of rayon's
+    let walk_dir = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::StdThreads(2))
+        .process_read_dir(|depth, _, _, _| {
+            if depth.is_some() {
+                assert!(rayon::current_thread_index().is_none());
+            }
+        });
+    assert!(walk_dir.into_iter().all(|dir_entry| dir_entry.is_ok()));
+}
+
+#[test]
+fn busy_policy_ignored_without_busy_timeout() {
+    let (test_dir, _temp_dir) = test_dir();
+    let caller = std::thread::current().id();
+    let walk_dir = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .busy_policy(BusyPolicy::FallbackNewPool(2))
+        .process_read_dir(move |_, _, _, _| {
+            assert_eq!(std::thread::current().id(), caller);
+        });
+    assert!(walk_dir.into_iter().all(|dir_entry| dir_entry.is_ok()));
+}
+
+#[test]
+fn into_iter_in_pool() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+    let paths = WalkDir::new(&test_dir)
+        .sort(true)
+        .into_iter_in_pool(&pool)
+        .map(|dir_entry| {
+            let dir_entry = dir_entry.unwrap();
+            let path = dir_entry.path();
+            let path = path.strip_prefix(&test_dir).unwrap();
+            format!("{} ({})", path.to_str().unwrap(), dir_entry.depth)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(paths, expected);
+}
+
+#[test]
+fn concurrency_limit() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+
+    let limit = ConcurrencyLimit::new(4);
+    limit.set(1);
+    assert_eq!(limit.get(), 1);
+
+    let reading = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let max_reading = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let walk_dir = {
+        let limit = limit.clone();
+        let reading = reading.clone();
+        let max_reading = max_reading.clone();
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::RayonNewPool(4))
+            .concurrency_limit(Some(limit.clone()))
+            .process_read_dir(move |_, _, _, _| {
+                assert!(limit.in_flight() <= 1);
+                let now_reading = reading.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                max_reading.fetch_max(now_reading, std::sync::atomic::Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                reading.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            })
+    };
+    assert_eq!(local_paths(walk_dir), expected);
+    assert_eq!(max_reading.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert_eq!(limit.in_flight(), 0);
+}
+
+#[test]
+fn adaptive_concurrency_limit() {
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+
+    let limit = ConcurrencyLimit::adaptive(4, 2);
+    assert_eq!(limit.effective(), 4);
+    let walk_dir = WalkDir::new(&test_dir)
+        .sort(true)
+        .parallelism(Parallelism::RayonNewPool(4))
+        .concurrency_limit(Some(limit.clone()));
+    assert_eq!(local_paths(walk_dir), expected);
+    assert_eq!(limit.backlog(), 0);
+    assert_eq!(limit.in_flight(), 0);
+    assert_eq!(limit.effective(), 4);
+}
+
+#[test]
+fn concurrency_limit_dropped_walk() {
+    let (test_dir, _temp_dir) = test_dir();
+    let limit = ConcurrencyLimit::adaptive(4, 2);
+    let mut walk_dir = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonNewPool(2))
+        .concurrency_limit(Some(limit.clone()))
+        .into_iter();
+    assert!(walk_dir.next().is_some());
+    drop(walk_dir);
+
+    // The backlog left by the walk is dropped with it, once its reads ended
+    let started = std::time::Instant::now();
+    while limit.backlog() > 0 && started.elapsed() < std::time::Duration::from_secs(10) {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert_eq!(limit.backlog(), 0);
+    assert_eq!(limit.effective(), 4);
+}
+
+#[test]
+fn concurrency_limit_skipped_reads() {
+    use std::sync::mpsc;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let limit = ConcurrencyLimit::new(1);
+    let (reading_tx, reading_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel::<()>();
+    let blocked_walk = {
+        let limit = limit.clone();
+        let test_dir = test_dir.clone();
+        let release_rx = Mutex::new(release_rx);
+        std::thread::spawn(move || {
+            WalkDir::new(&test_dir)
+                .parallelism(Parallelism::Serial)
+                .concurrency_limit(Some(limit))
+                .process_read_dir(move |depth, _, _, _| {
+                    if depth == Some(0) {
+                        reading_tx.send(()).unwrap();
+                        release_rx.lock().unwrap().recv().unwrap();
+                    }
+                })
+                .into_iter()
+                .count()
+        })
+    };
+    reading_rx.recv().unwrap();
+    assert_eq!(limit.in_flight(), 1);
+
+    // Directories beyond the limits of a walk are skipped without waiting
+    // for a permit held by another walk
+    let walk_dir = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .concurrency_limit(Some(limit.clone()))
+        .max_depth(0);
+    let (count_tx, count_rx) = mpsc::channel();
+    std::thread::spawn(move || count_tx.send(walk_dir.into_iter().count()).unwrap());
+    let count = count_rx.recv_timeout(std::time::Duration::from_secs(10));
+    assert_eq!(count, Ok(1));
+    assert_eq!(limit.backlog(), 0);
+
+    release_tx.send(()).unwrap();
+    assert_eq!(blocked_walk.join().unwrap(), 8);
+    assert_eq!(limit.in_flight(), 0);
+}
+
+#[test]
+fn instrumentation() {
+    #[derive(Default)]
+    struct Counts {
+        queued: Mutex<Vec<PathBuf>>,
+        started: Mutex<Vec<PathBuf>>,
+        finished: Mutex<Vec<(PathBuf, usize)>>,
+        errors: Mutex<usize>,
+    }
+
+    impl jwalk::instrument::Instrumentation for Counts {
+        fn on_spec_queued(&self, path: &std::path::Path, _depth: usize) {
+            self.queued.lock().unwrap().push(path.to_path_buf());
+        }
+        fn on_read_dir_start(&self, path: &std::path::Path, _depth: usize) {
+            self.started.lock().unwrap().push(path.to_path_buf());
+        }
+        fn on_read_dir_finish(
+            &self,
+            path: &std::path::Path,
+            _depth: usize,
+            entries: usize,
+            _elapsed: std::time::Duration,
+        ) {
+            self.finished
+                .lock()
+                .unwrap()
+                .push((path.to_path_buf(), entries));
+        }
+        fn on_error(&self, _error: &Error) {
+            *self.errors.lock().unwrap() += 1;
+        }
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch("a/x");
+    dir.touch("y");
+
+    let counts = std::sync::Arc::new(Counts::default());
+    let wd = WalkDir::new(dir.path()).instrumentation(Some(counts.clone()));
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let mut queued = counts.queued.lock().unwrap().clone();
+    queued.sort();
+    let mut started = counts.started.lock().unwrap().clone();
+    started.sort();
+    let mut finished = counts.finished.lock().unwrap().clone();
+    finished.sort();
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("a/b")];
+    assert_eq!(queued, expected);
+    assert_eq!(started, expected);
+    assert_eq!(
+        finished,
+        vec![
+            (dir.path().to_path_buf(), 2),
+            (dir.join("a"), 2),
+            (dir.join("a/b"), 0)
+        ]
+    );
+    assert_eq!(*counts.errors.lock().unwrap(), 0);
+}
+
+#[test]
+fn on_progress() {
+    use jwalk::instrument::Progress;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let reports = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let wd = {
+        let reports = reports.clone();
+        WalkDir::new(&test_dir).on_progress(std::time::Duration::from_secs(3600), move |progress| {
+            reports.lock().unwrap().push(*progress)
+        })
+    };
+    assert_eq!(wd.into_iter().count(), 8);
+
+    let reports = reports.lock().unwrap();
+    // The first read and the last read are reported
+    assert_eq!(reports.len(), 2);
+    assert_eq!(
+        reports.last(),
+        Some(&Progress {
+            entries: 7,
+            dirs_queued: 3,
+            dirs_completed: 3,
+            errors: 0,
+        })
+    );
+    assert_eq!(reports.last().unwrap().dirs_pending(), 0);
+}
+
+#[test]
+fn on_progress_every_read() {
+    let (test_dir, _temp_dir) = test_dir();
+    let reports = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let wd = {
+        let reports = reports.clone();
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::Serial)
+            .on_progress(std::time::Duration::ZERO, move |progress| {
+                reports.lock().unwrap().push(*progress)
+            })
+    };
+    assert_eq!(wd.into_iter().count(), 8);
+
+    // Every read is due without an interval, starting with the first one
+    let reports = reports.lock().unwrap();
+    let dirs_completed: Vec<_> = reports
+        .iter()
+        .map(|progress| progress.dirs_completed)
+        .collect();
+    assert_eq!(dirs_completed, vec![1, 2, 3]);
+    assert_eq!(reports[0].entries, 5);
+}
+
+#[test]
+fn slow_read_dirs() {
+    use jwalk::instrument::SlowReadDirs;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("fast");
+    dir.mkdirp("slow");
+    dir.touch("slow/a");
+
+    let reported = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let slow_read_dirs = {
+        let reported = reported.clone();
+        std::sync::Arc::new(
+            SlowReadDirs::new(std::time::Duration::from_millis(50)).on_slow_read_dir(
+                move |slow_read_dir| {
+                    reported.lock().unwrap().push(slow_read_dir.path.clone());
+                },
+            ),
+        )
+    };
+    let wd = WalkDir::new(dir.path())
+        .instrumentation(Some(slow_read_dirs.clone()))
+        .process_read_dir(|_, path, _, _| {
+            if path.ends_with("slow") {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+        });
+    dir.run_recursive(wd).assert_no_errors();
+
+    let slow = slow_read_dirs.slow_read_dirs();
+    assert_eq!(slow.len(), 1);
+    assert_eq!(slow[0].path, dir.join("slow"));
+    assert_eq!(slow[0].depth, 1);
+    assert_eq!(slow[0].entries, 1);
+    assert!(slow[0].elapsed >= std::time::Duration::from_millis(100));
+    assert_eq!(*reported.lock().unwrap(), vec![dir.join("slow")]);
+}
+
+#[test]
+fn process_read_dir_with_scratch() {
+    let (test_dir, _temp_dir) = test_dir();
+    let created = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let read_dirs = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    let walk_dir = {
+        let created = created.clone();
+        let read_dirs = read_dirs.clone();
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .parallelism(Parallelism::Serial)
+            .process_read_dir_with_scratch(
+                move || {
+                    created.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    Vec::<String>::new()
+                },
+                move |names, _, _, _, children| {
+                    read_dirs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    assert!(names.is_empty());
+                    names.extend(
+                        children
+                            .iter()
+                            .flatten()
+                            .map(|dir_entry| dir_entry.file_name.to_string_lossy().into_owned()),
+                    );
+                    children.retain(|dir_entry_result| {
+                        dir_entry_result
+                            .as_ref()
+                            .map(|dir_entry| dir_entry.file_name != "b.txt")
+                            .unwrap_or(true)
+                    });
+                    names.clear();
+                },
+            )
+    };
+    let paths = local_paths(walk_dir);
+    assert!(paths.contains(&"a.txt (1)".to_string()));
+    assert!(!paths.contains(&"b.txt (1)".to_string()));
+    assert!(read_dirs.load(std::sync::atomic::Ordering::SeqCst) > 1);
+    assert_eq!(created.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // One scratch value per thread, after an earlier `process_read_dir`
+    let init_threads = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let walk_dir = {
+        let init_threads = init_threads.clone();
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .process_read_dir(|_, _, _, children| {
+                children.retain(|dir_entry_result| {
+                    dir_entry_result
+                        .as_ref()
+                        .map(|dir_entry| dir_entry.file_name != "a.txt")
+                        .unwrap_or(true)
+                });
+            })
+            .process_read_dir_with_scratch(
+                move || {
+                    init_threads
+                        .lock()
+                        .unwrap()
+                        .push(std::thread::current().id());
+                    std::thread::current().id()
+                },
+                |thread_id, _, _, _, children| {
+                    assert_eq!(*thread_id, std::thread::current().id());
+                    assert!(children
+                        .iter()
+                        .flatten()
+                        .all(|dir_entry| dir_entry.file_name != "a.txt"));
+                },
+            )
+    };
+    let paths = local_paths(walk_dir);
+    assert!(!paths.contains(&"a.txt (1)".to_string()));
+    let mut init_threads = init_threads.lock().unwrap().clone();
+    let created = init_threads.len();
+    init_threads.sort_by_key(|thread_id| format!("{:?}", thread_id));
+    init_threads.dedup();
+    assert_eq!(init_threads.len(), created);
+}
+
+#[test]
+#[cfg(all(feature = "camino", unix))]
+fn utf8_walk_dir() {
+    use jwalk::utf8::{Utf8Policy, Utf8WalkDir};
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/b");
+    dir.touch(std::ffi::OsStr::from_bytes(b"invalid\xff"));
+    let root = camino::Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+    let walk = |policy| {
+        Utf8WalkDir::new(&root)
+            .policy(policy)
+            .walk_dir(|walk_dir| walk_dir.sort(true))
+            .into_iter()
+            .collect::<Vec<_>>()
+    };
+
+    let results = walk(Utf8Policy::Skip);
+    let paths: Vec<_> = results
+        .iter()
+        .map(|dir_entry| dir_entry.as_ref().unwrap().path().to_path_buf())
+        .collect();
+    assert_eq!(paths, vec![root.clone(), root.join("a"), root.join("a/b")]);
+
+    let results = walk(Utf8Policy::Error);
+    assert_eq!(results.len(), 4);
+    let err = results[3].as_ref().unwrap_err();
+    assert_eq!(err.depth(), 1);
+    assert_eq!(
+        err.io_error().unwrap().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn named_client_state() {
+    #[derive(Clone, Debug, Default)]
+    struct DirState {
+        parent_name: String,
+    }
+
+    #[derive(Debug, Default)]
+    struct EntryState {
+        parent_name: String,
+    }
+
+    client_state! {
+        struct NamedState {
+            read_dir: DirState,
+            dir_entry: EntryState,
+        }
+    }
+
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDirGeneric::<NamedState>::new(&test_dir)
+        .sort(true)
+        .process_read_dir(|_, path, dir_state, children| {
+            dir_state.parent_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            for dir_entry in children.iter_mut().flatten() {
+                dir_entry.client_state.parent_name = dir_state.parent_name.clone();
+            }
+        });
+    let names: Vec<_> = walk_dir
+        .into_iter()
+        .skip(1)
+        .map(|dir_entry| dir_entry.unwrap().client_state.parent_name)
+        .collect();
+    assert!(!names.is_empty());
+    assert_eq!(names[0], "test_dir");
+}
+
+#[test]
+fn for_each_ref() {
+    let (test_dir, _temp_dir) = test_dir();
+    let mut expected = local_paths(WalkDir::new(&test_dir));
+    expected.sort();
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let paths = Mutex::new(Vec::new());
+        WalkDir::new(&test_dir)
+            .parallelism(parallelism)
+            .for_each_ref(|dir_entry| {
+                let dir_entry = dir_entry.unwrap();
+                let path = dir_entry.path();
+                let path = path.strip_prefix(&test_dir).unwrap();
+                paths.lock().unwrap().push(format!(
+                    "{} ({})",
+                    path.to_str().unwrap(),
+                    dir_entry.depth()
+                ));
+            });
+        let mut paths = paths.into_inner().unwrap();
+        paths.sort();
+        assert_eq!(paths, expected);
+    }
+
+    let count = std::sync::atomic::AtomicUsize::new(0);
+    WalkDir::new(&test_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .for_each_ref(|dir_entry| {
+            assert_eq!(dir_entry.unwrap().depth(), 1);
+            count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        });
+    assert_eq!(
+        count.into_inner(),
+        local_paths(WalkDir::new(&test_dir).min_depth(1).max_depth(1)).len()
+    );
+}
+
+#[test]
+fn for_each_raw() {
+    let (test_dir, _temp_dir) = test_dir();
+    let dirs = std::sync::atomic::AtomicUsize::new(0);
+    let files = Mutex::new(Vec::new());
+    WalkDir::new(&test_dir).for_each_raw(|parent, name, file_type| {
+        if file_type.is_dir() {
+            dirs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        } else {
+            files.lock().unwrap().push(parent.join(name));
+        }
+    });
+
+    let entries: Vec<_> = WalkDir::new(&test_dir)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect();
+    let mut expected_files: Vec<_> = entries
+        .iter()
+        .filter(|dir_entry| !dir_entry.file_type.is_dir())
+        .map(|dir_entry| dir_entry.path())
+        .collect();
+    expected_files.sort();
+    let mut files = files.into_inner().unwrap();
+    files.sort();
+    assert_eq!(files, expected_files);
+    assert_eq!(dirs.into_inner(), entries.len() - expected_files.len());
+}
+
+#[test]
+fn injected_latency_keeps_order() {
+    use jwalk::file_system::Latency;
+    use jwalk::memfs::MemoryFs;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let mut memory_fs = MemoryFs::new();
+    memory_fs.add_file("/memory/a/slow/1");
+    memory_fs.add_file("/memory/b/fast/2");
+    memory_fs.add_file("/memory/c");
+
+    let expected: Vec<_> = memory_fs
+        .walk_dir::<((), ())>("/memory")
+        .sort(true)
+        .parallelism(Parallelism::Serial)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect();
+
+    let latency = Latency::new(memory_fs)
+        .delay_read_dir("/memory/a", Duration::from_millis(30))
+        .delay_read_dir("/memory/a/slow", Duration::from_millis(30));
+    let limit = ConcurrencyLimit::adaptive(4, 1);
+    let paths: Vec<_> = WalkDir::new("/memory")
+        .file_system(Some(Arc::new(latency)))
+        .sort(true)
+        .parallelism(Parallelism::RayonNewPool(4))
+        .concurrency_limit(Some(limit.clone()))
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect();
+    assert_eq!(paths, expected);
+    assert_eq!(limit.backlog(), 0);
+}
+
+#[test]
+fn injected_failures() {
+    use jwalk::file_system::Latency;
+    use jwalk::memfs::MemoryFs;
+    use std::io;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let mut memory_fs = MemoryFs::new();
+    memory_fs.add_file("/memory/a/1");
+    memory_fs.add_file("/memory/b/2");
+
+    let latency = Latency::new(memory_fs)
+        .delay_read_dir("/memory/a", Duration::from_millis(30))
+        .fail_read_dir("/memory/b", io::ErrorKind::TimedOut, 1)
+        .fail_file_type("/memory", io::ErrorKind::PermissionDenied, 1);
+    let walk = || -> Vec<_> {
+        WalkDir::new("/memory")
+            .file_system(Some(Arc::new(latency.clone())))
+            .sort(true)
+            .into_iter()
+            .map(|dir_entry| match dir_entry {
+                Ok(dir_entry) => Ok((
+                    dir_entry.path(),
+                    dir_entry
+                        .read_children_error
+                        .map(|err| err.io_error().unwrap().kind()),
+                )),
+                Err(err) => Err(err.io_error().unwrap().kind()),
+            })
+            .collect()
+    };
+
+    // Each scripted failure is returned once, later walks get further
+    assert_eq!(walk(), vec![Err(io::ErrorKind::PermissionDenied)]);
+    let start = Instant::now();
+    assert_eq!(
+        walk(),
+        vec![
+            Ok((PathBuf::from("/memory"), None)),
+            Ok((PathBuf::from("/memory/a"), None)),
+            Ok((PathBuf::from("/memory/a/1"), None)),
+            Ok((PathBuf::from("/memory/b"), Some(io::ErrorKind::TimedOut))),
+        ]
+    );
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(walk().len(), 5);
+}
+
+#[test]
+#[cfg(feature = "indicatif")]
+fn walk_progress() {
+    use jwalk::progress::WalkProgress;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let progress = std::sync::Arc::new(WalkProgress::new(indicatif::ProgressBar::hidden()));
+    let entries = WalkDir::new(&test_dir)
+        .instrumentation(Some(progress.clone()))
+        .into_iter()
+        .count();
+    progress.finish();
+
+    let dirs = WalkDir::new(&test_dir)
+        .into_iter()
+        .filter(|dir_entry| dir_entry.as_ref().unwrap().file_type.is_dir())
+        .count();
+    assert_eq!(progress.progress_bar().position() as usize, entries - 1);
+    assert_eq!(progress.dirs() as usize, dirs);
+    assert_eq!(progress.progress_bar().prefix(), dirs.to_string());
+}
+
+#[cfg(all(unix, feature = "locate"))]
+#[test]
+fn locate_db() {
+    use jwalk::locate::LocateDb;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    fn push_str(db: &mut Vec<u8>, s: &std::ffi::OsStr) {
+        db.extend_from_slice(s.as_bytes());
+        db.push(0);
+    }
+
+    let (test_dir, _temp_dir) = test_dir();
+
+    // Write a database like updatedb would
+    let mut db = b"\0mlocate".to_vec();
+    db.extend_from_slice(&0u32.to_be_bytes());
+    db.extend_from_slice(&[0, 0, 0, 0]);
+    push_str(&mut db, test_dir.as_os_str());
+    let mut expected = Vec::new();
+    for dir_entry in WalkDir::new(&test_dir).sort(true).skip_hidden(false) {
+        let dir_entry = dir_entry.unwrap();
+        if !dir_entry.file_type.is_dir() {
+            continue;
+        }
+        let metadata = dir_entry.metadata().unwrap();
+        let time = (metadata.ctime(), metadata.ctime_nsec())
+            .max((metadata.mtime(), metadata.mtime_nsec()));
+        db.extend_from_slice(&(time.0 as u64).to_be_bytes());
+        db.extend_from_slice(&(time.1 as u32).to_be_bytes());
+        db.extend_from_slice(&[0, 0, 0, 0]);
+        push_str(&mut db, dir_entry.path().as_os_str());
+        for child in WalkDir::new(dir_entry.path())
+            .sort(true)
+            .skip_hidden(false)
+            .min_depth(1)
+            .max_depth(1)
+        {
+            let child = child.unwrap();
+            db.push(child.file_type.is_dir() as u8);
+            push_str(&mut db, &child.file_name);
+            expected.push(child.path());
+        }
+        db.push(2);
+    }
+
+    let db = LocateDb::from_bytes(db).unwrap();
+    assert_eq!(db.root(), test_dir);
+    let dirs = db.dirs().collect::<std::io::Result<Vec<_>>>().unwrap();
+    assert!(dirs.iter().all(|dir| dir.is_fresh()));
+    let paths = dirs.iter().flat_map(|dir| dir.paths()).collect::<Vec<_>>();
+    assert_eq!(paths, expected);
+
+    let group_1 = test_dir.join("group 1");
+    let under = db
+        .dirs_under(&group_1)
+        .map(|dir| dir.unwrap().path)
+        .collect::<Vec<_>>();
+    assert_eq!(under, vec![group_1.clone()]);
+
+    std::fs::remove_dir_all(&group_1).unwrap();
+    assert!(!db.dirs_under(&group_1).next().unwrap().unwrap().is_fresh());
+
+    assert!(LocateDb::from_bytes(b"\0plocate".to_vec()).is_err());
+}
+
+#[test]
+fn diff_trees() {
+    use jwalk::diff::DiffEntry;
+
+    let dir = Dir::tmp();
+    dir.mkdirp("left/both/only_left");
+    dir.touch("left/both/a");
+    dir.touch("left/both/only_left/b");
+    dir.touch("left/kind");
+    dir.mkdirp("right/both");
+    dir.touch("right/both/a");
+    dir.touch("right/both/c");
+    dir.mkdirp("right/kind/d");
+
+    let diff_entries = jwalk::diff(dir.join("left"), dir.join("right"))
+        .walk_dir(|walk_dir| walk_dir.parallelism(Parallelism::RayonNewPool(2)))
+        .into_iter()
+        .map(|diff_entry| {
+            let diff_entry = diff_entry.unwrap();
+            let side = match diff_entry {
+                DiffEntry::OnlyLeft { .. } => "left",
+                DiffEntry::OnlyRight { .. } => "right",
+                DiffEntry::Both { .. } => "both",
+            };
+            format!("{} ({})", diff_entry.path().to_str().unwrap(), side)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        diff_entries,
+        vec![
+            " (both)",
+            "both (both)",
+            "both/a (both)",
+            "both/c (right)",
+            "both/only_left (left)",
+            "both/only_left/b (left)",
+            "kind (both)",
+            "kind/d (right)",
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "hash")]
+fn hash_files() {
+    use jwalk::hash::{HashState, Hasher};
+
+    // Digest of the length and the byte sum of the contents
+    #[derive(Default)]
+    struct SumHasher {
+        len: u8,
+        sum: u8,
+    }
+
+    impl Hasher for SumHasher {
+        fn update(&mut self, data: &[u8]) {
+            for byte in data {
+                self.len = self.len.wrapping_add(1);
+                self.sum = self.sum.wrapping_add(*byte);
+            }
+        }
+
+        fn finish(self) -> Vec<u8> {
+            vec![self.len, self.sum]
+        }
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("sub");
+    fs::write(dir.join("a"), [1, 2, 3]).unwrap();
+    fs::write(dir.join("sub/b"), []).unwrap();
+
+    let wd = WalkDirGeneric::<HashState>::new(dir.path())
+        .sort(true)
+        .process_read_dir(|_, _, _, children| {
+            // Runs before hashing
+            for dir_entry in children.iter_mut().flatten() {
+                dir_entry.client_state = Some(vec![]);
+            }
+        })
+        .hash_files::<SumHasher>();
+    let digests: Vec<_> = wd
+        .into_iter()
+        .map(|dir_entry| {
+            let dir_entry = dir_entry.unwrap();
+            (dir_entry.file_name().to_owned(), dir_entry.client_state)
+        })
+        .skip(1)
+        .collect();
+    assert_eq!(
+        digests,
+        vec![
+            ("a".into(), Some(vec![3, 6])),
+            ("sub".into(), Some(vec![])),
+            ("b".into(), Some(vec![0, 0])),
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "manifest")]
+fn verify_manifest() {
+    use jwalk::manifest::{Manifest, ManifestDiff, ManifestEntry};
+
+    let (test_dir, _temp_dir) = test_dir();
+    let hash = |path: &std::path::Path| std::fs::read(path);
+    let manifest = WalkDir::new(&test_dir)
+        .skip_hidden(false)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .filter(|dir_entry| dir_entry.file_type.is_file())
+        .map(|dir_entry| {
+            let path = dir_entry.path();
+            let entry =
+                ManifestEntry::new(dir_entry.metadata().unwrap().len()).hash(hash(&path).unwrap());
+            (path.strip_prefix(&test_dir).unwrap().to_path_buf(), entry)
+        })
+        .collect::<Manifest>();
+    assert_eq!(manifest.len(), 6);
+    assert_eq!(manifest.verify(&test_dir).hash(hash).into_iter().count(), 0);
+
+    std::fs::write(test_dir.join("a.txt"), "changed").unwrap();
+    std::fs::write(test_dir.join("group 1/d.txt"), "xxxxxxx").unwrap();
+    std::fs::remove_file(test_dir.join("b.txt")).unwrap();
+    std::fs::write(test_dir.join("group 2/f.txt"), "").unwrap();
+    let mut diffs = manifest
+        .verify(&test_dir)
+        .hash(hash)
+        .walk_dir(|walk_dir| walk_dir.parallelism(Parallelism::RayonNewPool(2)))
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    diffs.sort_by_key(|diff| diff.path().to_path_buf());
+    assert_eq!(
+        diffs,
+        vec![
+            ManifestDiff::SizeChanged {
+                path: PathBuf::from("a.txt"),
+                expected: 2,
+                actual: 7,
+            },
+            ManifestDiff::Removed(PathBuf::from("b.txt")),
+            ManifestDiff::HashChanged(PathBuf::from("group 1/d.txt")),
+            ManifestDiff::Added(PathBuf::from("group 2/f.txt")),
+        ]
+    );
+}
+
+#[test]
+fn classify() {
+    use jwalk::classify::Classify;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let mut classified = Classify::new(WalkDir::new(&test_dir).sort(true))
+        .class("dirs", |dir_entry| dir_entry.file_type.is_dir())
+        .class("group 1", |dir_entry| {
+            dir_entry.parent_path.ends_with("group 1")
+        })
+        .class("a", |dir_entry| dir_entry.file_name == "a.txt")
+        .otherwise("other")
+        .spawn();
+    classified.wait();
+    assert!(classified.receiver("missing").is_none());
+    let names = |name: &str| {
+        classified
+            .receiver(name)
+            .unwrap()
+            .try_iter()
+            .map(|dir_entry| dir_entry.file_name.to_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("dirs"), vec!["test_dir", "group 1", "group 2"]);
+    assert_eq!(names("group 1"), vec!["d.txt"]);
+    assert_eq!(names("a"), vec!["a.txt"]);
+    assert_eq!(names("other"), vec!["b.txt", "c.txt", "e.txt"]);
+    assert!(classified.errors().try_recv().is_err());
+}
+
+#[test]
+fn mtime_rollup() {
+    use jwalk::rollup::MtimeRollup;
+    use std::time::{Duration, SystemTime};
+
+    let (test_dir, _temp_dir) = test_dir();
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let new = old + Duration::from_secs(60);
+    // Directories last, changing their files doesn't touch them after that
+    let mut paths = WalkDir::new(&test_dir)
+        .skip_hidden(false)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap())
+        .collect::<Vec<_>>();
+    paths.sort_by_key(|dir_entry| dir_entry.file_type.is_dir());
+    for dir_entry in paths {
+        let file = fs::File::open(dir_entry.path()).unwrap();
+        file.set_modified(old).unwrap();
+    }
+    let d_txt = fs::File::open(test_dir.join("group 1/d.txt")).unwrap();
+    d_txt.set_modified(new).unwrap();
+
+    let rollup = MtimeRollup::from_walk(WalkDir::new(&test_dir)).unwrap();
+    assert_eq!(rollup.latest(&test_dir), Some(new));
+    assert_eq!(rollup.latest(test_dir.join("group 1")), Some(new));
+    assert_eq!(rollup.latest(test_dir.join("group 2")), Some(old));
+    assert_eq!(rollup.latest(test_dir.join("a.txt")), None);
+    assert_eq!(rollup.iter().count(), 3);
+}
+
+#[test]
+fn par_entries() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = || WalkDir::new(&test_dir).skip_hidden(false).min_depth(1);
+    let mut expected = walk_dir()
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect::<Vec<_>>();
+    expected.sort();
+    let mut paths = walk_dir()
+        .par_entries()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert_eq!(paths, expected);
+}
+
+#[test]
+fn strict_order() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = || WalkDir::new(&test_dir).skip_hidden(false);
+    let mut expected = walk_dir()
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect::<Vec<_>>();
+    expected.sort();
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let mut paths = Vec::new();
+        for dir_entry in walk_dir().parallelism(parallelism).strict_order(false) {
+            let path = dir_entry.unwrap().path();
+            // Directories are still yielded before their contents
+            assert!(path == test_dir || paths.contains(&path.parent().unwrap().to_path_buf()));
+            paths.push(path);
+        }
+        paths.sort();
+        assert_eq!(paths, expected);
+    }
+
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    let walk_dir = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .strict_order(false)
+        .process_read_dir(|depth, _, _, _| {
+            if depth == Some(1) {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        });
+    assert_eq!(walk_dir.into_iter().count(), 3);
+}
+
+#[test]
+fn visit() {
+    use jwalk::visit::{ParallelVisitor, WalkState};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (test_dir, _temp_dir) = test_dir();
+    let mut expected = WalkDir::new(&test_dir)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .filter(|path| !path.starts_with(test_dir.join("group 1")))
+        .collect::<Vec<_>>();
+    expected.sort();
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let paths = Mutex::new(Vec::new());
+        let visitors = AtomicUsize::new(0);
+        WalkDir::new(&test_dir)
+            .parallelism(parallelism)
+            .visit(&mut || {
+                visitors.fetch_add(1, Ordering::Relaxed);
+                Box::new(|dir_entry: Result<DirEntry<((), ())>>| {
+                    let path = dir_entry.unwrap().path();
+                    let skip = path.ends_with("group 1");
+                    paths.lock().unwrap().push(path);
+                    if skip {
+                        WalkState::Skip
+                    } else {
+                        WalkState::Continue
+                    }
+                }) as Box<dyn ParallelVisitor<_>>
+            });
+        let mut paths = paths.into_inner().unwrap();
+        paths.sort();
+        let mut expected = expected.clone();
+        expected.push(test_dir.join("group 1"));
+        expected.sort();
+        assert_eq!(paths, expected);
+        assert!(visitors.into_inner() >= 1);
+    }
+
+    let visited = AtomicUsize::new(0);
+    WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .visit(&mut || {
+            Box::new(|_: Result<DirEntry<((), ())>>| {
+                visited.fetch_add(1, Ordering::Relaxed);
+                WalkState::Quit
+            }) as Box<dyn ParallelVisitor<_>>
+        });
+    assert_eq!(visited.into_inner(), 1);
+}
+
+#[test]
+fn visit_skip_files() {
+    use jwalk::visit::{ParallelVisitor, WalkState};
+
+    // Skipping an entry that isn't a directory doesn't skip its siblings
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = WalkDir::new(&test_dir)
+        .sort(true)
+        .into_iter()
+        .map(|dir_entry| dir_entry.unwrap().path())
+        .collect::<Vec<_>>();
+    let paths = Mutex::new(Vec::new());
+    WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .sort(true)
+        .visit(&mut || {
+            Box::new(|dir_entry: Result<DirEntry<((), ())>>| {
+                let dir_entry = dir_entry.unwrap();
+                paths.lock().unwrap().push(dir_entry.path());
+                if dir_entry.file_type.is_dir() {
+                    WalkState::Continue
+                } else {
+                    WalkState::Skip
+                }
+            }) as Box<dyn ParallelVisitor<_>>
+        });
+    assert_eq!(paths.into_inner().unwrap(), expected);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn into_stream() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let root = test_dir.clone();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let local_paths = runtime.block_on(async move {
+        let mut stream = WalkDir::new(&root).sort(true).into_stream();
+        let mut local_paths = Vec::new();
+        while let Some(dir_entry) =
+            std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+        {
+            let dir_entry = dir_entry.unwrap();
+            let path = dir_entry.path();
+            let path = path.strip_prefix(&root).unwrap();
+            local_paths.push(format!("{} ({})", path.to_str().unwrap(), dir_entry.depth));
+        }
+        local_paths
+    });
+    assert_eq!(local_paths, expected);
+}
+
+#[test]
+#[cfg(feature = "watch")]
+fn watch() {
+    use jwalk::watch::WatchEvent;
+
+    let (test_dir, _temp_dir) = test_dir();
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true));
+    let local_paths: Vec<String> = WalkDir::new(&test_dir)
+        .sort(true)
+        .watch()
+        .unwrap()
+        .take(expected.len())
+        .map(|watch_event| match watch_event.unwrap() {
+            WatchEvent::Existing(dir_entry) => {
+                let path = dir_entry.path();
+                let path = path.strip_prefix(&test_dir).unwrap();
+                format!("{} ({})", path.to_str().unwrap(), dir_entry.depth)
+            }
+            watch_event => panic!("unexpected {:?}", watch_event),
+        })
+        .collect();
+    assert_eq!(local_paths, expected);
+}
@@ -0,0 +1,28 @@
+#![cfg(not(feature = "rayon"))]
+
+use jwalk::{Parallelism, WalkDir};
+use std::fs;
+
+#[test]
+fn walks_on_std_threads() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+    fs::write(temp_dir.path().join("a/b/c.txt"), "c").unwrap();
+    fs::write(temp_dir.path().join("d.txt"), "d").unwrap();
+
+    let paths = |walk_dir: WalkDir| -> Vec<_> {
+        walk_dir
+            .sort(true)
+            .into_iter()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .collect()
+    };
+    let expected = paths(WalkDir::new(temp_dir.path()).parallelism(Parallelism::Serial));
+    assert_eq!(expected.len(), 5);
+    // Defaults to `Parallelism::StdThreads` without rayon
+    assert_eq!(paths(WalkDir::new(temp_dir.path())), expected);
+    assert_eq!(
+        paths(WalkDir::new(temp_dir.path()).parallelism(Parallelism::StdThreads(2))),
+        expected
+    );
+}